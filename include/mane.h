@@ -0,0 +1,63 @@
+/*
+ * C API of mane's case-aware replacement engine.
+ *
+ * Build the shared library with
+ *
+ *   cargo build --lib --release
+ *
+ * and link against target/release/libmane.so (libmane.dylib, mane.dll).
+ *
+ *   const char *froms[] = { "foo_bar" }, *tos[] = { "baz_qux" };
+ *   ManeRules *rules = mane_rules_compile(froms, tos, 1, MANE_CASE);
+ *   size_t len;
+ *   char *out = mane_rules_replace(rules, "let fooBar;", 11, &len);
+ *   // out = "let bazQux;"
+ *   mane_string_free(out, len);
+ *   mane_rules_free(rules);
+ */
+
+#ifndef MANE_H
+#define MANE_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Replace case variants too (off is `case = false` in the config file) */
+#define MANE_CASE (1u << 0)
+/* Apply the rules one after another (--ordered) */
+#define MANE_ORDERED (1u << 1)
+/* Render TO in the shape of each occurrence (--same-shape) */
+#define MANE_SAME_SHAPE (1u << 2)
+/* Make digits words of their own (--split-digits) */
+#define MANE_SPLIT_DIGITS (1u << 3)
+
+typedef struct ManeRules ManeRules;
+
+/*
+ * Compile count FROM/TO pairs of NUL-terminated UTF-8 strings, or return NULL
+ * if one isn't UTF-8. The case and digit settings are process-wide, so rule
+ * sets compiled with different flags must not be used at the same time.
+ */
+ManeRules *mane_rules_compile(const char *const *froms, const char *const *tos, size_t count, uint32_t flags);
+
+/* Release a rule set (NULL is ignored) */
+void mane_rules_free(ManeRules *rules);
+
+/*
+ * Replace in len bytes of UTF-8 text. Returns a NUL-terminated result and
+ * stores its length in out_len, or returns NULL if the text isn't UTF-8.
+ */
+char *mane_rules_replace(const ManeRules *rules, const char *content, size_t len, size_t *out_len);
+
+/* Release a result of mane_rules_replace, with the length it stored */
+void mane_string_free(char *s, size_t len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
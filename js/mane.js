@@ -0,0 +1,66 @@
+// JavaScript API of the wasm build of mane's replacement core.
+//
+//   cargo build --lib --release --target wasm32-unknown-unknown
+//
+//   import { load } from "./mane.js";
+//   const mane = await load(fetch("mane.wasm"));
+//   mane.replace("let fooBar = 1;", [{ from: "foo_bar", to: "baz_qux" }]);
+//   // => "let bazQux = 1;"
+
+const FLAG_CASE = 1;
+const FLAG_ORDERED = 1 << 1;
+const FLAG_SAME_SHAPE = 1 << 2;
+const FLAG_SPLIT_DIGITS = 1 << 3;
+
+const encoder = new TextEncoder();
+const decoder = new TextDecoder();
+
+/**
+ * Instantiate the module from a Response, a promise of one, or its bytes.
+ *
+ * @returns {Promise<{replace: Function}>}
+ */
+export async function load(source) {
+  source = await source;
+  const { instance } = source instanceof Response
+    ? await WebAssembly.instantiateStreaming(source)
+    : await WebAssembly.instantiate(source);
+  const { memory, mane_alloc, mane_free, mane_replace } = instance.exports;
+
+  // Copy a string into the module's memory
+  const pass = (string) => {
+    const bytes = encoder.encode(string);
+    const ptr = mane_alloc(bytes.length);
+    new Uint8Array(memory.buffer, ptr, bytes.length).set(bytes);
+    return [ptr, bytes.length];
+  };
+
+  return {
+    /**
+     * Replace in a text like `mane -r FROM TO` does.
+     *
+     * @param {string} content - The text to replace in
+     * @param {{from: string, to: string}[]} rules - Rules, in order
+     * @param {{caseVariants?: boolean, ordered?: boolean, sameShape?: boolean, splitDigits?: boolean}} options
+     *   - Case variants are replaced unless `caseVariants` is false, as with `case = false` in the config file
+     * @returns {string} The replaced text
+     */
+    replace(content, rules, options = {}) {
+      const flags = (options.caseVariants === false ? 0 : FLAG_CASE)
+        | (options.ordered ? FLAG_ORDERED : 0)
+        | (options.sameShape ? FLAG_SAME_SHAPE : 0)
+        | (options.splitDigits ? FLAG_SPLIT_DIGITS : 0);
+      const [contentPtr, contentLen] = pass(content);
+      const [rulesPtr, rulesLen] = pass(rules.map(({ from, to }) => `${from}\0${to}\0`).join(""));
+
+      const result = mane_replace(contentPtr, contentLen, rulesPtr, rulesLen, flags);
+      const len = new DataView(memory.buffer).getUint32(result, true);
+      const replaced = decoder.decode(new Uint8Array(memory.buffer, result + 4, len));
+
+      mane_free(contentPtr, contentLen);
+      mane_free(rulesPtr, rulesLen);
+      mane_free(result, len + 4);
+      return replaced;
+    },
+  };
+}
@@ -0,0 +1,595 @@
+use crate::gzip;
+use crate::placeholder;
+use anyhow::{Result, anyhow};
+use std::fs;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+/// Archive formats opened by --archives
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Tar,
+    TarGz,
+    Zip,
+}
+
+impl Kind {
+    /// Find the archive format of a file by its name
+    ///
+    /// # Arguments
+    /// * `path` - Path of the file
+    ///
+    /// # Returns
+    /// * `Option<Kind>` - The format, or None if the file isn't an archive
+    pub fn detect(path: &Path) -> Option<Kind> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        if name.ends_with(".tar") {
+            Some(Kind::Tar)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Kind::TarGz)
+        } else if name.ends_with(".zip") {
+            Some(Kind::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+/// What an archive member is
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum EntryKind {
+    File,
+    Dir,
+
+    /// Links, devices and extended headers, which are kept as they are
+    Special,
+}
+
+/// Format-specific fields kept when repacking
+#[derive(Debug, Clone)]
+enum Meta {
+    /// The original ustar header
+    Tar(Box<[u8; 512]>),
+
+    Zip { version_made: u16, flags: u16, time: u16, date: u16, external_attr: u32 },
+}
+
+/// One member of an archive
+#[derive(Debug, Clone)]
+struct Entry {
+    name: String,
+    data: Vec<u8>,
+    kind: EntryKind,
+    meta: Meta,
+}
+
+/// Replace in the members of an archive and repack it
+///
+/// Text members go through `content`, and every component of every member
+/// path goes through `name`. Binary members and special entries (links,
+/// extended headers) are kept as they are.
+///
+/// # Arguments
+/// * `data` - The archive
+/// * `kind` - Archive format
+/// * `content` - Replacement for the content of a text member, given its path
+/// * `name` - Replacement for one path component, given whether it is a directory
+///
+/// # Returns
+/// * `Result<Option<Vec<u8>>>` - The repacked archive, or None if nothing changed
+pub fn transform(
+    data: &[u8],
+    kind: Kind,
+    mut content: impl FnMut(&str, &str) -> Result<String>,
+    mut name: impl FnMut(&str, bool) -> Result<String>,
+) -> Result<Option<Vec<u8>>> {
+    let mut entries = match kind {
+        Kind::Tar => read_tar(data)?,
+        Kind::TarGz => read_tar(&gzip::decompress(data)?)?,
+        Kind::Zip => read_zip(data)?,
+    };
+
+    let mut changed = false;
+    for entry in entries.iter_mut().filter(|entry| entry.kind != EntryKind::Special) {
+        if entry.kind == EntryKind::File {
+            if let Ok(text) = std::str::from_utf8(&entry.data) {
+                let replaced = content(&entry.name, text)?;
+                if replaced != text {
+                    entry.data = replaced.into_bytes();
+                    changed = true;
+                }
+            }
+        }
+
+        let is_dir = entry.kind == EntryKind::Dir;
+        let trailing_slash = entry.name.ends_with('/');
+        let components: Vec<&str> = entry.name.trim_end_matches('/').split('/').collect();
+        let last = components.len() - 1;
+        let renamed = components.iter().enumerate()
+            .map(|(i, component)| match *component {
+                "" | "." | ".." => Ok(component.to_string()),
+                _ => name(component, i < last || is_dir),
+            })
+            .collect::<Result<Vec<String>>>()?
+            .join("/") + if trailing_slash { "/" } else { "" };
+        if renamed != entry.name {
+            entry.name = renamed;
+            changed = true;
+        }
+    }
+
+    if !changed {
+        return Ok(None);
+    }
+    Ok(Some(match kind {
+        Kind::Tar => write_tar(&entries),
+        Kind::TarGz => gzip::compress(&write_tar(&entries)),
+        Kind::Zip => write_zip(&entries)?,
+    }))
+}
+
+/// Pack a directory tree into an archive
+///
+/// Members are named relative to `root` and sorted by name, keeping the file
+/// modes and modification times.
+///
+/// # Arguments
+/// * `root` - Directory to pack
+/// * `kind` - Archive format
+///
+/// # Returns
+/// * `Result<Vec<u8>>` - The archive
+pub fn pack_dir(root: &Path, kind: Kind) -> Result<Vec<u8>> {
+    let mut entries = Vec::new();
+    collect_entries(root, "", kind, &mut entries)?;
+
+    Ok(match kind {
+        Kind::Tar => write_tar(&entries),
+        Kind::TarGz => gzip::compress(&write_tar(&entries)),
+        Kind::Zip => write_zip(&entries)?,
+    })
+}
+
+/// Add the members of one directory, recursively
+fn collect_entries(dir: &Path, prefix: &str, kind: Kind, entries: &mut Vec<Entry>) -> Result<()> {
+    let mut children = fs::read_dir(dir)
+        .map_err(|e| anyhow!("Failed to read directory {}: {}", dir.display(), e))?
+        .collect::<std::io::Result<Vec<_>>>()?;
+    children.sort_by_key(|child| child.file_name());
+
+    for child in children {
+        let path = child.path();
+        let metadata = fs::symlink_metadata(&path)?;
+        let is_dir = metadata.is_dir();
+        if !is_dir && !metadata.is_file() {
+            warn!("Skipping {}: only files and directories are archived", path.display());
+            continue;
+        }
+
+        let name = format!("{}{}{}", prefix, child.file_name().to_string_lossy(), if is_dir { "/" } else { "" });
+        let mtime = metadata.modified().ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |duration| duration.as_secs());
+        entries.push(Entry {
+            meta: new_meta(kind, is_dir, file_mode(&metadata, is_dir), mtime),
+            kind: if is_dir { EntryKind::Dir } else { EntryKind::File },
+            data: if is_dir { Vec::new() } else { fs::read(&path)? },
+            name: name.clone(),
+        });
+        if is_dir {
+            collect_entries(&path, &name, kind, entries)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn file_mode(metadata: &fs::Metadata, _is_dir: bool) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o7777
+}
+
+#[cfg(not(unix))]
+fn file_mode(_metadata: &fs::Metadata, is_dir: bool) -> u32 {
+    if is_dir { 0o755 } else { 0o644 }
+}
+
+/// Format-specific fields of a member packed from the filesystem
+fn new_meta(kind: Kind, is_dir: bool, mode: u32, mtime: u64) -> Meta {
+    match kind {
+        Kind::Tar | Kind::TarGz => {
+            let mut header = tar_header(if is_dir { b'5' } else { b'0' });
+            put_octal(&mut header[100..108], mode as usize);
+            put_octal(&mut header[136..148], mtime as usize);
+            Meta::Tar(Box::new(header))
+        },
+        Kind::Zip => {
+            // DOS timestamps start in 1980 and have a two second resolution
+            let time = placeholder::utc_time(mtime as i64);
+            let (time, date) = if time.year < 1980 {
+                (0, 0x21)
+            } else {
+                (
+                    (time.hour << 11 | time.minute << 5 | (time.second / 2)) as u16,
+                    (((time.year - 1980) as u32) << 9 | time.month << 5 | time.day) as u16,
+                )
+            };
+            let file_type = if is_dir { 0o040000 } else { 0o100000 };
+            Meta::Zip {
+                version_made: 3 << 8 | 20,
+                flags: 0x0800,
+                time,
+                date,
+                external_attr: (file_type | mode) << 16 | if is_dir { 0x10 } else { 0 },
+            }
+        },
+    }
+}
+
+/// Check whether a header is in the old GNU format, which has no name prefix field
+fn is_gnu(header: &[u8; 512]) -> bool {
+    &header[257..265] == b"ustar  \0"
+}
+
+/// Parse a NUL-terminated header field
+fn c_str(field: &[u8]) -> String {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).to_string()
+}
+
+/// Parse an octal header field
+fn octal(field: &[u8]) -> Result<usize> {
+    let text = c_str(field);
+    let text = text.trim_matches(|c: char| c == ' ' || c == '\0');
+    if text.is_empty() {
+        return Ok(0);
+    }
+    usize::from_str_radix(text, 8).map_err(|_| anyhow!("Invalid number in tar header: {:?}", text))
+}
+
+fn read_tar(data: &[u8]) -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    let mut long_name: Option<String> = None;
+    let mut pos = 0;
+
+    while pos + 512 <= data.len() {
+        let header: [u8; 512] = data[pos..pos + 512].try_into()?;
+        if header.iter().all(|&b| b == 0) {
+            break;
+        }
+        if header[124] & 0x80 != 0 {
+            return Err(anyhow!("Tar members larger than 8 GiB are not supported"));
+        }
+
+        let size = octal(&header[124..136])?;
+        let body = data.get(pos + 512..pos + 512 + size).ok_or_else(|| anyhow!("Truncated tar archive"))?;
+        pos += 512 + size.div_ceil(512) * 512;
+
+        // GNU long names come as a separate entry before the member
+        let typeflag = header[156];
+        if typeflag == b'L' {
+            long_name = Some(c_str(body));
+            continue;
+        }
+
+        let prefix = if is_gnu(&header) { String::new() } else { c_str(&header[345..500]) };
+        let short_name = c_str(&header[..100]);
+        let name = long_name.take().unwrap_or_else(|| {
+            if prefix.is_empty() { short_name } else { format!("{}/{}", prefix, short_name) }
+        });
+        let kind = match typeflag {
+            b'0' | 0 | b'7' => EntryKind::File,
+            b'5' => EntryKind::Dir,
+            _ => EntryKind::Special,
+        };
+
+        entries.push(Entry { name, data: body.to_vec(), kind, meta: Meta::Tar(Box::new(header)) });
+    }
+
+    Ok(entries)
+}
+
+/// Write a number into an octal header field, followed by a NUL
+fn put_octal(field: &mut [u8], value: usize) {
+    let text = format!("{:0width$o}\0", value, width = field.len() - 1);
+    field.copy_from_slice(&text.as_bytes()[text.len() - field.len()..]);
+}
+
+/// Set the name fields of a header, splitting it into prefix and name if needed
+///
+/// # Returns
+/// * `bool` - False if the name doesn't fit and needs a GNU long name entry
+fn put_name(header: &mut [u8; 512], name: &str) -> bool {
+    let gnu = is_gnu(header);
+    header[..100].fill(0);
+    if !gnu {
+        header[345..500].fill(0);
+    }
+
+    if name.len() <= 100 {
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        return true;
+    }
+    let split = name.char_indices()
+        .find(|&(i, c)| !gnu && c == '/' && i <= 155 && name.len() - i - 1 <= 100)
+        .map(|(i, _)| i);
+    match split {
+        Some(i) => {
+            header[345..345 + i].copy_from_slice(&name.as_bytes()[..i]);
+            header[..name.len() - i - 1].copy_from_slice(&name.as_bytes()[i + 1..]);
+            true
+        },
+        None => {
+            let mut end = 100;
+            while !name.is_char_boundary(end) {
+                end -= 1;
+            }
+            header[..end].copy_from_slice(&name.as_bytes()[..end]);
+            false
+        },
+    }
+}
+
+/// Append a header (with its checksum) and a padded body
+fn put_tar_entry(out: &mut Vec<u8>, header: &mut [u8; 512], body: &[u8]) {
+    put_octal(&mut header[124..136], body.len());
+    header[148..156].fill(b' ');
+    let sum: usize = header.iter().map(|&b| b as usize).sum();
+    header[148..156].copy_from_slice(format!("{:06o}\0 ", sum).as_bytes());
+
+    out.extend_from_slice(header);
+    out.extend_from_slice(body);
+    out.resize(out.len().div_ceil(512) * 512, 0);
+}
+
+/// A fresh header for a member that doesn't come from a tar archive
+fn tar_header(typeflag: u8) -> [u8; 512] {
+    let mut header = [0u8; 512];
+    put_octal(&mut header[100..108], if typeflag == b'5' { 0o755 } else { 0o644 });
+    put_octal(&mut header[108..116], 0);
+    put_octal(&mut header[116..124], 0);
+    put_octal(&mut header[136..148], 0);
+    header[156] = typeflag;
+    header[257..265].copy_from_slice(b"ustar\x0000");
+    header
+}
+
+fn write_tar(entries: &[Entry]) -> Vec<u8> {
+    let mut out = Vec::new();
+
+    for entry in entries {
+        let mut header = match &entry.meta {
+            Meta::Tar(header) => **header,
+            Meta::Zip { .. } => tar_header(if entry.kind == EntryKind::Dir { b'5' } else { b'0' }),
+        };
+
+        if !put_name(&mut header, &entry.name) {
+            let mut long = tar_header(b'L');
+            long[..13].copy_from_slice(b"././@LongLink");
+            let mut name = entry.name.clone().into_bytes();
+            name.push(0);
+            put_tar_entry(&mut out, &mut long, &name);
+        }
+        put_tar_entry(&mut out, &mut header, &entry.data);
+    }
+
+    // Two zero blocks end the archive
+    out.resize(out.len() + 1024, 0);
+    out
+}
+
+fn u16_at(data: &[u8], pos: usize) -> Result<u16> {
+    let bytes = data.get(pos..pos + 2).ok_or_else(|| anyhow!("Truncated zip archive"))?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn u32_at(data: &[u8], pos: usize) -> Result<u32> {
+    let bytes = data.get(pos..pos + 4).ok_or_else(|| anyhow!("Truncated zip archive"))?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+const ZIP_LOCAL: u32 = 0x04034b50;
+const ZIP_CENTRAL: u32 = 0x02014b50;
+const ZIP_END: u32 = 0x06054b50;
+
+fn read_zip(data: &[u8]) -> Result<Vec<Entry>> {
+    // The end of central directory record is at the end, before an optional comment
+    let end = (0..data.len().saturating_sub(21)).rev()
+        .take(65536 + 22)
+        .find(|&pos| u32_at(data, pos).is_ok_and(|sig| sig == ZIP_END))
+        .ok_or_else(|| anyhow!("Not a zip archive"))?;
+    let count = u16_at(data, end + 10)? as usize;
+    let mut pos = u32_at(data, end + 16)? as usize;
+    if count == 0xffff || pos == 0xffff_ffff {
+        return Err(anyhow!("Zip64 archives are not supported"));
+    }
+
+    let mut entries = Vec::with_capacity(count);
+    for _ in 0..count {
+        if u32_at(data, pos)? != ZIP_CENTRAL {
+            return Err(anyhow!("Corrupt zip central directory"));
+        }
+        let flags = u16_at(data, pos + 8)?;
+        let method = u16_at(data, pos + 10)?;
+        let compressed_size = u32_at(data, pos + 20)? as usize;
+        let name_len = u16_at(data, pos + 28)? as usize;
+        let extra_len = u16_at(data, pos + 30)? as usize;
+        let comment_len = u16_at(data, pos + 32)? as usize;
+        let local = u32_at(data, pos + 42)? as usize;
+        let name_bytes = data.get(pos + 46..pos + 46 + name_len).ok_or_else(|| anyhow!("Truncated zip archive"))?;
+        let name = String::from_utf8_lossy(name_bytes).to_string();
+
+        if flags & 1 != 0 {
+            return Err(anyhow!("Encrypted zip member: {}", name));
+        }
+        if u32_at(data, local)? != ZIP_LOCAL {
+            return Err(anyhow!("Corrupt zip local header: {}", name));
+        }
+        let start = local + 30 + u16_at(data, local + 26)? as usize + u16_at(data, local + 28)? as usize;
+        let raw = data.get(start..start + compressed_size).ok_or_else(|| anyhow!("Truncated zip member: {}", name))?;
+        let content = match method {
+            0 => raw.to_vec(),
+            8 => gzip::inflate_raw(raw)?,
+            _ => return Err(anyhow!("Unsupported compression method {} in zip member: {}", method, name)),
+        };
+
+        entries.push(Entry {
+            kind: if name.ends_with('/') { EntryKind::Dir } else { EntryKind::File },
+            name,
+            data: content,
+            meta: Meta::Zip {
+                version_made: u16_at(data, pos + 4)?,
+                flags: flags & 0x0800,
+                time: u16_at(data, pos + 12)?,
+                date: u16_at(data, pos + 14)?,
+                external_attr: u32_at(data, pos + 38)?,
+            },
+        });
+        pos += 46 + name_len + extra_len + comment_len;
+    }
+
+    Ok(entries)
+}
+
+fn write_zip(entries: &[Entry]) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut central = Vec::new();
+    let too_large = || anyhow!("Zip archives over 4 GiB are not supported");
+
+    for entry in entries {
+        let (version_made, flags, time, date, external_attr) = match entry.meta {
+            Meta::Zip { version_made, flags, time, date, external_attr } => (version_made, flags, time, date, external_attr),
+            Meta::Tar(_) => (20, 0x0800, 0, 0x21, 0),
+        };
+
+        // Store members that don't get smaller
+        let deflated = gzip::deflate(&entry.data);
+        let (method, stored) = if deflated.len() < entry.data.len() {
+            (8u16, deflated.as_slice())
+        } else {
+            (0u16, entry.data.as_slice())
+        };
+        let crc = gzip::crc32(&entry.data);
+        let offset = u32::try_from(out.len()).map_err(|_| too_large())?;
+        let compressed_size = u32::try_from(stored.len()).map_err(|_| too_large())?;
+        let size = u32::try_from(entry.data.len()).map_err(|_| too_large())?;
+        let name = entry.name.as_bytes();
+
+        // Fields shared by the local and the central header
+        let mut common = Vec::new();
+        common.extend_from_slice(&20u16.to_le_bytes());
+        common.extend_from_slice(&flags.to_le_bytes());
+        common.extend_from_slice(&method.to_le_bytes());
+        common.extend_from_slice(&time.to_le_bytes());
+        common.extend_from_slice(&date.to_le_bytes());
+        common.extend_from_slice(&crc.to_le_bytes());
+        common.extend_from_slice(&compressed_size.to_le_bytes());
+        common.extend_from_slice(&size.to_le_bytes());
+        common.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        common.extend_from_slice(&0u16.to_le_bytes());
+
+        out.extend_from_slice(&ZIP_LOCAL.to_le_bytes());
+        out.extend_from_slice(&common);
+        out.extend_from_slice(name);
+        out.extend_from_slice(stored);
+
+        central.extend_from_slice(&ZIP_CENTRAL.to_le_bytes());
+        central.extend_from_slice(&version_made.to_le_bytes());
+        central.extend_from_slice(&common);
+        central.extend_from_slice(&[0; 6]); // comment length, disk, internal attributes
+        central.extend_from_slice(&external_attr.to_le_bytes());
+        central.extend_from_slice(&offset.to_le_bytes());
+        central.extend_from_slice(name);
+    }
+
+    let central_offset = u32::try_from(out.len()).map_err(|_| too_large())?;
+    let count = u16::try_from(entries.len()).map_err(|_| anyhow!("Zip archives with over 65535 members are not supported"))?;
+    out.extend_from_slice(&central);
+    out.extend_from_slice(&ZIP_END.to_le_bytes());
+    out.extend_from_slice(&[0; 4]); // disk numbers
+    out.extend_from_slice(&count.to_le_bytes());
+    out.extend_from_slice(&count.to_le_bytes());
+    out.extend_from_slice(&(central.len() as u32).to_le_bytes());
+    out.extend_from_slice(&central_offset.to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes());
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::TestDir;
+
+    fn entry(name: &str, data: &[u8], kind: EntryKind, meta: Meta) -> Entry {
+        Entry { name: name.to_string(), data: data.to_vec(), kind, meta }
+    }
+
+    fn rename(archive: &[u8], kind: Kind) -> Vec<u8> {
+        transform(
+            archive,
+            kind,
+            |_, text| Ok(text.replace("foo", "bar")),
+            |component, _| Ok(component.replace("foo", "bar")),
+        ).unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_tar() {
+        let long_dir = "d".repeat(120);
+        let header = |typeflag| Meta::Tar(Box::new(tar_header(typeflag)));
+        let tar = write_tar(&[
+            entry("foo/", b"", EntryKind::Dir, header(b'5')),
+            entry("foo/foo.txt", b"foo", EntryKind::File, header(b'0')),
+            entry(&format!("{}/foo.bin", long_dir), b"\xfffoo", EntryKind::File, header(b'0')),
+        ]);
+
+        let entries = read_tar(&rename(&tar, Kind::Tar)).unwrap();
+        let names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["bar/", "bar/bar.txt", &format!("{}/bar.bin", long_dir)]);
+        assert_eq!(entries[1].data, b"bar");
+        assert_eq!(entries[0].kind, EntryKind::Dir);
+        assert_eq!(entries[2].data, b"\xfffoo");
+
+        // Nothing to replace
+        assert!(transform(&tar, Kind::Tar, |_, t| Ok(t.to_string()), |c, _| Ok(c.to_string())).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_zip() {
+        let meta = Meta::Zip { version_made: 20, flags: 0, time: 0, date: 0x21, external_attr: 0 };
+        let text = "foo ".repeat(100);
+        let zip = write_zip(&[
+            entry("foo/", b"", EntryKind::Dir, meta.clone()),
+            entry("foo/a.txt", text.as_bytes(), EntryKind::File, meta.clone()),
+        ]).unwrap();
+
+        let entries = read_zip(&rename(&zip, Kind::Zip)).unwrap();
+        assert_eq!(entries[0].name, "bar/");
+        assert_eq!(entries[1].name, "bar/a.txt");
+        assert_eq!(entries[1].data, "bar ".repeat(100).as_bytes());
+        assert_eq!(Kind::detect(Path::new("fixtures.TGZ")), Some(Kind::TarGz));
+    }
+
+    #[test]
+    fn test_pack_dir() {
+        let root = TestDir::new("pack");
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("src/main.rs"), "fn main() {}").unwrap();
+        fs::write(root.join("README"), "hello").unwrap();
+
+        for kind in [Kind::Tar, Kind::TarGz, Kind::Zip] {
+            let packed = pack_dir(&root, kind).unwrap();
+            let entries = match kind {
+                Kind::Tar => read_tar(&packed),
+                Kind::TarGz => read_tar(&gzip::decompress(&packed).unwrap()),
+                Kind::Zip => read_zip(&packed),
+            }.unwrap();
+            let names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+            assert_eq!(names, ["README", "src/", "src/main.rs"]);
+            assert_eq!(entries[1].kind, EntryKind::Dir);
+            assert_eq!(entries[2].data, b"fn main() {}");
+        }
+    }
+}
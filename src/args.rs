@@ -1,11 +1,36 @@
-use clap::{Parser, ArgAction};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand, ArgAction};
 use atty::Stream;
-use anyhow::{Result, anyhow};
-use std::path::PathBuf;
+use anyhow::{Result, Context, anyhow};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::WalkBuilder;
+use std::path::{Path, PathBuf};
+use crate::binary::Detection;
+use crate::color::{self, ColorChoice};
+use crate::config;
+use crate::copier::OnExisting;
+use crate::hooks::HookErrorPolicy;
+use crate::inflect;
+use crate::lint;
+use crate::markdown::MarkdownScope;
+use crate::structured::{Format, Selection, Selector, StructuredPart};
+use crate::logger;
+use crate::placeholder::{self, GLOBAL_COUNTER, GLOBAL_COUNTER_WIDTH};
+use crate::pool;
+use crate::prefilter::Prefilter;
+use crate::script::ScriptFormat;
+use crate::summary::{self, Event};
+use crate::ripgrep::RgScope;
+use crate::remote::Remote;
+use crate::rename_match::RenameMatch;
+use crate::preview::ReportFormat;
+use crate::template;
+use std::sync::OnceLock;
 use std::sync::atomic::{AtomicBool, Ordering};
 
 // Global static configuration
-pub static GLOBAL_CASE_ENABLED: AtomicBool = AtomicBool::new(true);
+pub use crate::case::GLOBAL_SPLIT_DIGITS;
+pub static GLOBAL_SAME_SHAPE: AtomicBool = AtomicBool::new(false);
+pub static GLOBAL_ORDERED: AtomicBool = AtomicBool::new(false);
 pub static GLOBAL_RENAME_FILE_ENABLED: AtomicBool = AtomicBool::new(true);
 pub static GLOBAL_RENAME_DIR_ENABLED: AtomicBool = AtomicBool::new(true);
 
@@ -18,6 +43,21 @@ pub enum Mode {
     Files,        // Replace only file contents
     FilesAndNames, // Replace file contents and filenames
     Copy,         // Copy files/directories with replacements
+    Render,       // Render stdin as a template to stdout
+}
+
+/// What to do when a directory can't be walked, such as for a permission error (--on-walk-error)
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq)]
+pub enum WalkErrorPolicy {
+    /// Report the error and skip the path
+    #[default]
+    Warn,
+
+    /// Stop with an error
+    Fail,
+
+    /// Skip the path silently
+    Ignore,
 }
 
 /// Copy operation specification
@@ -28,6 +68,26 @@ pub struct CopySpec {
 
     /// Target path
     pub target: PathBuf,
+
+    /// Rules of this copy only, on top of the others (--with)
+    pub rules: Vec<ReplacementRule>,
+}
+
+impl CopySpec {
+    /// The rules of this copy: the run's rules, with its own overriding those with the same FROM
+    ///
+    /// # Arguments
+    /// * `rules` - Rules of the run
+    ///
+    /// # Returns
+    /// * `Vec<ReplacementRule>` - The run's rules followed by the copy's own
+    pub fn rules(&self, rules: &[ReplacementRule]) -> Vec<ReplacementRule> {
+        rules.iter()
+            .filter(|rule| !self.rules.iter().any(|own| own.from == rule.from))
+            .chain(&self.rules)
+            .cloned()
+            .collect()
+    }
 }
 
 /// Replacement rule
@@ -38,35 +98,561 @@ pub struct ReplacementRule {
 
     /// TO string to replace with
     pub to: String,
+
+    /// Path globs the rule is limited to (None applies it everywhere)
+    pub scope: Option<GlobSet>,
+}
+
+impl ReplacementRule {
+    /// Create a rule that applies to every path
+    pub fn new(from: String, to: String) -> Self {
+        ReplacementRule { from, to, scope: None }
+    }
+
+    /// Check whether the rule applies to a path
+    ///
+    /// Scoped rules never apply to content without a path (stdin).
+    ///
+    /// # Arguments
+    /// * `path` - Path being processed, relative to the current directory or the copy source
+    ///
+    /// # Returns
+    /// * `bool` - True if the rule applies
+    pub fn applies_to(&self, path: Option<&Path>) -> bool {
+        match (&self.scope, path) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(scope), Some(path)) => scope.is_match(path.strip_prefix(".").unwrap_or(path)),
+        }
+    }
+}
+
+/// Compile the path globs of a scoped rule
+///
+/// # Arguments
+/// * `globs` - Glob patterns such as `src/**/*.rs`
+///
+/// # Returns
+/// * `Result<GlobSet>` - The compiled globs
+pub fn build_scope(globs: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        builder.add(Glob::new(glob).with_context(|| format!("Invalid rule scope glob: {}", glob))?);
+    }
+    Ok(builder.build()?)
+}
+
+/// Subcommands
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Apply a patch written by --emit-patch
+    Apply {
+        /// Patch file to apply
+        patch: PathBuf,
+    },
+
+    /// Execute a plan written by --plan
+    ApplyPlan {
+        /// Plan file to execute
+        plan: PathBuf,
+    },
+
+    /// Time the replacement engines on a tree with the given rules (or the config file's)
+    Bench {
+        /// Tree to run the rules against
+        path: PathBuf,
+
+        /// Replacement rules to benchmark
+        #[arg(short = 'r', long = "replace", value_names = ["FROM", "TO"], num_args = 2, action = ArgAction::Append)]
+        rules: Vec<String>,
+
+        /// Number of runs per engine (the fastest is reported)
+        #[arg(long = "iterations", value_name = "N", default_value_t = 3)]
+        iterations: usize,
+    },
+
+    /// Print the case variants each rule (given or from the config file) searches for, and what each becomes
+    Explain {
+        /// Replacement rules to explain
+        #[arg(short = 'r', long = "replace", value_names = ["FROM", "TO"], num_args = 2, action = ArgAction::Append)]
+        rules: Vec<String>,
+
+        /// Treat digits as words of their own in case variants
+        #[arg(long = "split-digits")]
+        split_digits: bool,
+
+        /// Also show the plural rules --plurals would add
+        #[arg(long = "plurals")]
+        plurals: bool,
+    },
+
+    /// Check the rules (given or from the config file) for problems and print every spelling they replace
+    CheckRules {
+        /// Replacement rules to check
+        #[arg(short = 'r', long = "replace", value_names = ["FROM", "TO"], num_args = 2, action = ArgAction::Append)]
+        rules: Vec<String>,
+    },
+
+    /// Answer JSON-RPC requests (replace-content, plan-rename, apply) on stdio or a unix socket
+    Serve {
+        /// Unix socket to listen on instead of stdin/stdout
+        #[arg(long = "socket", value_name = "PATH")]
+        socket: Option<PathBuf>,
+
+        /// Default replacement rules, for requests that don't send their own
+        #[arg(short = 'r', long = "replace", value_names = ["FROM", "TO"], num_args = 2, action = ArgAction::Append)]
+        rules: Vec<String>,
+    },
+
+    /// Run `mane` with the arguments after `--` in each of many repositories, and summarize the runs
+    Multi {
+        /// File listing repository directories, one per line
+        #[arg(long = "repos", value_name = "FILE")]
+        repos: Option<PathBuf>,
+
+        /// Repository directories, or globs of them
+        #[arg(value_name = "DIR")]
+        dirs: Vec<String>,
+
+        /// Arguments of the run in each repository
+        #[arg(last = true, required = true, value_name = "ARGS")]
+        run_args: Vec<String>,
+    },
+
+    /// Copy a template, replacing its placeholder word with NAME in every case variant
+    New {
+        /// Template file or directory
+        template: PathBuf,
+
+        /// Name of the new copy, written in any case (`billing-api`, `BillingApi`, ...)
+        name: String,
+
+        /// Where to create the copy [default: NAME]
+        target: Option<PathBuf>,
+
+        /// Word the template uses for the name [default: the template's file or directory name]
+        #[arg(long = "word", value_name = "WORD")]
+        word: Option<String>,
+
+        /// More replacement rules, applied with the one for the name
+        #[arg(short = 'r', long = "replace", value_names = ["FROM", "TO"], num_args = 2, action = ArgAction::Append)]
+        rules: Vec<String>,
+
+        /// Set a template variable (repeatable)
+        #[arg(long = "var", value_name = "NAME=VALUE", action = ArgAction::Append)]
+        vars: Vec<String>,
+    },
+
+    /// Write a starter .mane.toml with example rules and options, commented
+    Init {
+        /// Overwrite an existing .mane.toml
+        #[arg(long = "force")]
+        force: bool,
+    },
+
+    /// Inspect a copy-mode template
+    Template {
+        #[command(subcommand)]
+        command: TemplateCommand,
+    },
+}
+
+/// Subcommands of `mane template`
+#[derive(Subcommand, Debug, Clone)]
+pub enum TemplateCommand {
+    /// List the variables a template declares or uses, with their defaults and descriptions
+    Vars {
+        /// Template directory
+        path: PathBuf,
+
+        /// Print the variables as JSON
+        #[arg(long = "json")]
+        json: bool,
+    },
 }
 
 /// Command line arguments parser
 #[derive(Parser, Debug)]
 #[command(author, version, about = "A command-line replacement tool without requiring template files")]
 #[command(name = "mane")]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// Replacement rules to apply
     #[arg(short = 'r', long = "replace", value_names = ["FROM", "TO"], num_args = 2, action = ArgAction::Append)]
     pub replacement_rules: Vec<String>,
 
-    /// Copy files or directories to a single target
-    #[arg(short = 'c', long = "copy", value_names = ["SOURCE", "TARGET"], num_args = 2.., action = ArgAction::Append)]
+    /// Limit the preceding -r rule to paths matching GLOB (repeatable)
+    #[arg(long = "rule-scope", value_name = "GLOB", action = ArgAction::Append)]
+    pub rule_scopes: Vec<String>,
+
+    /// How much of a file or directory name a rule has to match to rename it
+    #[arg(long = "rename-match", value_name = "MODE", value_enum, default_value_t = RenameMatch::Word)]
+    pub rename_match: RenameMatch,
+
+    /// Leave file extensions out of name replacement (parser.rs keeps its .rs)
+    #[arg(long = "exclude-extension", overrides_with = "include_extension")]
+    pub exclude_extension: bool,
+
+    /// Apply the rules to file extensions too when renaming (the default)
+    #[arg(long = "include-extension", overrides_with = "exclude_extension")]
+    pub include_extension: bool,
+
+    /// Keep the names of paths matching GLOB, still replacing in their content (repeatable)
+    #[arg(long = "no-rename-path", value_name = "GLOB", action = ArgAction::Append)]
+    pub no_rename_paths: Vec<String>,
+
+    /// Copy files or directories to the last path given, or to the -t target (repeatable, each with its own target)
+    #[arg(short = 'c', long = "copy", value_names = ["SOURCE", "TARGET"], num_args = 1.., action = ArgAction::Append)]
     pub copy_specs_raw: Vec<String>,
 
+    /// Target of every -c, which then takes only sources
+    #[arg(short = 't', long = "target", value_name = "TARGET", requires = "copy_specs_raw")]
+    pub copy_target: Option<String>,
+
+    /// The paths of each -c given, in order
+    #[arg(skip)]
+    pub copy_groups: Vec<Vec<String>>,
+
+    /// Replace FROM with TO in the copy of the -c given before it only (repeatable)
+    #[arg(long = "with", value_names = ["FROM", "TO"], num_args = 2, action = ArgAction::Append, requires = "copy_specs_raw")]
+    pub with_rules_raw: Vec<String>,
+
+    /// The -c each --with belongs to
+    #[arg(skip)]
+    pub with_rule_owners: Vec<usize>,
+
+    /// Write the copied tree into a tar, tar.gz or zip archive instead (`-` for a tar stream on stdout)
+    #[arg(long = "to-archive", value_name = "FILE")]
+    pub to_archive: Option<PathBuf>,
+
+    /// Copy target on another machine (`[user@]host:path`), written to over SSH
+    #[arg(skip)]
+    pub remote_target: Option<Remote>,
+
+    /// What to do when a copied directory's target directory already exists
+    #[arg(long = "on-existing", value_name = "POLICY", value_enum, default_value_t = OnExisting::Merge)]
+    pub on_existing: OnExisting,
+
+    /// Move files a copy would overwrite, or a rename would replace, to the trash first
+    #[arg(long = "trash")]
+    pub trash: bool,
+
+    /// Give copied files and directories the owner and group of their source, like `cp -a`
+    #[arg(long = "preserve-owner")]
+    pub preserve_owner: bool,
+
+    /// Print the tree a copy would create, marking files whose content changes, without copying
+    #[arg(long = "tree", requires = "copy_specs_raw", conflicts_with_all = ["to_archive", "incremental"])]
+    pub tree: bool,
+
+    /// Hard-link copied files whose content the rules don't change instead of duplicating them
+    #[arg(long = "link-unchanged")]
+    pub link_unchanged: bool,
+
+    /// Only copy files whose source or rules changed since the last --incremental copy to the same target
+    #[arg(long = "incremental")]
+    pub incremental: bool,
+
+    /// After copying, re-read the targets and check they have the expected content
+    #[arg(long = "verify")]
+    pub verify: bool,
+
+    /// After copying, warn about placeholders and FROM spellings left in the copied files
+    #[arg(long = "check-unreplaced")]
+    pub check_unreplaced: bool,
+
+    /// Template variable for copy mode (NAME=VALUE, repeatable)
+    #[arg(long = "var", value_name = "NAME=VALUE", action = ArgAction::Append)]
+    pub vars_raw: Vec<String>,
+
     /// Files to process
     pub files: Vec<PathBuf>,
 
+    /// Also process the files listed in FILE, one per line (`-` reads the list from stdin)
+    #[arg(long = "files-from", value_name = "FILE")]
+    pub files_from: Option<PathBuf>,
+
+    /// Paths in the --files-from list are separated by NUL characters, as printed by `find -print0` or `git ls-files -z`
+    #[arg(short = '0', long = "null", requires = "files_from")]
+    pub null: bool,
+
+    /// Only replace on the files and lines reported by `rg --json` in FILE (`-` reads them from stdin)
+    #[arg(long = "scope-from-rg", value_name = "FILE")]
+    pub scope_from_rg: Option<PathBuf>,
+
+    /// Interpret \n, \t, \r, \0, \xNN and \\ escapes in the FROM and TO strings of -r
+    #[arg(long = "escapes")]
+    pub escapes: bool,
+
+    /// Review the planned edits and renames in a full-screen list before applying them (with -i)
+    #[arg(long = "tui", requires = "in_place")]
+    pub tui: bool,
+
+    /// Apply rules one after another in the given order instead of longest match first
+    #[arg(long = "ordered")]
+    pub ordered: bool,
+
+    /// Also replace the plural forms of every rule (box -> crate also turns boxes into crates)
+    #[arg(long = "plurals")]
+    pub plurals: bool,
+
+    /// Render TO in the exact shape (separators, capitalization) of each matched occurrence
+    #[arg(long = "same-shape")]
+    pub same_shape: bool,
+
+    /// Treat digits as words of their own in case variants (oauth2Client -> oauth_2_client)
+    #[arg(long = "split-digits")]
+    pub split_digits: bool,
+
+    /// Apply every rule inverted (TO -> FROM, in reverse order) to undo a previous run
+    #[arg(long = "reverse")]
+    pub reverse: bool,
+
+    /// Fail instead of warning when rules conflict or overlap
+    #[arg(long = "strict-rules")]
+    pub strict_rules: bool,
+
+    /// Fail on any warning (a skipped binary file, a rename whose target exists, a walk error), before changing anything if possible
+    #[arg(long = "strict")]
+    pub strict: bool,
+
+    /// Fail before writing anything if applying the rules again would change the result
+    #[arg(long = "idempotent")]
+    pub idempotent: bool,
+
+    /// Replace only in the keys or the string values of JSON/YAML/TOML files (names are kept)
+    #[arg(long = "structured", value_name = "PART", value_enum)]
+    pub structured: Option<StructuredPart>,
+
+    /// Replace only under this JSONPath-like selector in JSON files, e.g. `$.dependencies.*`
+    #[arg(long = "json-path", value_name = "SELECTOR")]
+    pub json_path: Option<String>,
+
+    /// Replace only under this path selector in YAML files, e.g. `spec.template.metadata.labels`
+    #[arg(long = "yaml-path", value_name = "SELECTOR")]
+    pub yaml_path: Option<String>,
+
+    /// Replace only in fenced/inline `code`, in `prose` or in `links` (URLs) of Markdown files
+    #[arg(long = "markdown-scope", value_name = "SCOPE", value_enum)]
+    pub markdown_scope: Option<MarkdownScope>,
+
+    /// Replace only in these columns (named by the header row) of CSV/TSV files
+    #[arg(long = "csv-columns", value_name = "NAMES", value_delimiter = ',')]
+    pub csv_columns: Vec<String>,
+
+    /// Decompress gzip files (.gz) before replacing and compress them again afterwards
+    #[arg(long = "decompress")]
+    pub decompress: bool,
+
+    /// Treat every file as text, decoding bytes that aren't UTF-8 as U+FFFD
+    #[arg(long = "text")]
+    pub text: bool,
+
+    /// Treat files with more than PERCENT% bytes that aren't UTF-8 as binary; below, they are decoded as U+FFFD
+    #[arg(long = "binary-threshold", value_name = "PERCENT", default_value_t = 0.0)]
+    pub binary_threshold: f64,
+
+    /// Open zip and tar(.gz) archives, replace in their members and repack them
+    #[arg(long = "archives")]
+    pub archives: bool,
+
+    /// Replace stdin line by line, writing each line as soon as it is read (default when stdin is a pipe)
+    #[arg(long = "line-buffered")]
+    pub line_buffered: bool,
+
+    /// Read a path or name per line of stdin and print each one the rules rename, followed by its new name
+    #[arg(long = "names", conflicts_with_all = ["in_place", "copy_specs_raw", "files", "files_from", "scope_from_rg"])]
+    pub names: bool,
+
+    /// Render stdin as a template, filling in its `{{var}}` placeholders, and write it to stdout
+    #[arg(long = "render", conflicts_with_all = ["in_place", "copy_specs_raw", "files", "files_from", "scope_from_rg", "names"])]
+    pub render: bool,
+
+    /// Replace only in identifiers of source files, not in strings or comments
+    #[arg(long = "syntax-aware")]
+    pub syntax_aware: bool,
+
+    /// Leave string literals of source files alone
+    #[arg(long = "skip-strings")]
+    pub skip_strings: bool,
+
+    /// Leave comments of source files alone
+    #[arg(long = "skip-comments")]
+    pub skip_comments: bool,
+
     /// Replace in file/directory names as well
     #[arg(short = 'i', long = "in-place")]
     pub in_place: bool,
 
-    /// Include files that match .gitignore patterns
+    /// Print only changed lines as `path:line:` before/after pairs instead of whole files
+    #[arg(long = "print-changes")]
+    pub print_changes: bool,
+
+    /// Write the result of replacing stdin to FILE, replacing it in one step, instead of stdout
+    #[arg(long = "output", value_name = "FILE", conflicts_with_all = ["in_place", "copy_specs_raw", "files", "files_from",
+        "scope_from_rg", "names", "render", "line_buffered", "format", "print_changes", "files_with_matches"])]
+    pub output: Option<PathBuf>,
+
+    /// Write the changed files under DIR, mirroring their paths, instead of to stdout or in place
+    #[arg(short = 'o', long = "output-dir", value_name = "DIR",
+        conflicts_with_all = ["emit_patch", "plan", "emit_script", "format", "print_changes", "commit", "trash", "fix_symlinks"])]
+    pub output_dir: Option<PathBuf>,
+
+    /// Print whole files even to a terminal, which otherwise gets --print-changes for several files
+    #[arg(long = "stdout", conflicts_with_all = ["in_place", "print_changes", "files_with_matches", "output_dir"])]
+    pub stdout: bool,
+
+    /// Report each replacement in FORMAT instead of printing whole files
+    #[arg(long = "format", value_name = "FORMAT", value_enum, conflicts_with_all = ["print_changes", "files_with_matches", "in_place"])]
+    pub format: Option<ReportFormat>,
+
+    /// Print a `==> PATH <==` header before the content of each file written to stdout
+    #[arg(short = 'H', long = "with-filename")]
+    pub with_filename: bool,
+
+    /// Print SEP between the contents of files written to stdout
+    #[arg(long = "separator", value_name = "SEP")]
+    pub separator: Option<String>,
+
+    /// List the files whose content the rules change instead of printing them
+    #[arg(short = 'l', long = "files-with-matches", conflicts_with = "print_changes")]
+    pub files_with_matches: bool,
+
+    /// End each path listed by --files-with-matches with NUL instead of a newline, for `xargs -0`
+    #[arg(long = "print0", requires = "files_with_matches")]
+    pub print0: bool,
+
+    /// Write all changes as a unified diff to FILE instead of applying them
+    #[arg(long = "emit-patch", value_name = "FILE")]
+    pub emit_patch: Option<PathBuf>,
+
+    /// Write the intended renames and content edits to a JSON plan FILE instead of applying them
+    #[arg(long = "plan", value_name = "FILE", conflicts_with = "emit_patch")]
+    pub plan: Option<PathBuf>,
+
+    /// Print a `sh` or `ps1` script of commands reproducing the changes instead of applying them
+    #[arg(long = "emit-script", value_name = "SHELL", value_enum, conflicts_with_all = ["emit_patch", "plan", "tui"])]
+    pub emit_script: Option<ScriptFormat>,
+
+    /// Write the old and new path of every renamed file and directory to FILE as JSON (`-` for stdout)
+    #[arg(long = "rename-map", value_name = "FILE", requires = "in_place")]
+    pub rename_map: Option<PathBuf>,
+
+    /// Rewrite relative paths in files (imports, includes, Markdown links) that point at renamed files
+    #[arg(long = "fix-references", requires = "in_place")]
+    pub fix_references: bool,
+
+    /// Point symlinks whose target is renamed at its new path
+    #[arg(long = "fix-symlinks", requires = "in_place", conflicts_with_all = ["emit_patch", "plan", "emit_script"])]
+    pub fix_symlinks: bool,
+
+    /// Stage the changed and renamed paths and commit them to git, with MESSAGE or one listing the rules
+    #[arg(long = "commit", value_name = "MESSAGE", num_args = 0..=1, requires = "in_place",
+        conflicts_with_all = ["emit_patch", "plan", "emit_script"])]
+    pub commit: Option<Option<String>>,
+
+    /// Config file to load instead of ./.mane.toml
+    #[arg(long = "config", value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Use the rules and options of [profiles.NAME] from the config file
+    #[arg(long = "profile", value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// Include files that match .gitignore patterns (and the global and info/exclude ignores)
     #[arg(long = "include-git-ignore")]
     pub include_git_ignore: bool,
 
-    /// Enable verbose output
-    #[arg(long = "verbose")]
-    pub verbose: bool,
+    /// Don't respect the global gitignore (core.excludesFile)
+    #[arg(long = "no-ignore-global")]
+    pub no_ignore_global: bool,
+
+    /// Don't respect the repository's .git/info/exclude
+    #[arg(long = "no-ignore-exclude")]
+    pub no_ignore_exclude: bool,
+
+    /// Also walk into version control metadata (.git, .hg, .svn, ...), which is skipped by default
+    #[arg(long = "include-vcs-dirs")]
+    pub include_vcs_dirs: bool,
+
+    /// Walk directories in filesystem order instead of sorting entries by name
+    #[arg(long = "unsorted")]
+    pub unsorted: bool,
+
+    /// When copying, honor .gitignore files in subdirectories of the source, not only at its root
+    #[arg(long = "ignore-nested", value_name = "BOOL", action = ArgAction::Set, default_value_t = true)]
+    pub ignore_nested: bool,
+
+    /// Maximum number of files to process at once (default: number of CPUs)
+    #[arg(short = 'j', long = "jobs", value_name = "N", default_value_t = pool::default_jobs())]
+    pub jobs: usize,
+
+    /// Process fewer files at once when their buffers would take more than SIZE (e.g. 512M)
+    #[arg(long = "max-memory", value_name = "SIZE")]
+    pub max_memory: Option<String>,
+
+    /// Remember the files the rules don't match in .mane-cache, and skip them while they stay unchanged
+    #[arg(long = "match-cache", conflicts_with = "scope_from_rg")]
+    pub match_cache: bool,
+
+    /// Increase output verbosity (-v, -vv)
+    #[arg(short = 'v', long = "verbose", action = ArgAction::Count)]
+    pub verbose: u8,
+
+    /// When to use colors in output
+    #[arg(long = "color", value_name = "WHEN", value_enum, default_value_t = ColorChoice::Auto)]
+    pub color: ColorChoice,
+
+    /// Suppress warnings and informational messages
+    #[arg(short = 'q', long = "quiet", conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Print only the counts of changed, renamed, copied and skipped files at the end, instead of a line per file
+    #[arg(long = "summary", conflicts_with_all = ["quiet", "verbose"])]
+    pub summary: bool,
+
+    /// Fail unless exactly COUNT replacements are made
+    #[arg(long = "expect", value_name = "COUNT", conflicts_with_all = ["expect_min", "expect_max"])]
+    pub expect: Option<usize>,
+
+    /// Fail if fewer than COUNT replacements are made
+    #[arg(long = "expect-min", value_name = "COUNT")]
+    pub expect_min: Option<usize>,
+
+    /// Fail if more than COUNT replacements are made
+    #[arg(long = "expect-max", value_name = "COUNT")]
+    pub expect_max: Option<usize>,
+
+    /// First value of the {{n}} counter placeholder
+    #[arg(long = "counter-start", value_name = "N", default_value_t = 1)]
+    pub counter_start: usize,
+
+    /// Zero-pad {{n}} counter values to WIDTH digits
+    #[arg(long = "counter-width", value_name = "WIDTH", default_value_t = 0)]
+    pub counter_width: usize,
+
+    /// Shell command to run before the operation (repeatable)
+    #[arg(long = "pre-hook", value_name = "CMD", action = ArgAction::Append)]
+    pub pre_hooks: Vec<String>,
+
+    /// Shell command to run after the operation (repeatable)
+    #[arg(long = "post-hook", value_name = "CMD", action = ArgAction::Append)]
+    pub post_hooks: Vec<String>,
+
+    /// What to do when a directory can't be walked: warn and skip it, fail, or ignore it
+    #[arg(long = "on-walk-error", value_name = "POLICY", value_enum, default_value_t = WalkErrorPolicy::Warn)]
+    pub on_walk_error: WalkErrorPolicy,
+
+    /// What to do when a hook fails [default: fail]
+    #[arg(long = "hook-errors", value_name = "POLICY", value_enum)]
+    pub hook_errors: Option<HookErrorPolicy>,
+
+    /// Run the hooks of a config file found in the current directory, not only those of --config
+    #[arg(long = "allow-config-hooks")]
+    pub allow_config_hooks: bool,
 
     #[arg(skip)]
     pub mode: Mode,
@@ -75,6 +661,30 @@ pub struct Args {
     #[arg(skip)]
     pub rules: Vec<ReplacementRule>,
 
+    /// Search for the FROM strings of all rules, built on first use
+    #[arg(skip)]
+    pub prefilter: OnceLock<Prefilter>,
+
+    /// Files and lines read with --scope-from-rg
+    #[arg(skip)]
+    pub rg_scope: Option<RgScope>,
+
+    /// Compiled --no-rename-path globs
+    #[arg(skip)]
+    pub no_rename: Option<GlobSet>,
+
+    /// Bytes of --max-memory
+    #[arg(skip)]
+    pub memory_limit: Option<u64>,
+
+    /// Index of the -r rule each --rule-scope follows
+    #[arg(skip)]
+    pub rule_scope_owners: Vec<usize>,
+
+    /// Parsed template variables
+    #[arg(skip)]
+    pub vars: Vec<(String, String)>,
+
     /// Compiled list of copy specifications
     #[arg(skip)]
     pub copy_specs: Vec<CopySpec>,
@@ -90,6 +700,123 @@ pub struct Args {
     /// Directory name replacement options
     #[arg(skip)]
     pub rename_dir: bool,
+
+    /// Parsed --json-path selector
+    #[arg(skip)]
+    pub json_selector: Option<Selector>,
+
+    /// Parsed --yaml-path selector
+    #[arg(skip)]
+    pub yaml_selector: Option<Selector>,
+}
+
+impl Args {
+    /// A walk over a tree honoring the git ignore options
+    ///
+    /// Like ripgrep, `.gitignore` files, the global gitignore and
+    /// `.git/info/exclude` are respected inside git repositories, each unless
+    /// turned off; --include-git-ignore turns off all three. Version control
+    /// metadata is never walked into unless --include-vcs-dirs is given, since
+    /// rewriting it corrupts the repository.
+    ///
+    /// Entries are sorted by name so output, renames and `{{n}}` counters are
+    /// the same on every run and machine, unless --unsorted is given.
+    pub fn walk_builder(&self, root: &Path) -> WalkBuilder {
+        let mut builder = WalkBuilder::new(root);
+        builder
+            .git_ignore(!self.include_git_ignore)
+            .git_global(!self.include_git_ignore && !self.no_ignore_global)
+            .git_exclude(!self.include_git_ignore && !self.no_ignore_exclude);
+        if !self.unsorted {
+            builder.sort_by_file_name(|a, b| a.cmp(b));
+        }
+        if !self.include_vcs_dirs {
+            // The root itself is kept, so a tree given explicitly is still processed
+            builder.filter_entry(|entry| entry.depth() == 0 || !is_vcs_dir(entry.file_name()));
+        }
+        builder
+    }
+
+    /// Handle an error of a walk from `walk_builder` as --on-walk-error says
+    ///
+    /// Every error is counted for the summary, whatever the policy.
+    ///
+    /// # Arguments
+    /// * `err` - The walk error
+    ///
+    /// # Returns
+    /// * `Result<()>` - Error if the walk has to stop
+    pub fn walk_error(&self, err: ignore::Error) -> Result<()> {
+        summary::record(Event::WalkError);
+        match self.on_walk_error {
+            WalkErrorPolicy::Warn => warn!("Error walking directory: {}", err),
+            WalkErrorPolicy::Fail => return Err(anyhow!("Error walking directory: {}", err)),
+            WalkErrorPolicy::Ignore => {},
+        }
+        Ok(())
+    }
+
+    /// Check whether a path keeps its name (--no-rename-path)
+    ///
+    /// # Arguments
+    /// * `path` - Path being renamed, relative to the current directory or the copy source
+    ///
+    /// # Returns
+    /// * `bool` - True if the path matches a --no-rename-path glob
+    pub fn keeps_name(&self, path: &Path) -> bool {
+        self.no_rename.as_ref().is_some_and(|globs| globs.is_match(path.strip_prefix(".").unwrap_or(path)))
+    }
+
+    /// The memory budget of the jobs processing files (--max-memory)
+    pub fn budget(&self) -> Option<pool::Budget> {
+        self.memory_limit.map(pool::Budget::new)
+    }
+
+    /// How file content is told apart as text or binary (--text, --binary-threshold)
+    pub fn detection(&self) -> Detection {
+        Detection { force_text: self.text, threshold: self.binary_threshold }
+    }
+
+    /// The search for the FROM strings of the final rule set
+    pub fn prefilter(&self) -> &Prefilter {
+        self.prefilter.get_or_init(|| Prefilter::new(&self.rules, self.case_enabled, self.same_shape))
+    }
+
+    /// Check whether the changes are written out (--emit-patch, --plan or
+    /// --emit-script) instead of applied
+    pub fn writes_plan(&self) -> bool {
+        self.emit_patch.is_some() || self.plan.is_some() || self.emit_script.is_some()
+    }
+
+    /// Check whether replacement is limited to parts of structured files
+    /// (--structured, --json-path or --yaml-path)
+    pub fn is_structured(&self) -> bool {
+        self.structured.is_some() || self.json_path.is_some() || self.yaml_path.is_some()
+    }
+
+    /// Which scalars of a file to replace in when replacement is structured
+    ///
+    /// # Arguments
+    /// * `path` - Path of the file (its extension selects the format)
+    ///
+    /// # Returns
+    /// * `Option<(Format, Selection)>` - The format and selection, or None to leave the file unchanged
+    pub fn structured_selection(&self, path: &Path) -> Option<(Format, Selection<'_>)> {
+        let format = Format::detect(path)?;
+        let selector = match format {
+            Format::Json => self.json_selector.as_ref(),
+            Format::Yaml => self.yaml_selector.as_ref(),
+            Format::Toml => None,
+        };
+
+        // Once a selector is given, only files of the formats it addresses are touched
+        let selecting = self.json_selector.is_some() || self.yaml_selector.is_some();
+        if selecting && selector.is_none() && self.structured.is_none() {
+            return None;
+        }
+
+        Some((format, Selection { part: self.structured, selector }))
+    }
 }
 
 /// Parse command line arguments and validate them
@@ -97,7 +824,70 @@ pub struct Args {
 /// # Returns
 /// * `Result<Args>` - Parsed and validated arguments
 pub fn parse() -> Result<Args> {
-    let mut args = Args::parse();
+    parse_from(std::env::args_os())
+}
+
+/// Parse and validate the given command line
+///
+/// # Arguments
+/// * `argv` - The program name followed by its arguments
+///
+/// # Returns
+/// * `Result<Args>` - Parsed and validated arguments
+pub fn parse_from<I, T>(argv: I) -> Result<Args>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<std::ffi::OsString> + Clone,
+{
+    let matches = Args::command().get_matches_from(argv);
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+    // A --rule-scope belongs to the last -r given before it
+    let rule_indices: Vec<usize> = matches.indices_of("replacement_rules").map(Iterator::collect).unwrap_or_default();
+    for scope_index in matches.indices_of("rule_scopes").into_iter().flatten() {
+        let preceding_values = rule_indices.iter().filter(|&&i| i < scope_index).count();
+        if preceding_values < 2 {
+            return Err(anyhow!("--rule-scope must follow the -r/--replace rule it limits"));
+        }
+        args.rule_scope_owners.push(preceding_values / 2 - 1);
+    }
+
+    // Each -c keeps its own paths, the last of them its target unless -t is given
+    args.copy_groups = matches.get_occurrences::<String>("copy_specs_raw")
+        .map(|occurrences| occurrences.map(|values| values.cloned().collect()).collect())
+        .unwrap_or_default();
+
+    // A --with belongs to the last -c given before it
+    let copy_indices: Vec<usize> = matches.indices_of("copy_specs_raw").map(Iterator::collect).unwrap_or_default();
+    let group_starts: Vec<usize> = args.copy_groups.iter()
+        .scan(0, |taken, group| {
+            let start = copy_indices[*taken];
+            *taken += group.len();
+            Some(start)
+        })
+        .collect();
+    for with_index in matches.indices_of("with_rules_raw").into_iter().flatten().step_by(2) {
+        match group_starts.iter().rposition(|&start| start < with_index) {
+            Some(owner) => args.with_rule_owners.push(owner),
+            None => return Err(anyhow!("--with must follow the -c/--copy it applies to")),
+        }
+    }
+
+    // `mane new` is a copy of the template, with a rule from its placeholder word to NAME
+    if let Some(Command::New { template, name, target, word, rules, vars }) = args.command.take_if(|command| matches!(command, Command::New { .. })) {
+        let word = match word {
+            Some(word) => word,
+            None => template.file_stem()
+                .ok_or_else(|| anyhow!("Can't tell the placeholder word of {:?}; give it with --word", template))?
+                .to_string_lossy().to_string(),
+        };
+        let target = target.unwrap_or_else(|| PathBuf::from(&name));
+        args.copy_groups = vec![vec![template.to_string_lossy().to_string(), target.to_string_lossy().to_string()]];
+        // NAME takes the case the word is written in, and case variants do the rest
+        let to = crate::case::convert_like(&name, &word, args.split_digits);
+        args.replacement_rules = [word, to].into_iter().chain(rules).collect();
+        args.vars_raw = vars;
+    }
 
     // Set defaults for options
     args.case_enabled = true;
@@ -106,41 +896,189 @@ pub fn parse() -> Result<Args> {
     args.copy_specs = Vec::new();
 
     // Initialize global static configuration
-    GLOBAL_CASE_ENABLED.store(true, Ordering::Relaxed);
     GLOBAL_RENAME_FILE_ENABLED.store(true, Ordering::Relaxed);
     GLOBAL_RENAME_DIR_ENABLED.store(true, Ordering::Relaxed);
+    logger::set_level(logger::level_from_flags(args.quiet, args.verbose));
+    logger::GLOBAL_STRICT.store(args.strict, Ordering::Relaxed);
+    logger::GLOBAL_SUMMARY.store(args.summary, Ordering::Relaxed);
+    color::set_choice(args.color);
+    GLOBAL_COUNTER.store(args.counter_start, Ordering::Relaxed);
+    GLOBAL_COUNTER_WIDTH.store(args.counter_width, Ordering::Relaxed);
+
+    // bench, explain, check-rules and serve take their own rules, on top of the config file's
+    if let Some(Command::Bench { rules, .. } | Command::Explain { rules, .. } | Command::CheckRules { rules } | Command::Serve { rules, .. }) = &args.command {
+        let pairs = rules.clone();
+        // check-rules reports an empty FROM along with the other problems
+        let checking = matches!(args.command, Some(Command::CheckRules { .. }));
+        config::load(&mut args)?;
+        for pair in pairs.chunks(2) {
+            if pair[0].is_empty() && !checking {
+                return Err(anyhow!("Empty FROM string is not allowed in replacement rules"));
+            }
+            args.rules.retain(|rule| rule.from != pair[0]);
+            args.rules.push(ReplacementRule::new(pair[0].clone(), pair[1].clone()));
+        }
+        if let Some(Command::Explain { split_digits, plurals, .. }) = args.command {
+            args.split_digits |= split_digits;
+            if plurals {
+                args.rules = inflect::with_plurals(std::mem::take(&mut args.rules));
+            }
+        }
+        GLOBAL_SPLIT_DIGITS.store(args.split_digits, Ordering::Relaxed);
+        GLOBAL_SAME_SHAPE.store(args.same_shape, Ordering::Relaxed);
+        GLOBAL_ORDERED.store(args.ordered, Ordering::Relaxed);
+        return Ok(args);
+    }
+
+    // Other subcommands don't take replacement rules
+    if args.command.is_some() {
+        return Ok(args);
+    }
+
+    // Load rules and options from the config file
+    config::load(&mut args)?;
+    GLOBAL_SPLIT_DIGITS.store(args.split_digits, Ordering::Relaxed);
+    GLOBAL_SAME_SHAPE.store(args.same_shape, Ordering::Relaxed);
+    GLOBAL_ORDERED.store(args.ordered, Ordering::Relaxed);
+    GLOBAL_RENAME_FILE_ENABLED.store(args.rename_file, Ordering::Relaxed);
+    GLOBAL_RENAME_DIR_ENABLED.store(args.rename_dir, Ordering::Relaxed);
+
+    // Structured replacement edits documents, not the names of the files holding them
+    if args.is_structured() {
+        args.rename_file = false;
+        args.rename_dir = false;
+        GLOBAL_RENAME_FILE_ENABLED.store(false, Ordering::Relaxed);
+        GLOBAL_RENAME_DIR_ENABLED.store(false, Ordering::Relaxed);
+    }
+
+    if args.jobs == 0 {
+        return Err(anyhow!("--jobs must be at least 1"));
+    }
+    if let Some(size) = &args.max_memory {
+        args.memory_limit = Some(pool::parse_size(size).context("Invalid --max-memory")?);
+    }
+    if !(0.0..=100.0).contains(&args.binary_threshold) {
+        return Err(anyhow!("--binary-threshold must be between 0 and 100"));
+    }
+
+    // Paths whose names are load-bearing keep them, config globs included
+    if !args.no_rename_paths.is_empty() {
+        let mut builder = GlobSetBuilder::new();
+        for glob in &args.no_rename_paths {
+            builder.add(Glob::new(glob).with_context(|| format!("Invalid --no-rename-path glob: {}", glob))?);
+        }
+        args.no_rename = Some(builder.build()?);
+    }
+
+    // Files listed with --files-from are processed like files given as arguments
+    if let Some(list) = &args.files_from {
+        let listed = read_file_list(list, args.null)?;
+        if listed.is_empty() && args.files.is_empty() {
+            // An empty list must not fall back to the whole current directory
+            return Err(anyhow!("No files listed in {}", list.display()));
+        }
+        args.files.extend(listed);
+    }
+
+    // Files ripgrep reported matches in are processed, but only on the matching lines
+    if let Some(source) = &args.scope_from_rg {
+        if source == Path::new("-") && args.files_from.as_deref() == Some(Path::new("-")) {
+            return Err(anyhow!("--scope-from-rg and --files-from can't both read stdin"));
+        }
+        let scope = RgScope::read(source)?;
+        if scope.files().is_empty() && args.files.is_empty() {
+            return Err(anyhow!("No matches listed in {}", source.display()));
+        }
+        args.files.extend(scope.files());
+        args.rg_scope = Some(scope);
+    }
 
     // Process copy specs if any
-    if !args.copy_specs_raw.is_empty() {
-        // Need at least 2 arguments for --copy (at least one source and one target)
-        if args.copy_specs_raw.len() < 2 {
-            return Err(anyhow!("The -c/--copy option requires at least one SOURCE and one TARGET argument"));
+    if !args.copy_groups.is_empty() {
+        // Pair every source with its target: the last path of its -c, or the -t target
+        let mut pairs: Vec<(String, String, usize)> = Vec::new();
+        for (index, group) in args.copy_groups.iter().enumerate() {
+            let (target, sources) = match &args.copy_target {
+                Some(target) => (target, group.as_slice()),
+                None => match group.split_last() {
+                    Some((target, sources)) if !sources.is_empty() => (target, sources),
+                    _ => return Err(anyhow!("The -c/--copy option requires at least one SOURCE and one TARGET argument, or -t TARGET")),
+                },
+            };
+            pairs.extend(sources.iter().map(|source| (source.clone(), target.clone(), index)));
+        }
+        let mut targets: Vec<&String> = Vec::new();
+        for (_, target, _) in &pairs {
+            if !targets.contains(&target) {
+                targets.push(target);
+            }
+        }
+
+        // `-` and remote targets are copied through one staged archive, so they can't share a run
+        let streamed = |target: &&String| *target == "-" || Remote::parse(target).is_some();
+        if targets.len() > 1 && targets.iter().any(streamed) {
+            return Err(anyhow!("A `-` or remote copy target can't be combined with other copy targets"));
+        }
+        if targets.len() > 1 && args.incremental {
+            return Err(anyhow!("--incremental needs a single copy target"));
         }
 
-        // The last argument is always the target
-        let target_path = args.copy_specs_raw.last().unwrap();
-        let target = PathBuf::from(target_path);
+        let mut resolved: Vec<(String, PathBuf)> = Vec::new();
+        for target_path in targets {
+            let mut target = PathBuf::from(target_path);
 
-        // All preceding arguments are sources
-        for i in 0..args.copy_specs_raw.len() - 1 {
-            let source_path = &args.copy_specs_raw[i];
-            let source = PathBuf::from(source_path);
+            // A target of `-` streams the copied tree to stdout as a tar archive
+            if target_path == "-" {
+                args.to_archive.get_or_insert_with(|| PathBuf::from("-"));
+                target = PathBuf::from(".");
+            } else if let Some(remote) = Remote::parse(target_path) {
+                // A copy to another machine is staged and sent as a tar stream, like `-`
+                if args.to_archive.is_some() {
+                    return Err(anyhow!("--to-archive can't be used with a remote copy target"));
+                }
+                target = remote.staged_target();
+                args.remote_target = Some(remote);
+            }
+            resolved.push((target_path.clone(), target));
+        }
 
+        for (source, target_path, index) in &pairs {
+            let target = resolved.iter().find(|(path, _)| path == target_path).map(|(_, target)| target.clone()).unwrap();
+            let mut rules = Vec::new();
+            for (pair, _) in args.with_rules_raw.chunks(2).zip(&args.with_rule_owners).filter(|(_, owner)| *owner == index) {
+                if pair[0].is_empty() {
+                    return Err(anyhow!("Empty FROM string is not allowed in replacement rules"));
+                }
+                rules.retain(|rule: &ReplacementRule| rule.from != pair[0]);
+                rules.push(ReplacementRule::new(pair[0].clone(), placeholder::expand(&pair[1])?));
+            }
             args.copy_specs.push(CopySpec {
-                source,
-                target: target.clone(),
+                source: PathBuf::from(source),
+                target,
+                rules,
             });
         }
 
+        if args.incremental && args.to_archive.is_some() {
+            return Err(anyhow!("--incremental can't be used with --to-archive"));
+        }
+        if args.incremental && args.remote_target.is_some() {
+            return Err(anyhow!("--incremental can't be used with a remote copy target"));
+        }
+
         // Set mode to Copy if we have copy specs
         args.mode = Mode::Copy;
+    } else if args.to_archive.is_some() {
+        return Err(anyhow!("--to-archive can only be used with -c/--copy"));
     } else {
         // Determine the execution mode if no copy specs
-        if args.in_place {
+        if args.render {
+            args.mode = Mode::Render;
+        } else if args.in_place {
             args.mode = Mode::FilesAndNames;
         } else if !args.files.is_empty() {
             args.mode = Mode::Files;
-        } else if !atty::is(Stream::Stdin) {
+        } else if args.names || !atty::is(Stream::Stdin) {
             args.mode = Mode::StdinStdout;
         }
     }
@@ -155,22 +1093,200 @@ pub fn parse() -> Result<Args> {
 impl Default for Args {
     fn default() -> Self {
         Self {
+            command: None,
             replacement_rules: Vec::new(),
+            rule_scopes: Vec::new(),
+            rename_match: RenameMatch::Word,
+            exclude_extension: false,
+            include_extension: false,
+            no_rename_paths: Vec::new(),
             copy_specs_raw: Vec::new(),
+            copy_target: None,
+            copy_groups: Vec::new(),
+            with_rules_raw: Vec::new(),
+            with_rule_owners: Vec::new(),
+            vars_raw: Vec::new(),
             files: Vec::new(),
+            escapes: false,
+            split_digits: false,
+            same_shape: false,
+            plurals: false,
+            ordered: false,
+            tui: false,
+            reverse: false,
+            strict_rules: false,
+            strict: false,
+            idempotent: false,
+            structured: None,
+            json_path: None,
+            yaml_path: None,
+            markdown_scope: None,
+            csv_columns: Vec::new(),
+            decompress: false,
+            text: false,
+            binary_threshold: 0.0,
+            archives: false,
+            to_archive: None,
+            remote_target: None,
+            on_existing: OnExisting::Merge,
+            trash: false,
+            preserve_owner: false,
+            tree: false,
+            link_unchanged: false,
+            incremental: false,
+            verify: false,
+            check_unreplaced: false,
+            line_buffered: false,
+            names: false,
+            render: false,
+            syntax_aware: false,
+            skip_strings: false,
+            skip_comments: false,
             in_place: false,
             include_git_ignore: false,
-            verbose: false,
+            no_ignore_global: false,
+            no_ignore_exclude: false,
+            include_vcs_dirs: false,
+            unsorted: false,
+            ignore_nested: true,
+            print_changes: false,
+            stdout: false,
+            output_dir: None,
+            output: None,
+            format: None,
+            with_filename: false,
+            separator: None,
+            files_with_matches: false,
+            print0: false,
+            emit_patch: None,
+            emit_script: None,
+            commit: None,
+            rename_map: None,
+            fix_references: false,
+            fix_symlinks: false,
+            plan: None,
+            config: None,
+            profile: None,
+            jobs: pool::default_jobs(),
+            max_memory: None,
+            match_cache: false,
+            files_from: None,
+            scope_from_rg: None,
+            rg_scope: None,
+            null: false,
+            verbose: 0,
+            quiet: false,
+            summary: false,
+            color: ColorChoice::Auto,
+            expect: None,
+            expect_min: None,
+            expect_max: None,
+            counter_start: 1,
+            counter_width: 0,
+            pre_hooks: Vec::new(),
+            post_hooks: Vec::new(),
+            hook_errors: None,
+            allow_config_hooks: false,
+            on_walk_error: WalkErrorPolicy::Warn,
             mode: Mode::default(),
             rules: Vec::new(),
+            prefilter: OnceLock::new(),
+            no_rename: None,
+            memory_limit: None,
+            rule_scope_owners: Vec::new(),
+            vars: Vec::new(),
             copy_specs: Vec::new(),
             case_enabled: true,
             rename_file: true,
             rename_dir: true,
+            json_selector: None,
+            yaml_selector: None,
         }
     }
 }
 
+/// Interpret the escape sequences of a rule string (--escapes)
+///
+/// # Arguments
+/// * `s` - String with `\n`, `\t`, `\r`, `\0`, `\xNN` or `\\` escapes
+///
+/// # Returns
+/// * `Result<String>` - The string with escapes replaced, or an error for an unknown escape
+fn unescape(s: &str) -> Result<String> {
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some('r') => result.push('\r'),
+            Some('0') => result.push('\0'),
+            Some('\\') => result.push('\\'),
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                let byte = u8::from_str_radix(&hex, 16).ok().filter(|_| hex.len() == 2)
+                    .ok_or_else(|| anyhow!("Invalid escape \\x{} in '{}': \\x needs two hex digits", hex, s))?;
+                if !byte.is_ascii() {
+                    return Err(anyhow!("Invalid escape \\x{} in '{}': only ASCII (\\x00-\\x7f) can be escaped", hex, s));
+                }
+                result.push(byte as char);
+            },
+            Some(other) => return Err(anyhow!("Unknown escape \\{} in '{}'", other, s)),
+            None => return Err(anyhow!("Trailing backslash in '{}'", s)),
+        }
+    }
+    Ok(result)
+}
+
+/// Read the paths of a --files-from list
+///
+/// # Arguments
+/// * `list` - File holding the list, or `-` for stdin
+/// * `null` - Whether paths are separated by NUL instead of newlines
+///
+/// # Returns
+/// * `Result<Vec<PathBuf>>` - The listed paths, without empty entries
+fn read_file_list(list: &Path, null: bool) -> Result<Vec<PathBuf>> {
+    use std::io::Read;
+
+    let mut data = Vec::new();
+    if list == Path::new("-") {
+        std::io::stdin().read_to_end(&mut data).context("Failed to read the file list from stdin")?;
+    } else {
+        data = std::fs::read(list).with_context(|| format!("Failed to read file list: {}", list.display()))?;
+    }
+
+    let separator = if null { b'\0' } else { b'\n' };
+    Ok(data.split(|&b| b == separator)
+        .map(|entry| if null { entry } else { entry.strip_suffix(b"\r").unwrap_or(entry) })
+        .filter(|entry| !entry.is_empty())
+        .map(path_from_bytes)
+        .collect())
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// Version control metadata directories (or, for git worktrees, files)
+const VCS_DIRS: [&str; 6] = [".git", ".hg", ".svn", ".bzr", "_darcs", ".jj"];
+
+/// Check whether a file name is version control metadata
+fn is_vcs_dir(name: &std::ffi::OsStr) -> bool {
+    VCS_DIRS.iter().any(|vcs| name == *vcs)
+}
+
 /// Validate command line arguments for consistency
 ///
 /// # Arguments
@@ -179,12 +1295,21 @@ impl Default for Args {
 /// # Returns
 /// * `Result<()>` - Ok if valid, Error otherwise
 fn validate_args(args: &mut Args) -> Result<()> {
+    let mut rule_problems = Vec::new();
+
     // If there are replacement rules specified on the command line
     if !args.replacement_rules.is_empty() {
-        if args.replacement_rules.len() % 2 != 0 {
+        if !args.replacement_rules.len().is_multiple_of(2) {
             return Err(anyhow!("Each -r/--replace option requires both FROM and TO arguments"));
         }
 
+        // Turn escape sequences into the characters they stand for
+        if args.escapes {
+            for value in &mut args.replacement_rules {
+                *value = unescape(value)?;
+            }
+        }
+
         // Check for empty FROM values (which are invalid according to the spec)
         for i in (0..args.replacement_rules.len()).step_by(2) {
             if args.replacement_rules[i].is_empty() {
@@ -192,23 +1317,86 @@ fn validate_args(args: &mut Args) -> Result<()> {
             }
         }
 
-        // Process all replacement rules from command line
-        for i in (0..args.replacement_rules.len()).step_by(2) {
-            // Check if the rule already exists in loaded rules (override config file rules)
-            let from = args.replacement_rules[i].clone();
-            let to = args.replacement_rules[i + 1].clone();
+        let mut cli_rules = Vec::new();
+        for (i, pair) in args.replacement_rules.chunks(2).enumerate() {
+            let mut rule = ReplacementRule::new(pair[0].clone(), pair[1].clone());
+
+            // Globs given with --rule-scope right after this rule
+            let globs: Vec<String> = args.rule_scope_owners.iter()
+                .zip(&args.rule_scopes)
+                .filter(|(owner, _)| **owner == i)
+                .map(|(_, glob)| glob.clone())
+                .collect();
+            if !globs.is_empty() {
+                rule.scope = Some(build_scope(&globs)?);
+            }
+            cli_rules.push(rule);
+        }
 
-            // Remove existing rules with the same FROM string
-            args.rules.retain(|rule| rule.from != from);
+        // The same FROM given twice on the command line silently drops the first TO
+        rule_problems.extend(lint::duplicate_froms(&cli_rules));
+
+        // Process all replacement rules from command line
+        for rule in cli_rules {
+            // Remove existing rules with the same FROM string (override config file rules)
+            args.rules.retain(|r| r.from != rule.from);
 
             // Add new rule
-            args.rules.push(ReplacementRule {
-                from,
-                to,
-            });
+            args.rules.push(rule);
         }
     }
 
+    // Parse template variables
+    // Values from --var override the config file's
+    for raw in &args.vars_raw {
+        let (name, value) = template::parse_var(raw)?;
+        args.vars.retain(|(other, _)| other != &name);
+        args.vars.push((name, value));
+    }
+
+    // Expand built-in placeholders ({{env.NAME}}, {{date:FORMAT}}, {{uuid}}) in TO strings
+    for rule in &mut args.rules {
+        rule.to = placeholder::expand(&rule.to)?;
+    }
+
+    // Invert the rules so re-running the same command with --reverse undoes it
+    if args.reverse {
+        for rule in &args.rules {
+            if rule.to.is_empty() {
+                return Err(anyhow!("Cannot reverse the rule '{}' -> '': its TO string is empty", rule.from));
+            }
+            if placeholder::has_counter(&rule.to) || placeholder::has_file_metadata(&rule.to) {
+                return Err(anyhow!("Cannot reverse the rule '{}' -> '{}': per-file placeholders can't be matched", rule.from, rule.to));
+            }
+        }
+        args.rules = args.rules.drain(..)
+            .rev()
+            .map(|rule| ReplacementRule { from: rule.to, to: rule.from, scope: rule.scope })
+            .collect();
+    }
+
+    // Plural companions go after --reverse, so they stay ahead of their singular rules
+    if args.plurals {
+        args.rules = inflect::with_plurals(std::mem::take(&mut args.rules));
+    }
+
+    // Report rules that interfere with each other
+    rule_problems.extend(lint::lint_rules(&args.rules, args.case_enabled, args.ordered));
+    if args.idempotent {
+        rule_problems.extend(lint::self_amplifying(&args.rules, args.case_enabled));
+    }
+    if args.strict_rules && !rule_problems.is_empty() {
+        return Err(anyhow!("Conflicting rules (--strict-rules):\n  {}", rule_problems.join("\n  ")));
+    }
+    for problem in &rule_problems {
+        warn!("{}", problem);
+    }
+
+    // File-metadata placeholders are filled in from the file being renamed
+    if args.mode != Mode::FilesAndNames && args.rules.iter().any(|rule| placeholder::has_file_metadata(&rule.to)) {
+        warn!("{{{{mtime}}}}, {{{{size}}}} and {{{{sha256}}}} are only expanded in renames (-i/--in-place)");
+    }
+
     // If we have replacement rules but no valid mode is set,
     // it means there's no input source (files or stdin)
     if !args.rules.is_empty() && args.mode == Mode::None {
@@ -216,9 +1404,9 @@ fn validate_args(args: &mut Args) -> Result<()> {
     }
 
     // Check if we have replacement rules
-    if args.mode != Mode::Copy && args.rules.is_empty() {
-        // Error if no replacement rules are specified on command line
-        // and we're not in copy mode (copy mode can work without replacement rules)
+    if !matches!(args.mode, Mode::Copy | Mode::Render) && args.rules.is_empty() {
+        // Error if no replacement rules are specified on command line and we're
+        // not copying or rendering (which can work without replacement rules)
         return Err(anyhow!("No replacement rules specified. Use -r/--replace FROM TO"));
     }
 
@@ -227,6 +1415,25 @@ fn validate_args(args: &mut Args) -> Result<()> {
         return Err(anyhow!("No copy specifications provided. Use -c SOURCE [SOURCE...] TARGET"));
     }
 
+    // Expectation bounds must describe a non-empty range
+    if let (Some(min), Some(max)) = (args.expect_min, args.expect_max) {
+        if min > max {
+            return Err(anyhow!("--expect-min ({}) must not be greater than --expect-max ({})", min, max));
+        }
+    }
+
+    // The format of a structured file comes from its extension
+    if args.is_structured() && args.mode == Mode::StdinStdout {
+        return Err(anyhow!("--structured needs files: the format is detected from the file extension"));
+    }
+    args.json_selector = args.json_path.as_deref().map(Selector::parse).transpose()?;
+    args.yaml_selector = args.yaml_path.as_deref().map(Selector::parse).transpose()?;
+
+    // Output files mirror the paths of input files
+    if args.output_dir.is_some() && !matches!(args.mode, Mode::Files | Mode::FilesAndNames) {
+        return Err(anyhow!("--output-dir needs files or directories to replace in"));
+    }
+
     // When not in copy mode, verify that we have input files (or using stdin)
     if args.mode == Mode::Files && args.files.is_empty() {
         return Err(anyhow!("No input files provided. Specify files to process or use stdin."));
@@ -234,3 +1441,121 @@ fn validate_args(args: &mut Args) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::TestDir;
+    use std::fs;
+
+    #[test]
+    fn test_parse_subcommands() {
+        let args = parse_from(["mane", "explain", "-r", "foo", "bar"]).unwrap();
+        assert!(matches!(args.command, Some(Command::Explain { .. })));
+        assert_eq!(args.rules.len(), 1);
+
+        let args = parse_from(["mane", "apply", "changes.patch"]).unwrap();
+        assert!(matches!(args.command, Some(Command::Apply { .. })));
+
+        // `mane new` becomes a copy of the template with a rule for the name
+        let args = parse_from(["mane", "new", "templates/service", "billing-api"]).unwrap();
+        assert!(args.command.is_none());
+        assert_eq!(args.copy_groups, [["templates/service", "billing-api"]]);
+        assert_eq!(args.replacement_rules, ["service", "billing_api"]);
+    }
+
+    #[test]
+    fn test_walk_skips_vcs_dirs() {
+        let dir = TestDir::new("args-vcs");
+        fs::create_dir_all(dir.join(".git/refs")).unwrap();
+        fs::create_dir_all(dir.join("src/.hg")).unwrap();
+        fs::write(dir.join(".git/config"), "").unwrap();
+        fs::write(dir.join("src/main.rs"), "").unwrap();
+        fs::write(dir.join(".github"), "").unwrap();
+
+        let walked = |args: &Args, root: &Path| -> Vec<PathBuf> {
+            let mut paths: Vec<PathBuf> = args.walk_builder(root).hidden(false).build()
+                .flatten()
+                .map(|entry| entry.path().strip_prefix(&dir).unwrap().to_path_buf())
+                .collect();
+            paths.sort();
+            paths
+        };
+        let args = Args::default();
+        assert_eq!(walked(&args, &dir), ["", ".github", "src", "src/main.rs"].map(PathBuf::from));
+
+        // A metadata directory given as the root is still walked
+        assert_eq!(walked(&args, &dir.join(".git")).len(), 3);
+
+        let args = Args { include_vcs_dirs: true, ..Args::default() };
+        assert!(walked(&args, &dir).contains(&PathBuf::from(".git/config")));
+        assert!(walked(&args, &dir).contains(&PathBuf::from("src/.hg")));
+    }
+
+    #[test]
+    fn test_parse_copy_groups() {
+        let pairs = |args: &Args| -> Vec<(String, String)> {
+            args.copy_specs.iter().map(|spec| (spec.source.display().to_string(), spec.target.display().to_string())).collect()
+        };
+        let pair = |source: &str, target: &str| (source.to_string(), target.to_string());
+
+        // Each -c has its own target, the last path of it
+        let args = parse_from(["mane", "-c", "tmpl", "svc-a", "-c", "tmpl", "docs", "svc-b"]).unwrap();
+        assert_eq!(pairs(&args), [pair("tmpl", "svc-a"), pair("tmpl", "svc-b"), pair("docs", "svc-b")]);
+
+        // With -t every path of every -c is a source
+        let args = parse_from(["mane", "-c", "a", "b", "-c", "c", "-t", "out"]).unwrap();
+        assert_eq!(pairs(&args), [pair("a", "out"), pair("b", "out"), pair("c", "out")]);
+
+        assert!(parse_from(["mane", "-c", "a"]).is_err());
+        assert!(parse_from(["mane", "-c", "a", "-", "-c", "b", "out"]).is_err());
+        assert!(parse_from(["mane", "-c", "a", "x", "-c", "b", "y", "--incremental"]).is_err());
+    }
+
+    #[test]
+    fn test_with_rules() {
+        let from_to = |rules: &[ReplacementRule]| -> Vec<(String, String)> {
+            rules.iter().map(|rule| (rule.from.clone(), rule.to.clone())).collect()
+        };
+        let pair = |from: &str, to: &str| (from.to_string(), to.to_string());
+
+        // A --with goes to the -c before it, and each source of that -c
+        let args = parse_from([
+            "mane", "-r", "tmpl", "svc",
+            "-c", "tmpl", "docs", "svc-a", "--with", "port", "8001", "--with", "port", "8081",
+            "-c", "tmpl", "svc-b", "--with", "tmpl", "billing",
+        ]).unwrap();
+        let own: Vec<_> = args.copy_specs.iter().map(|spec| from_to(&spec.rules)).collect();
+        assert_eq!(own, [vec![pair("port", "8081")], vec![pair("port", "8081")], vec![pair("tmpl", "billing")]]);
+
+        // Its own rules override the run's with the same FROM, after the others
+        let rules = [ReplacementRule::new("tmpl".to_string(), "svc".to_string()), ReplacementRule::new("x".to_string(), "y".to_string())];
+        assert_eq!(from_to(&args.copy_specs[0].rules(&rules)), [pair("tmpl", "svc"), pair("x", "y"), pair("port", "8081")]);
+        assert_eq!(from_to(&args.copy_specs[2].rules(&rules)), [pair("x", "y"), pair("tmpl", "billing")]);
+
+        assert!(parse_from(["mane", "--with", "a", "b", "-c", "tmpl", "out"]).is_err());
+        assert!(parse_from(["mane", "-c", "tmpl", "out", "--with", "", "b"]).is_err());
+    }
+
+    #[test]
+    fn test_files_from() {
+        let dir = TestDir::new("files-from");
+        let list = dir.join("list");
+        let list_arg = list.to_string_lossy().to_string();
+
+        // Lines end with LF or CRLF, and empty lines are skipped
+        fs::write(&list, "a.txt\r\n\nsrc/b c.txt\n").unwrap();
+        let args = parse_from(["mane", "-r", "x", "y", "main.rs", "--files-from", &list_arg]).unwrap();
+        assert_eq!(args.files, [PathBuf::from("main.rs"), PathBuf::from("a.txt"), PathBuf::from("src/b c.txt")]);
+
+        // With -0 only NUL separates, so a name may hold a newline
+        fs::write(&list, "a.txt\0two\nlines\0\0").unwrap();
+        let args = parse_from(["mane", "-r", "x", "y", "--files-from", &list_arg, "-0"]).unwrap();
+        assert_eq!(args.files, [PathBuf::from("a.txt"), PathBuf::from("two\nlines")]);
+
+        // An empty list doesn't fall back to the current directory
+        fs::write(&list, "\n").unwrap();
+        assert!(parse_from(["mane", "-r", "x", "y", "--files-from", &list_arg]).is_err());
+        assert!(parse_from(["mane", "-r", "x", "y", "--files-from", &dir.join("missing").to_string_lossy()]).is_err());
+    }
+}
@@ -0,0 +1,159 @@
+use crate::args::{Args, ReplacementRule};
+use crate::case;
+use crate::prefilter::Prefilter;
+use anyhow::{Result, anyhow};
+use regex::Regex;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Replacement in the content of one file
+type Replace = Box<dyn Fn(&str, &Path) -> String + Sync>;
+
+/// A way of applying the rules to one file's content
+struct Engine {
+    name: &'static str,
+
+    /// Build the engine for a rule set, returning the function that replaces in one text
+    build: fn(&Args) -> Result<Replace>,
+}
+
+const ENGINES: [Engine; 4] = [
+    Engine { name: "naive", build: naive },
+    Engine { name: "aho-corasick", build: prefiltered },
+    Engine { name: "regex", build: regex },
+    Engine { name: "streaming", build: streaming },
+];
+
+/// Every rule in order, each replacing its case variants one `String::replace` pass at a time
+fn naive(args: &Args) -> Result<Replace> {
+    let (rules, case_enabled, split_digits) = (args.rules.clone(), args.case_enabled, args.split_digits);
+    Ok(Box::new(move |text, path| replace_naive(text, &rules, case_enabled, split_digits, path)))
+}
+
+fn replace_naive(text: &str, rules: &[ReplacementRule], case_enabled: bool, split_digits: bool, path: &Path) -> String {
+    rules.iter()
+        .filter(|rule| rule.applies_to(Some(path)))
+        .fold(text.to_string(), |text, rule| match case_enabled {
+            true => case::replace_with_case_variants_counted(&text, &rule.from, &rule.to, split_digits)
+                .map_or(text, |(replaced, _)| replaced),
+            false => text.replace(&rule.from, &rule.to),
+        })
+}
+
+/// The naive engine behind the Aho-Corasick prefilter, as used for files
+fn prefiltered(args: &Args) -> Result<Replace> {
+    let (rules, case_enabled, split_digits) = (args.rules.clone(), args.case_enabled, args.split_digits);
+    let prefilter = Prefilter::new(&rules, case_enabled, false);
+    Ok(Box::new(move |text, path| {
+        if prefilter.may_match(text.as_bytes(), &rules, Some(path)) {
+            replace_naive(text, &rules, case_enabled, split_digits, path)
+        } else {
+            text.to_string()
+        }
+    }))
+}
+
+/// The prefiltered engine applied one line at a time, as --line-buffered does
+fn streaming(args: &Args) -> Result<Replace> {
+    let replace = prefiltered(args)?;
+    Ok(Box::new(move |text, path| text.split_inclusive('\n').map(|line| replace(line, path)).collect()))
+}
+
+/// One regex per rule, replacing all of its case variants in a single pass
+fn regex(args: &Args) -> Result<Replace> {
+    let mut compiled = Vec::new();
+    for rule in &args.rules {
+        let pairs = if args.case_enabled {
+            case::variant_pairs(&rule.from, &rule.to)
+        } else {
+            vec![(rule.from.clone(), rule.to.clone())]
+        };
+        let pattern = pairs.iter().map(|(from, _)| regex::escape(from)).collect::<Vec<_>>().join("|");
+        compiled.push((rule.clone(), Regex::new(&pattern)?, pairs));
+    }
+
+    Ok(Box::new(move |text, path| {
+        compiled.iter()
+            .filter(|(rule, _, _)| rule.applies_to(Some(path)))
+            .fold(text.to_string(), |text, (_, re, pairs)| {
+                re.replace_all(&text, |caps: &regex::Captures| {
+                    pairs.iter().find(|(from, _)| *from == caps[0]).map_or_else(String::new, |(_, to)| to.clone())
+                }).into_owned()
+            })
+    }))
+}
+
+/// Time every engine on a tree and print their throughput
+///
+/// Files are read into memory first, so only replacement is measured. Engines
+/// whose output differs from the naive engine are flagged.
+///
+/// # Arguments
+/// * `args` - Command line arguments holding the rules
+/// * `root` - Tree to run the rules against
+/// * `iterations` - Number of runs per engine; the fastest is reported
+///
+/// # Returns
+/// * `Result<()>` - Result of the operation
+pub fn run(args: &Args, root: &Path, iterations: usize) -> Result<()> {
+    if args.rules.is_empty() {
+        return Err(anyhow!("No replacement rules to benchmark. Use -r FROM TO or a config file."));
+    }
+
+    let mut files: Vec<(PathBuf, String)> = Vec::new();
+    for entry in args.walk_builder(root).build().flatten() {
+        if entry.file_type().is_some_and(|t| t.is_file()) {
+            if let Ok(text) = fs::read_to_string(entry.path()) {
+                files.push((entry.path().to_path_buf(), text));
+            }
+        }
+    }
+    let bytes: usize = files.iter().map(|(_, text)| text.len()).sum();
+    println!("{} text files, {:.1} MB, {} rules, best of {}", files.len(), bytes as f64 / 1e6, args.rules.len(), iterations);
+    println!("{:<14} {:>10} {:>10} {:>8}", "engine", "time", "MB/s", "changed");
+
+    let mut expected: Option<Vec<String>> = None;
+    for engine in &ENGINES {
+        let replace = (engine.build)(args)?;
+        let mut best = Duration::MAX;
+        let mut outputs = Vec::new();
+        for _ in 0..iterations.max(1) {
+            let start = Instant::now();
+            outputs = files.iter().map(|(path, text)| replace(text, path)).collect();
+            best = best.min(start.elapsed());
+        }
+
+        let changed = outputs.iter().zip(&files).filter(|(output, (_, text))| *output != text).count();
+        let throughput = bytes as f64 / 1e6 / best.as_secs_f64().max(1e-9);
+        let differs = expected.as_ref().is_some_and(|expected| *expected != outputs);
+        println!(
+            "{:<14} {:>8.1}ms {:>10.1} {:>8}{}",
+            engine.name, best.as_secs_f64() * 1000.0, throughput, changed,
+            if differs { "  (output differs from naive)" } else { "" },
+        );
+        expected.get_or_insert(outputs);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_engines_agree() {
+        let args = Args {
+            rules: vec![ReplacementRule::new("foo_bar".to_string(), "baz_qux".to_string())],
+            case_enabled: true,
+            ..Args::default()
+        };
+        let text = "foo_bar FooBar fooBar FOO_BAR foo-bar other";
+        let outputs: Vec<String> = ENGINES.iter()
+            .map(|engine| (engine.build)(&args).unwrap()(text, Path::new("a.txt")))
+            .collect();
+        assert_eq!(outputs[0], "baz_qux BazQux bazQux BAZ_QUX baz-qux other");
+        assert!(outputs.iter().all(|output| *output == outputs[0]));
+    }
+}
@@ -0,0 +1,118 @@
+use crate::logger::{self, Level};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// How many leading bytes are looked at for a NUL byte, as git does
+const SNIFF_LEN: usize = 8000;
+
+/// How file content is told apart as text or binary
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Detection {
+    /// Treat every file as text, decoding invalid UTF-8 lossily (--text)
+    pub force_text: bool,
+
+    /// Percentage of invalid UTF-8 bytes a text file may have (--binary-threshold)
+    pub threshold: f64,
+}
+
+impl Detection {
+    /// Every file is text
+    pub const TEXT: Detection = Detection { force_text: true, threshold: 0.0 };
+}
+
+/// Decode file content as text, unless it looks binary
+///
+/// Content with a NUL byte near its start is binary. Otherwise it is decoded
+/// as UTF-8, with the bytes that aren't valid UTF-8 replaced by U+FFFD, as
+/// long as they are no more than the threshold's share of the content.
+/// `--text` decodes everything that way.
+///
+/// # Arguments
+/// * `data` - File content
+/// * `detection` - How text is told apart from binary
+///
+/// # Returns
+/// * `io::Result<String>` - The text, or an `InvalidData` error for binary content
+pub fn decode(data: Vec<u8>, detection: Detection) -> io::Result<String> {
+    if !detection.force_text && data[..data.len().min(SNIFF_LEN)].contains(&0) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "binary content (contains a NUL byte)"));
+    }
+
+    let data = match String::from_utf8(data) {
+        Ok(text) => return Ok(text),
+        Err(e) => e.into_bytes(),
+    };
+    let invalid: usize = data.utf8_chunks().map(|chunk| chunk.invalid().len()).sum();
+    if !detection.force_text && invalid as f64 * 100.0 > detection.threshold * data.len() as f64 {
+        let bytes = if invalid == 1 { "1 byte isn't".to_string() } else { format!("{} bytes aren't", invalid) };
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("binary content ({} UTF-8)", bytes)));
+    }
+    Ok(String::from_utf8_lossy(&data).into_owned())
+}
+
+/// Check whether an error reading a file as text means it is binary
+pub fn is_binary_error(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::InvalidData
+}
+
+/// Files left as they were because they aren't text, with the reason
+static SKIPPED: Mutex<Vec<(PathBuf, String)>> = Mutex::new(Vec::new());
+
+/// Remember a file whose content would have been replaced in, but was skipped as binary
+///
+/// # Arguments
+/// * `path` - The skipped file
+/// * `reason` - Why it isn't text
+pub fn record_skipped(path: &Path, reason: &str) {
+    SKIPPED.lock().unwrap().push((path.to_path_buf(), reason.to_string()));
+}
+
+/// Number of distinct files skipped as binary so far
+pub fn skipped_count() -> usize {
+    let mut paths: Vec<PathBuf> = SKIPPED.lock().unwrap().iter().map(|(path, _)| path.clone()).collect();
+    paths.sort();
+    paths.dedup();
+    paths.len()
+}
+
+/// Warn about every file skipped as binary in this run, so a partly
+/// transformed tree doesn't go unnoticed
+pub fn report_skipped() {
+    let mut skipped = SKIPPED.lock().unwrap().clone();
+    if skipped.is_empty() {
+        return;
+    }
+    skipped.sort();
+    skipped.dedup();
+
+    if skipped.len() == 1 {
+        warn!("1 file wasn't replaced in because it looks binary (see --text and --binary-threshold):");
+    } else {
+        warn!("{} files weren't replaced in because they look binary (see --text and --binary-threshold):", skipped.len());
+    }
+    if logger::enabled(Level::Normal) {
+        for (path, reason) in &skipped {
+            eprintln!("  {}: {}", path.display(), reason);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let strict = Detection::default();
+        let lenient = Detection { force_text: false, threshold: 10.0 };
+
+        assert_eq!(decode(b"foo\n".to_vec(), strict).unwrap(), "foo\n");
+        assert!(decode(b"foo\0bar".to_vec(), lenient).is_err());
+        assert!(decode(b"caf\xe9 latte\n".to_vec(), strict).is_err());
+        assert_eq!(decode(b"caf\xe9 latte\n".to_vec(), lenient).unwrap(), "caf\u{fffd} latte\n");
+        assert!(decode(b"\xff\xfe\xfd".to_vec(), lenient).is_err());
+
+        assert_eq!(decode(b"a\0\xffb".to_vec(), Detection::TEXT).unwrap(), "a\0\u{fffd}b");
+    }
+}
@@ -1,5 +1,18 @@
+use crate::multiline;
 use anyhow::Result;
-use convert_case::{Case, Casing};
+use convert_case::{Boundary, Case, Casing};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether digits are words of their own in this run (--split-digits)
+///
+/// Only the command line sets it; the engine and the library exports take
+/// the setting in their options instead.
+pub static GLOBAL_SPLIT_DIGITS: AtomicBool = AtomicBool::new(false);
+
+/// Whether this run makes digits words of their own (--split-digits)
+fn split_digits() -> bool {
+    GLOBAL_SPLIT_DIGITS.load(Ordering::Relaxed)
+}
 
 /// String naming convention (case) types
 #[derive(Debug)]
@@ -39,7 +52,7 @@ pub fn detect_case(s: &str) -> StringCase {
     }
 }
 
-/// Convert a string to a specified case style
+/// Convert a string to a specified case style, splitting digits as this run does
 /// 
 /// # Arguments
 /// * `s` - The string to convert
@@ -48,16 +61,139 @@ pub fn detect_case(s: &str) -> StringCase {
 /// # Returns
 /// * `String` - The converted string
 pub fn convert_case(s: &str, case_type: &StringCase) -> String {
-    match case_type {
-        StringCase::Pascal => s.to_case(Case::Pascal),
-        StringCase::Kebab => s.to_case(Case::Kebab),
-        StringCase::Camel => s.to_case(Case::Camel),
-        StringCase::ScreamingSnake => s.to_case(Case::UpperSnake),
-        StringCase::Snake => s.to_case(Case::Snake),
-        StringCase::Unknown => s.to_string(),
+    convert_case_with(s, case_type, split_digits())
+}
+
+/// Convert a string to a specified case style
+/// 
+/// # Arguments
+/// * `s` - The string to convert
+/// * `case_type` - The target case style
+/// * `split_digits` - Whether digits are words of their own
+/// 
+/// # Returns
+/// * `String` - The converted string
+pub fn convert_case_with(s: &str, case_type: &StringCase, split_digits: bool) -> String {
+    let case = match case_type {
+        StringCase::Pascal => Case::Pascal,
+        StringCase::Kebab => Case::Kebab,
+        StringCase::Camel => Case::Camel,
+        StringCase::ScreamingSnake => Case::UpperSnake,
+        StringCase::Snake => Case::Snake,
+        StringCase::Unknown => return s.to_string(),
+    };
+    s.with_boundaries(&word_boundaries(split_digits)).to_case(case)
+}
+
+/// Write a string in the case style of another
+///
+/// A word without separators or inner capitals, which `detect_case` can't
+/// place, counts as snake_case, or SCREAMING_SNAKE_CASE if it is in capitals.
+///
+/// # Arguments
+/// * `s` - The string to convert
+/// * `example` - A string written in the wanted style
+/// * `split_digits` - Whether digits are words of their own
+///
+/// # Returns
+/// * `String` - The converted string
+pub fn convert_like(s: &str, example: &str, split_digits: bool) -> String {
+    let case_type = match detect_case(example) {
+        StringCase::Unknown if example.chars().any(char::is_lowercase) => StringCase::Snake,
+        StringCase::Unknown if example.chars().any(char::is_uppercase) => StringCase::ScreamingSnake,
+        case_type => case_type,
+    };
+    convert_case_with(s, &case_type, split_digits)
+}
+
+/// Boundaries between the words of an identifier
+///
+/// Digits stick to the word before them (`oauth2Client` is `oauth2` and
+/// `Client`, `S3Bucket` is `S3` and `Bucket`) unless --split-digits makes
+/// them words of their own. A capital after a digit still starts a new word.
+fn word_boundaries(split_digits: bool) -> Vec<Boundary> {
+    if split_digits {
+        return Boundary::defaults();
     }
+    Boundary::defaults().into_iter()
+        .filter(|boundary| !matches!(boundary, Boundary::UpperDigit | Boundary::LowerDigit | Boundary::DigitLower))
+        .collect()
 }
 
+/// List the FROM/TO pairs a case-aware replacement applies
+/// 
+/// # Arguments
+/// * `from` - The string to replace
+/// * `to` - The replacement string
+/// 
+/// # Returns
+/// * `Vec<(String, String)>` - The original pair followed by one pair per distinct case variant
+pub fn variant_pairs(from: &str, to: &str) -> Vec<(String, String)> {
+    variant_pairs_with(from, to, split_digits())
+}
+
+/// List the FROM/TO pairs a case-aware replacement applies, splitting digits as told
+pub fn variant_pairs_with(from: &str, to: &str, split_digits: bool) -> Vec<(String, String)> {
+    named_variant_pairs_with(from, to, split_digits).into_iter().map(|(from, to, _)| (from, to)).collect()
+}
+
+/// Name of the pair a rule is applied as written, among its case variants
+pub const AS_WRITTEN: &str = "as written";
+
+/// List the FROM/TO pairs a case-aware replacement applies, with the case style of each
+/// 
+/// # Arguments
+/// * `from` - The string to replace
+/// * `to` - The replacement string
+/// 
+/// # Returns
+/// * `Vec<(String, String, &'static str)>` - The pairs of `variant_pairs`, each with
+///   [`AS_WRITTEN`] or the name of its case style (such as `kebab-case`)
+pub fn named_variant_pairs(from: &str, to: &str) -> Vec<(String, String, &'static str)> {
+    named_variant_pairs_with(from, to, split_digits())
+}
+
+/// The pairs of `named_variant_pairs`, splitting digits as told
+fn named_variant_pairs_with(from: &str, to: &str, split_digits: bool) -> Vec<(String, String, &'static str)> {
+    let mut pairs = vec![(from.to_string(), to.to_string(), AS_WRITTEN)];
+    if multiline::is_multiline(from) {
+        return pairs;
+    }
+    
+    for case_type in &CASE_VARIANTS {
+        let from_variant = convert_case_with(from, case_type, split_digits);
+        if from_variant.is_empty() || pairs.iter().any(|(f, _, _)| *f == from_variant) {
+            continue;
+        }
+        pairs.push((from_variant, convert_case_with(to, case_type, split_digits), case_type.name()));
+    }
+    
+    pairs
+}
+
+impl StringCase {
+    /// Name of the case style, written in that style
+    pub fn name(&self) -> &'static str {
+        match self {
+            StringCase::Pascal => "PascalCase",
+            StringCase::Kebab => "kebab-case",
+            StringCase::Camel => "camelCase",
+            StringCase::ScreamingSnake => "SCREAMING_SNAKE_CASE",
+            StringCase::Snake => "snake_case",
+            StringCase::Unknown => "unknown case",
+        }
+    }
+}
+
+/// Case styles tried by case-aware replacement, in order
+const CASE_VARIANTS: [StringCase; 5] = [
+    StringCase::Pascal,
+    StringCase::Kebab,
+    StringCase::Camel,
+    StringCase::ScreamingSnake,
+    StringCase::Snake,
+];
+
 /// Replace strings while considering multiple case variants
 /// 
 /// # Arguments
@@ -67,52 +203,186 @@ pub fn convert_case(s: &str, case_type: &StringCase) -> String {
 /// 
 /// # Returns
 /// * `Result<String>` - The replaced content
+#[allow(dead_code)]
 pub fn replace_with_case_variants(content: &str, from: &str, to: &str) -> Result<String> {
-    use std::sync::atomic::Ordering;
-    use crate::args::GLOBAL_CASE_ENABLED;
-    
+    replace_with_case_variants_counted(content, from, to, split_digits()).map(|(result, _)| result)
+}
+
+/// Replace strings while considering multiple case variants, counting the replacements made
+/// 
+/// # Arguments
+/// * `content` - The content to replace in
+/// * `from` - The string to replace
+/// * `to` - The replacement string
+/// * `split_digits` - Whether digits are words of their own
+/// 
+/// # Returns
+/// * `Result<(String, usize)>` - The replaced content and the number of replacements
+pub fn replace_with_case_variants_counted(content: &str, from: &str, to: &str, split_digits: bool) -> Result<(String, usize)> {
     let mut result = content.to_string();
     
     // Direct replacement (original case)
+    let mut count = result.matches(from).count();
     result = result.replace(from, to);
     
-    // If case transformation is enabled, handle different case variants
-    if GLOBAL_CASE_ENABLED.load(Ordering::Relaxed) {
-        // For each case variant, create and apply replacements, including the current case
-        // This ensures we apply transformations for all cases, not just the ones different from the original
-        let case_variants = [
-            StringCase::Pascal,
-            StringCase::Kebab,
-            StringCase::Camel,
-            StringCase::ScreamingSnake,
-            StringCase::Snake,
-        ];
-        
-        for case_type in &case_variants {
-            // Skip if this is exactly the same as the original input string to avoid redundant replacements
-            // (Not skipping based on case types, which was causing issues with mixed casing)
-            let from_variant = convert_case(from, case_type);
-            
-            // Skip if converting to this case gives the same string as original
-            // or if the variant is empty
-            if from_variant == from || from_variant.is_empty() {
-                continue;
-            }
-            
-            // Make sure the from_variant actually exists in the original content
-            if !content.contains(&from_variant) {
-                continue;
-            }
-            
-            // Convert the 'to' string to the same case variant
-            let to_variant = convert_case(to, case_type);
-            
-            // Apply this case-specific replacement
-            result = result.replace(&from_variant, &to_variant);
+    // Then each case variant; callers only come here when case variants are replaced
+    // For each case variant, create and apply replacements, including the current case
+    // This ensures we apply transformations for all cases, not just the ones different from the original
+    for case_type in &CASE_VARIANTS {
+        // Skip if this is exactly the same as the original input string to avoid redundant replacements
+        // (Not skipping based on case types, which was causing issues with mixed casing)
+        let from_variant = convert_case_with(from, case_type, split_digits);
+        
+        // Skip if converting to this case gives the same string as original
+        // or if the variant is empty
+        if from_variant == from || from_variant.is_empty() {
+            continue;
+        }
+        
+        // Make sure the from_variant actually exists in the original content
+        if !content.contains(&from_variant) {
+            continue;
         }
+        
+        // Convert the 'to' string to the same case variant
+        let to_variant = convert_case_with(to, case_type, split_digits);
+        
+        // Apply this case-specific replacement
+        count += result.matches(from_variant.as_str()).count();
+        result = result.replace(&from_variant, &to_variant);
     }
     
-    Ok(result)
+    Ok((result, count))
+}
+
+/// Split an identifier into its lowercase words
+///
+/// # Arguments
+/// * `s` - Identifier in any case style
+///
+/// # Returns
+/// * `Vec<String>` - Its words, split at the same boundaries as case conversion
+pub fn words(s: &str) -> Vec<String> {
+    words_with(s, split_digits())
+}
+
+/// Split an identifier into its lowercase words, splitting digits as told
+fn words_with(s: &str, split_digits: bool) -> Vec<String> {
+    convert_case_with(s, &StringCase::Snake, split_digits).split('_').filter(|w| !w.is_empty()).map(str::to_string).collect()
+}
+
+/// Capitalization of one word of a matched occurrence
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum WordShape {
+    Lower,       // user
+    Capitalized, // User
+    Upper,       // USER
+}
+
+impl WordShape {
+    fn of(word: &str) -> Self {
+        let mut chars = word.chars().filter(|c| c.is_alphabetic());
+        match chars.next() {
+            Some(first) if first.is_uppercase() => {
+                let rest: Vec<char> = chars.collect();
+                if !rest.is_empty() && rest.iter().all(|c| c.is_uppercase()) {
+                    WordShape::Upper
+                } else if rest.iter().any(|c| c.is_uppercase()) {
+                    // Irregular words like `iOS` or `McDonald` are rendered in lowercase
+                    WordShape::Lower
+                } else {
+                    WordShape::Capitalized
+                }
+            },
+            _ => WordShape::Lower,
+        }
+    }
+
+    fn render(self, word: &str) -> String {
+        match self {
+            WordShape::Lower => word.to_lowercase(),
+            WordShape::Upper => word.to_uppercase(),
+            WordShape::Capitalized => {
+                let mut chars = word.chars();
+                chars.next().map_or_else(String::new, |first| first.to_uppercase().chain(chars).collect())
+            },
+        }
+    }
+}
+
+/// Replace every occurrence of FROM's words, in any shape, with TO rendered in that same shape (--same-shape)
+///
+/// An occurrence is FROM's words in order, in any capitalization, joined by
+/// `_`, `-` or nothing. TO takes over each occurrence's separators and the
+/// capitalization of each word, so irregular styles such as `Foo_barBaz`
+/// are kept rather than snapped to one of the five case variants. The
+/// exact FROM string is replaced with TO as written.
+///
+/// # Arguments
+/// * `content` - The content to replace in
+/// * `from` - The string to replace
+/// * `to` - The replacement string
+/// * `split_digits` - Whether digits are words of their own
+///
+/// # Returns
+/// * `(String, usize)` - The replaced content and the number of replacements
+pub fn replace_same_shape_counted(content: &str, from: &str, to: &str, split_digits: bool) -> (String, usize) {
+    let from_words = words_with(from, split_digits);
+    let to_words = words_with(to, split_digits);
+    if from_words.is_empty() || to_words.is_empty() {
+        return (content.replace(from, to), content.matches(from).count());
+    }
+
+    let pattern = from_words.iter()
+        .map(|word| format!("({})", regex::escape(word)))
+        .collect::<Vec<_>>()
+        .join("([_-]?)");
+    let Ok(re) = regex::RegexBuilder::new(&pattern).case_insensitive(true).build() else {
+        return (content.replace(from, to), content.matches(from).count());
+    };
+
+    // Separator of TO as written, for lowercase one-word occurrences
+    let to_separator = to.chars().find(|c| *c == '_' || *c == '-').map_or_else(String::new, String::from);
+
+    let mut count = 0;
+    let result = re.replace_all(content, |caps: &regex::Captures| {
+        count += 1;
+        if &caps[0] == from {
+            return to.to_string();
+        }
+
+        // Single capitals like the `S` of `S_3` are only capitalized if the rest of the occurrence is
+        let upper = !caps[0].chars().any(char::is_lowercase);
+        let shapes: Vec<WordShape> = (0..from_words.len())
+            .map(|i| if upper { WordShape::Upper } else { WordShape::of(&caps[2 * i + 1]) })
+            .collect();
+        let separators: Vec<&str> = (1..from_words.len()).map(|i| caps.get(2 * i).map_or("", |m| m.as_str())).collect();
+
+        let mut rendered = String::new();
+        for (j, word) in to_words.iter().enumerate() {
+            let mut shape = shapes[j.min(shapes.len() - 1)];
+            if j > 0 {
+                // A one-word occurrence has no separator to copy: PascalCase, SCREAMING_SNAKE or TO's own
+                let mut separator = separators.get(j - 1).or(separators.last()).map_or_else(|| match shape {
+                    WordShape::Capitalized => String::new(),
+                    WordShape::Upper => "_".to_string(),
+                    WordShape::Lower => to_separator.clone(),
+                }, |s| s.to_string());
+                if j >= shapes.len() && separator.is_empty() {
+                    // Keep extra words readable: camelCase goes on, SCREAMING words get underscores
+                    match shape {
+                        WordShape::Lower => shape = WordShape::Capitalized,
+                        WordShape::Upper => separator = "_".to_string(),
+                        WordShape::Capitalized => {},
+                    }
+                }
+                rendered.push_str(&separator);
+            }
+            rendered.push_str(&shape.render(word));
+        }
+        rendered
+    });
+    (result.into_owned(), count)
 }
 
 #[cfg(test)]
@@ -167,12 +437,46 @@ mod tests {
     }
     
     #[test]
-    fn test_replace_with_case_variants() {
-        // Configure globals for testing
-        use std::sync::atomic::Ordering;
-        use crate::args::GLOBAL_CASE_ENABLED;
-        GLOBAL_CASE_ENABLED.store(true, Ordering::Relaxed);
+    fn test_case_conversion_digits() {
+        assert_eq!(convert_case_with("oauth2Client", &StringCase::Snake, false), "oauth2_client");
+        assert_eq!(convert_case_with("S3Bucket", &StringCase::Kebab, false), "s3-bucket");
+        assert_eq!(convert_case_with("v2Api", &StringCase::ScreamingSnake, false), "V2_API");
+        assert_eq!(convert_case_with("oauth2_client", &StringCase::Camel, false), "oauth2Client");
+        assert_eq!(convert_case_with("s3_bucket", &StringCase::Pascal, false), "S3Bucket");
+        
+        assert_eq!(convert_case_with("oauth2Client", &StringCase::Snake, true), "oauth_2_client");
+        assert_eq!(variant_pairs_with("oauth2", "v3", true)[1], ("Oauth2".to_string(), "V3".to_string()));
+        assert_eq!(words_with("oauth2Client", true), ["oauth", "2", "client"]);
+    }
+    
+    #[test]
+    fn test_named_variant_pairs() {
+        let pairs = named_variant_pairs("service-name", "user-account");
+        assert_eq!(pairs[0], ("service-name".to_string(), "user-account".to_string(), AS_WRITTEN));
+        assert_eq!(pairs[1], ("ServiceName".to_string(), "UserAccount".to_string(), "PascalCase"));
+        // The kebab-case variant is the rule as written
+        assert_eq!(pairs.iter().map(|(_, _, name)| *name).collect::<Vec<_>>(),
+            [AS_WRITTEN, "PascalCase", "camelCase", "SCREAMING_SNAKE_CASE", "snake_case"]);
+    }
+
+    #[test]
+    fn test_replace_same_shape_counted() {
+        let content = "foo_bar FooBar fooBar FOO_BAR foo-bar Foo_barBaz Foo-Bar";
+        let (result, count) = replace_same_shape_counted(content, "foo_bar", "baz_qux", false);
+        assert_eq!(result, "baz_qux BazQux bazQux BAZ_QUX baz-qux Baz_quxBaz Baz-Qux");
+        assert_eq!(count, 7);
+        
+        // TO words beyond FROM's follow the last separator and capitalization
+        let (result, _) = replace_same_shape_counted("user User USER user_name", "user", "customer_account", false);
+        assert_eq!(result, "customer_account CustomerAccount CUSTOMER_ACCOUNT customer_account_name");
+        let (result, _) = replace_same_shape_counted("fooBar Foo_bar", "foo_bar", "a_b_c", false);
+        assert_eq!(result, "aBC A_b_c");
         
+        assert_eq!(replace_same_shape_counted("nothing here", "foo_bar", "x", false).1, 0);
+    }
+    
+    #[test]
+    fn test_replace_with_case_variants() {
         // Test with a simple example like in the spec
         let content = "Hello, World\nhello, world";
         
@@ -200,4 +504,23 @@ mod tests {
         assert!(result3.contains("GOOD_MORNING"));
         assert!(result3.contains("good-morning"));
     }
+    
+    #[test]
+    fn test_replace_with_case_variants_counted() {
+        let content = "HelloWorld helloWorld hello_world HELLO_WORLD hello-world";
+        let (result, count) = replace_with_case_variants_counted(content, "HelloWorld", "GoodMorning", false).unwrap();
+        assert_eq!(result, "GoodMorning goodMorning good_morning GOOD_MORNING good-morning");
+        assert_eq!(count, 5);
+        
+        let (_, count) = replace_with_case_variants_counted("nothing here", "HelloWorld", "GoodMorning", false).unwrap();
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn test_convert_like() {
+        assert_eq!(convert_like("billing-api", "service", false), "billing_api");
+        assert_eq!(convert_like("billing-api", "SERVICE", false), "BILLING_API");
+        assert_eq!(convert_like("billing_api", "MyService", false), "BillingApi");
+        assert_eq!(convert_like("BillingApi", "my-service", false), "billing-api");
+    }
 }
@@ -0,0 +1,142 @@
+use crate::args::{Args, ReplacementRule};
+use crate::case;
+use crate::lint;
+use crate::multiline;
+use anyhow::{Result, anyhow};
+
+/// Check a rule set for problems and print the spellings it replaces (`mane check-rules`)
+///
+/// Nothing is read or written, so a profile can be checked before it goes
+/// into a shared config file. Each problem is printed as a warning, and any
+/// problem makes the command fail.
+///
+/// # Arguments
+/// * `args` - Command line arguments, with the rules to check
+///
+/// # Returns
+/// * `Result<()>` - Error if there are no rules or any of them has a problem
+pub fn run(args: &Args) -> Result<()> {
+    if args.rules.is_empty() {
+        return Err(anyhow!("No replacement rules to check. Use -r/--replace FROM TO"));
+    }
+    print!("{}", variant_table(&args.rules, args.case_enabled));
+
+    let problems = problems(&args.rules, args.case_enabled, args.ordered);
+    for problem in &problems {
+        warn!("{}", problem);
+    }
+    match problems.len() {
+        0 => {
+            info!("{} rules, no problems found", args.rules.len());
+            Ok(())
+        },
+        n => Err(anyhow!("Found {} problems in {} rules", n, args.rules.len())),
+    }
+}
+
+/// Every problem of a rule set
+///
+/// Besides what `lint` finds between the rules, this reports empty FROMs and
+/// TOs, and FROMs written like a regular expression, which rules only ever
+/// match literally.
+///
+/// # Arguments
+/// * `rules` - Rules in the order they are applied
+/// * `case_enabled` - Whether case variants are replaced as well
+/// * `ordered` - Whether rules apply one after another (--ordered)
+///
+/// # Returns
+/// * `Vec<String>` - One message per problem
+fn problems(rules: &[ReplacementRule], case_enabled: bool, ordered: bool) -> Vec<String> {
+    let mut problems = Vec::new();
+    for (index, rule) in rules.iter().enumerate() {
+        if rule.from.is_empty() {
+            problems.push(format!("Rule #{} has an empty FROM", index + 1));
+        } else if rule.to.is_empty() {
+            problems.push(format!("Rule '{}' -> '' deletes every match", rule.from));
+        }
+        if looks_like_regex(&rule.from) {
+            problems.push(format!("Rule '{}' -> '{}' looks like a regular expression, but rules match literally", rule.from, rule.to));
+        }
+    }
+
+    // An empty FROM would match everywhere, and drown out the rest
+    let rules: Vec<ReplacementRule> = rules.iter().filter(|rule| !rule.from.is_empty()).cloned().collect();
+    problems.extend(lint::duplicate_froms(&rules));
+    problems.extend(lint::self_amplifying(&rules, case_enabled));
+    problems.extend(lint::lint_rules(&rules, case_enabled, ordered));
+    problems
+}
+
+/// Whether a FROM uses the syntax of a regular expression
+fn looks_like_regex(from: &str) -> bool {
+    const SYNTAX: [&str; 9] = [".*", ".+", "\\d", "\\w", "\\s", "\\b", "(?", "[^", "|^"];
+    SYNTAX.iter().any(|syntax| from.contains(syntax))
+        || (from.len() > 1 && (from.starts_with('^') || (from.ends_with('$') && !from.ends_with("\\$"))))
+}
+
+/// The spellings every rule searches for and what each is replaced with, as one table
+///
+/// # Arguments
+/// * `rules` - Rules in the order they are applied
+/// * `case_enabled` - Whether case variants are replaced as well
+///
+/// # Returns
+/// * `String` - The table, with a header row
+fn variant_table(rules: &[ReplacementRule], case_enabled: bool) -> String {
+    let mut rows = vec![["RULE".to_string(), "CASE".to_string(), "FROM".to_string(), "TO".to_string()]];
+    for (index, rule) in rules.iter().enumerate() {
+        let pairs = if !case_enabled || multiline::is_multiline(&rule.from) {
+            vec![(rule.from.clone(), rule.to.clone(), case::AS_WRITTEN)]
+        } else {
+            case::named_variant_pairs(&rule.from, &rule.to)
+        };
+        for (from, to, variant) in pairs {
+            rows.push([format!("#{}", index + 1), variant.to_string(), format!("{:?}", from), format!("{:?}", to)]);
+        }
+    }
+
+    let widths: Vec<usize> = (0..4).map(|column| rows.iter().map(|row| row[column].chars().count()).max().unwrap_or(0)).collect();
+    let mut out = String::new();
+    for row in &rows {
+        let line = format!("{:w0$}  {:w1$}  {:w2$}  {}", row[0], row[1], row[2], row[3], w0 = widths[0], w1 = widths[1], w2 = widths[2]);
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(from: &str, to: &str) -> ReplacementRule {
+        ReplacementRule::new(from.to_string(), to.to_string())
+    }
+
+    #[test]
+    fn test_problems() {
+        assert!(problems(&[rule("foo", "bar"), rule("baz", "qux")], true, false).is_empty());
+        assert_eq!(problems(&[rule("", "x"), rule("foo", "")], true, false), [
+            "Rule #1 has an empty FROM",
+            "Rule 'foo' -> '' deletes every match",
+        ]);
+        assert_eq!(problems(&[rule("user_.*", "account")], true, false).len(), 1);
+        assert!(!looks_like_regex("price"));
+        assert!(looks_like_regex("^main$"));
+        assert!(!looks_like_regex("a.b"));
+        assert_eq!(problems(&[rule("api", "api_v2")], true, false).len(), 1);
+    }
+
+    #[test]
+    fn test_variant_table() {
+        assert_eq!(variant_table(&[rule("fooBar", "bazQux")], true), concat!(
+            "RULE  CASE                  FROM       TO\n",
+            "#1    as written            \"fooBar\"   \"bazQux\"\n",
+            "#1    PascalCase            \"FooBar\"   \"BazQux\"\n",
+            "#1    kebab-case            \"foo-bar\"  \"baz-qux\"\n",
+            "#1    SCREAMING_SNAKE_CASE  \"FOO_BAR\"  \"BAZ_QUX\"\n",
+            "#1    snake_case            \"foo_bar\"  \"baz_qux\"\n",
+        ));
+    }
+}
@@ -0,0 +1,128 @@
+use atty::Stream;
+use clap::ValueEnum;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const RESET: &str = "\x1b[0m";
+
+/// When to colorize output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    #[default]
+    Auto,   // Colorize when writing to a terminal and NO_COLOR is unset
+    Always, // Always colorize
+    Never,  // Never colorize
+}
+
+/// Configured color choice, set once from the command line
+pub static GLOBAL_COLOR_CHOICE: AtomicU8 = AtomicU8::new(ColorChoice::Auto as u8);
+
+/// Set the global color choice
+///
+/// # Arguments
+/// * `choice` - The color choice from --color
+pub fn set_choice(choice: ColorChoice) {
+    GLOBAL_COLOR_CHOICE.store(choice as u8, Ordering::Relaxed);
+}
+
+/// Check whether output written to `stream` should be colorized
+///
+/// # Arguments
+/// * `stream` - The stream the output goes to
+///
+/// # Returns
+/// * `bool` - true if ANSI colors should be used
+pub fn enabled(stream: Stream) -> bool {
+    match GLOBAL_COLOR_CHOICE.load(Ordering::Relaxed) {
+        c if c == ColorChoice::Always as u8 => true,
+        c if c == ColorChoice::Never as u8 => false,
+        // https://no-color.org: any non-empty value disables color
+        _ => std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()) && atty::is(stream),
+    }
+}
+
+/// Wrap text in red (removed text)
+pub fn red(text: &str, stream: Stream) -> String {
+    paint(text, RED, stream)
+}
+
+/// Wrap text in green (inserted text)
+pub fn green(text: &str, stream: Stream) -> String {
+    paint(text, GREEN, stream)
+}
+
+fn paint(text: &str, code: &str, stream: Stream) -> String {
+    if text.is_empty() || !enabled(stream) {
+        text.to_string()
+    } else {
+        format!("{}{}{}", code, text, RESET)
+    }
+}
+
+/// Highlight the differing middle part of a before/after pair
+///
+/// The common prefix and suffix are left untouched; the changed part of `old`
+/// is painted red and the changed part of `new` green.
+///
+/// # Arguments
+/// * `old` - Text before replacement
+/// * `new` - Text after replacement
+/// * `stream` - The stream the output goes to
+///
+/// # Returns
+/// * `(String, String)` - Highlighted old and new text
+pub fn highlight_change(old: &str, new: &str, stream: Stream) -> (String, String) {
+    if !enabled(stream) || old == new {
+        return (old.to_string(), new.to_string());
+    }
+
+    let prefix = common_prefix_len(old, new);
+    let suffix = common_suffix_len(&old[prefix..], &new[prefix..]);
+
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+
+    (
+        format!("{}{}{}", &old[..prefix], red(old_mid, stream), &old[old.len() - suffix..]),
+        format!("{}{}{}", &new[..prefix], green(new_mid, stream), &new[new.len() - suffix..]),
+    )
+}
+
+/// Length in bytes of the common prefix, on a char boundary
+fn common_prefix_len(a: &str, b: &str) -> usize {
+    a.char_indices()
+        .zip(b.chars())
+        .find(|((_, ca), cb)| ca != cb)
+        .map(|((i, _), _)| i)
+        .unwrap_or_else(|| a.len().min(b.len()))
+}
+
+/// Length in bytes of the common suffix, on a char boundary
+fn common_suffix_len(a: &str, b: &str) -> usize {
+    a.chars()
+        .rev()
+        .zip(b.chars().rev())
+        .take_while(|(ca, cb)| ca == cb)
+        .map(|(c, _)| c.len_utf8())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highlight_change() {
+        set_choice(ColorChoice::Always);
+
+        let (old, new) = highlight_change("src/foo_item.rs", "src/bar_item.rs", Stream::Stdout);
+        assert_eq!(old, "src/\x1b[31mfoo\x1b[0m_item.rs");
+        assert_eq!(new, "src/\x1b[32mbar\x1b[0m_item.rs");
+
+        // Pure insertion leaves the old side unpainted
+        let (old, new) = highlight_change("ab", "axb", Stream::Stdout);
+        assert_eq!(old, "ab");
+        assert_eq!(new, "a\x1b[32mx\x1b[0mb");
+    }
+}
@@ -0,0 +1,271 @@
+use crate::args::{self, Args, ReplacementRule};
+use crate::hooks::HookErrorPolicy;
+use crate::json::Value;
+use crate::toml;
+use anyhow::{Result, Context, anyhow};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Default config file name, looked up in the current directory
+pub const CONFIG_FILE_NAME: &str = ".mane.toml";
+
+/// Keys allowed in the top-level section and in each profile
+const SECTION_KEYS: &[&str] = &[
+    "rules", "case", "split_digits", "rename_file", "rename_dir", "no_rename_paths", "include_git_ignore",
+    "pre_hooks", "post_hooks", "hook_errors", "vars",
+];
+
+/// Check whether a path is a config file, which walks leave alone
+///
+/// Every `.mane.toml` is skipped, and so is the file given with `--config`,
+/// so a run doesn't rewrite the rules it was started with.
+///
+/// # Arguments
+/// * `path` - Path found by a walk
+/// * `args` - Command line arguments
+///
+/// # Returns
+/// * `bool` - Whether the path is a config file
+pub fn is_config_file(path: &Path, args: &Args) -> bool {
+    if path.file_name().is_some_and(|name| name == CONFIG_FILE_NAME) {
+        return true;
+    }
+    let Some(config) = &args.config else {
+        return false;
+    };
+    path.file_name() == config.file_name()
+        && path.canonicalize().is_ok_and(|path| config.canonicalize().is_ok_and(|config| path == config))
+}
+
+/// Load the config file (if any) and merge it into the arguments
+///
+/// Top-level rules and options apply first, then the selected `[profiles.NAME]`
+/// section. Rules given with -r/--replace later override config rules with the same FROM.
+/// Config hooks run before hooks given on the command line. A config file that
+/// was found rather than given with --config may come with a checked-out
+/// repository, so its hooks only run with --allow-config-hooks; otherwise they
+/// are listed and skipped.
+///
+/// # Arguments
+/// * `args` - Parsed command line arguments to update
+///
+/// # Returns
+/// * `Result<()>` - Ok if the config was loaded (or none exists), Error otherwise
+pub fn load(args: &mut Args) -> Result<()> {
+    let path = match &args.config {
+        Some(path) => path.clone(),
+        None => {
+            let default_path = PathBuf::from(CONFIG_FILE_NAME);
+            if !default_path.exists() {
+                if let Some(profile) = &args.profile {
+                    return Err(anyhow!("Profile '{}' requested but no {} was found", profile, CONFIG_FILE_NAME));
+                }
+                return Ok(());
+            }
+            default_path
+        },
+    };
+    load_file(args, &path)
+}
+
+/// Merge a config file into the arguments, as `load` does
+///
+/// # Arguments
+/// * `args` - Parsed command line arguments to update
+/// * `path` - The config file, given with --config or found in the current directory
+///
+/// # Returns
+/// * `Result<()>` - Ok if the config was loaded, Error otherwise
+fn load_file(args: &mut Args, path: &Path) -> Result<()> {
+    let document = read_config(path)?;
+    let source = path.display().to_string();
+
+    // Config hooks come first; command line hooks are appended after them
+    let cli_pre_hooks = std::mem::take(&mut args.pre_hooks);
+    let cli_post_hooks = std::mem::take(&mut args.post_hooks);
+
+    apply_section(args, &document, &source, true)?;
+
+    if let Some(profile) = args.profile.clone() {
+        let section = document.get("profiles")
+            .and_then(|profiles| profiles.get(&profile))
+            .ok_or_else(|| anyhow!("Profile '{}' not found in {}", profile, source))?;
+        apply_section(args, section, &format!("{} [profiles.{}]", source, profile), false)?;
+    }
+
+    if args.config.is_none() && !args.allow_config_hooks {
+        skip_hooks(args, &source);
+    }
+    args.pre_hooks.extend(cli_pre_hooks);
+    args.post_hooks.extend(cli_post_hooks);
+
+    Ok(())
+}
+
+/// Drop the hooks a config file set, listing them
+///
+/// # Arguments
+/// * `args` - Arguments holding only the config file's hooks
+/// * `source` - Path of the config file, for messages
+fn skip_hooks(args: &mut Args, source: &str) {
+    let pre_hooks = std::mem::take(&mut args.pre_hooks);
+    let post_hooks = std::mem::take(&mut args.post_hooks);
+    if pre_hooks.is_empty() && post_hooks.is_empty() {
+        return;
+    }
+    warn!("Skipped the hooks of {} (give it with --config, or pass --allow-config-hooks, to run them):", source);
+    for hook in &pre_hooks {
+        warn!("  pre-hook: {}", hook);
+    }
+    for hook in &post_hooks {
+        warn!("  post-hook: {}", hook);
+    }
+}
+
+/// Read and parse a config file
+///
+/// # Arguments
+/// * `path` - Config file path
+///
+/// # Returns
+/// * `Result<Value>` - The parsed document
+pub fn read_config(path: &Path) -> Result<Value> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file: {:?}", path))?;
+    toml::parse(&text).with_context(|| format!("Invalid config file: {:?}", path))
+}
+
+/// Merge the rules and options of one config section into the arguments
+///
+/// # Arguments
+/// * `args` - Arguments to update
+/// * `section` - Table with rules and options
+/// * `source` - Description of the section for messages
+/// * `top_level` - Whether this is the top-level section (which may contain profiles)
+///
+/// # Returns
+/// * `Result<()>` - Ok if the section is valid
+fn apply_section(args: &mut Args, section: &Value, source: &str, top_level: bool) -> Result<()> {
+    let Value::Object(entries) = section else {
+        return Err(anyhow!("Expected a table in {}", source));
+    };
+
+    for (key, value) in entries {
+        match key.as_str() {
+            "rules" => {
+                for rule in parse_rules(value, source)? {
+                    // Later sections override earlier rules with the same FROM string
+                    args.rules.retain(|r| r.from != rule.from);
+                    args.rules.push(rule);
+                }
+            },
+            "case" => args.case_enabled = expect_bool(value, key, source)?,
+            "split_digits" => args.split_digits |= expect_bool(value, key, source)?,
+            "rename_file" => args.rename_file = expect_bool(value, key, source)?,
+            "rename_dir" => args.rename_dir = expect_bool(value, key, source)?,
+            "no_rename_paths" => args.no_rename_paths.extend(expect_strings(value, key, source)?),
+            "include_git_ignore" => args.include_git_ignore |= expect_bool(value, key, source)?,
+            "pre_hooks" => args.pre_hooks.extend(expect_strings(value, key, source)?),
+            "post_hooks" => args.post_hooks.extend(expect_strings(value, key, source)?),
+            "hook_errors" => {
+                let name = value.as_str()
+                    .ok_or_else(|| anyhow!("'{}' must be a string in {}", key, source))?;
+                let policy = HookErrorPolicy::from_name(name)
+                    .ok_or_else(|| anyhow!("'{}' must be \"fail\" or \"warn\" in {}", key, source))?;
+                // --hook-errors on the command line wins
+                args.hook_errors.get_or_insert(policy);
+            },
+            "vars" => {
+                let Value::Object(vars) = value else {
+                    return Err(anyhow!("'{}' must be a table in {}", key, source));
+                };
+                for (name, value) in vars {
+                    let value = value.as_str()
+                        .ok_or_else(|| anyhow!("Template variable '{}' must be a string in {}", name, source))?;
+                    args.vars.retain(|(other, _)| other != name);
+                    args.vars.push((name.clone(), value.to_string()));
+                }
+            },
+            "profiles" if top_level => {},
+            _ => warn!("Unknown key '{}' in {} (expected one of: {})", key, source, SECTION_KEYS.join(", ")),
+        }
+    }
+
+    Ok(())
+}
+
+/// Parse a `rules` array of `{ from = "...", to = "...", paths = ["glob", ...] }` tables
+pub fn parse_rules(value: &Value, source: &str) -> Result<Vec<ReplacementRule>> {
+    let items = value.as_array()
+        .ok_or_else(|| anyhow!("'rules' must be an array of tables in {}", source))?;
+
+    items.iter()
+        .enumerate()
+        .map(|(i, item)| {
+            let field = |name: &str| {
+                item.get(name)
+                    .and_then(Value::as_str)
+                    .map(str::to_string)
+                    .ok_or_else(|| anyhow!("Rule #{} in {} is missing a string '{}'", i + 1, source, name))
+            };
+            let from = field("from")?;
+            if from.is_empty() {
+                return Err(anyhow!("Rule #{} in {} has an empty 'from'", i + 1, source));
+            }
+            let mut rule = ReplacementRule::new(from, field("to")?);
+            if let Some(paths) = item.get("paths") {
+                let globs = paths.as_array()
+                    .and_then(|items| items.iter().map(|p| p.as_str().map(str::to_string)).collect::<Option<Vec<_>>>())
+                    .ok_or_else(|| anyhow!("Rule #{} in {} has 'paths' that isn't an array of strings", i + 1, source))?;
+                rule.scope = Some(args::build_scope(&globs)?);
+            }
+            Ok(rule)
+        })
+        .collect()
+}
+
+/// Accept either a single string or an array of strings
+fn expect_strings(value: &Value, key: &str, source: &str) -> Result<Vec<String>> {
+    if let Some(s) = value.as_str() {
+        return Ok(vec![s.to_string()]);
+    }
+    value.as_array()
+        .and_then(|items| items.iter().map(|item| item.as_str().map(str::to_string)).collect())
+        .ok_or_else(|| anyhow!("'{}' must be a string or an array of strings in {}", key, source))
+}
+
+fn expect_bool(value: &Value, key: &str, source: &str) -> Result<bool> {
+    match value {
+        Value::Bool(b) => Ok(*b),
+        _ => Err(anyhow!("'{}' must be a boolean in {}", key, source)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args;
+    use crate::testdir::TestDir;
+
+    #[test]
+    fn test_config_hooks() {
+        let dir = TestDir::new("config-hooks");
+        let config = dir.join(CONFIG_FILE_NAME);
+        fs::write(&config, "pre_hooks = \"make setup\"\npost_hooks = [\"cargo fmt\"]\n").unwrap();
+
+        // A config given with --config runs its hooks, ahead of those of the command line
+        let config_arg = config.to_string_lossy().to_string();
+        let args = args::parse_from(["mane", "--config", &config_arg, "--post-hook", "make check", "-r", "a", "b", "x"]).unwrap();
+        assert_eq!(args.pre_hooks, ["make setup"]);
+        assert_eq!(args.post_hooks, ["cargo fmt", "make check"]);
+
+        // One that was found only runs them when allowed
+        let found = |allow_config_hooks| {
+            let mut args = args::Args { post_hooks: vec!["make check".to_string()], allow_config_hooks, ..Default::default() };
+            load_file(&mut args, &config).unwrap();
+            (args.pre_hooks, args.post_hooks)
+        };
+        assert_eq!(found(false), (vec![], vec!["make check".to_string()]));
+        assert_eq!(found(true), (vec!["make setup".to_string()], vec!["cargo fmt".to_string(), "make check".to_string()]));
+    }
+}
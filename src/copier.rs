@@ -1,9 +1,233 @@
+use crate::archive;
+use crate::binary;
 use crate::args::{Args, ReplacementRule};
+use crate::color;
+use crate::config;
+use crate::gzip;
+use crate::incremental;
+use crate::leftovers;
+use crate::logger::{self, Level};
+use crate::mmap;
+use crate::placeholder;
+use crate::pool;
+use crate::remote::Remote;
 use crate::replacer;
+use crate::sha256;
+use crate::sparse;
+use crate::structured::Quote;
+use crate::summary::{self, Event};
+use crate::template::{self, Vars};
+use crate::trash;
 use anyhow::{Result, Context, anyhow};
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::fs;
-use ignore::WalkBuilder;
+use std::sync::Mutex;
+use std::sync::atomic::Ordering;
+use std::io::{self, Write};
+use atty::Stream;
+use clap::ValueEnum;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+/// What to do when a copied directory's target directory already exists (--on-existing)
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq)]
+pub enum OnExisting {
+    /// Stop with an error
+    Fail,
+
+    /// Copy into it, overwriting files with the same names
+    #[default]
+    Merge,
+
+    /// Delete it first
+    Overwrite,
+
+    /// Move it aside to NAME.bak (or NAME.bak.N) first
+    Backup,
+}
+
+/// State shared by all copy operations of one run
+struct CopyContext<'a> {
+    /// Command line arguments
+    args: &'a Args,
+
+    /// Resolved template variables
+    vars: Vars,
+
+    /// Files copied without rendering template variables, relative to the copied directory
+    copy_without_render: GlobSet,
+
+    /// Fingerprints of the previous copy (--incremental)
+    cache: Option<Mutex<incremental::Cache>>,
+
+    /// Files written and what they should contain (--verify)
+    written: Option<Mutex<Vec<(PathBuf, Expected)>>>,
+
+    /// Target directories this run copied into, which later sources merge into
+    roots: Mutex<Vec<PathBuf>>,
+
+    /// Targets holding a subdirectory per source: those of several sources, and
+    /// those cookiecutter templates render into (staged paths when staging)
+    containers: Vec<PathBuf>,
+}
+
+/// One entry of a copied directory
+struct CopyStep {
+    source: PathBuf,
+    target: PathBuf,
+
+    /// Rules and whether to render template variables, for a file (None for a directory)
+    file: Option<(Vec<ReplacementRule>, bool)>,
+}
+
+/// What a copied file should contain, checked by --verify
+enum Expected {
+    /// The same bytes as the source file
+    Source(PathBuf),
+
+    /// Transformed content with this SHA-256 digest
+    Digest([u8; 32]),
+}
+
+impl CopyContext<'_> {
+    /// Whether a directory copied to an existing target goes into it
+    ///
+    /// Unless the target holds a subdirectory per source, --on-existing
+    /// `overwrite` and `backup` clear it away and the copy takes its place.
+    fn copies_into(&self, target: &Path) -> bool {
+        matches!(self.args.on_existing, OnExisting::Merge | OnExisting::Fail)
+            || self.containers.iter().any(|container| container == target)
+    }
+
+    /// Remember what a target file should contain, if it is verified afterwards
+    fn expect(&self, target: &Path, expected: Expected) {
+        if let Some(written) = &self.written {
+            written.lock().unwrap().push((target.to_path_buf(), expected));
+        }
+    }
+
+    /// Stop expecting files that were deleted after copying, and the contents of deleted directories
+    fn forget(&self, deleted: &[PathBuf]) {
+        if let Some(written) = &self.written {
+            written.lock().unwrap().retain(|(target, _)| !deleted.iter().any(|path| target.starts_with(path)));
+        }
+    }
+
+    /// Targets written with transformed content rather than copied unchanged
+    fn transformed(&self) -> Vec<PathBuf> {
+        self.written.as_ref().map_or_else(Vec::new, |written| {
+            written.lock().unwrap().iter()
+                .filter(|(_, expected)| matches!(expected, Expected::Digest(_)))
+                .map(|(target, _)| target.clone())
+                .collect()
+        })
+    }
+
+    /// Every target file written
+    fn written_files(&self) -> Vec<PathBuf> {
+        self.written.as_ref().map_or_else(Vec::new, |written| {
+            written.lock().unwrap().iter().map(|(target, _)| target.clone()).collect()
+        })
+    }
+
+    /// Re-read the copied files and check their content (--verify)
+    ///
+    /// # Returns
+    /// * `Result<()>` - Error if any file doesn't have the expected content
+    fn verify(&self) -> Result<()> {
+        let Some(written) = &self.written else {
+            return Ok(());
+        };
+
+        let written = written.lock().unwrap();
+        let mut failures = 0;
+        for (target, expected) in written.iter() {
+            let problem = match (fs::read(target), expected) {
+                (Err(e), _) => Some(format!("can't be read: {}", e)),
+                (Ok(content), Expected::Source(source)) => match fs::read(source) {
+                    Ok(original) if original == content => None,
+                    Ok(_) => Some(format!("differs from {}", source.display())),
+                    Err(e) => Some(format!("can't be compared with {}: {}", source.display(), e)),
+                },
+                (Ok(content), Expected::Digest(digest)) => {
+                    (sha256::digest(&content) != *digest).then(|| "doesn't match the transformed content".to_string())
+                },
+            };
+            if let Some(problem) = problem {
+                error!("Verification failed: {} {}", target.display(), problem);
+                failures += 1;
+            }
+        }
+
+        if failures > 0 {
+            return Err(anyhow!("{} of {} copied files failed verification", failures, written.len()));
+        }
+        info!("Verified {} copied files", written.len());
+        Ok(())
+    }
+
+    /// Everything besides the source content that decides how a file is copied
+    ///
+    /// # Arguments
+    /// * `rules` - Replacement rules for the file
+    /// * `render` - Whether template variables are filled in
+    ///
+    /// # Returns
+    /// * `String` - Settings to fingerprint the file with
+    fn fingerprint_settings(&self, rules: &[ReplacementRule], render: bool) -> String {
+        let args = self.args;
+        let rules: Vec<(&str, &str)> = rules.iter().map(|rule| (rule.from.as_str(), rule.to.as_str())).collect();
+        format!(
+            "{:?}",
+            (
+                rules,
+                render.then_some(&self.vars),
+                (args.case_enabled, args.rename_file, args.rename_dir, args.rename_match, args.exclude_extension, &args.no_rename_paths, args.decompress, args.archives),
+                (&args.structured, &args.json_path, &args.yaml_path, &args.markdown_scope, &args.csv_columns),
+                (args.syntax_aware, args.skip_strings, args.skip_comments),
+            ),
+        )
+    }
+
+    /// Replacement rules for one copied path
+    ///
+    /// Path-scoped rules are kept only if they apply to the path, and paths whose
+    /// name or content the rules match take the next `{{n}}` counter value.
+    ///
+    /// # Arguments
+    /// * `source` - Source path
+    /// * `scope_path` - Path matched against rule scopes (relative to the copied directory)
+    /// * `rules` - Rules of the copy the path belongs to
+    ///
+    /// # Returns
+    /// * `Vec<ReplacementRule>` - Rules with counter placeholders expanded
+    fn rules_for(&self, source: &Path, scope_path: &Path, rules: &[ReplacementRule]) -> Vec<ReplacementRule> {
+        let args = self.args;
+        let rules: Vec<ReplacementRule> = rules.iter()
+            .filter(|rule| rule.applies_to(Some(scope_path)))
+            .cloned()
+            .collect();
+        if !replacer::uses_counter(args) {
+            return rules;
+        }
+
+        let name = source.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        let matched = replacer::matches(&name, args, Some(scope_path))
+            || source.is_file()
+                && replacer::file_may_match(source, args).unwrap_or(true)
+                && gzip::read_text(source, args.decompress, args.detection())
+                    .is_ok_and(|(content, _)| replacer::matches(&content, args, Some(scope_path)));
+        if !matched {
+            return rules;
+        }
+
+        let n = placeholder::next_counter();
+        rules.into_iter()
+            .map(|rule| ReplacementRule { to: placeholder::expand_counter(&rule.to, n), ..rule })
+            .collect()
+    }
+}
 
 /// Copy files and directories with replacements
 ///
@@ -13,9 +237,224 @@ use ignore::WalkBuilder;
 /// # Returns
 /// * `Result<()>` - Ok if successful, Error otherwise
 pub fn copy_with_replacements(args: &Args) -> Result<()> {
+    if args.tree {
+        return print_tree(args);
+    }
+    if let Some(remote) = &args.remote_target {
+        return copy_to_remote(args, remote);
+    }
+    match &args.to_archive {
+        Some(output) => copy_to_archive(args, output),
+        None => copy_into(args, None).map(|_| ()),
+    }
+}
+
+/// Create a staging directory for a copy that isn't written to its targets
+fn create_staging(purpose: &str) -> Result<PathBuf> {
+    let nanos = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.subsec_nanos());
+    let staging = std::env::temp_dir().join(format!("mane-{}-{}-{}", purpose, std::process::id(), nanos));
+    fs::create_dir_all(&staging)
+        .context(format!("Failed to create staging directory: {}", staging.display()))?;
+    Ok(staging)
+}
+
+/// Copy into a staging directory and print the trees the targets would get (--tree)
+///
+/// # Arguments
+/// * `args` - Command line arguments
+///
+/// # Returns
+/// * `Result<()>` - Ok if successful, Error otherwise
+fn print_tree(args: &Args) -> Result<()> {
+    let staging = create_staging("tree")?;
+    let result = copy_into(args, Some(&staging)).and_then(|changed| {
+        let mut out = io::stdout().lock();
+        let mut printed = Vec::new();
+        for copy_spec in &args.copy_specs {
+            let root = staged_path(&staging, &copy_spec.target);
+            if printed.contains(&root) {
+                continue;
+            }
+            writeln!(out, "{}", copy_spec.target.display())?;
+            write_tree(&mut out, &root, "", &changed)?;
+            printed.push(root);
+        }
+        if !changed.is_empty() {
+            writeln!(out, "\n* content changed by the rules or template variables")?;
+        }
+        Ok(())
+    });
+    let _ = fs::remove_dir_all(&staging);
+    result
+}
+
+/// Print the entries of a staged directory as tree branches
+///
+/// # Arguments
+/// * `out` - Where to print
+/// * `dir` - Directory to list
+/// * `prefix` - Branch lines of the parent levels
+/// * `changed` - Staged files whose content was transformed
+///
+/// # Returns
+/// * `Result<()>` - Result of the operation
+fn write_tree(out: &mut impl Write, dir: &Path, prefix: &str, changed: &[PathBuf]) -> Result<()> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read directory: {}", dir.display()))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<_>>()?;
+    entries.sort();
+
+    for (i, path) in entries.iter().enumerate() {
+        let last = i + 1 == entries.len();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let marker = if changed.contains(path) { " *" } else { "" };
+        let link = fs::read_link(path).map(|to| format!(" -> {}", to.display())).unwrap_or_default();
+        let slash = if path.is_dir() && link.is_empty() { "/" } else { "" };
+        writeln!(out, "{}{}{}{}{}{}", prefix, if last { "└── " } else { "├── " }, name, slash, link, marker)?;
+        if !slash.is_empty() {
+            write_tree(out, path, &format!("{}{}", prefix, if last { "    " } else { "│   " }), changed)?;
+        }
+    }
+    Ok(())
+}
+
+/// Where a target is placed under a staging directory: its path from the archive
+/// or staging root, without any root or `..` components
+fn staged_path(staging: &Path, target: &Path) -> PathBuf {
+    target.components()
+        .filter(|c| matches!(c, std::path::Component::Normal(_)))
+        .fold(staging.to_path_buf(), |path, c| path.join(c))
+}
+
+/// Copy into a staging directory and pack it into an archive
+///
+/// # Arguments
+/// * `args` - Command line arguments
+/// * `output` - Archive to write, or `-` for a tar stream on stdout
+///
+/// # Returns
+/// * `Result<()>` - Ok if successful, Error otherwise
+fn copy_to_archive(args: &Args, output: &Path) -> Result<()> {
+    let to_stdout = output == Path::new("-");
+    let kind = if to_stdout {
+        archive::Kind::Tar
+    } else {
+        archive::Kind::detect(output)
+            .ok_or_else(|| anyhow!("Unknown archive format (use .tar, .tar.gz, .tgz or .zip): {}", output.display()))?
+    };
+
+    let staging = create_staging("archive")?;
+    let packed = copy_into(args, Some(&staging)).and_then(|_| archive::pack_dir(&staging, kind));
+    let _ = fs::remove_dir_all(&staging);
+    let packed = packed?;
+
+    if to_stdout {
+        std::io::stdout().write_all(&packed).context("Failed to write archive to stdout")?;
+    } else {
+        fs::write(output, packed).context(format!("Failed to write archive: {}", output.display()))?;
+        info!("Wrote {}", output.display());
+    }
+    Ok(())
+}
+
+/// Copy into a staging directory and unpack it into a directory on another machine
+///
+/// # Arguments
+/// * `args` - Command line arguments
+/// * `remote` - Host and directory to copy to
+///
+/// # Returns
+/// * `Result<()>` - Ok if successful, Error otherwise
+fn copy_to_remote(args: &Args, remote: &Remote) -> Result<()> {
+    let staging = create_staging("remote")?;
+    let packed = copy_into(args, Some(&staging)).and_then(|_| archive::pack_dir(&staging, archive::Kind::Tar));
+    let _ = fs::remove_dir_all(&staging);
+    remote.upload(&packed?)
+}
+
+/// Copy all copy specs
+///
+/// # Arguments
+/// * `args` - Command line arguments
+/// * `staging` - Directory that targets are placed under instead of the filesystem root
+///
+/// # Returns
+/// * `Result<Vec<PathBuf>>` - Targets whose content was transformed (tracked with --verify or --tree)
+fn copy_into(args: &Args, staging: Option<&Path>) -> Result<Vec<PathBuf>> {
+    // Resolve template variables declared by the sources (prompting if needed) before copying anything
+    let mut manifest = template::Manifest::default();
+    let mut cookiecutter_sources = Vec::new();
+    let mut no_render = GlobSetBuilder::new();
+    let mut actions: HashMap<PathBuf, Vec<template::Action>> = HashMap::new();
+    let mut conditions: HashMap<PathBuf, Vec<(String, String)>> = HashMap::new();
+    for copy_spec in &args.copy_specs {
+        if let Some(source_manifest) = template::load_manifest(&copy_spec.source)? {
+            if source_manifest.cookiecutter {
+                cookiecutter_sources.push(copy_spec.source.clone());
+            }
+            for pattern in &source_manifest.copy_without_render {
+                no_render.add(Glob::new(pattern)
+                    .with_context(|| format!("Invalid pattern in _copy_without_render: {}", pattern))?);
+            }
+            manifest.variables.extend(source_manifest.variables);
+            actions.insert(copy_spec.source.clone(), source_manifest.actions);
+            conditions.insert(copy_spec.source.clone(), source_manifest.conditions);
+        }
+    }
+    let ctx = CopyContext {
+        args,
+        vars: template::resolve_variables(&manifest, &args.vars)?,
+        copy_without_render: no_render.build()?,
+        cache: match staging {
+            None if args.incremental => Some(Mutex::new(incremental::Cache::load(&cache_dir(args))?)),
+            _ => None,
+        },
+        written: (args.verify || args.tree || args.check_unreplaced).then(|| Mutex::new(Vec::new())),
+        roots: Mutex::new(Vec::new()),
+        containers: container_targets(args, staging, &cookiecutter_sources),
+    };
+
+    // --expect and its bounds stop a copy before anything is written; kept
+    // files of an --incremental copy aren't replaced in, so it is only checked at the end
+    let expects = args.expect.is_some() || args.expect_min.is_some() || args.expect_max.is_some();
+    if expects && !args.incremental {
+        // The pre-count takes {{n}} values too, which the copy takes again from the start
+        let before = replacer::GLOBAL_REPLACEMENT_COUNT.load(Ordering::Relaxed);
+        let counter = placeholder::GLOBAL_COUNTER.load(Ordering::Relaxed);
+        let counted = count_copies(args, staging, &cookiecutter_sources, &conditions, &ctx);
+        let checked = counted.and_then(|_| replacer::check_expected_count(args));
+        replacer::GLOBAL_REPLACEMENT_COUNT.store(before, Ordering::Relaxed);
+        placeholder::GLOBAL_COUNTER.store(counter, Ordering::Relaxed);
+        checked?;
+    }
+
     for copy_spec in &args.copy_specs {
-        let source = &copy_spec.source;
-        let target = &copy_spec.target;
+        let rules = copy_spec.rules(&args.rules);
+
+        // Inside an archive, the target is a relative path from the archive root
+        let target = &match staging {
+            Some(staging) => staged_path(staging, &copy_spec.target),
+            None => copy_spec.target.clone(),
+        };
+
+        // A preview copies into the target's subdirectory when the real copy would
+        if args.tree && copy_spec.target.is_dir() && ctx.copies_into(target) {
+            fs::create_dir_all(target)
+                .context(format!("Failed to create staging directory: {}", target.display()))?;
+        }
+
+        // A cookiecutter template renders its project directory into the target directory
+        let source = &if cookiecutter_sources.contains(&copy_spec.source) {
+            fs::create_dir_all(target)
+                .context(format!("Failed to create target directory: {}", target.display()))?;
+            template::cookiecutter_project_dir(&copy_spec.source)?
+        } else {
+            copy_spec.source.clone()
+        };
 
         // Check if source exists
         if !source.exists() {
@@ -24,21 +463,245 @@ pub fn copy_with_replacements(args: &Args) -> Result<()> {
 
         // Check for invalid combinations - source directory to target file
         if source.is_dir() && target.exists() && target.is_file() {
-            eprintln!("Error: Cannot copy directory {} to file {}", source.display(), target.display());
+            error!("Cannot copy directory {} to file {}", source.display(), target.display());
             continue; // Skip this copy spec but continue with others
         }
 
         if source.is_file() {
             // Copy single file
-            copy_file(source, target, args)?;
+            let file_name = Path::new(source.file_name().unwrap_or_default());
+            if let Some(actual_target) = copy_file(source, target, &ctx, &ctx.rules_for(source, file_name, &rules), true)? {
+                log_copy(source, &actual_target);
+                summary::record(Event::Copied);
+            }
         } else if source.is_dir() {
-            // Copy directory
-            copy_directory(source, target, args)?;
+            // Copy directory, then run the actions its manifest declares on the result
+            let excluded = template::excluded_paths(conditions.get(&copy_spec.source).map_or(&[], Vec::as_slice), &ctx.vars)?;
+            let root = copy_directory(source, target, &excluded, &rules, &ctx)?;
+            if let Some(actions) = actions.get(&copy_spec.source) {
+                let deleted = template::run_actions(actions, &root, &ctx.vars)?;
+                ctx.forget(&deleted);
+            }
         } else {
             return Err(anyhow!("Unsupported source type: {}", source.display()));
         }
     }
 
+    if let Some(cache) = &ctx.cache {
+        cache.lock().unwrap().save()?;
+    }
+    if args.verify {
+        ctx.verify()?;
+    }
+    if args.check_unreplaced {
+        let rules: Vec<ReplacementRule> = args.copy_specs.iter().flat_map(|spec| spec.rules(&args.rules)).collect();
+        leftovers::check(&ctx.written_files(), &ctx.roots.lock().unwrap(), &rules, args.case_enabled);
+    }
+    Ok(ctx.transformed())
+}
+
+/// Targets that hold a subdirectory per source, as `CopyContext::containers` lists them
+///
+/// # Arguments
+/// * `args` - Command line arguments
+/// * `staging` - Directory that targets are placed under instead of the filesystem root
+/// * `cookiecutter_sources` - Sources that are cookiecutter templates
+///
+/// # Returns
+/// * `Vec<PathBuf>` - The targets, staged if `staging` is given
+fn container_targets(args: &Args, staging: Option<&Path>, cookiecutter_sources: &[PathBuf]) -> Vec<PathBuf> {
+    args.copy_specs.iter()
+        .filter(|spec| cookiecutter_sources.contains(&spec.source)
+            || args.copy_specs.iter().filter(|other| other.target == spec.target).count() > 1)
+        .map(|spec| match staging {
+            Some(staging) => staged_path(staging, &spec.target),
+            None => spec.target.clone(),
+        })
+        .collect()
+}
+
+/// Count the replacements of every copy spec, as `copy_into` would make them
+///
+/// # Arguments
+/// * `args` - Command line arguments
+/// * `staging` - Directory that targets are placed under instead of the filesystem root
+/// * `cookiecutter_sources` - Sources that are cookiecutter templates
+/// * `conditions` - The [conditions] of each source's manifest
+/// * `ctx` - Copy context
+///
+/// # Returns
+/// * `Result<()>` - Error if a source can't be read
+fn count_copies(args: &Args, staging: Option<&Path>, cookiecutter_sources: &[PathBuf], conditions: &HashMap<PathBuf, Vec<(String, String)>>, ctx: &CopyContext) -> Result<()> {
+    // Targets the copies before create, which later copies into the same target go into
+    let mut created: Vec<PathBuf> = Vec::new();
+    for copy_spec in &args.copy_specs {
+        let rules = copy_spec.rules(&args.rules);
+        let target = match staging {
+            Some(staging) => staged_path(staging, &copy_spec.target),
+            None => copy_spec.target.clone(),
+        };
+        let cookiecutter = cookiecutter_sources.contains(&copy_spec.source);
+        let source = if cookiecutter { template::cookiecutter_project_dir(&copy_spec.source)? } else { copy_spec.source.clone() };
+        if !source.exists() {
+            continue;
+        }
+        let into = cookiecutter || created.contains(&target)
+            || (target.is_dir() && ctx.copies_into(&target))
+            || (staging.is_some() && args.tree && copy_spec.target.is_dir() && ctx.copies_into(&target));
+        let excluded = template::excluded_paths(conditions.get(&copy_spec.source).map_or(&[], Vec::as_slice), &ctx.vars)?;
+        count_replacements(&source, &target, into, &excluded, &rules, ctx)?;
+        created.push(target);
+    }
+    Ok(())
+}
+
+/// Directory the --incremental cache is kept in: the target directory, or the
+/// directory of a target file
+fn cache_dir(args: &Args) -> PathBuf {
+    let Some(target) = args.copy_specs.last().map(|spec| &spec.target) else {
+        return PathBuf::from(".");
+    };
+    if target.is_dir() || args.copy_specs.iter().any(|spec| spec.source.is_dir()) {
+        return target.clone();
+    }
+    match target.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// The `.gitignore` at the root of a copied directory (--ignore-nested=false)
+///
+/// # Arguments
+/// * `source_dir` - Copied directory
+///
+/// # Returns
+/// * `Gitignore` - Its patterns, or none if it has no readable `.gitignore`
+fn root_gitignore(source_dir: &Path) -> Gitignore {
+    let mut builder = GitignoreBuilder::new(source_dir);
+    if let Some(err) = builder.add(source_dir.join(".gitignore")) {
+        if source_dir.join(".gitignore").exists() {
+            verbose!("Warning: {}", err);
+        }
+    }
+    builder.build().unwrap_or_else(|_| Gitignore::empty())
+}
+
+/// Directory a copied directory goes to
+///
+/// Like cp, a directory copied into an existing one goes to a subdirectory
+/// named after it, with the rules applied to the name.
+///
+/// # Arguments
+/// * `source_dir` - Directory to copy
+/// * `target_dir` - Target given for it
+/// * `into` - Whether the target is an existing directory to copy into
+/// * `rules` - Rules of the copy
+/// * `ctx` - Copy context
+///
+/// # Returns
+/// * `Result<PathBuf>` - Where the copy of the directory goes
+fn target_root(source_dir: &Path, target_dir: &Path, into: bool, rules: &[ReplacementRule], ctx: &CopyContext) -> Result<PathBuf> {
+    let args = ctx.args;
+    if !into {
+        return Ok(target_dir.to_path_buf());
+    }
+    let source_dir_name = source_dir.file_name().ok_or_else(||
+        anyhow!("Failed to get source directory name: {}", source_dir.display()))?;
+    let dir_name_str = template::render(&source_dir_name.to_string_lossy(), &ctx.vars);
+
+    // Apply replacements to the directory name if required
+    if args.rename_dir && !args.keeps_name(Path::new(source_dir_name)) {
+        let pairs: Vec<(&str, String)> = rules.iter()
+            .filter(|rule| rule.applies_to(Some(Path::new(source_dir_name))))
+            .map(|rule| (rule.from.as_str(), rule.to.clone()))
+            .collect();
+        Ok(target_dir.join(replacer::apply_name_replacements(&dir_name_str, &pairs, args)))
+    } else {
+        Ok(target_dir.join(dir_name_str))
+    }
+}
+
+/// Walk a copied directory, respecting git ignores unless specified otherwise
+///
+/// The walk is sorted unless --unsorted, so {{n}} counters are assigned in a
+/// stable order. Templates are often copied from outside a git checkout, so
+/// their .gitignore files are honored either way; --ignore-nested=false keeps
+/// only the root one, which is returned to be matched by hand.
+///
+/// # Arguments
+/// * `source_dir` - Directory to walk
+/// * `args` - Command line arguments
+///
+/// # Returns
+/// * `(Walk, Option<Gitignore>)` - The walk, and the root .gitignore with --ignore-nested=false
+fn source_walk(source_dir: &Path, args: &Args) -> (ignore::Walk, Option<Gitignore>) {
+    let mut builder = args.walk_builder(source_dir);
+    builder.require_git(false);
+    let root_ignore = (!args.ignore_nested && !args.include_git_ignore).then(|| root_gitignore(source_dir));
+    if root_ignore.is_some() {
+        builder.git_ignore(false);
+    }
+    (builder.build(), root_ignore)
+}
+
+/// Whether a walked path is never copied: the source directory itself, the
+/// template manifest, config files and what the root .gitignore ignores
+fn is_left_out(source_path: &Path, source_dir: &Path, root_ignore: Option<&Gitignore>, args: &Args) -> bool {
+    source_path == source_dir
+        || template::is_manifest(source_path, source_dir)
+        || config::is_config_file(source_path, args)
+        || root_ignore.is_some_and(|ignore| ignore.matched_path_or_any_parents(source_path, source_path.is_dir()).is_ignore())
+}
+
+/// Count the replacements a copy makes in names and contents, without writing anything
+///
+/// This lets --expect, --expect-min and --expect-max stop a copy before it
+/// touches the target. The count is added to the run's replacement count.
+///
+/// # Arguments
+/// * `source` - File or directory to copy
+/// * `target` - Target given for it
+/// * `into` - Whether the target is a directory the source is copied into
+/// * `excluded` - Source paths not to copy, relative to a source directory
+/// * `rules` - Rules of the copy
+/// * `ctx` - Copy context
+///
+/// # Returns
+/// * `Result<()>` - Error if a file can't be read
+fn count_replacements(source: &Path, target: &Path, into: bool, excluded: &GlobSet, rules: &[ReplacementRule], ctx: &CopyContext) -> Result<()> {
+    let args = ctx.args;
+    let count_file = |path: &Path, rules: &[ReplacementRule], render: bool| -> Result<()> {
+        if let Some(kind) = archive::Kind::detect(path).filter(|_| args.archives) {
+            let data = fs::read(path).context(format!("Failed to read source file: {}", path.display()))?;
+            archive::transform(
+                &data,
+                kind,
+                |member, content| {
+                    let rendered = if render { template::render(content, &ctx.vars) } else { content.to_string() };
+                    replace_file_content(&rendered, Path::new(member), rules, args)
+                },
+                |name, is_dir| Ok(transform_name(name, is_dir, rules, false, args, &ctx.vars)),
+            ).with_context(|| format!("Failed to process archive: {}", path.display()))?;
+        } else if let Some(Ok((content, _))) = (!sparse::has_holes(path)).then(|| gzip::read_text(path, args.decompress, args.detection())) {
+            let rendered = if render { template::render(&content, &ctx.vars) } else { content };
+            replace_file_content(&rendered, path, rules, args)?;
+        }
+        Ok(())
+    };
+
+    if source.is_file() {
+        let file_name = Path::new(source.file_name().unwrap_or_default());
+        return count_file(source, &ctx.rules_for(source, file_name, rules), true);
+    }
+
+    // The same steps the copy runs, so names and {{n}} values come out the same
+    let root = target_root(source, target, into, rules, ctx)?;
+    for step in plan_steps(source, &root, excluded, rules, false, ctx)? {
+        if let Some((rules, render)) = &step.file {
+            count_file(&step.source, rules, *render)?;
+        }
+    }
     Ok(())
 }
 
@@ -47,22 +710,36 @@ pub fn copy_with_replacements(args: &Args) -> Result<()> {
 /// # Arguments
 /// * `source` - Source file path
 /// * `target` - Target file path
-/// * `args` - Command line arguments
+/// * `ctx` - Copy context
+/// * `rules` - Replacement rules for this file
+/// * `render` - Whether to fill in template variables in the content
 ///
 /// # Returns
-/// * `Result<()>` - Ok if successful, Error otherwise
-fn copy_file(source: &Path, target: &Path, args: &Args) -> Result<()> {
+/// * `Result<Option<PathBuf>>` - The file written, or None if it was up to date
+fn copy_file(source: &Path, target: &Path, ctx: &CopyContext, rules: &[ReplacementRule], render: bool) -> Result<Option<PathBuf>> {
+    let args = ctx.args;
+
     // Handle target path
     let actual_target = if target.is_dir() {
         // If target is a directory, the file will be copied into that directory
         // with the same name as the source file
         let file_name = source.file_name().ok_or_else(||
             anyhow!("Failed to get source file name: {}", source.display()))?;
-        target.join(file_name)
+        target.join(template::render(&file_name.to_string_lossy(), &ctx.vars))
     } else {
         target.to_path_buf()
     };
 
+    // With --incremental, a target made from the same source with the same rules is kept
+    if let Some(cache) = &ctx.cache {
+        let fingerprint = incremental::fingerprint(source, &ctx.fingerprint_settings(rules, render))
+            .context(format!("Failed to read source file: {}", source.display()))?;
+        if cache.lock().unwrap().check(&actual_target, fingerprint) {
+            debug!("Up to date: {}", actual_target.display());
+            return Ok(None);
+        }
+    }
+
     // Create target directory if it doesn't exist
     if let Some(parent) = actual_target.parent() {
         fs::create_dir_all(parent).context("Failed to create target directory")?;
@@ -71,106 +748,341 @@ fn copy_file(source: &Path, target: &Path, args: &Args) -> Result<()> {
     // Always override existing files (cp -r standard behavior)
     // We won't show a special message for overriding - it will be shown in the standard output format
 
-    // Check if the source is readable as text
-    match fs::read_to_string(source) {
-        Ok(content) => {
-            // Apply replacements to content
-            let replaced_content = apply_all_replacements(&content, &args.rules);
+    // With --trash, the file being overwritten goes to the trash first
+    if args.trash && actual_target.symlink_metadata().is_ok_and(|metadata| !metadata.is_dir()) {
+        trash::move_to_trash(&actual_target)?;
+        verbose!("Moved existing {} to the trash", actual_target.display());
+    }
+
+    // A target hard-linked by an earlier --link-unchanged run is replaced, not written through
+    unlink_shared(&actual_target)
+        .context(format!("Failed to replace target file: {}", actual_target.display()))?;
+
+    // With --archives, members of zip/tar archives are replaced and the archive repacked
+    if let Some(kind) = archive::Kind::detect(source).filter(|_| args.archives) {
+        let data = fs::read(source)
+            .context(format!("Failed to read source file: {}", source.display()))?;
+        let repacked = archive::transform(
+            &data,
+            kind,
+            |member, content| {
+                let rendered = if render { template::render(content, &ctx.vars) } else { content.to_string() };
+                replace_file_content(&rendered, Path::new(member), rules, args)
+            },
+            |name, is_dir| Ok(transform_name(name, is_dir, rules, false, args, &ctx.vars)),
+        ).with_context(|| format!("Failed to process archive: {}", source.display()))?;
+
+        match repacked {
+            Some(repacked) => {
+                ctx.expect(&actual_target, Expected::Digest(sha256::digest(&repacked)));
+                fs::write(&actual_target, repacked)
+            },
+            None => {
+                ctx.expect(&actual_target, Expected::Source(source.to_path_buf()));
+                copy_unchanged(source, &actual_target, args.link_unchanged)
+            },
+        }.context(format!("Failed to write target file: {}", actual_target.display()))?;
+        if args.preserve_owner {
+            preserve_owner(source, &actual_target)?;
+        }
+        return Ok(Some(actual_target));
+    }
+
+    // Check if the source is readable as text; sparse files (disk images) are
+    // binary, and aren't read into memory to find out
+    let text = if sparse::has_holes(source) { None } else { Some(gzip::read_text(source, args.decompress, args.detection())) };
+    match text {
+        Some(Ok((content, compressed))) => {
+            // Fill in template variables, then apply replacements to content
+            let rendered = if render { Some(template::render(&content, &ctx.vars)) } else { None };
+            let replaced_content = replace_file_content(rendered.as_deref().unwrap_or(&content), source, rules, args)?;
+            replacer::check_idempotent_rules(&replaced_content, args, rules, &format!("{:?}", actual_target))?;
 
-            // Write to target file
-            fs::write(&actual_target, replaced_content)
-                .context(format!("Failed to write target file: {}", actual_target.display()))?;
+            // Write to target file, cloning it when nothing changed
+            if replaced_content == content {
+                ctx.expect(&actual_target, Expected::Source(source.to_path_buf()));
+                copy_unchanged(source, &actual_target, args.link_unchanged)
+            } else {
+                let encoded = gzip::encode(&replaced_content, compressed);
+                ctx.expect(&actual_target, Expected::Digest(sha256::digest(&encoded)));
+                fs::write(&actual_target, encoded)
+            }.context(format!("Failed to write target file: {}", actual_target.display()))?;
         },
-        Err(_) => {
-            // If reading as text fails, copy the file as binary
-            let content = fs::read(source)
-                .context(format!("Failed to read source file: {}", source.display()))?;
-
-            // Write to target file
-            fs::write(&actual_target, content)
-                .context(format!("Failed to write target file: {}", actual_target.display()))?;
+        failed => {
+            // If reading as text fails, copy the file as binary, reporting it
+            // at the end if the rules or template variables would have changed it
+            if let Some(Err(e)) = &failed {
+                if binary::is_binary_error(e) && would_change(source, render, ctx) {
+                    binary::record_skipped(source, &e.to_string());
+                }
+            }
+            ctx.expect(&actual_target, Expected::Source(source.to_path_buf()));
+            copy_unchanged(source, &actual_target, args.link_unchanged)
+                .context(format!("Failed to copy {} to {}", source.display(), actual_target.display()))?;
         }
     }
 
-    // Print copy information only if verbose mode is enabled
-    if args.verbose {
-        println!("{} -> {}", source.display(), actual_target.display());
+    if args.preserve_owner {
+        preserve_owner(source, &actual_target)?;
+    }
+    Ok(Some(actual_target))
+}
+
+/// Check whether the bytes of a file hold a FROM string, or a placeholder when
+/// template variables are filled in, so copying it as binary leaves something unchanged
+fn would_change(source: &Path, render: bool, ctx: &CopyContext) -> bool {
+    let placeholders = render && !ctx.vars.is_empty()
+        && mmap::open(source).is_ok_and(|bytes| memchr::memmem::find(&bytes, b"{{").is_some());
+    placeholders || replacer::file_may_match(source, ctx.args).unwrap_or(false)
+}
+
+/// Give a copied file or directory the owner and group of its source (--preserve-owner)
+///
+/// As with `cp -a`, a user other than root who isn't allowed to give the
+/// target away keeps owning it, without an error.
+///
+/// # Arguments
+/// * `source` - Source path
+/// * `target` - Copied path
+///
+/// # Returns
+/// * `Result<()>` - Error if the owner can't be read or set
+#[cfg(unix)]
+fn preserve_owner(source: &Path, target: &Path) -> Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = source.metadata().context(format!("Failed to read the owner of {}", source.display()))?;
+    match std::os::unix::fs::chown(target, Some(metadata.uid()), Some(metadata.gid())) {
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied && unsafe { libc::geteuid() } != 0 => {
+            debug!("Can't give {} to {}:{}: {}", target.display(), metadata.uid(), metadata.gid(), e);
+            Ok(())
+        },
+        result => result.context(format!("Failed to set the owner of {}", target.display())),
     }
+}
+
+#[cfg(not(unix))]
+fn preserve_owner(_source: &Path, _target: &Path) -> Result<()> {
     Ok(())
 }
 
+/// Copy a file whose content doesn't change, hard-linking it with --link-unchanged
+///
+/// # Arguments
+/// * `source` - Source file path
+/// * `target` - Target file path
+/// * `link` - Whether to hard-link the target to the source
+///
+/// # Returns
+/// * `io::Result<()>` - Ok if successful, Error otherwise
+fn copy_unchanged(source: &Path, target: &Path, link: bool) -> io::Result<()> {
+    if link {
+        if target.symlink_metadata().is_ok() {
+            fs::remove_file(target)?;
+        }
+        match fs::hard_link(source, target) {
+            Ok(()) => return Ok(()),
+            // e.g. across filesystems
+            Err(e) => debug!("Can't hard-link {} to {}, copying instead: {}", target.display(), source.display(), e),
+        }
+    }
+    clone_file(source, target)
+}
+
+/// Remove a file that has other hard links, so writing the target doesn't change them
+#[cfg(unix)]
+fn unlink_shared(target: &Path) -> io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    match target.symlink_metadata() {
+        Ok(metadata) if metadata.is_file() && metadata.nlink() > 1 => fs::remove_file(target),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+fn unlink_shared(_target: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Clone a file
+///
+/// The copy shares its blocks with the source where the filesystem supports it
+/// (reflinks on Btrfs/XFS, clonefile on APFS). Otherwise sparse files are copied
+/// keeping their holes, and other files with `fs::copy`, which still copies in
+/// the kernel with `copy_file_range` on Linux.
+///
+/// # Arguments
+/// * `source` - Source file path
+/// * `target` - Target file path
+///
+/// # Returns
+/// * `io::Result<()>` - Ok if successful, Error otherwise
+fn clone_file(source: &Path, target: &Path) -> io::Result<()> {
+    if try_clone(source, target) {
+        return Ok(());
+    }
+    if sparse::has_holes(source) {
+        return sparse::copy(source, target);
+    }
+    fs::copy(source, target).map(|_| ())
+}
+
+#[cfg(target_os = "linux")]
+fn try_clone(source: &Path, target: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::io::AsRawFd;
+
+    let Ok(src) = fs::File::open(source) else { return false };
+    let Ok(metadata) = src.metadata() else { return false };
+    let Ok(dst) = fs::File::create(target) else { return false };
+
+    // SAFETY: both descriptors are valid for the lifetime of the files
+    let cloned = unsafe { libc::ioctl(dst.as_raw_fd(), libc::FICLONE, src.as_raw_fd()) == 0 };
+    cloned && fs::set_permissions(target, fs::Permissions::from_mode(metadata.permissions().mode())).is_ok()
+}
+
+#[cfg(target_os = "macos")]
+fn try_clone(source: &Path, target: &Path) -> bool {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let (Ok(src), Ok(dst)) = (CString::new(source.as_os_str().as_bytes()), CString::new(target.as_os_str().as_bytes())) else {
+        return false;
+    };
+    // clonefile refuses to replace an existing file
+    if target.exists() && fs::remove_file(target).is_err() {
+        return false;
+    }
+
+    // SAFETY: both paths are valid NUL-terminated strings
+    unsafe { libc::clonefile(src.as_ptr(), dst.as_ptr(), 0) == 0 }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn try_clone(_source: &Path, _target: &Path) -> bool {
+    false
+}
+
 /// Copy a directory recursively with replacements
 ///
 /// # Arguments
 /// * `source_dir` - Source directory path
 /// * `target_dir` - Target directory path
-/// * `args` - Command line arguments
+/// * `excluded` - Source paths not to copy, relative to the source directory
+/// * `rules` - Rules of the copy
+/// * `ctx` - Copy context
 ///
 /// # Returns
-/// * `Result<()>` - Ok if successful, Error otherwise
-fn copy_directory(source_dir: &Path, target_dir: &Path, args: &Args) -> Result<()> {
-    // Determine the actual target directory
-    let actual_target_dir = if target_dir.exists() && target_dir.is_dir() {
-        // Get the source directory name
-        let source_dir_name = source_dir.file_name().ok_or_else(||
-            anyhow!("Failed to get source directory name: {}", source_dir.display()))?;
-
-        // If target is a directory, create a subdirectory with the source dir name
-        let target_with_source_name = target_dir.join(source_dir_name);
-
-        // Apply replacements to the directory name if required
-        if args.rename_dir {
-            let dir_name_str = source_dir_name.to_string_lossy().to_string();
-            let mut transformed_name = dir_name_str.clone();
-
-            for rule in &args.rules {
-                transformed_name = replacer::apply_replacement(&transformed_name, &rule.from, &rule.to, true);
-            }
+/// * `Result<PathBuf>` - The directory the tree was copied to
+fn copy_directory(source_dir: &Path, target_dir: &Path, excluded: &GlobSet, rules: &[ReplacementRule], ctx: &CopyContext) -> Result<PathBuf> {
+    let args = ctx.args;
 
-            target_dir.join(transformed_name)
-        } else {
-            target_with_source_name
-        }
-    } else {
-        target_dir.to_path_buf()
+    // An existing target (other than the last --incremental copy or one this run made) is
+    // handled by --on-existing: the target given, before the copy goes into a subdirectory
+    // of it, or the source's own subdirectory when the target holds one per source
+    let previous_root = ctx.cache.as_ref().and_then(|cache| cache.lock().unwrap().root(source_dir));
+    let needs_way = |dir: &Path| previous_root.is_none() && dir.is_dir() && !ctx.roots.lock().unwrap().iter().any(|root| root == dir);
+    let container = ctx.containers.iter().any(|container| container == target_dir);
+    if !container && needs_way(target_dir) {
+        make_way(target_dir, args.on_existing, args.trash)?;
+    }
+
+    // Determine the actual target directory (where the last --incremental copy went, if any)
+    let actual_target_dir = match previous_root.clone() {
+        Some(root) => root,
+        None => target_root(source_dir, target_dir, target_dir.is_dir(), rules, ctx)?,
     };
+    if container && needs_way(&actual_target_dir) {
+        make_way(&actual_target_dir, args.on_existing, args.trash)?;
+    }
+    ctx.roots.lock().unwrap().push(actual_target_dir.clone());
 
     // Create target directory if it doesn't exist
     fs::create_dir_all(&actual_target_dir).context("Failed to create target directory")?;
+    if args.preserve_owner {
+        preserve_owner(source_dir, &actual_target_dir)?;
+    }
+    if let Some(cache) = &ctx.cache {
+        cache.lock().unwrap().set_root(source_dir, &actual_target_dir);
+    }
 
     // Print verbose info for the root directory
-    if args.verbose {
-        println!("{} -> {}", source_dir.display(), actual_target_dir.display());
+    log_copy(source_dir, &actual_target_dir);
+
+    let steps = plan_steps(source_dir, &actual_target_dir, excluded, rules, true, ctx)?;
+
+    // A strict copy stops on walk errors before anything under the target is written
+    logger::check_strict()?;
+
+    for step in steps.iter().filter(|step| step.file.is_none()) {
+        fs::create_dir_all(&step.target)
+            .context(format!("Failed to create directory: {}", step.target.display()))?;
+        if args.preserve_owner {
+            preserve_owner(&step.source, &step.target)?;
+        }
     }
 
-    // Build a Walk iterator that respects .gitignore unless specified otherwise
-    let walker = if args.include_git_ignore {
-        // Include all files, even those that match .gitignore patterns
-        WalkBuilder::new(source_dir)
-            .git_ignore(false)  // Ignore .gitignore rules
-            .build()
-    } else {
-        // Respect .gitignore rules
-        WalkBuilder::new(source_dir)
-            .git_ignore(true)   // Respect .gitignore rules
-            .build()
-    };
+    let budget = args.budget();
+    let results = pool::map(args.jobs, &steps, |step| match &step.file {
+        Some((rules, render)) => {
+            let _share = budget.as_ref().map(|budget| budget.take(pool::footprint(&step.source)));
+            copy_file(&step.source, &step.target, ctx, rules, *render)
+        },
+        None => Ok(Some(step.target.clone())),
+    });
+
+    // Report in walk order, stopping at the first failure
+    for (step, result) in steps.iter().zip(results) {
+        if let Some(target) = result? {
+            log_copy(&step.source, &target);
+            if step.file.is_some() {
+                summary::record(Event::Copied);
+            }
+        }
+    }
+
+    Ok(actual_target_dir)
+}
+
+/// Work out where every entry of a copied directory goes, without writing anything
+///
+/// Parents are walked first, so the target of each entry is under the renamed
+/// target of its parent. Paths whose [conditions] don't hold are left out with
+/// everything under them.
+///
+/// # Arguments
+/// * `source_dir` - Source directory path
+/// * `target_dir` - Directory the tree is copied to
+/// * `excluded` - Source paths not to copy, relative to the source directory
+/// * `rules` - Rules of the copy
+/// * `report_errors` - Whether walk errors are reported (and fail a strict copy)
+/// * `ctx` - Copy context
+///
+/// # Returns
+/// * `Result<Vec<CopyStep>>` - The entries in walk order
+fn plan_steps(source_dir: &Path, target_dir: &Path, excluded: &GlobSet, rules: &[ReplacementRule], report_errors: bool, ctx: &CopyContext) -> Result<Vec<CopyStep>> {
+    let args = ctx.args;
+    let (walker, root_ignore) = source_walk(source_dir, args);
+
+    // Target path of every directory planned so far, relative to the target directory
+    let mut target_dirs: HashMap<PathBuf, PathBuf> = HashMap::new();
+    let mut excluded_dirs: Vec<PathBuf> = Vec::new();
+    let mut steps: Vec<CopyStep> = Vec::new();
 
     for result in walker {
         let entry = match result {
             Ok(entry) => entry,
             Err(err) => {
-                if args.verbose {
-                    eprintln!("Warning: {}", err);
+                if report_errors {
+                    args.walk_error(err)?;
                 }
                 continue;
             }
         };
 
         let source_path = entry.path();
-
-        // Skip the source directory itself
-        if source_path == source_dir {
+        if is_left_out(source_path, source_dir, root_ignore.as_ref(), args) {
             continue;
         }
 
@@ -178,30 +1090,89 @@ fn copy_directory(source_dir: &Path, target_dir: &Path, args: &Args) -> Result<(
         let relative_path = source_path.strip_prefix(source_dir)
             .context(format!("Failed to strip prefix from {}", source_path.display()))?;
 
-        // Apply replacements to each path component if required
-        let replaced_relative_path = if args.rename_file || args.rename_dir {
-            transform_path(relative_path, &args.rules, args.rename_file, args.rename_dir)?
-        } else {
-            relative_path.to_path_buf()
-        };
+        if excluded_dirs.iter().any(|dir| relative_path.starts_with(dir)) {
+            continue;
+        }
+        if excluded.is_match(relative_path) {
+            if report_errors {
+                verbose!("Skipping {} (its condition doesn't hold)", source_path.display());
+            }
+            if source_path.is_dir() {
+                excluded_dirs.push(relative_path.to_path_buf());
+            }
+            continue;
+        }
+
+        let parent = relative_path.parent().unwrap_or(Path::new(""));
+        let target_parent = target_dirs.get(parent).cloned().unwrap_or_else(|| parent.to_path_buf());
 
-        // Combine with target directory
-        let target_path = actual_target_dir.join(&replaced_relative_path);
+        // Apply template variables and replacements to the entry's own name
+        let rules = ctx.rules_for(source_path, relative_path, rules);
+        let file_name = relative_path.file_name().unwrap_or_default().to_string_lossy();
+        let replaced_relative_path = target_parent.join(transform_name(
+            &file_name, source_path.is_dir(), &rules, args.keeps_name(relative_path), args, &ctx.vars));
+        let target_path = target_dir.join(&replaced_relative_path);
 
         if source_path.is_file() {
-            copy_file(source_path, &target_path, args)?;
+            let render = !ctx.copy_without_render.is_match(relative_path);
+            steps.push(CopyStep { source: source_path.to_path_buf(), target: target_path, file: Some((rules, render)) });
         } else if source_path.is_dir() {
-            // Always create the directory (or ensure it exists)
-            fs::create_dir_all(&target_path)
-                .context(format!("Failed to create directory: {}", target_path.display()))?;
-
-            if args.verbose {
-                println!("{} -> {}", source_path.display(), target_path.display());
-            }
+            target_dirs.insert(relative_path.to_path_buf(), replaced_relative_path);
+            steps.push(CopyStep { source: source_path.to_path_buf(), target: target_path, file: None });
         }
     }
+    Ok(steps)
+}
 
-    Ok(())
+/// Deal with an existing target directory before copying into it (--on-existing)
+///
+/// # Arguments
+/// * `target` - The existing directory
+/// * `policy` - What to do with it
+/// * `trash` - Whether `overwrite` moves it to the trash instead of deleting it (--trash)
+///
+/// # Returns
+/// * `Result<()>` - Error with `fail`, or if it can't be moved or deleted
+fn make_way(target: &Path, policy: OnExisting, trash: bool) -> Result<()> {
+    match policy {
+        OnExisting::Merge => Ok(()),
+        OnExisting::Fail => Err(anyhow!(
+            "Target directory already exists: {} (use --on-existing merge, overwrite or backup)", target.display())),
+        OnExisting::Overwrite if trash => {
+            trash::move_to_trash(target)?;
+            info!("Moved existing {} to the trash", target.display());
+            Ok(())
+        },
+        OnExisting::Overwrite => {
+            fs::remove_dir_all(target).context(format!("Failed to delete existing directory: {}", target.display()))?;
+            info!("Deleted existing {}", target.display());
+            Ok(())
+        },
+        OnExisting::Backup => {
+            let name = target.file_name().unwrap_or_default().to_string_lossy().to_string();
+            let backup = (0..)
+                .map(|n| target.with_file_name(if n == 0 { format!("{}.bak", name) } else { format!("{}.bak.{}", name, n) }))
+                .find(|path| path.symlink_metadata().is_err())
+                .unwrap();
+            fs::rename(target, &backup)
+                .context(format!("Failed to move {} to {}", target.display(), backup.display()))?;
+            info!("Moved existing {} to {}", target.display(), backup.display());
+            Ok(())
+        },
+    }
+}
+
+/// Print a verbose "source -> target" line, highlighting the renamed part
+///
+/// # Arguments
+/// * `source` - Source path
+/// * `target` - Target path
+fn log_copy(source: &Path, target: &Path) {
+    if logger::enabled(Level::Verbose) {
+        let (source_display, target_display) = color::highlight_change(
+            &source.display().to_string(), &target.display().to_string(), Stream::Stderr);
+        eprintln!("{} -> {}", source_display, target_display);
+    }
 }
 
 /// Apply all replacement rules to a string
@@ -209,57 +1180,140 @@ fn copy_directory(source_dir: &Path, target_dir: &Path, args: &Args) -> Result<(
 /// # Arguments
 /// * `content` - String to apply replacements to
 /// * `rules` - Replacement rules to apply
+/// * `case_enabled` - Whether to replace case variants as well
 ///
 /// # Returns
 /// * `String` - String with replacements applied
-fn apply_all_replacements(content: &str, rules: &[ReplacementRule]) -> String {
-    let mut result = content.to_string();
-
-    for rule in rules {
-        // Use replacer::apply_replacement instead of replace_content
-        result = replacer::apply_replacement(&result, &rule.from, &rule.to, true);
-    }
+fn apply_all_replacements(content: &str, rules: &[ReplacementRule], case_enabled: bool) -> String {
+    let pairs: Vec<(&str, String)> = rules.iter().map(|rule| (rule.from.as_str(), rule.to.clone())).collect();
+    replacer::apply_replacements(content, &pairs, case_enabled)
+}
 
-    result
+/// Apply the rules to the content of a copied file
+///
+/// Only the parts selected by --structured, --json-path/--yaml-path,
+/// --markdown-scope, --csv-columns, --syntax-aware or --skip-strings/--skip-comments
+/// are replaced.
+///
+/// # Arguments
+/// * `content` - Content to apply replacements to
+/// * `source` - Source file path (its extension selects the format)
+/// * `rules` - Replacement rules for this file
+/// * `args` - Command line arguments
+///
+/// # Returns
+/// * `Result<String>` - Content with replacements applied
+fn replace_file_content(content: &str, source: &Path, rules: &[ReplacementRule], args: &Args) -> Result<String> {
+    replacer::replace_selected(content, Some(source), args, |piece, quote| {
+        if quote == Quote::None {
+            return Ok(apply_all_replacements(piece, rules, args.case_enabled));
+        }
+        let escaped: Vec<ReplacementRule> = rules.iter()
+            .map(|rule| ReplacementRule { to: quote.escape(&rule.to), ..rule.clone() })
+            .collect();
+        Ok(apply_all_replacements(piece, &escaped, args.case_enabled))
+    })
 }
 
-/// Transform a path by applying replacements to each component
+/// Transform a file or directory name by applying template variables and replacements
 ///
 /// # Arguments
-/// * `path` - Path to transform
+/// * `name` - Name to transform
+/// * `is_dir` - Whether the name is a directory name
 /// * `rules` - Replacement rules to apply
-/// * `rename_file` - Whether to rename files
-/// * `rename_dir` - Whether to rename directories
+/// * `keep_name` - Whether the path keeps its name (--no-rename-path)
+/// * `args` - Command line arguments
+/// * `vars` - Template variables (always substituted)
 ///
 /// # Returns
-/// * `Result<PathBuf>` - Transformed path
-fn transform_path(
-    path: &Path,
-    rules: &[ReplacementRule],
-    rename_file: bool,
-    rename_dir: bool
-) -> Result<PathBuf> {
-    let mut result = PathBuf::new();
-
-    for component in path.components() {
-        let component_str = component.as_os_str().to_string_lossy();
-        let is_file = !path.join(&*component_str).is_dir();
-
-        // Apply transformations based on component type
-        let transformed_component = if (is_file && rename_file) || (!is_file && rename_dir) {
-            // Apply all replacement rules
-            let mut transformed = component_str.to_string();
-            for rule in rules {
-                // Use replacer::apply_replacement which handles all case transformations
-                transformed = replacer::apply_replacement(&transformed, &rule.from, &rule.to, true);
-            }
-            transformed
-        } else {
-            component_str.to_string()
-        };
+/// * `String` - Transformed name
+fn transform_name(name: &str, is_dir: bool, rules: &[ReplacementRule], keep_name: bool, args: &Args, vars: &Vars) -> String {
+    let rendered = template::render(name, vars);
+
+    // Apply transformations based on the entry type
+    if !keep_name && ((!is_dir && args.rename_file) || (is_dir && args.rename_dir)) {
+        let pairs: Vec<(&str, String)> = rules.iter().map(|rule| (rule.from.as_str(), rule.to.clone())).collect();
+        replacer::apply_name_replacements(&rendered, &pairs, args)
+    } else {
+        rendered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args;
+    use crate::testdir::TestDir;
+
+    /// Copy `template` (holding `a.txt`) to `services/billing`, which already holds `old.txt`
+    fn copy_onto_existing(dir: &Path, policy: &str) -> Result<()> {
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir_all(dir.join("template")).unwrap();
+        fs::create_dir_all(dir.join("services/billing")).unwrap();
+        fs::write(dir.join("template/a.txt"), "service\n").unwrap();
+        fs::write(dir.join("services/billing/old.txt"), "old\n").unwrap();
+
+        let source = dir.join("template").to_string_lossy().to_string();
+        let target = dir.join("services/billing").to_string_lossy().to_string();
+        let args = args::parse_from(["mane", "-c", &source, &target, "-r", "service", "billing", "--on-existing", policy]).unwrap();
+        copy_with_replacements(&args)
+    }
 
-        result = result.join(transformed_component);
+    #[test]
+    fn test_on_existing() {
+        let dir = TestDir::new("copier-on-existing");
+        let billing = dir.join("services/billing");
+
+        // The target given is the existing directory, not a subdirectory of it
+        assert!(copy_onto_existing(&dir, "fail").is_err());
+        assert!(billing.join("old.txt").exists());
+        assert!(!billing.join("template").exists() && !billing.join("a.txt").exists());
+
+        copy_onto_existing(&dir, "overwrite").unwrap();
+        assert!(!billing.join("old.txt").exists());
+        assert_eq!(fs::read_to_string(billing.join("a.txt")).unwrap(), "billing\n");
+
+        copy_onto_existing(&dir, "backup").unwrap();
+        assert_eq!(fs::read_to_string(dir.join("services/billing.bak/old.txt")).unwrap(), "old\n");
+        assert_eq!(fs::read_to_string(billing.join("a.txt")).unwrap(), "billing\n");
+        assert!(!billing.join("old.txt").exists());
+
+        // Merging still copies into the existing directory, as cp does
+        copy_onto_existing(&dir, "merge").unwrap();
+        assert!(billing.join("old.txt").exists());
+        assert_eq!(fs::read_to_string(billing.join("template/a.txt")).unwrap(), "billing\n");
     }
 
-    Ok(result)
+    #[test]
+    fn test_with_rules() {
+        let dir = TestDir::new("copier-with");
+        fs::create_dir_all(dir.join("template")).unwrap();
+        fs::write(dir.join("template/service.txt"), "service on port\n").unwrap();
+
+        let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+        let args = args::parse_from([
+            "mane", "-r", "service", "app",
+            "-c", &path("template"), &path("a"), "--with", "port", "8001", "--with", "service", "billing",
+            "-c", &path("template"), &path("b"),
+        ]).unwrap();
+        copy_with_replacements(&args).unwrap();
+
+        // The --with rules apply to the copy before them only, names included
+        assert_eq!(fs::read_to_string(dir.join("a/billing.txt")).unwrap(), "billing on 8001\n");
+        assert_eq!(fs::read_to_string(dir.join("b/app.txt")).unwrap(), "app on port\n");
+    }
+
+    #[test]
+    fn test_expect_keeps_counter() {
+        let dir = TestDir::new("copier-expect-counter");
+        fs::create_dir_all(dir.join("tmpl")).unwrap();
+        fs::write(dir.join("tmpl/foo.txt"), "foo foo\n").unwrap();
+
+        let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+        let args = args::parse_from(["mane", "-c", &path("tmpl"), &path("out"), "-r", "foo", "bar{{n}}", "--expect-min", "1"]).unwrap();
+        copy_with_replacements(&args).unwrap();
+
+        // The count before the copy doesn't use up {{n}} values
+        assert_eq!(fs::read_to_string(dir.join("out/bar1.txt")).unwrap(), "bar1 bar1\n");
+    }
 }
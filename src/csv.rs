@@ -0,0 +1,173 @@
+use anyhow::{Result, anyhow};
+use std::path::Path;
+
+/// One field of a record (byte offsets of the raw field, quotes included)
+struct Field {
+    start: usize,
+    end: usize,
+    quoted: bool,
+}
+
+/// Find the delimiter of a CSV or TSV file by its extension
+///
+/// # Arguments
+/// * `path` - Path of the file
+///
+/// # Returns
+/// * `Option<u8>` - `,` for .csv, tab for .tsv, None for other files
+pub fn delimiter(path: &Path) -> Option<u8> {
+    match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+        "csv" => Some(b','),
+        "tsv" | "tab" => Some(b'\t'),
+        _ => None,
+    }
+}
+
+/// Split a document into records of fields
+fn records(text: &str, delimiter: u8) -> Vec<Vec<Field>> {
+    let bytes = text.as_bytes();
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let start = i;
+        let quoted = bytes[i] == b'"';
+        if quoted {
+            // Quotes inside a quoted field are doubled
+            i += 1;
+            while i < bytes.len() {
+                if bytes[i] == b'"' {
+                    if bytes.get(i + 1) == Some(&b'"') {
+                        i += 2;
+                        continue;
+                    }
+                    i += 1;
+                    break;
+                }
+                i += 1;
+            }
+        }
+        while i < bytes.len() && bytes[i] != delimiter && bytes[i] != b'\n' {
+            i += 1;
+        }
+
+        let end = if bytes[start..i].ends_with(b"\r") { i - 1 } else { i };
+        record.push(Field { start, end, quoted });
+
+        match bytes.get(i) {
+            Some(&b) if b == delimiter => {
+                i += 1;
+                // A delimiter at the very end leaves an empty last field
+                if i == bytes.len() {
+                    record.push(Field { start: i, end: i, quoted: false });
+                }
+            },
+            _ => {
+                records.push(std::mem::take(&mut record));
+                i += 1;
+            },
+        }
+        if i >= bytes.len() && !record.is_empty() {
+            records.push(std::mem::take(&mut record));
+        }
+    }
+
+    records
+}
+
+/// The value of a raw field, with quoting removed
+fn unquote(raw: &str, quoted: bool) -> String {
+    match raw.strip_prefix('"').and_then(|r| r.strip_suffix('"')).filter(|_| quoted) {
+        Some(inner) => inner.replace("\"\"", "\""),
+        None => raw.to_string(),
+    }
+}
+
+/// Replace only inside some columns of a CSV/TSV document
+///
+/// Columns are named by the header row, which is left unchanged. Quoting and
+/// delimiters are preserved; a replaced field is quoted when it needs to be.
+///
+/// # Arguments
+/// * `text` - Document text
+/// * `delimiter` - Field delimiter
+/// * `columns` - Names of the columns to replace in
+/// * `replace` - Replacement for one field value
+///
+/// # Returns
+/// * `Result<String>` - The document with replacements applied
+pub fn replace(
+    text: &str,
+    delimiter: u8,
+    columns: &[String],
+    mut replace: impl FnMut(&str) -> Result<String>,
+) -> Result<String> {
+    let records = records(text, delimiter);
+    let Some(header) = records.first() else {
+        return Ok(text.to_string());
+    };
+
+    let names: Vec<String> = header.iter().map(|f| unquote(&text[f.start..f.end], f.quoted)).collect();
+    let selected = columns.iter()
+        .map(|column| names.iter().position(|name| name.trim() == column)
+            .ok_or_else(|| anyhow!("Column '{}' not found in the header ({})", column, names.join(", "))))
+        .collect::<Result<Vec<usize>>>()?;
+
+    let mut result = String::with_capacity(text.len());
+    let mut copied = 0;
+    for record in &records[1..] {
+        for (index, field) in record.iter().enumerate() {
+            if !selected.contains(&index) {
+                continue;
+            }
+            let value = unquote(&text[field.start..field.end], field.quoted);
+            let replaced = replace(&value)?;
+            if replaced == value {
+                continue;
+            }
+
+            let needs_quotes = field.quoted || replaced.contains([delimiter as char, '"', '\n', '\r']);
+            result.push_str(&text[copied..field.start]);
+            if needs_quotes {
+                result.push('"');
+                result.push_str(&replaced.replace('"', "\"\""));
+                result.push('"');
+            } else {
+                result.push_str(&replaced);
+            }
+            copied = field.end;
+        }
+    }
+    result.push_str(&text[copied..]);
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn upper(text: &str, delimiter: u8, columns: &[&str]) -> Result<String> {
+        let columns: Vec<String> = columns.iter().map(|c| c.to_string()).collect();
+        replace(text, delimiter, &columns, |s| Ok(s.replace("foo", "foo, \"bar\"")))
+    }
+
+    #[test]
+    fn test_replace_columns() {
+        let csv = "id,name,\"description\"\r\n1,foo,\"a \"\"foo\"\"\nline\"\r\n2,foo,foo\r\n";
+        assert_eq!(
+            upper(csv, b',', &["name"]).unwrap(),
+            "id,name,\"description\"\r\n1,\"foo, \"\"bar\"\"\",\"a \"\"foo\"\"\nline\"\r\n2,\"foo, \"\"bar\"\"\",foo\r\n"
+        );
+        assert_eq!(
+            upper(csv, b',', &["description"]).unwrap(),
+            "id,name,\"description\"\r\n1,foo,\"a \"\"foo, \"\"bar\"\"\"\"\nline\"\r\n2,foo,\"foo, \"\"bar\"\"\"\r\n"
+        );
+        assert!(upper(csv, b',', &["missing"]).is_err());
+
+        // Tab-separated fields are quoted the same way
+        assert_eq!(upper("a\tb\nfoo\tfoo", b'\t', &["b"]).unwrap(), "a\tb\nfoo\t\"foo, \"\"bar\"\"\"");
+        assert_eq!(delimiter(Path::new("data.TSV")), Some(b'\t'));
+    }
+}
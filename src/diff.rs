@@ -0,0 +1,211 @@
+/// A single line-level edit operation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Edit {
+    Equal(usize, usize), // Line kept: index in old, index in new
+    Delete(usize),       // Line removed: index in old
+    Insert(usize),       // Line added: index in new
+}
+
+/// A unified-diff hunk
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hunk {
+    /// 1-based start line in the old content
+    pub old_start: usize,
+
+    /// Number of old lines covered
+    pub old_len: usize,
+
+    /// 1-based start line in the new content
+    pub new_start: usize,
+
+    /// Number of new lines covered
+    pub new_len: usize,
+
+    /// Hunk body lines, each prefixed with ' ', '-' or '+'
+    pub lines: Vec<String>,
+}
+
+/// Compute the shortest line edit script from `old` to `new` (Myers' algorithm)
+///
+/// # Arguments
+/// * `old` - Lines before the change
+/// * `new` - Lines after the change
+///
+/// # Returns
+/// * `Vec<Edit>` - Edit operations in order
+pub fn diff_lines(old: &[&str], new: &[&str]) -> Vec<Edit> {
+    let n = old.len() as isize;
+    let m = new.len() as isize;
+    let max = (n + m) as usize;
+    let offset = max as isize + 1;
+
+    // v[k + offset] holds the furthest x reached on diagonal k
+    let mut v = vec![0isize; 2 * max + 3];
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    'outer: for d in 0..=max as isize {
+        trace.push(v.clone());
+        let mut k = -d;
+        while k <= d {
+            let idx = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+                v[idx + 1]
+            } else {
+                v[idx - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[idx] = x;
+            if x >= n && y >= m {
+                break 'outer;
+            }
+            k += 2;
+        }
+    }
+
+    // Walk the trace backwards to recover the edit script
+    let mut edits = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for d in (0..trace.len() as isize).rev() {
+        let v = &trace[d as usize];
+        let k = x - y;
+        let idx = (k + offset) as usize;
+        let prev_k = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) { k + 1 } else { k - 1 };
+        let prev_x = if d == 0 { 0 } else { v[(prev_k + offset) as usize] };
+        let prev_y = prev_x - prev_k;
+
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            edits.push(Edit::Equal(x as usize, y as usize));
+        }
+        if d > 0 {
+            if x == prev_x {
+                edits.push(Edit::Insert(prev_y as usize));
+            } else {
+                edits.push(Edit::Delete(prev_x as usize));
+            }
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+
+    edits.reverse();
+    edits
+}
+
+/// Group an edit script into unified-diff hunks
+///
+/// # Arguments
+/// * `old` - Lines before the change
+/// * `new` - Lines after the change
+/// * `context` - Number of unchanged context lines around each change
+///
+/// # Returns
+/// * `Vec<Hunk>` - The hunks, empty if the contents are identical
+pub fn hunks(old: &[&str], new: &[&str], context: usize) -> Vec<Hunk> {
+    let edits = diff_lines(old, new);
+    let mut result = Vec::new();
+
+    // Indexes of edits that are actual changes
+    let changes: Vec<usize> = edits.iter()
+        .enumerate()
+        .filter(|(_, e)| !matches!(e, Edit::Equal(..)))
+        .map(|(i, _)| i)
+        .collect();
+
+    let mut i = 0;
+    while i < changes.len() {
+        // Extend the hunk while the next change is within 2 * context lines
+        let mut j = i;
+        while j + 1 < changes.len() && changes[j + 1] - changes[j] <= 2 * context + 1 {
+            j += 1;
+        }
+
+        let start = changes[i].saturating_sub(context);
+        let end = (changes[j] + context + 1).min(edits.len());
+
+        let (mut old_start, mut new_start) = (None, None);
+        let (mut old_len, mut new_len) = (0, 0);
+        let mut lines = Vec::new();
+        for edit in &edits[start..end] {
+            match *edit {
+                Edit::Equal(o, n) => {
+                    old_start.get_or_insert(o);
+                    new_start.get_or_insert(n);
+                    old_len += 1;
+                    new_len += 1;
+                    lines.push(format!(" {}", old[o]));
+                },
+                Edit::Delete(o) => {
+                    old_start.get_or_insert(o);
+                    old_len += 1;
+                    lines.push(format!("-{}", old[o]));
+                },
+                Edit::Insert(n) => {
+                    new_start.get_or_insert(n);
+                    new_len += 1;
+                    lines.push(format!("+{}", new[n]));
+                },
+            }
+        }
+
+        // Empty sides start at the line before the hunk, per unified diff convention
+        let old_start = old_start.map(|s| s + 1).unwrap_or_else(|| position_before(&edits[..start], true));
+        let new_start = new_start.map(|s| s + 1).unwrap_or_else(|| position_before(&edits[..start], false));
+
+        result.push(Hunk { old_start, old_len, new_start, new_len, lines });
+        i = j + 1;
+    }
+
+    result
+}
+
+/// Number of old (or new) lines consumed by the given edits
+fn position_before(edits: &[Edit], old_side: bool) -> usize {
+    edits.iter()
+        .filter(|e| match e {
+            Edit::Equal(..) => true,
+            Edit::Delete(_) => old_side,
+            Edit::Insert(_) => !old_side,
+        })
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_lines() {
+        let old = ["a", "b", "c"];
+        let new = ["a", "x", "c", "d"];
+        assert_eq!(diff_lines(&old, &new), vec![
+            Edit::Equal(0, 0),
+            Edit::Delete(1),
+            Edit::Insert(1),
+            Edit::Equal(2, 2),
+            Edit::Insert(3),
+        ]);
+    }
+
+    #[test]
+    fn test_hunks() {
+        let old: Vec<String> = (1..=20).map(|i| format!("line {}", i)).collect();
+        let mut new = old.clone();
+        new[1] = "changed 2".to_string();
+        new[17] = "changed 18".to_string();
+        let old: Vec<&str> = old.iter().map(String::as_str).collect();
+        let new: Vec<&str> = new.iter().map(String::as_str).collect();
+
+        let hunks = hunks(&old, &new, 3);
+        assert_eq!(hunks.len(), 2);
+        assert_eq!((hunks[0].old_start, hunks[0].old_len), (1, 5));
+        assert_eq!(hunks[0].lines[1], "-line 2");
+        assert_eq!(hunks[0].lines[2], "+changed 2");
+        assert_eq!((hunks[1].old_start, hunks[1].old_len, hunks[1].new_len), (15, 6, 6));
+    }
+}
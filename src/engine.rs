@@ -0,0 +1,219 @@
+use crate::case;
+use crate::multiline;
+use aho_corasick::{AhoCorasick, MatchKind};
+
+/// How a set of FROM/TO pairs is applied to a text
+#[derive(Debug, Clone, Copy)]
+pub struct Options {
+    /// Also replace the case variants of each FROM (off with `case = false` in the config file)
+    pub case_enabled: bool,
+
+    /// Apply the rules one after another instead of in one longest-match pass (--ordered)
+    pub ordered: bool,
+
+    /// Render TO in the shape of each occurrence instead of the five case variants (--same-shape)
+    pub same_shape: bool,
+
+    /// Make digits words of their own in case variants (--split-digits)
+    pub split_digits: bool,
+}
+
+impl Options {
+    /// Options of a plain case-aware run
+    pub fn new(case_enabled: bool) -> Self {
+        Options { case_enabled, ordered: false, same_shape: false, split_digits: false }
+    }
+}
+
+/// Apply several FROM/TO pairs and return the number of replacements
+///
+/// All FROM strings and case variants are matched in one left-to-right pass
+/// where the longest match wins, so `user_id` stays one identifier however
+/// `user` and `user_id` are ordered, and no rule sees another rule's output.
+/// With --ordered, or when a rule needs a matcher of its own (multi-line FROM,
+/// --same-shape), the rules apply one after another instead.
+///
+/// # Arguments
+/// * `content` - The content to replace in
+/// * `pairs` - FROM strings and their TO strings, in rule order
+/// * `options` - How the pairs are applied
+///
+/// # Returns
+/// * `(String, usize)` - The replaced content and the number of replacements
+pub fn replace_rules_counted(content: &str, pairs: &[(&str, String)], options: Options) -> (String, usize) {
+    Compiled::new(pairs, options).replace_counted(content)
+}
+
+/// A rule set prepared once to replace in many texts
+pub struct Compiled {
+    /// FROM strings and their TO strings, in rule order
+    pairs: Vec<(String, String)>,
+
+    options: Options,
+
+    /// The one-pass search and its TO strings, None when the rules apply one after another
+    automaton: Option<(AhoCorasick, Vec<String>)>,
+
+    /// Whether the rules apply one after another
+    sequential: bool,
+}
+
+impl Compiled {
+    /// Prepare a rule set, building the one-pass search when the rules use it
+    ///
+    /// # Arguments
+    /// * `pairs` - FROM strings and their TO strings, in rule order
+    /// * `options` - How the pairs are applied
+    pub fn new(pairs: &[(&str, String)], options: Options) -> Self {
+        let sequential = pairs.len() < 2
+            || options.ordered
+            || (options.case_enabled && options.same_shape)
+            || pairs.iter().any(|(from, _)| multiline::is_multiline(from));
+        Compiled {
+            pairs: pairs.iter().map(|(from, to)| (from.to_string(), to.clone())).collect(),
+            options,
+            automaton: if sequential { None } else { combined_automaton(pairs, options) },
+            sequential,
+        }
+    }
+
+    /// Apply the rules to a text and return the number of replacements
+    ///
+    /// # Arguments
+    /// * `content` - The content to replace in
+    ///
+    /// # Returns
+    /// * `(String, usize)` - The replaced content and the number of replacements
+    pub fn replace_counted(&self, content: &str) -> (String, usize) {
+        if self.sequential {
+            return self.pairs.iter().fold((content.to_string(), 0), |(text, total), (from, to)| {
+                let (text, count) = replace_counted(&text, from, to, self.options);
+                (text, total + count)
+            });
+        }
+
+        let Some((automaton, tos)) = &self.automaton else {
+            return (content.to_string(), 0);
+        };
+        let mut result = String::with_capacity(content.len());
+        let mut count = 0;
+        let mut last = 0;
+        for m in automaton.find_iter(content) {
+            result.push_str(&content[last..m.start()]);
+            result.push_str(&tos[m.pattern().as_usize()]);
+            last = m.end();
+            count += 1;
+        }
+        result.push_str(&content[last..]);
+        (result, count)
+    }
+}
+
+/// Build the one-pass search over all FROM strings and case variants
+///
+/// # Arguments
+/// * `pairs` - FROM strings and their TO strings, in rule order
+/// * `options` - Whether to search for the case variants too, and how they split digits
+///
+/// # Returns
+/// * `Option<(AhoCorasick, Vec<String>)>` - The search and the TO string of each of its patterns
+pub fn combined_automaton(pairs: &[(&str, String)], options: Options) -> Option<(AhoCorasick, Vec<String>)> {
+    let mut froms = Vec::new();
+    let mut tos = Vec::new();
+    for (from, to) in pairs {
+        let variants = if options.case_enabled {
+            case::variant_pairs_with(from, to, options.split_digits)
+        } else {
+            vec![(from.to_string(), to.clone())]
+        };
+        for (from, to) in variants.into_iter().filter(|(from, _)| !from.is_empty()) {
+            froms.push(from);
+            tos.push(to);
+        }
+    }
+
+    // Equally long matches go to the earlier rule
+    let automaton = AhoCorasick::builder().match_kind(MatchKind::LeftmostLongest).build(&froms).ok()?;
+    Some((automaton, tos))
+}
+
+/// Apply a single replacement with case handling and return the number of replacements
+///
+/// # Arguments
+/// * `content` - The content to replace in
+/// * `from` - The string to replace
+/// * `to` - The replacement string
+/// * `options` - How the replacement is applied
+///
+/// # Returns
+/// * `(String, usize)` - The replaced content and the number of replacements
+pub fn replace_counted(content: &str, from: &str, to: &str, options: Options) -> (String, usize) {
+    // FROM strings spanning lines are matched over the whole buffer, without case variants
+    if multiline::is_multiline(from) {
+        return multiline::replace_counted(content, from, to);
+    }
+
+    // --same-shape renders TO like each occurrence instead of using the five case variants
+    if options.case_enabled && options.same_shape {
+        return case::replace_same_shape_counted(content, from, to, options.split_digits);
+    }
+
+    if !options.case_enabled {
+        return (content.replace(from, to), content.matches(from).count());
+    }
+
+    // Use the case-aware replacement function
+    match case::replace_with_case_variants_counted(content, from, to, options.split_digits) {
+        Ok(replaced) => replaced,
+        Err(_) => {
+            // Fallback to simple replacement if case-aware replacement fails
+            (content.replace(from, to), content.matches(from).count())
+        }
+    }
+}
+
+/// Apply several FROM/TO pairs to every file and directory name of a `/`-separated path
+///
+/// # Arguments
+/// * `path` - The path to rename, e.g. `src/foo_bar/FooBar.ts`
+/// * `pairs` - FROM strings and their TO strings, in rule order
+/// * `options` - How the pairs are applied
+///
+/// # Returns
+/// * `String` - The renamed path
+pub fn rename_path(path: &str, pairs: &[(&str, String)], options: Options) -> String {
+    path.split('/')
+        .map(|name| replace_rules_counted(name, pairs, options).0)
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replace_rules_counted() {
+        let pairs = [("user", "customer".to_string()), ("user_id", "account_key".to_string())];
+        let content = "user_id userId User";
+
+        // The longest match wins, wherever its rule is
+        assert_eq!(
+            replace_rules_counted(content, &pairs, Options::new(true)),
+            ("account_key accountKey Customer".to_string(), 3),
+        );
+
+        // --ordered applies `user` first, leaving nothing for `user_id`
+        let ordered = Options { ordered: true, ..Options::new(true) };
+        assert_eq!(replace_rules_counted(content, &pairs, ordered).0, "customer_id customerId Customer");
+
+        assert_eq!(replace_rules_counted(content, &pairs, Options::new(false)).0, "account_key customerId User");
+    }
+
+    #[test]
+    fn test_rename_path() {
+        let pairs = [("foo_bar", "baz_qux".to_string())];
+        assert_eq!(rename_path("src/foo_bar/FooBar.ts", &pairs, Options::new(true)), "src/baz_qux/BazQux.ts");
+        assert_eq!(rename_path("/foo/foo_bar/", &pairs, Options::new(false)), "/foo/baz_qux/");
+    }
+}
@@ -0,0 +1,92 @@
+use crate::args::{Args, ReplacementRule};
+use crate::case;
+use crate::lint;
+use crate::multiline;
+use anyhow::{Result, anyhow};
+
+/// Print the spellings each rule searches for and what each is replaced with (`mane explain`)
+///
+/// Nothing is read or written; this shows the case variants that would be
+/// generated, followed by the problems `lint` finds between the rules.
+///
+/// # Arguments
+/// * `args` - Command line arguments, with the rules to explain
+///
+/// # Returns
+/// * `Result<()>` - Error if there are no rules
+pub fn run(args: &Args) -> Result<()> {
+    if args.rules.is_empty() {
+        return Err(anyhow!("No replacement rules to explain. Use -r/--replace FROM TO"));
+    }
+    print!("{}", explain(&args.rules, args.case_enabled));
+    for problem in lint::lint_rules(&args.rules, args.case_enabled, args.ordered) {
+        warn!("{}", problem);
+    }
+    Ok(())
+}
+
+/// The search strings and replacements of every rule, one block per rule
+///
+/// # Arguments
+/// * `rules` - Rules in the order they are applied
+/// * `case_enabled` - Whether case variants are replaced as well
+///
+/// # Returns
+/// * `String` - The listing
+fn explain(rules: &[ReplacementRule], case_enabled: bool) -> String {
+    let mut out = String::new();
+    for (index, rule) in rules.iter().enumerate() {
+        let mut notes = Vec::new();
+        if rule.scope.is_some() {
+            notes.push("only in the paths it is scoped to");
+        }
+        let pairs = if !case_enabled {
+            notes.push("case variants off");
+            vec![(rule.from.clone(), rule.to.clone(), case::AS_WRITTEN)]
+        } else if multiline::is_multiline(&rule.from) {
+            notes.push("multi-line, so no case variants");
+            vec![(rule.from.clone(), rule.to.clone(), case::AS_WRITTEN)]
+        } else {
+            case::named_variant_pairs(&rule.from, &rule.to)
+        };
+
+        // Numbered as in the provenance of -v and --format json
+        out.push_str(&format!("#{} {:?} -> {:?}", index + 1, rule.from, rule.to));
+        if !notes.is_empty() {
+            out.push_str(&format!(" ({})", notes.join("; ")));
+        }
+        out.push('\n');
+
+        let quoted: Vec<(String, String, &str)> = pairs.iter()
+            .map(|(from, to, variant)| (format!("{:?}", from), format!("{:?}", to), *variant))
+            .collect();
+        let from_width = quoted.iter().map(|(from, _, _)| from.chars().count()).max().unwrap_or(0);
+        let to_width = quoted.iter().map(|(_, to, _)| to.chars().count()).max().unwrap_or(0);
+        for (from, to, variant) in &quoted {
+            out.push_str(&format!("    {:from_width$} -> {:to_width$}  {}\n", from, to, variant));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_explain() {
+        let rules = [ReplacementRule::new("serviceName".to_string(), "userAccount".to_string())];
+        assert_eq!(explain(&rules, true), concat!(
+            "#1 \"serviceName\" -> \"userAccount\"\n",
+            "    \"serviceName\"  -> \"userAccount\"   as written\n",
+            "    \"ServiceName\"  -> \"UserAccount\"   PascalCase\n",
+            "    \"service-name\" -> \"user-account\"  kebab-case\n",
+            "    \"SERVICE_NAME\" -> \"USER_ACCOUNT\"  SCREAMING_SNAKE_CASE\n",
+            "    \"service_name\" -> \"user_account\"  snake_case\n",
+        ));
+        assert_eq!(explain(&rules, false), concat!(
+            "#1 \"serviceName\" -> \"userAccount\" (case variants off)\n",
+            "    \"serviceName\" -> \"userAccount\"  as written\n",
+        ));
+    }
+}
@@ -0,0 +1,155 @@
+use crate::engine::{self, Options};
+
+/// `flags` bit: replace case variants too
+pub const FLAG_CASE: u32 = 1;
+
+/// `flags` bit: apply the rules one after another (--ordered)
+pub const FLAG_ORDERED: u32 = 1 << 1;
+
+/// `flags` bit: render TO in the shape of each occurrence (--same-shape)
+pub const FLAG_SAME_SHAPE: u32 = 1 << 2;
+
+/// `flags` bit: make digits words of their own (--split-digits)
+pub const FLAG_SPLIT_DIGITS: u32 = 1 << 3;
+
+/// Decode rules given as `FROM\0TO\0FROM\0TO...` and the `FLAG_*` bits
+fn decode(rules: &str, flags: u32) -> (Vec<(&str, String)>, Options) {
+    let fields: Vec<&str> = rules.strip_suffix('\0').unwrap_or(rules).split('\0').collect();
+    let pairs = fields.chunks_exact(2)
+        .filter(|pair| !pair[0].is_empty())
+        .map(|pair| (pair[0], pair[1].to_string()))
+        .collect();
+    (pairs, options(flags))
+}
+
+/// Options for the `FLAG_*` bits
+pub(crate) fn options(flags: u32) -> Options {
+    Options {
+        case_enabled: flags & FLAG_CASE != 0,
+        ordered: flags & FLAG_ORDERED != 0,
+        same_shape: flags & FLAG_SAME_SHAPE != 0,
+        split_digits: flags & FLAG_SPLIT_DIGITS != 0,
+    }
+}
+
+/// Replace in a text with rules given as `FROM\0TO\0FROM\0TO...`
+///
+/// # Arguments
+/// * `content` - The content to replace in
+/// * `rules` - FROM and TO strings, each ended or separated by NUL
+/// * `flags` - `FLAG_*` bits
+///
+/// # Returns
+/// * `String` - The replaced content
+pub fn replace(content: &str, rules: &str, flags: u32) -> String {
+    let (pairs, options) = decode(rules, flags);
+    engine::replace_rules_counted(content, &pairs, options).0
+}
+
+/// Rename every file and directory name of a `/`-separated path with rules given as `FROM\0TO\0...`
+///
+/// # Arguments
+/// * `path` - The path to rename
+/// * `rules` - FROM and TO strings, each ended or separated by NUL
+/// * `flags` - `FLAG_*` bits
+///
+/// # Returns
+/// * `String` - The renamed path
+pub fn rename_path(path: &str, rules: &str, flags: u32) -> String {
+    let (pairs, options) = decode(rules, flags);
+    engine::rename_path(path, &pairs, options)
+}
+
+/// Move a string into a buffer holding its little-endian `u32` length and its bytes
+fn into_buffer(s: String) -> *mut u8 {
+    let mut buffer = Vec::with_capacity(4 + s.len());
+    buffer.extend_from_slice(&(s.len() as u32).to_le_bytes());
+    buffer.extend_from_slice(s.as_bytes());
+    let ptr = buffer.as_mut_ptr();
+    std::mem::forget(buffer);
+    ptr
+}
+
+/// Borrow `len` bytes of the caller's memory as a string
+///
+/// # Safety
+/// `ptr` must point to `len` readable bytes.
+unsafe fn borrow<'a>(ptr: *const u8, len: usize) -> std::borrow::Cow<'a, str> {
+    String::from_utf8_lossy(std::slice::from_raw_parts(ptr, len))
+}
+
+/// Allocate a buffer of `len` bytes for passing a string
+#[no_mangle]
+pub extern "C" fn mane_alloc(len: usize) -> *mut u8 {
+    let mut buffer = Vec::<u8>::with_capacity(len);
+    let ptr = buffer.as_mut_ptr();
+    std::mem::forget(buffer);
+    ptr
+}
+
+/// Release a buffer from `mane_alloc` or `mane_replace`
+///
+/// # Safety
+/// `ptr` must come from `mane_alloc(len)`, or from `mane_replace` with `len`
+/// being 4 plus the length it holds, and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn mane_free(ptr: *mut u8, len: usize) {
+    drop(Vec::from_raw_parts(ptr, 0, len));
+}
+
+/// Replace in a text, returning a length-prefixed buffer with the result
+///
+/// # Safety
+/// `content_ptr` and `rules_ptr` must point to `content_len` and `rules_len`
+/// bytes of UTF-8.
+#[no_mangle]
+pub unsafe extern "C" fn mane_replace(
+    content_ptr: *const u8,
+    content_len: usize,
+    rules_ptr: *const u8,
+    rules_len: usize,
+    flags: u32,
+) -> *mut u8 {
+    into_buffer(replace(&borrow(content_ptr, content_len), &borrow(rules_ptr, rules_len), flags))
+}
+
+/// Rename the names of a path, returning a length-prefixed buffer with the result
+///
+/// # Safety
+/// `path_ptr` and `rules_ptr` must point to `path_len` and `rules_len`
+/// bytes of UTF-8.
+#[no_mangle]
+pub unsafe extern "C" fn mane_rename_path(
+    path_ptr: *const u8,
+    path_len: usize,
+    rules_ptr: *const u8,
+    rules_len: usize,
+    flags: u32,
+) -> *mut u8 {
+    into_buffer(rename_path(&borrow(path_ptr, path_len), &borrow(rules_ptr, rules_len), flags))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replace() {
+        assert_eq!(replace("fooBar FOO_BAR", "foo_bar\0baz_qux\0", FLAG_CASE), "bazQux BAZ_QUX");
+        assert_eq!(replace("fooBar foo_bar", "foo_bar\0baz_qux", 0), "fooBar baz_qux");
+        assert_eq!(replace("user_id", "user\0customer\0user_id\0key\0", FLAG_CASE | FLAG_ORDERED), "customer_id");
+        assert_eq!(rename_path("src/foo_bar/FooBar.ts", "foo_bar\0baz_qux\0", FLAG_CASE), "src/baz_qux/BazQux.ts");
+        // Digit handling is per call, so one call splitting digits leaves the next alone
+        assert_eq!(replace("oauth_2_client", "oauth2_client\0x_y\0", FLAG_CASE | FLAG_SPLIT_DIGITS), "x_y");
+        assert_eq!(replace("oauth_2_client", "oauth2_client\0x_y\0", FLAG_CASE), "oauth_2_client");
+
+        let content = "fooBar";
+        let rules = "foo_bar\0baz_qux\0";
+        unsafe {
+            let result = mane_replace(content.as_ptr(), content.len(), rules.as_ptr(), rules.len(), FLAG_CASE);
+            let len = u32::from_le_bytes(std::slice::from_raw_parts(result, 4).try_into().unwrap()) as usize;
+            assert_eq!(std::slice::from_raw_parts(result.add(4), len), b"bazQux");
+            mane_free(result, 4 + len);
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use crate::engine::Compiled;
+use crate::exports;
+use std::ffi::{c_char, CStr};
+use std::ptr;
+
+/// A compiled rule set handed to C callers as an opaque pointer
+pub struct ManeRules {
+    compiled: Compiled,
+
+    /// `FLAG_*` bits the rules were compiled with
+    flags: u32,
+}
+
+/// Compile `count` FROM/TO pairs for replacing in many texts
+///
+/// Returns NULL if a string isn't UTF-8. The handle is released with
+/// `mane_rules_free`. The case and digit settings are process-wide, so rule
+/// sets compiled with different flags must not be used at the same time.
+///
+/// # Safety
+/// `froms` and `tos` must point to `count` NUL-terminated strings each.
+#[no_mangle]
+pub unsafe extern "C" fn mane_rules_compile(
+    froms: *const *const c_char,
+    tos: *const *const c_char,
+    count: usize,
+    flags: u32,
+) -> *mut ManeRules {
+    let mut pairs = Vec::with_capacity(count);
+    for i in 0..count {
+        let (Ok(from), Ok(to)) = (CStr::from_ptr(*froms.add(i)).to_str(), CStr::from_ptr(*tos.add(i)).to_str()) else {
+            return ptr::null_mut();
+        };
+        if !from.is_empty() {
+            pairs.push((from, to.to_string()));
+        }
+    }
+
+    let compiled = Compiled::new(&pairs, exports::options(flags));
+    Box::into_raw(Box::new(ManeRules { compiled, flags }))
+}
+
+/// Release a rule set from `mane_rules_compile`
+///
+/// # Safety
+/// `rules` must come from `mane_rules_compile` (or be NULL) and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn mane_rules_free(rules: *mut ManeRules) {
+    if !rules.is_null() {
+        drop(Box::from_raw(rules));
+    }
+}
+
+/// Replace in `len` bytes of text with a compiled rule set
+///
+/// Returns a NUL-terminated copy of the result, whose length (without the
+/// NUL) is stored in `out_len`, or NULL if the text isn't UTF-8. The result
+/// is released with `mane_string_free`.
+///
+/// # Safety
+/// `rules` must be a live handle from `mane_rules_compile`, `content` must
+/// point to `len` readable bytes and `out_len` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn mane_rules_replace(
+    rules: *const ManeRules,
+    content: *const c_char,
+    len: usize,
+    out_len: *mut usize,
+) -> *mut c_char {
+    let Ok(content) = std::str::from_utf8(std::slice::from_raw_parts(content.cast::<u8>(), len)) else {
+        return ptr::null_mut();
+    };
+    let rules = &*rules;
+    exports::options(rules.flags);
+    let (replaced, _) = rules.compiled.replace_counted(content);
+
+    *out_len = replaced.len();
+    let mut bytes = replaced.into_bytes();
+    bytes.push(0);
+    Box::into_raw(bytes.into_boxed_slice()).cast::<c_char>()
+}
+
+/// Release a string from `mane_rules_replace`
+///
+/// # Safety
+/// `s` must come from `mane_rules_replace` with `len` the length it stored
+/// in `out_len`, and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn mane_string_free(s: *mut c_char, len: usize) {
+    if !s.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(s.cast::<u8>(), len + 1)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exports::FLAG_CASE;
+
+    #[test]
+    fn test_rules_replace() {
+        let froms = [c"user".as_ptr(), c"user_id".as_ptr()];
+        let tos = [c"customer".as_ptr(), c"account_key".as_ptr()];
+        unsafe {
+            let rules = mane_rules_compile(froms.as_ptr(), tos.as_ptr(), 2, FLAG_CASE);
+            assert!(!rules.is_null());
+
+            let content = "user_id: userId, User";
+            let mut len = 0;
+            let result = mane_rules_replace(rules, content.as_ptr().cast(), content.len(), &mut len);
+            assert_eq!(CStr::from_ptr(result).to_str().unwrap(), "account_key: accountKey, Customer");
+            assert_eq!(len, "account_key: accountKey, Customer".len());
+            mane_string_free(result, len);
+
+            assert!(mane_rules_replace(rules, b"\xff".as_ptr().cast(), 1, &mut len).is_null());
+            mane_rules_free(rules);
+        }
+    }
+}
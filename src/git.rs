@@ -0,0 +1,115 @@
+use crate::args::ReplacementRule;
+use crate::plan::Plan;
+use anyhow::{Result, Context, anyhow};
+use std::ffi::OsStr;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Check that the changes can be committed once applied (--commit)
+///
+/// The current directory has to be in a git work tree whose index has nothing
+/// staged, so the commit holds only what `mane` changed.
+///
+/// # Returns
+/// * `Result<()>` - Error if a commit can't be made
+pub fn check_ready() -> Result<()> {
+    let inside = git(["rev-parse", "--is-inside-work-tree"])?;
+    if !inside.status.success() {
+        return Err(anyhow!("--commit needs to run inside a git work tree"));
+    }
+    let staged = git(["diff", "--cached", "--quiet"])?;
+    match staged.status.code() {
+        Some(0) => Ok(()),
+        Some(1) => Err(anyhow!("--commit needs an index without staged changes; commit or unstage them first")),
+        _ => Err(anyhow!("git diff --cached failed: {}", String::from_utf8_lossy(&staged.stderr).trim())),
+    }
+}
+
+/// Stage the paths an applied plan modified and renamed, and commit them
+///
+/// # Arguments
+/// * `plan` - The applied plan
+/// * `rules` - Rules the plan was made with, listed in a generated message
+/// * `message` - Commit message, or None to generate one
+///
+/// # Returns
+/// * `Result<()>` - Result of the operation
+pub fn commit(plan: &Plan, rules: &[ReplacementRule], message: Option<&str>) -> Result<()> {
+    // Renames skipped because their target existed stay where they were
+    let changed: Vec<PathBuf> = plan.edits.iter().map(|edit| &edit.path)
+        .chain(plan.archives.iter().map(|edit| &edit.path))
+        .chain(plan.renames.iter().map(|rename| &rename.from))
+        .chain(plan.links.iter().map(|link| &link.path))
+        .map(|path| plan.final_path(path))
+        .filter(|path| path.symlink_metadata().is_ok())
+        .collect();
+    let removed: Vec<&PathBuf> = plan.renames.iter()
+        .map(|rename| &rename.from)
+        .filter(|path| path.symlink_metadata().is_err())
+        .collect();
+    if changed.is_empty() {
+        info!("Nothing changed, no commit made");
+        return Ok(());
+    }
+
+    if !removed.is_empty() {
+        let mut args: Vec<&OsStr> = ["rm", "-r", "--cached", "--quiet", "--ignore-unmatch", "--"].map(OsStr::new).to_vec();
+        args.extend(removed.iter().map(|path| path.as_os_str()));
+        run(args)?;
+    }
+    let mut args: Vec<&OsStr> = ["add", "--all", "--"].map(OsStr::new).to_vec();
+    args.extend(changed.iter().map(|path| path.as_os_str()));
+    run(args)?;
+
+    let message = message.map_or_else(|| commit_message(rules), str::to_string);
+    run(["commit", "--quiet", "--message", &message].map(OsStr::new))?;
+    info!("Committed: {}", message.lines().next().unwrap_or_default());
+    Ok(())
+}
+
+/// A commit message listing the rules
+///
+/// # Arguments
+/// * `rules` - Replacement rules
+///
+/// # Returns
+/// * `String` - Subject line, and a body with one line per rule if there are several
+pub fn commit_message(rules: &[ReplacementRule]) -> String {
+    match rules {
+        [rule] => format!("Replace {} with {}", rule.from, rule.to),
+        _ => {
+            let mut message = format!("Apply {} replacement rules\n\n", rules.len());
+            for rule in rules {
+                message.push_str(&format!("- {} -> {}\n", rule.from, rule.to));
+            }
+            message
+        },
+    }
+}
+
+fn git<I: IntoIterator<Item = S>, S: AsRef<OsStr>>(args: I) -> Result<std::process::Output> {
+    Command::new("git").args(args).output().context("Failed to run git")
+}
+
+/// Run git, failing with its error output if it fails
+fn run<I: IntoIterator<Item = S>, S: AsRef<OsStr>>(args: I) -> Result<()> {
+    let output = git(args)?;
+    if !output.status.success() {
+        return Err(anyhow!("git failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commit_message() {
+        let rules = [ReplacementRule::new("acme".to_string(), "initech".to_string())];
+        assert_eq!(commit_message(&rules), "Replace acme with initech");
+
+        let rules = [rules[0].clone(), ReplacementRule::new("Widget".to_string(), "Gadget".to_string())];
+        assert_eq!(commit_message(&rules), "Apply 2 replacement rules\n\n- acme -> initech\n- Widget -> Gadget\n");
+    }
+}
@@ -0,0 +1,205 @@
+// Source code is split into code, string and comment pieces by parsing it
+// with a tree-sitter grammar, for the languages syntax.rs has a grammar for.
+// Unlike the lexer, the parser tells a regex or a JSX text from a string, and
+// finds the code of interpolations however deeply they nest.
+
+use crate::syntax::Token;
+use tree_sitter::{Node, Parser};
+
+/// Node kinds of a grammar that are literals
+struct Grammar {
+    language: fn() -> tree_sitter::Language,
+    comments: &'static [&'static str],
+    strings: &'static [&'static str],
+
+    /// Nodes of a string that hold code, such as `${x}` or Python's `{x!r}`
+    interpolations: &'static [&'static str],
+
+    /// Nodes of an interpolation that are part of the string, such as Python's `!r` and `:>10`
+    string_parts: &'static [&'static str],
+}
+
+const JAVASCRIPT_COMMENTS: &[&str] = &["comment", "html_comment"];
+const JAVASCRIPT_STRINGS: &[&str] = &["string", "template_string"];
+
+/// Find the grammar of a language of syntax.rs by its name
+fn grammar(name: &str) -> Option<Grammar> {
+    let grammar = match name {
+        "Rust" => Grammar {
+            language: || tree_sitter_rust::LANGUAGE.into(),
+            comments: &["line_comment", "block_comment"],
+            strings: &["string_literal", "raw_string_literal", "char_literal"],
+            interpolations: &[],
+            string_parts: &[],
+        },
+        "JavaScript" => Grammar {
+            language: || tree_sitter_javascript::LANGUAGE.into(),
+            comments: JAVASCRIPT_COMMENTS,
+            strings: JAVASCRIPT_STRINGS,
+            interpolations: &["template_substitution"],
+            string_parts: &[],
+        },
+        "TypeScript" => Grammar {
+            language: || tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
+            comments: JAVASCRIPT_COMMENTS,
+            strings: JAVASCRIPT_STRINGS,
+            interpolations: &["template_substitution"],
+            string_parts: &[],
+        },
+        "TSX" => Grammar {
+            language: || tree_sitter_typescript::LANGUAGE_TSX.into(),
+            comments: JAVASCRIPT_COMMENTS,
+            strings: JAVASCRIPT_STRINGS,
+            interpolations: &["template_substitution"],
+            string_parts: &[],
+        },
+        "Python" => Grammar {
+            language: || tree_sitter_python::LANGUAGE.into(),
+            comments: &["comment"],
+            strings: &["string"],
+            interpolations: &["interpolation", "format_expression"],
+            string_parts: &["type_conversion", "format_specifier"],
+        },
+        "Go" => Grammar {
+            language: || tree_sitter_go::LANGUAGE.into(),
+            comments: &["comment"],
+            strings: &["interpreted_string_literal", "raw_string_literal", "rune_literal"],
+            interpolations: &[],
+            string_parts: &[],
+        },
+        _ => return None,
+    };
+    Some(grammar)
+}
+
+/// Split source code into code, string and comment pieces by parsing it
+///
+/// # Arguments
+/// * `text` - Source code
+/// * `name` - Name of the language in syntax.rs
+///
+/// # Returns
+/// * `Option<Vec<(usize, usize, Token)>>` - Consecutive pieces covering the whole text,
+///   or None if the language has no grammar or the text has syntax errors
+pub fn tokens(text: &str, name: &str) -> Option<Vec<(usize, usize, Token)>> {
+    let grammar = grammar(name)?;
+    let mut parser = Parser::new();
+    parser.set_language(&(grammar.language)()).ok()?;
+    let tree = parser.parse(text, None)?;
+    if tree.root_node().has_error() {
+        return None;
+    }
+
+    let mut literals = Vec::new();
+    code(tree.root_node(), &grammar, &mut literals);
+
+    // Fill the gaps between literals with code
+    let mut pieces = Vec::new();
+    let mut end = 0;
+    for (start, literal_end, token) in literals {
+        if end < start {
+            pieces.push((end, start, Token::Code));
+        }
+        pieces.push((start, literal_end, token));
+        end = literal_end;
+    }
+    if end < text.len() {
+        pieces.push((end, text.len(), Token::Code));
+    }
+    Some(pieces)
+}
+
+/// Collect the literals of a code node, in order
+fn code(node: Node, grammar: &Grammar, literals: &mut Vec<(usize, usize, Token)>) {
+    if grammar.comments.contains(&node.kind()) {
+        literals.push((node.start_byte(), node.end_byte(), Token::Comment));
+    } else if grammar.strings.contains(&node.kind()) {
+        // A string is cut into pieces by the code interpolated into it
+        let mut holes = Vec::new();
+        interpolated(node, grammar, &mut holes);
+        let mut start = node.start_byte();
+        for hole in holes {
+            if start < hole.start_byte() {
+                literals.push((start, hole.start_byte(), Token::String));
+            }
+            code(hole, grammar, literals);
+            start = hole.end_byte();
+        }
+        if start < node.end_byte() {
+            literals.push((start, node.end_byte(), Token::String));
+        }
+    } else {
+        let mut cursor = node.walk();
+        for child in node.children(&mut cursor) {
+            code(child, grammar, literals);
+        }
+    }
+}
+
+/// Collect the code nodes interpolated into a string node, in order
+fn interpolated<'tree>(node: Node<'tree>, grammar: &Grammar, holes: &mut Vec<Node<'tree>>) {
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        if !grammar.interpolations.contains(&child.kind()) {
+            interpolated(child, grammar, holes);
+            continue;
+        }
+        // Delimiters and format specifiers stay in the string, whose interpolations they may hold
+        let mut cursor = child.walk();
+        for part in child.named_children(&mut cursor) {
+            if grammar.string_parts.contains(&part.kind()) {
+                interpolated(part, grammar, holes);
+            } else {
+                holes.push(part);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::{self, Language, Skip};
+    use std::path::Path;
+
+    fn rename(text: &str, file: &str) -> String {
+        let lang = Language::detect(Path::new(file)).unwrap();
+        syntax::replace_identifiers(text, lang, |s| Ok(s.replace("foo", "bar"))).unwrap()
+    }
+
+    #[test]
+    fn test_tokens() {
+        let pieces = tokens("let foo = \"foo\"; // foo\n", "Rust").unwrap();
+        assert_eq!(pieces, vec![(0, 10, Token::Code), (10, 15, Token::String), (15, 17, Token::Code), (17, 23, Token::Comment), (23, 24, Token::Code)]);
+        assert_eq!(tokens("x = `${y}`", "JavaScript").unwrap()[1..], [(4, 7, Token::String), (7, 8, Token::Code), (8, 10, Token::String)]);
+
+        // Text that doesn't parse is left to the lexer
+        assert!(tokens("fn foo( {", "Rust").is_none());
+        assert!(tokens("foo", "Lua").is_none());
+    }
+
+    #[test]
+    fn test_replace_identifiers() {
+        assert_eq!(
+            rename("fn foo<'a>(x: &'a str) { /* a /* foo */ */ foo_x(\"foo\", 'f', r#\"foo\"#, b'f') } // foo\n", "lib.rs"),
+            "fn bar<'a>(x: &'a str) { /* a /* foo */ */ bar_x(\"foo\", 'f', r#\"foo\"#, b'f') } // foo\n"
+        );
+        // Regexes and JSX text are code, not strings
+        assert_eq!(rename("const re = /\"/; foo(\"foo\");\n", "app.js"), "const re = /\"/; bar(\"foo\");\n");
+        assert_eq!(rename("const x = <A title=\"foo\">{foo}</A>;\n", "app.tsx"), "const x = <A title=\"foo\">{bar}</A>;\n");
+        assert_eq!(rename("let foo = <T>(`foo ${foo}`);\n", "app.ts"), "let bar = <T>(`foo ${bar}`);\n");
+        assert_eq!(
+            rename("f\"foo {foo!r:>{foo}} {{foo}}\"  # foo\n", "mod.py"),
+            "f\"foo {bar!r:>{bar}} {{foo}}\"  # foo\n"
+        );
+        assert_eq!(rename("package foo\nvar foo = `foo` + \"foo\" + 'f' // foo\n", "main.go"), "package bar\nvar bar = `foo` + \"foo\" + 'f' // foo\n");
+    }
+
+    #[test]
+    fn test_replace_outside() {
+        let lang = Language::detect(Path::new("main.go")).unwrap();
+        let skip = Skip { strings: true, comments: false };
+        let result = syntax::replace_outside("foo(\"foo\") /* foo */", lang, skip, |s| Ok(s.replace("foo", "bar"))).unwrap();
+        assert_eq!(result, "bar(\"foo\") /* bar */");
+    }
+}
@@ -0,0 +1,485 @@
+use anyhow::{Result, anyhow};
+use crate::binary::{self, Detection};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The first two bytes of every gzip member
+const MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Base lengths of length codes 257..285, and their extra bits
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+
+/// Base distances of distance codes 0..29, and their extra bits
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097,
+    6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+/// Order in which code length code lengths are stored in a dynamic block
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 { 0xedb88320 ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+}
+
+/// CRC-32 of some data, as stored in gzip trailers and zip headers
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &b| CRC_TABLE[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8))
+}
+
+/// Check whether data starts like a gzip stream
+pub fn is_gzip(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+/// Read a file as text, decompressing it first if it is gzip-compressed and
+/// decompression is enabled (--decompress)
+///
+/// # Arguments
+/// * `path` - File to read
+/// * `decompress` - Whether gzip files are decompressed
+/// * `detection` - How text is told apart from binary
+///
+/// # Returns
+/// * `io::Result<(String, bool)>` - The text, and whether it was compressed
+pub fn read_text(path: &Path, decompress: bool, detection: Detection) -> io::Result<(String, bool)> {
+    let data = fs::read(path)?;
+    let compressed = decompress && is_gzip(&data);
+    let data = if compressed {
+        self::decompress(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{:?}: {}", path, e)))?
+    } else {
+        data
+    };
+    let text = binary::decode(data, detection)?;
+
+    Ok((text, compressed))
+}
+
+/// The bytes to write for some text, compressed again if it was read compressed
+pub fn encode(text: &str, compressed: bool) -> Vec<u8> {
+    if compressed {
+        compress(text.as_bytes())
+    } else {
+        text.as_bytes().to_vec()
+    }
+}
+
+/// Decompress a gzip stream (all of its members)
+///
+/// # Arguments
+/// * `data` - Compressed bytes
+///
+/// # Returns
+/// * `Result<Vec<u8>>` - Decompressed bytes
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut pos = 0;
+
+    while pos < data.len() {
+        let member = &data[pos..];
+        // Some tools pad the stream with zeros
+        if member.iter().all(|&b| b == 0) {
+            break;
+        }
+        if !is_gzip(member) || member.get(2) != Some(&8) {
+            return Err(anyhow!("Not a gzip (deflate) stream at byte {}", pos));
+        }
+        let flags = *member.get(3).ok_or_else(|| anyhow!("Truncated gzip header"))?;
+
+        // Skip the optional header fields
+        let mut header = 10;
+        if flags & 0x04 != 0 {
+            let len = member.get(header..header + 2).ok_or_else(|| anyhow!("Truncated gzip header"))?;
+            header += 2 + u16::from_le_bytes([len[0], len[1]]) as usize;
+        }
+        for flag in [0x08, 0x10] {
+            if flags & flag != 0 {
+                let end = member.get(header..).and_then(|r| r.iter().position(|&b| b == 0))
+                    .ok_or_else(|| anyhow!("Truncated gzip header"))?;
+                header += end + 1;
+            }
+        }
+        if flags & 0x02 != 0 {
+            header += 2;
+        }
+
+        let start = out.len();
+        let mut reader = BitReader { data: member, pos: header, bits: 0, count: 0 };
+        inflate(&mut reader, &mut out)?;
+
+        let trailer = member.get(reader.pos..reader.pos + 8).ok_or_else(|| anyhow!("Truncated gzip trailer"))?;
+        let crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+        if crc != crc32(&out[start..]) {
+            return Err(anyhow!("gzip checksum mismatch"));
+        }
+        pos += reader.pos + 8;
+    }
+
+    Ok(out)
+}
+
+/// Compress data as a single gzip member
+///
+/// # Arguments
+/// * `data` - Bytes to compress
+///
+/// # Returns
+/// * `Vec<u8>` - Compressed bytes
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff];
+    out.extend_from_slice(&deflate(data));
+    out.extend_from_slice(&crc32(data).to_le_bytes());
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out
+}
+
+/// Compress data as a raw deflate stream (fixed Huffman codes with LZ77 matching)
+///
+/// # Arguments
+/// * `data` - Bytes to compress
+///
+/// # Returns
+/// * `Vec<u8>` - Deflate stream
+pub fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter { out: Vec::new(), bits: 0, count: 0 };
+    // A single final block with fixed codes
+    writer.put(1, 1);
+    writer.put(1, 2);
+
+    const WINDOW: usize = 32768;
+    const HASH_SIZE: usize = 1 << 15;
+    const MAX_CHAIN: usize = 64;
+    let hash = |i: usize| {
+        ((data[i] as usize) << 10 ^ (data[i + 1] as usize) << 5 ^ data[i + 2] as usize) & (HASH_SIZE - 1)
+    };
+    let mut head = vec![usize::MAX; HASH_SIZE];
+    let mut prev = vec![usize::MAX; data.len()];
+
+    let mut i = 0;
+    while i < data.len() {
+        let mut best = (0, 0);
+        if i + 3 <= data.len() {
+            let h = hash(i);
+            let mut candidate = head[h];
+            let mut chain = 0;
+            while candidate != usize::MAX && i - candidate <= WINDOW && chain < MAX_CHAIN {
+                let len = data[candidate..].iter().zip(&data[i..]).take(258).take_while(|(a, b)| a == b).count();
+                if len > best.0 {
+                    best = (len, i - candidate);
+                }
+                candidate = prev[candidate];
+                chain += 1;
+            }
+            prev[i] = head[h];
+            head[h] = i;
+        }
+
+        if best.0 >= 3 {
+            let (len, dist) = best;
+            let code = LENGTH_BASE.iter().rposition(|&base| base as usize <= len).unwrap_or(0);
+            writer.put_literal(257 + code as u16);
+            writer.put((len - LENGTH_BASE[code] as usize) as u32, LENGTH_EXTRA[code]);
+            let code = DIST_BASE.iter().rposition(|&base| base as usize <= dist).unwrap_or(0);
+            writer.put_code(code as u32, 5);
+            writer.put((dist - DIST_BASE[code] as usize) as u32, DIST_EXTRA[code]);
+
+            // Index the skipped positions so later matches can refer to them
+            let end = (i + len).min(data.len().saturating_sub(2));
+            for (j, prev) in prev.iter_mut().enumerate().take(end).skip(i + 1) {
+                let h = hash(j);
+                *prev = head[h];
+                head[h] = j;
+            }
+            i += len;
+        } else {
+            writer.put_literal(data[i] as u16);
+            i += 1;
+        }
+    }
+    writer.put_literal(256);
+
+    writer.finish()
+}
+
+/// Decompress a raw deflate stream
+///
+/// # Arguments
+/// * `data` - Deflate stream
+///
+/// # Returns
+/// * `Result<Vec<u8>>` - Decompressed bytes
+pub fn inflate_raw(data: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    inflate(&mut BitReader { data, pos: 0, bits: 0, count: 0 }, &mut out)?;
+    Ok(out)
+}
+
+/// Reads bits least-significant first, as deflate stores them
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bits: u32,
+    count: u8,
+}
+
+impl BitReader<'_> {
+    fn bits(&mut self, n: u8) -> Result<u32> {
+        while self.count < n {
+            let byte = *self.data.get(self.pos).ok_or_else(|| anyhow!("Unexpected end of deflate stream"))?;
+            self.bits |= (byte as u32) << self.count;
+            self.pos += 1;
+            self.count += 8;
+        }
+        let value = self.bits & ((1u64 << n) - 1) as u32;
+        self.bits >>= n;
+        self.count -= n;
+        Ok(value)
+    }
+
+    /// Drop the bits left in the current byte
+    fn align(&mut self) {
+        self.bits = 0;
+        self.count = 0;
+    }
+}
+
+/// Canonical Huffman code, decoded one bit at a time
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0u16; 16];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut offsets = [0u16; 16];
+        for len in 1..15 {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..16 {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[len] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(anyhow!("Invalid Huffman code in deflate stream"))
+    }
+}
+
+/// Decompress a raw deflate stream
+fn inflate(reader: &mut BitReader, out: &mut Vec<u8>) -> Result<()> {
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => {
+                // Stored block
+                reader.align();
+                let header = reader.data.get(reader.pos..reader.pos + 4).ok_or_else(|| anyhow!("Truncated stored block"))?;
+                let len = u16::from_le_bytes([header[0], header[1]]) as usize;
+                let block = reader.data.get(reader.pos + 4..reader.pos + 4 + len)
+                    .ok_or_else(|| anyhow!("Truncated stored block"))?;
+                out.extend_from_slice(block);
+                reader.pos += 4 + len;
+            },
+            1 => {
+                let mut lengths = [0u8; 288];
+                lengths[..144].fill(8);
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                lengths[280..].fill(8);
+                inflate_block(reader, out, &Huffman::new(&lengths), &Huffman::new(&[5; 30]))?;
+            },
+            2 => {
+                let (literals, distances) = dynamic_codes(reader)?;
+                inflate_block(reader, out, &literals, &distances)?;
+            },
+            _ => return Err(anyhow!("Invalid deflate block type")),
+        }
+
+        if last {
+            // The trailer starts at the next byte
+            reader.align();
+            return Ok(());
+        }
+    }
+}
+
+/// Read the Huffman codes of a dynamic block
+fn dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman)> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+
+    let mut code_lengths = [0u8; 19];
+    for &index in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[index] = reader.bits(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&code_lengths);
+
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let symbol = code_length_code.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => (*lengths.last().ok_or_else(|| anyhow!("Invalid code lengths"))?, 3 + reader.bits(2)?),
+            17 => (0, 3 + reader.bits(3)?),
+            _ => (0, 11 + reader.bits(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(value, repeat as usize));
+    }
+    if lengths.len() > literal_count + distance_count {
+        return Err(anyhow!("Invalid code lengths"));
+    }
+
+    Ok((Huffman::new(&lengths[..literal_count]), Huffman::new(&lengths[literal_count..])))
+}
+
+/// Decode the symbols of a compressed block
+fn inflate_block(reader: &mut BitReader, out: &mut Vec<u8>, literals: &Huffman, distances: &Huffman) -> Result<()> {
+    loop {
+        let symbol = literals.decode(reader)? as usize;
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let code = symbol - 257;
+                let base = *LENGTH_BASE.get(code).ok_or_else(|| anyhow!("Invalid length code"))?;
+                let len = base as usize + reader.bits(LENGTH_EXTRA[code])? as usize;
+
+                let code = distances.decode(reader)? as usize;
+                let base = *DIST_BASE.get(code).ok_or_else(|| anyhow!("Invalid distance code"))?;
+                let dist = base as usize + reader.bits(DIST_EXTRA[code])? as usize;
+                if dist > out.len() {
+                    return Err(anyhow!("Invalid distance in deflate stream"));
+                }
+
+                // The copy may overlap the bytes it produces
+                let start = out.len() - dist;
+                for k in 0..len {
+                    out.push(out[start + k]);
+                }
+            },
+        }
+    }
+}
+
+/// Writes bits least-significant first
+struct BitWriter {
+    out: Vec<u8>,
+    bits: u32,
+    count: u8,
+}
+
+impl BitWriter {
+    fn put(&mut self, value: u32, n: u8) {
+        self.bits |= value << self.count;
+        self.count += n;
+        while self.count >= 8 {
+            self.out.push(self.bits as u8);
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Write a Huffman code, which is stored most-significant bit first
+    fn put_code(&mut self, code: u32, n: u8) {
+        self.put(code.reverse_bits() >> (32 - n), n);
+    }
+
+    /// Write a literal/length symbol with the fixed code
+    fn put_literal(&mut self, symbol: u16) {
+        let symbol = symbol as u32;
+        match symbol {
+            0..=143 => self.put_code(0x30 + symbol, 8),
+            144..=255 => self.put_code(0x190 + symbol - 144, 9),
+            256..=279 => self.put_code(symbol - 256, 7),
+            _ => self.put_code(0xc0 + symbol - 280, 8),
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.bits as u8);
+        }
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decompress() {
+        // Written by Python's gzip module
+        let data = [
+            0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xcb, 0xcd, 0x2c, 0x2e, 0x06, 0xa1, 0x82, 0x82,
+            0x4c, 0x85, 0xa2, 0xcc, 0xb2, 0xd4, 0x22, 0x1d, 0x85, 0x5c, 0x24, 0xa1, 0xe2, 0x92, 0xc4, 0x92, 0x54, 0x6b,
+            0x14, 0x21, 0x4a, 0x55, 0x01, 0x39, 0x60, 0x0c, 0x92, 0xe2, 0x02, 0x00, 0x50, 0xba, 0xaa, 0x04, 0x7f, 0x00,
+            0x00, 0x00,
+        ];
+        let expected = "mississippi river, mississippi state; ".repeat(3) + "sip sip miss\n";
+        assert_eq!(decompress(&data).unwrap(), expected.as_bytes());
+
+        // A stored block
+        let mut stored = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff, 0x01, 3, 0, 0xfc, 0xff];
+        stored.extend_from_slice(b"abc");
+        stored.extend_from_slice(&crc32(b"abc").to_le_bytes());
+        stored.extend_from_slice(&3u32.to_le_bytes());
+        assert_eq!(decompress(&stored).unwrap(), b"abc");
+
+        assert!(decompress(b"plain text").is_err());
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let text = "foo bar foo bar foo\n".repeat(500) + "the end";
+        let compressed = compress(text.as_bytes());
+        assert!(is_gzip(&compressed));
+        assert!(compressed.len() < text.len() / 10);
+        assert_eq!(decompress(&compressed).unwrap(), text.as_bytes());
+        assert_eq!(decompress(&compress(b"")).unwrap(), b"");
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+    }
+}
@@ -0,0 +1,107 @@
+use crate::args::{Args, Mode};
+use anyhow::{Result, Context, anyhow};
+use clap::ValueEnum;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// What to do when a hook command fails
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq)]
+pub enum HookErrorPolicy {
+    /// Stop with an error
+    #[default]
+    Fail,
+
+    /// Report the failure and carry on
+    Warn,
+}
+
+impl HookErrorPolicy {
+    /// Parse a policy name as used in the config file
+    pub fn from_name(name: &str) -> Option<Self> {
+        HookErrorPolicy::from_str(name, true).ok()
+    }
+}
+
+/// When a hook runs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stage {
+    Pre,
+    Post,
+}
+
+impl Stage {
+    fn name(self) -> &'static str {
+        match self {
+            Stage::Pre => "pre",
+            Stage::Post => "post",
+        }
+    }
+}
+
+/// Run the hook commands of a stage
+///
+/// Each command runs through the shell in the current directory, with
+/// `MANE_TARGET` (the copy target, or the first path processed) and
+/// `MANE_HOOK` (`pre` or `post`) set. Hook output goes to stderr so it never
+/// mixes with replaced content on stdout.
+///
+/// # Arguments
+/// * `args` - Command line arguments
+/// * `stage` - Which hooks to run
+///
+/// # Returns
+/// * `Result<()>` - Ok unless a hook failed and failures are fatal
+pub fn run_hooks(args: &Args, stage: Stage) -> Result<()> {
+    let commands = match stage {
+        Stage::Pre => &args.pre_hooks,
+        Stage::Post => &args.post_hooks,
+    };
+    if commands.is_empty() {
+        return Ok(());
+    }
+
+    let target = hook_target(args);
+    for command in commands {
+        verbose!("Running {}-hook: {}", stage.name(), command);
+
+        let status = shell(command)
+            .env("MANE_TARGET", &target)
+            .env("MANE_HOOK", stage.name())
+            .stdout(std::io::stderr())
+            .status()
+            .with_context(|| format!("Failed to run {}-hook: {}", stage.name(), command))?;
+
+        if !status.success() {
+            let message = format!("{}-hook failed ({}): {}", stage.name(), status, command);
+            match args.hook_errors.unwrap_or_default() {
+                HookErrorPolicy::Fail => return Err(anyhow!(message)),
+                HookErrorPolicy::Warn => warn!("{}", message),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The path exposed to hooks as MANE_TARGET
+fn hook_target(args: &Args) -> PathBuf {
+    match args.mode {
+        Mode::Copy => args.copy_specs.last().map(|spec| spec.target.clone()),
+        _ => args.files.first().cloned(),
+    }
+    .unwrap_or_else(|| PathBuf::from("."))
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(command);
+    cmd
+}
+
+#[cfg(not(unix))]
+fn shell(command: &str) -> Command {
+    let mut cmd = Command::new("cmd");
+    cmd.arg("/C").arg(command);
+    cmd
+}
@@ -0,0 +1,181 @@
+use crate::json::{self, Value};
+use crate::sha256;
+use anyhow::{Result, Context, anyhow};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Name of the cache file kept in the copy target
+pub const CACHE_FILE: &str = ".mane-incremental.json";
+
+const CACHE_VERSION: usize = 1;
+
+/// Fingerprints of the files written by the previous copy (--incremental)
+///
+/// A target file whose source and rules have the same fingerprint as last time
+/// is left as it is instead of being copied again.
+#[derive(Debug)]
+pub struct Cache {
+    /// The cache file
+    path: PathBuf,
+
+    /// Fingerprints recorded by the previous run, by target path
+    previous: HashMap<String, String>,
+
+    /// Fingerprints of this run, by target path
+    current: HashMap<String, String>,
+
+    /// Directories that copied source directories were written to, by source path
+    previous_roots: HashMap<String, String>,
+
+    /// Directories that source directories are written to by this run
+    current_roots: HashMap<String, String>,
+}
+
+impl Cache {
+    /// Load the cache of a copy target, or start an empty one
+    ///
+    /// # Arguments
+    /// * `dir` - Directory the cache file is kept in
+    ///
+    /// # Returns
+    /// * `Result<Cache>` - The cache
+    pub fn load(dir: &Path) -> Result<Cache> {
+        let path = dir.join(CACHE_FILE);
+        let mut previous = HashMap::new();
+        let mut previous_roots = HashMap::new();
+
+        if path.exists() {
+            let text = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read cache file: {:?}", path))?;
+            let document = json::parse(&text)
+                .with_context(|| format!("Invalid cache file: {:?}", path))?;
+            if document.get("version") == Some(&Value::from(CACHE_VERSION)) {
+                for (key, map) in [("files", &mut previous), ("roots", &mut previous_roots)] {
+                    if let Some(Value::Object(entries)) = document.get(key) {
+                        for (name, value) in entries {
+                            let value = value.as_str()
+                                .ok_or_else(|| anyhow!("Invalid cache entry for {} in {:?}", name, path))?;
+                            map.insert(name.clone(), value.to_string());
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(Cache { path, previous, current: HashMap::new(), previous_roots, current_roots: HashMap::new() })
+    }
+
+    /// The directory the cache file is kept in
+    fn dir(&self) -> &Path {
+        self.path.parent().unwrap_or(Path::new(""))
+    }
+
+    /// Key of a target path, relative to the cache directory when possible
+    fn key(&self, target: &Path) -> String {
+        target.strip_prefix(self.dir()).unwrap_or(target).to_string_lossy().to_string()
+    }
+
+    /// The directory a source directory was copied to last time
+    ///
+    /// Copying a directory to an existing directory creates a subdirectory, so
+    /// without this a second run would copy into the output of the first.
+    ///
+    /// # Arguments
+    /// * `source` - Source directory
+    ///
+    /// # Returns
+    /// * `Option<PathBuf>` - The directory, if it still exists
+    pub fn root(&self, source: &Path) -> Option<PathBuf> {
+        self.previous_roots.get(&source.to_string_lossy().to_string())
+            .map(|key| self.dir().join(key))
+            .filter(|root| root.is_dir())
+    }
+
+    /// Record the directory a source directory is copied to
+    pub fn set_root(&mut self, source: &Path, root: &Path) {
+        let key = self.key(root);
+        self.current_roots.insert(source.to_string_lossy().to_string(), key);
+    }
+
+    /// Check whether a target is up to date, and record its fingerprint for this run
+    ///
+    /// # Arguments
+    /// * `target` - Target file path
+    /// * `fingerprint` - Fingerprint of its source and rules
+    ///
+    /// # Returns
+    /// * `bool` - True if the target exists and had the same fingerprint last time
+    pub fn check(&mut self, target: &Path, fingerprint: String) -> bool {
+        let key = self.key(target);
+        let fresh = target.exists() && self.previous.get(&key) == Some(&fingerprint);
+        self.current.insert(key, fingerprint);
+        fresh
+    }
+
+    /// Write the fingerprints of this run
+    ///
+    /// # Returns
+    /// * `Result<()>` - Result of the operation
+    pub fn save(&self) -> Result<()> {
+        let sorted = |map: &HashMap<String, String>| {
+            let mut entries: Vec<(String, Value)> = map.iter()
+                .map(|(key, value)| (key.clone(), Value::from(value.as_str())))
+                .collect();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            Value::Object(entries)
+        };
+
+        let document = json::object([
+            ("version", Value::from(CACHE_VERSION)),
+            ("roots", sorted(&self.current_roots)),
+            ("files", sorted(&self.current)),
+        ]);
+        fs::write(&self.path, document.to_pretty_string() + "\n")
+            .with_context(|| format!("Failed to write cache file: {:?}", self.path))
+    }
+}
+
+/// Fingerprint of a copied file: its source content and everything that transforms it
+///
+/// # Arguments
+/// * `source` - Source file path
+/// * `settings` - Rules and options the content and name are transformed with
+///
+/// # Returns
+/// * `io::Result<String>` - Hex digest
+pub fn fingerprint(source: &Path, settings: &str) -> io::Result<String> {
+    let mut data = settings.as_bytes().to_vec();
+    data.push(0);
+    data.extend(fs::read(source)?);
+    Ok(sha256::hex_digest(&data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::TestDir;
+
+    #[test]
+    fn test_cache() {
+        let dir = TestDir::new("incremental");
+        let source = dir.join("source.txt");
+        let target = dir.join("target.txt");
+        fs::write(&source, "foo").unwrap();
+        fs::write(&target, "bar").unwrap();
+
+        let fp = fingerprint(&source, "foo=>bar").unwrap();
+        assert_ne!(fp, fingerprint(&source, "foo=>baz").unwrap());
+
+        let mut cache = Cache::load(&dir).unwrap();
+        assert!(!cache.check(&target, fp.clone()));
+        cache.set_root(Path::new("template"), &dir);
+        cache.save().unwrap();
+
+        let mut cache = Cache::load(&dir).unwrap();
+        assert!(cache.check(&target, fp.clone()));
+        assert_eq!(cache.root(Path::new("template")), Some(dir.join("")));
+        assert!(!cache.check(&dir.join("missing.txt"), fp));
+    }
+}
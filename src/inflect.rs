@@ -0,0 +1,167 @@
+use crate::args::ReplacementRule;
+use crate::case;
+
+/// Nouns whose plural doesn't follow a suffix rule
+const IRREGULAR: &[(&str, &str)] = &[
+    ("person", "people"),
+    ("child", "children"),
+    ("man", "men"),
+    ("woman", "women"),
+    ("mouse", "mice"),
+    ("goose", "geese"),
+    ("foot", "feet"),
+    ("tooth", "teeth"),
+    ("ox", "oxen"),
+    ("index", "indices"),
+    ("matrix", "matrices"),
+    ("vertex", "vertices"),
+    ("criterion", "criteria"),
+    ("datum", "data"),
+    ("medium", "media"),
+    ("quiz", "quizzes"),
+];
+
+/// Nouns that are the same in singular and plural
+const UNCOUNTABLE: &[&str] = &[
+    "data", "metadata", "information", "equipment", "series", "species", "news",
+    "sheep", "fish", "deer", "software", "hardware", "feedback", "media",
+];
+
+/// Words ending in `o` that take `-es`
+const O_ES: &[&str] = &["hero", "potato", "tomato", "echo", "veto", "torpedo"];
+
+/// Plural of a lowercase English noun
+///
+/// # Arguments
+/// * `word` - Singular noun in lowercase
+///
+/// # Returns
+/// * `String` - Its plural
+pub fn pluralize_word(word: &str) -> String {
+    if UNCOUNTABLE.contains(&word) {
+        return word.to_string();
+    }
+    if let Some((_, plural)) = IRREGULAR.iter().find(|(singular, _)| *singular == word) {
+        return plural.to_string();
+    }
+
+    let before_last = |n: usize| word.chars().rev().nth(n);
+    let is_vowel = |c: Option<char>| c.is_some_and(|c| "aeiou".contains(c));
+
+    if ["s", "x", "z", "ch", "sh"].iter().any(|suffix| word.ends_with(suffix)) {
+        // analysis -> analyses, but bus -> buses
+        if let Some(stem) = word.strip_suffix("is").filter(|stem| stem.len() > 1) {
+            return format!("{}es", stem);
+        }
+        return format!("{}es", word);
+    }
+    if let Some(stem) = word.strip_suffix('y').filter(|_| !is_vowel(before_last(1))) {
+        return format!("{}ies", stem);
+    }
+    if let Some(stem) = word.strip_suffix("fe") {
+        return format!("{}ves", stem);
+    }
+    if word.ends_with("lf") || word.ends_with("af") {
+        return format!("{}ves", &word[..word.len() - 1]);
+    }
+    if O_ES.contains(&word) {
+        return format!("{}es", word);
+    }
+    format!("{}s", word)
+}
+
+/// Plural of an identifier, inflecting its last word in that word's capitalization
+///
+/// # Arguments
+/// * `s` - Identifier such as `user`, `UserAccount` or `ORDER_ITEM`
+///
+/// # Returns
+/// * `Option<String>` - Its plural (`users`, `UserAccounts`, `ORDER_ITEMS`), or None if it has no English last word
+pub fn pluralize(s: &str) -> Option<String> {
+    let last = case::words(s).pop()?;
+    if !s.is_ascii() || !last.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+    let (stem, original) = s.split_at(s.len().checked_sub(last.len())?);
+    if !original.eq_ignore_ascii_case(&last) {
+        return None;
+    }
+
+    let plural = pluralize_word(&last);
+    let rendered = if original.len() > 1 && original.chars().all(|c| !c.is_ascii_lowercase()) {
+        plural.to_uppercase()
+    } else if original.starts_with(|c: char| c.is_ascii_uppercase()) {
+        let mut chars = plural.chars();
+        chars.next().map_or_else(String::new, |first| first.to_ascii_uppercase().to_string() + chars.as_str())
+    } else {
+        plural
+    };
+    Some(format!("{}{}", stem, rendered))
+}
+
+/// Add a rule for the plural forms of every rule (--plurals)
+///
+/// Each plural rule goes right before its singular one, so `boxes` becomes
+/// `crates` rather than `cratees`. Plurals that plain substring replacement
+/// already gets right (`users` -> `customers`) and rules whose plural FROM is
+/// already a rule are skipped.
+///
+/// # Arguments
+/// * `rules` - Rules in the order they are applied
+///
+/// # Returns
+/// * `Vec<ReplacementRule>` - The rules with plural companions
+pub fn with_plurals(rules: Vec<ReplacementRule>) -> Vec<ReplacementRule> {
+    let froms: Vec<String> = rules.iter().map(|rule| rule.from.clone()).collect();
+    let mut result = Vec::new();
+
+    for rule in rules {
+        let plurals = pluralize(&rule.from).zip(pluralize(&rule.to))
+            .filter(|_| !rule.to.contains("{{"))
+            .filter(|(from, to)| *from != format!("{}s", rule.from) || *to != format!("{}s", rule.to))
+            .filter(|(from, _)| *from != rule.from && !froms.contains(from));
+        if let Some((from, to)) = plurals {
+            debug!("Adding plural rule '{}' -> '{}'", from, to);
+            result.push(ReplacementRule { from, to, scope: rule.scope.clone() });
+        }
+        result.push(rule);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pluralize() {
+        assert_eq!(pluralize("user").as_deref(), Some("users"));
+        assert_eq!(pluralize("box").as_deref(), Some("boxes"));
+        assert_eq!(pluralize("category").as_deref(), Some("categories"));
+        assert_eq!(pluralize("key").as_deref(), Some("keys"));
+        assert_eq!(pluralize("person").as_deref(), Some("people"));
+        assert_eq!(pluralize("analysis").as_deref(), Some("analyses"));
+        assert_eq!(pluralize("knife").as_deref(), Some("knives"));
+        assert_eq!(pluralize("UserAccount").as_deref(), Some("UserAccounts"));
+        assert_eq!(pluralize("ORDER_ITEM").as_deref(), Some("ORDER_ITEMS"));
+        assert_eq!(pluralize("order-category").as_deref(), Some("order-categories"));
+        assert_eq!(pluralize("metadata").as_deref(), Some("metadata"));
+        assert_eq!(pluralize("v2"), None);
+    }
+
+    #[test]
+    fn test_with_plurals() {
+        let rules = with_plurals(vec![
+            ReplacementRule::new("box".to_string(), "crate".to_string()),
+            ReplacementRule::new("user".to_string(), "customer".to_string()),
+            ReplacementRule::new("person".to_string(), "member".to_string()),
+        ]);
+        let pairs: Vec<(&str, &str)> = rules.iter().map(|rule| (rule.from.as_str(), rule.to.as_str())).collect();
+        assert_eq!(pairs, [
+            ("boxes", "crates"), ("box", "crate"),
+            ("user", "customer"),
+            ("people", "members"), ("person", "member"),
+        ]);
+    }
+}
@@ -0,0 +1,137 @@
+use crate::config::CONFIG_FILE_NAME;
+use crate::json;
+use crate::toml;
+use anyhow::{Result, Context, anyhow};
+use std::fs;
+use std::path::Path;
+
+/// Write a starter config file in the current directory (`mane init`)
+///
+/// The file has commented example rules and options, and a `rename` profile.
+/// When the directory has a Cargo.toml or package.json, the profile holds a
+/// rule renaming the project, ready for its TO to be filled in.
+///
+/// # Arguments
+/// * `force` - Whether to overwrite an existing config file
+///
+/// # Returns
+/// * `Result<()>` - Error if the file exists (without `force`) or can't be written
+pub fn run(force: bool) -> Result<()> {
+    write_starter(Path::new("."), force)
+}
+
+/// Write the starter config file into a directory
+///
+/// # Arguments
+/// * `dir` - Directory of the project
+/// * `force` - Whether to overwrite an existing config file
+///
+/// # Returns
+/// * `Result<()>` - Error if the file exists (without `force`) or can't be written
+pub fn write_starter(dir: &Path, force: bool) -> Result<()> {
+    let path = dir.join(CONFIG_FILE_NAME);
+    if path.exists() && !force {
+        return Err(anyhow!("{} already exists; use --force to overwrite it", path.display()));
+    }
+
+    let project = detect_project_name(dir);
+    fs::write(&path, starter_config(project.as_ref().map(|(name, _)| name.as_str())))
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    info!("Wrote {}", path.display());
+    if let Some((name, manifest)) = &project {
+        info!("Added a rule renaming {} (from {}) to the rename profile", name, manifest);
+    }
+    Ok(())
+}
+
+/// The project's name, and the manifest it was read from
+///
+/// # Arguments
+/// * `dir` - Directory of the project
+///
+/// # Returns
+/// * `Option<(String, &str)>` - The `[package]` name of Cargo.toml, or else the
+///   `name` of package.json without its npm scope
+fn detect_project_name(dir: &Path) -> Option<(String, &'static str)> {
+    let read = |name: &str| fs::read_to_string(dir.join(name)).ok();
+    let cargo = read("Cargo.toml").and_then(|text| {
+        let document = toml::parse(&text).ok()?;
+        Some(document.get("package")?.get("name")?.as_str()?.to_string())
+    });
+    if let Some(name) = cargo {
+        return Some((name, "Cargo.toml"));
+    }
+    let npm = read("package.json").and_then(|text| {
+        let document = json::parse(&text).ok()?;
+        let name = document.get("name")?.as_str()?;
+        Some(name.rsplit('/').next().unwrap_or(name).to_string())
+    });
+    npm.filter(|name| !name.is_empty()).map(|name| (name, "package.json"))
+}
+
+/// The content of the starter config file
+///
+/// # Arguments
+/// * `project` - Name of the project, to prefill the rename profile with
+///
+/// # Returns
+/// * `String` - The commented config file
+fn starter_config(project: Option<&str>) -> String {
+    let rename_rule = match project {
+        Some(name) => format!("  {{ from = {}, to = \"new-name\" }},\n", json::quote(name)),
+        None => "  # { from = \"old-name\", to = \"new-name\" },\n".to_string(),
+    };
+    format!(r#"# mane config, read from the current directory (or given with --config).
+# Rules given with -r override the rules here with the same FROM.
+
+# Applied on every run, with their case variants
+rules = [
+  # {{ from = "Awesome", to = "Cool" }},
+  # {{ from = "awesome-lib", to = "cool-lib", paths = [".github/**", "*.yml"] }},
+]
+
+# Replace the case variants of each rule too
+case = true
+
+# Also walk the files git ignores (--include-git-ignore)
+include_git_ignore = false
+
+# Keep the names of matching paths, still replacing in their content (--no-rename-path)
+no_rename_paths = [
+  # "migrations/*",
+]
+
+# Selected with --profile rename: set the new name, then run `mane --profile rename -i .`
+[profiles.rename]
+rules = [
+{rename_rule}]
+"#)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::TestDir;
+
+    #[test]
+    fn test_starter_config() {
+        for project in [None, Some("my-app")] {
+            let document = toml::parse(&starter_config(project)).unwrap();
+            assert_eq!(document.get("case"), Some(&json::Value::Bool(true)));
+            let rules = document.get("profiles").and_then(|p| p.get("rename")).and_then(|p| p.get("rules")).unwrap();
+            assert_eq!(rules.as_array().unwrap().len(), project.iter().count());
+        }
+        assert!(starter_config(Some("my-app")).contains("{ from = \"my-app\", to = \"new-name\" },"));
+    }
+
+    #[test]
+    fn test_detect_project_name() {
+        let dir = TestDir::new("init");
+        assert_eq!(detect_project_name(&dir), None);
+
+        fs::write(dir.join("package.json"), r#"{ "name": "@acme/web-app", "version": "1.0.0" }"#).unwrap();
+        assert_eq!(detect_project_name(&dir), Some(("web-app".to_string(), "package.json")));
+        fs::write(dir.join("Cargo.toml"), "[package]\nname = \"billing\"\nversion = \"0.1.0\"\n").unwrap();
+        assert_eq!(detect_project_name(&dir), Some(("billing".to_string(), "Cargo.toml")));
+    }
+}
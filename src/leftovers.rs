@@ -0,0 +1,112 @@
+use crate::args::ReplacementRule;
+use crate::case;
+use crate::multiline;
+use crate::template;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Report placeholders and rule matches left in copied files (--check-unreplaced)
+///
+/// Each file is searched, and so is its path under the directory it was
+/// copied into, for `{{...}}` placeholders that weren't filled in and for the
+/// FROM spellings of the rules. A file with any is a warning, so `--strict`
+/// turns them into an error. Spellings a rule's TO contains are not searched,
+/// as the replacement itself has them.
+///
+/// # Arguments
+/// * `files` - Files the copy wrote
+/// * `roots` - Target directories the files were copied into
+/// * `rules` - Rules of the copy
+/// * `case_enabled` - Whether case variants are replaced as well
+///
+/// # Returns
+/// * `usize` - How many files have leftovers
+pub fn check(files: &[PathBuf], roots: &[PathBuf], rules: &[ReplacementRule], case_enabled: bool) -> usize {
+    let searches = search_strings(rules, case_enabled);
+    let mut reported = 0;
+    for file in files {
+        let relative = roots.iter()
+            .filter_map(|root| file.strip_prefix(root).ok())
+            .min_by_key(|relative| relative.as_os_str().len())
+            .unwrap_or_else(|| Path::new(file.file_name().unwrap_or_default()));
+
+        let mut found: Vec<String> = find(&relative.to_string_lossy(), &searches).into_iter()
+            .map(|(item, _)| format!("{} (path)", item))
+            .collect();
+        // Binary files only have their path checked
+        if let Ok(content) = fs::read_to_string(file) {
+            found.extend(find(&content, &searches).into_iter().map(|(item, line)| format!("{} (line {})", item, line)));
+        }
+        if !found.is_empty() {
+            warn!("Unreplaced in {}: {}", file.display(), found.join(", "));
+            reported += 1;
+        }
+    }
+    if reported == 0 {
+        info!("No unreplaced placeholders or rule matches in {} copied files", files.len());
+    }
+    reported
+}
+
+/// The FROM spellings the rules replace, without those their TO contains
+fn search_strings(rules: &[ReplacementRule], case_enabled: bool) -> Vec<String> {
+    let mut searches: Vec<String> = Vec::new();
+    for rule in rules {
+        let pairs = if !case_enabled || multiline::is_multiline(&rule.from) {
+            vec![(rule.from.clone(), rule.to.clone())]
+        } else {
+            case::variant_pairs(&rule.from, &rule.to)
+        };
+        for (from, to) in pairs {
+            if !to.contains(&from) && !searches.contains(&from) {
+                searches.push(from);
+            }
+        }
+    }
+    searches
+}
+
+/// Unfilled placeholders and search strings in a text, each once
+///
+/// # Arguments
+/// * `text` - Content or path to search
+/// * `searches` - Strings that shouldn't be left
+///
+/// # Returns
+/// * `Vec<(String, usize)>` - What was found, quoted, and the line it is first on
+fn find(text: &str, searches: &[String]) -> Vec<(String, usize)> {
+    let line_of = |offset: usize| text[..offset].matches('\n').count() + 1;
+    let mut found: Vec<(String, usize)> = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        for name in template::placeholder_names(line) {
+            let item = format!("{{{{{}}}}}", name);
+            if !found.iter().any(|(seen, _)| *seen == item) {
+                found.push((item, index + 1));
+            }
+        }
+    }
+    for search in searches {
+        if let Some(offset) = text.find(search.as_str()) {
+            found.push((format!("{:?}", search), line_of(offset)));
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find() {
+        let rules = [ReplacementRule::new("fooBar".to_string(), "bazQux".to_string())];
+        let searches = search_strings(&rules, true);
+        let text = "let bazQux = 1;\nconst FOO_BAR: u8 = {{ version }};\n";
+        assert_eq!(find(text, &searches), [("{{version}}".to_string(), 2), ("\"FOO_BAR\"".to_string(), 2)]);
+        assert_eq!(find("let bazQux = 1;\n", &searches), []);
+
+        // A TO that contains its FROM leaves it on purpose
+        let rules = [ReplacementRule::new("api".to_string(), "api_v2".to_string())];
+        assert!(search_strings(&rules, true).is_empty());
+    }
+}
@@ -0,0 +1,24 @@
+// The replacement core, without file system or terminal access, so it also
+// builds for wasm32-unknown-unknown. The `mane` binary is built on top of it,
+// and its `cdylib` builds are loaded by js/mane.js and C callers through
+// include/mane.h, and imported as a Python module with `--features python`.
+
+pub mod case;
+pub mod engine;
+pub mod multiline;
+
+/// Functions exported by the `cdylib` builds of the library
+///
+/// Strings cross the boundary as UTF-8 bytes: the caller copies them into
+/// buffers from `mane_alloc`, and results come back as a buffer holding a
+/// little-endian `u32` length followed by the bytes, which the caller
+/// releases with `mane_free`. The same functions serve the wasm32 module
+/// (wrapped by `js/mane.js`) and native shared libraries.
+pub mod exports;
+
+/// C API for embedding the engine in native tools, declared in `include/mane.h`
+pub mod ffi;
+
+/// The `mane` Python module, built with maturin from `python/pyproject.toml`
+#[cfg(feature = "python")]
+mod python;
@@ -0,0 +1,137 @@
+use crate::args::ReplacementRule;
+use crate::case;
+
+/// Find rules that override each other (the same FROM with different TOs)
+///
+/// # Arguments
+/// * `rules` - Rules in the order given
+///
+/// # Returns
+/// * `Vec<String>` - One message per overridden rule
+pub fn duplicate_froms(rules: &[ReplacementRule]) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    for (i, rule) in rules.iter().enumerate() {
+        if let Some(later) = rules[i + 1..].iter().find(|r| r.from == rule.from && r.to != rule.to) {
+            problems.push(format!(
+                "Rule '{}' -> '{}' is overridden by '{}' -> '{}'",
+                rule.from, rule.to, later.from, later.to));
+        }
+    }
+
+    problems
+}
+
+/// Find rules whose TO contains their own FROM, so every run adds another copy
+///
+/// # Arguments
+/// * `rules` - Rules to check
+/// * `case_enabled` - Whether case variants are replaced as well
+///
+/// # Returns
+/// * `Vec<String>` - One message per self-amplifying rule
+pub fn self_amplifying(rules: &[ReplacementRule], case_enabled: bool) -> Vec<String> {
+    rules.iter()
+        .filter(|rule| {
+            let pairs = if case_enabled {
+                case::variant_pairs(&rule.from, &rule.to)
+            } else {
+                vec![(rule.from.clone(), rule.to.clone())]
+            };
+            pairs.iter().any(|(_, to)| pairs.iter().any(|(from, _)| to.contains(from.as_str())))
+        })
+        .map(|rule| format!(
+            "Rule '{}' -> '{}' is self-amplifying: its TO contains its FROM, so every run replaces again",
+            rule.from, rule.to))
+        .collect()
+}
+
+/// Analyze a rule set for rules that interfere with each other
+///
+/// Reports:
+/// * a rule whose TO is rewritten by a later rule (with --ordered)
+/// * different rules whose case variants collide but map to different targets
+///
+/// # Arguments
+/// * `rules` - Rules in the order they are applied
+/// * `case_enabled` - Whether case variants are replaced as well
+/// * `ordered` - Whether rules apply one after another (--ordered) rather than in one pass
+///
+/// # Returns
+/// * `Vec<String>` - One message per problem found
+pub fn lint_rules(rules: &[ReplacementRule], case_enabled: bool, ordered: bool) -> Vec<String> {
+    let pairs: Vec<Vec<(String, String)>> = rules.iter()
+        .map(|rule| if case_enabled {
+            case::variant_pairs(&rule.from, &rule.to)
+        } else {
+            vec![(rule.from.clone(), rule.to.clone())]
+        })
+        .collect();
+
+    let mut problems = Vec::new();
+
+    for (i, rule) in rules.iter().enumerate() {
+        for (j, later) in rules.iter().enumerate().skip(i + 1) {
+            // Two path-scoped rules are normally meant for different files
+            if rule.scope.is_some() && later.scope.is_some() {
+                continue;
+            }
+
+            // With --ordered, the output of rule i is fed into every later rule
+            let rewritten = pairs[i].iter().filter(|_| ordered).find_map(|(_, to)| {
+                pairs[j].iter().find(|(from, _)| to.contains(from.as_str())).map(|(from, _)| from)
+            });
+            if let Some(from) = rewritten {
+                problems.push(format!(
+                    "The TO of rule '{}' -> '{}' contains '{}', which the later rule '{}' -> '{}' rewrites",
+                    rule.from, rule.to, from, later.from, later.to));
+            }
+
+            // Two rules claiming the same spelling for different targets
+            if rule.from != later.from {
+                let collision = pairs[i].iter().find(|(from, to)| {
+                    pairs[j].iter().any(|(other_from, other_to)| other_from == from && other_to != to)
+                });
+                if let Some((from, _)) = collision {
+                    problems.push(format!(
+                        "Rules '{}' -> '{}' and '{}' -> '{}' both match '{}' but replace it differently",
+                        rule.from, rule.to, later.from, later.to, from));
+                }
+            }
+        }
+    }
+
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(from: &str, to: &str) -> ReplacementRule {
+        ReplacementRule::new(from.to_string(), to.to_string())
+    }
+
+    #[test]
+    fn test_lint_rules() {
+        assert!(lint_rules(&[rule("foo", "bar"), rule("baz", "qux")], true, true).is_empty());
+
+        let chained = lint_rules(&[rule("foo", "bar"), rule("bar", "baz")], true, true);
+        assert!(lint_rules(&[rule("foo", "bar"), rule("bar", "baz")], true, false).is_empty());
+        assert_eq!(chained.len(), 1);
+        assert!(chained[0].contains("later rule 'bar' -> 'baz'"));
+        // Only later rules see the output of earlier ones
+        assert!(lint_rules(&[rule("bar", "baz"), rule("foo", "bar")], true, true).is_empty());
+
+        let collision = lint_rules(&[rule("foo-bar", "x-y"), rule("FooBar", "Alpha")], true, true);
+        assert!(collision.iter().any(|p| p.contains("replace it differently")));
+        assert!(lint_rules(&[rule("foo-bar", "x-y"), rule("FooBar", "Alpha")], false, true).is_empty());
+
+        assert_eq!(self_amplifying(&[rule("abc", "abcabc"), rule("foo", "bar")], true).len(), 1);
+        assert_eq!(self_amplifying(&[rule("foo-bar", "FooBar2")], true).len(), 1);
+        assert!(self_amplifying(&[rule("foo-bar", "FooBar2")], false).is_empty());
+
+        let duplicates = duplicate_froms(&[rule("a", "b"), rule("a", "c"), rule("a", "c")]);
+        assert_eq!(duplicates, vec!["Rule 'a' -> 'b' is overridden by 'a' -> 'c'"]);
+    }
+}
@@ -0,0 +1,188 @@
+use anyhow::{Result, Context, anyhow};
+use std::fs::{self, File, OpenOptions};
+use std::path::{Path, PathBuf};
+
+/// Name of the advisory lock file created in the processed tree
+pub const LOCK_FILE_NAME: &str = ".mane.lock";
+
+/// Advisory lock held for the duration of an in-place run on a tree
+///
+/// The lock is released (and the lock file removed) when the value is dropped.
+#[derive(Debug)]
+pub struct TreeLock {
+    #[cfg(not(unix))]
+    path: PathBuf,
+    // Kept open so the flock stays held until drop
+    _file: File,
+    // The directory holding the lock file, which follows it when `-i` renames it
+    #[cfg(unix)]
+    dir: File,
+}
+
+impl TreeLock {
+    /// Acquire the lock for the tree rooted at `root`
+    ///
+    /// # Arguments
+    /// * `root` - File or directory being processed; files are locked via their parent directory
+    ///
+    /// # Returns
+    /// * `Result<TreeLock>` - The held lock, or an error if another mane process holds it
+    pub fn acquire(root: &Path) -> Result<TreeLock> {
+        let dir = lock_dir(root);
+        let path = dir.join(LOCK_FILE_NAME);
+
+        loop {
+            let file = OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(&path)
+                .with_context(|| format!("Failed to create lock file: {:?}", path))?;
+
+            if !try_lock(&file) {
+                return Err(anyhow!(
+                    "Another mane process is already running on {:?} (lock file: {:?})",
+                    dir, path
+                ));
+            }
+
+            // The holder we waited on removes the file as it lets go, so the
+            // lock only counts if it is still on the file at the path
+            if is_same_file(&file, &path) {
+                return Ok(TreeLock {
+                    #[cfg(unix)]
+                    dir: File::open(&dir).with_context(|| format!("Failed to open {:?}", dir))?,
+                    #[cfg(not(unix))]
+                    path,
+                    _file: file,
+                });
+            }
+        }
+    }
+}
+
+impl Drop for TreeLock {
+    fn drop(&mut self) {
+        // Removed while the flock is still held; a process that locked the
+        // unlinked file sees it is gone from the path and opens a new one.
+        // Best effort: a leftover lock file is harmless since the flock is what matters
+        let _ = self.remove();
+    }
+}
+
+impl TreeLock {
+    /// Remove the lock file from the directory it was created in, wherever that is now
+    #[cfg(unix)]
+    fn remove(&self) -> std::io::Result<()> {
+        use std::os::unix::io::AsRawFd;
+
+        let name = std::ffi::CString::new(LOCK_FILE_NAME).unwrap();
+        // SAFETY: the descriptor is valid for the lifetime of `self.dir` and the name is NUL-terminated
+        match unsafe { libc::unlinkat(self.dir.as_raw_fd(), name.as_ptr(), 0) } {
+            0 => Ok(()),
+            _ => Err(std::io::Error::last_os_error()),
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn remove(&self) -> std::io::Result<()> {
+        fs::remove_file(&self.path)
+    }
+}
+
+#[cfg(unix)]
+fn is_same_file(file: &File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (file.metadata(), fs::metadata(path)) {
+        (Ok(held), Ok(current)) => held.dev() == current.dev() && held.ino() == current.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn is_same_file(_file: &File, _path: &Path) -> bool {
+    true
+}
+
+/// Check whether a path is a mane lock file (so walkers can skip it)
+pub fn is_lock_file(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| name == LOCK_FILE_NAME)
+}
+
+/// Determine the directory that holds the lock for a given root path
+pub fn lock_dir(root: &Path) -> PathBuf {
+    if root.is_dir() {
+        root.to_path_buf()
+    } else {
+        match root.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn try_lock(file: &File) -> bool {
+    use std::os::unix::io::AsRawFd;
+
+    // SAFETY: the descriptor is valid for the lifetime of `file`
+    unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) == 0 }
+}
+
+#[cfg(not(unix))]
+fn try_lock(_file: &File) -> bool {
+    // No advisory locking available; the lock file only documents the run
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::TestDir;
+
+    #[test]
+    fn test_acquire_is_exclusive() {
+        let dir = TestDir::new("lock");
+
+        let lock = TreeLock::acquire(&dir).unwrap();
+        let error = TreeLock::acquire(&dir).unwrap_err().to_string();
+        assert!(error.starts_with("Another mane process is already running"), "{}", error);
+
+        drop(lock);
+        assert!(!dir.join(LOCK_FILE_NAME).exists());
+        let lock = TreeLock::acquire(&dir).unwrap();
+        assert!(TreeLock::acquire(&dir).is_err());
+        drop(lock);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unlinked_lock_file_is_not_held() {
+        let dir = TestDir::new("lock-unlinked");
+        let path = dir.join(LOCK_FILE_NAME);
+
+        // A file locked after the holder unlinked it, as a waiter that lost the race has
+        let stale = File::create(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(try_lock(&stale));
+        assert!(!is_same_file(&stale, &path));
+
+        let lock = TreeLock::acquire(&dir).unwrap();
+        assert!(is_same_file(&lock._file, &path));
+        drop(lock);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_lock_file_follows_renamed_root() {
+        let dir = TestDir::new("lock-renamed");
+        fs::create_dir_all(dir.join("foo")).unwrap();
+
+        // `mane -r foo bar -i foo` renames the tree the lock is in
+        let lock = TreeLock::acquire(&dir.join("foo")).unwrap();
+        fs::rename(dir.join("foo"), dir.join("bar")).unwrap();
+        drop(lock);
+        assert!(!dir.join("bar").join(LOCK_FILE_NAME).exists());
+    }
+}
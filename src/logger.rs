@@ -0,0 +1,147 @@
+use anyhow::{Result, anyhow};
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+
+/// Output verbosity level
+///
+/// All messages are written to stderr so stdout stays reserved for replaced content.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Quiet = 0,   // Errors only (-q)
+    Normal = 1,  // Warnings and a line per modification (default)
+    Verbose = 2, // Per-file details (-v)
+    Debug = 3,   // Everything (-vv)
+}
+
+/// Current verbosity level, configured once from the command line
+pub static GLOBAL_LOG_LEVEL: AtomicU8 = AtomicU8::new(Level::Normal as u8);
+
+/// Set the global verbosity level
+///
+/// # Arguments
+/// * `level` - The new verbosity level
+pub fn set_level(level: Level) {
+    GLOBAL_LOG_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Check whether messages of the given level should be printed
+///
+/// # Arguments
+/// * `level` - The level of the message
+///
+/// # Returns
+/// * `bool` - true if the message should be printed
+pub fn enabled(level: Level) -> bool {
+    GLOBAL_LOG_LEVEL.load(Ordering::Relaxed) >= level as u8
+}
+
+/// Whether per-file messages are left out for a summary at the end (--summary)
+pub static GLOBAL_SUMMARY: AtomicBool = AtomicBool::new(false);
+
+/// Check whether informational messages should be printed
+///
+/// # Returns
+/// * `bool` - true unless -q or --summary was given
+pub fn chatty() -> bool {
+    enabled(Level::Normal) && !GLOBAL_SUMMARY.load(Ordering::Relaxed)
+}
+
+/// Whether warnings are fatal (--strict)
+pub static GLOBAL_STRICT: AtomicBool = AtomicBool::new(false);
+
+/// Number of warnings so far, printed or not
+static WARNINGS: AtomicUsize = AtomicUsize::new(0);
+
+/// Count a warning, for --strict
+///
+/// `warn!` counts its own; conditions reported another way (collected for a
+/// summary, or only shown with -v) are counted with this.
+pub fn record_warning() {
+    WARNINGS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Number of warnings so far
+pub fn warnings() -> usize {
+    WARNINGS.load(Ordering::Relaxed)
+}
+
+/// Fail if there has been any warning and warnings are fatal (--strict)
+///
+/// Called before changes are written, so a strict run stops before it has
+/// done anything, and again at the end for warnings raised while writing.
+///
+/// # Returns
+/// * `Result<()>` - Error if --strict is set and something was warned about
+pub fn check_strict() -> Result<()> {
+    let warnings = WARNINGS.load(Ordering::Relaxed);
+    if !GLOBAL_STRICT.load(Ordering::Relaxed) || warnings == 0 {
+        return Ok(());
+    }
+    match warnings {
+        1 => Err(anyhow!("Stopped because of a warning (--strict)")),
+        n => Err(anyhow!("Stopped because of {} warnings (--strict)", n)),
+    }
+}
+
+/// Derive the verbosity level from -q and the number of -v flags
+///
+/// # Arguments
+/// * `quiet` - Whether -q/--quiet was given
+/// * `verbose` - Number of -v/--verbose flags
+///
+/// # Returns
+/// * `Level` - The resulting level
+pub fn level_from_flags(quiet: bool, verbose: u8) -> Level {
+    if quiet {
+        return Level::Quiet;
+    }
+
+    match verbose {
+        0 => Level::Normal,
+        1 => Level::Verbose,
+        _ => Level::Debug,
+    }
+}
+
+/// Print an error message (shown even in quiet mode)
+macro_rules! error {
+    ($($arg:tt)*) => {
+        eprintln!("Error: {}", format_args!($($arg)*))
+    };
+}
+
+/// Print a warning message (hidden by -q)
+macro_rules! warn {
+    ($($arg:tt)*) => {{
+        $crate::logger::record_warning();
+        if $crate::logger::enabled($crate::logger::Level::Normal) {
+            eprintln!("Warning: {}", format_args!($($arg)*));
+        }
+    }};
+}
+
+/// Print an informational message (hidden by -q and --summary)
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if $crate::logger::chatty() {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+/// Print a message only with -v
+macro_rules! verbose {
+    ($($arg:tt)*) => {
+        if $crate::logger::enabled($crate::logger::Level::Verbose) {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+/// Print a message only with -vv
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if $crate::logger::enabled($crate::logger::Level::Debug) {
+            eprintln!($($arg)*);
+        }
+    };
+}
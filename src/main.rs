@@ -1,10 +1,60 @@
+#[macro_use]
+mod logger;
 mod args;
+mod color;
 mod replacer;
 mod scanner;
-mod case;
+mod template;
+mod toml;
+mod config;
 mod copier;
+mod json;
+mod diff;
+mod patch;
+mod placeholder;
+mod plan;
+mod script;
+mod sha256;
+mod preview;
+mod lock;
+mod lint;
+mod structured;
+mod markdown;
+mod syntax;
+#[cfg(feature = "tree-sitter")]
+mod grammar;
+mod csv;
+mod gzip;
+mod archive;
+mod incremental;
+mod pool;
+mod mmap;
+mod prefilter;
+mod ripgrep;
+mod rename_match;
+mod inflect;
+mod tui;
+mod serve;
+mod bench;
+mod hooks;
+mod trash;
+mod sparse;
+mod binary;
+mod match_cache;
+mod git;
+mod multi;
+mod explain;
+mod check;
+mod init;
+mod remote;
+mod summary;
+mod references;
+mod leftovers;
+#[cfg(test)]
+mod testdir;
 
 use anyhow::{Result, Context};
+use mane::{case, engine, multiline};
 use std::process;
 
 /// Main entry point of the application
@@ -14,8 +64,16 @@ fn main() -> Result<()> {
     let args = args::parse().context("Failed to parse arguments")?;
 
     // Execute the program
-    if let Err(e) = run(args) {
-        eprintln!("Error: {}", e);
+    let print_summary = args.summary;
+    let result = run(args);
+    if let Err(e) = &result {
+        error!("{}", e);
+        summary::record(summary::Event::Error);
+    }
+    if print_summary {
+        eprint!("{}", summary::render());
+    }
+    if result.is_err() {
         process::exit(1);
     }
 
@@ -27,6 +85,32 @@ fn main() -> Result<()> {
 /// # Arguments
 /// * `args` - Parsed command line arguments
 fn run(args: args::Args) -> Result<()> {
+    // Subcommands run on their own, without replacement rules
+    if let Some(command) = &args.command {
+        return match command {
+            args::Command::Apply { patch: patch_file } => {
+                let _lock = lock::TreeLock::acquire(std::path::Path::new("."))?;
+                patch::apply_patch_file(patch_file)
+            },
+            args::Command::ApplyPlan { plan: plan_file } => {
+                let _lock = lock::TreeLock::acquire(std::path::Path::new("."))?;
+                plan::load_plan(plan_file)?.apply(false)
+            },
+            args::Command::Bench { path, iterations, .. } => bench::run(&args, path, *iterations),
+            args::Command::Serve { socket, .. } => serve::run(&args, socket.as_deref()),
+            args::Command::Explain { .. } => explain::run(&args),
+            args::Command::CheckRules { .. } => check::run(&args),
+            args::Command::Multi { repos, dirs, run_args } => multi::run(repos.as_deref(), dirs, run_args),
+            args::Command::Init { force } => init::run(*force),
+            args::Command::New { .. } => unreachable!("mane new is parsed into a copy"),
+            args::Command::Template { command: args::TemplateCommand::Vars { path, json } } => {
+                template::print_vars(&args, path, *json)
+            },
+        };
+    }
+
+    hooks::run_hooks(&args, hooks::Stage::Pre)?;
+
     // Execute appropriate action based on the command mode
     match args.mode {
         args::Mode::StdinStdout => {
@@ -45,11 +129,24 @@ fn run(args: args::Args) -> Result<()> {
             // Copy files/directories with replacements
             copier::copy_with_replacements(&args)?;
         },
+        args::Mode::Render => {
+            // Render stdin as a template to stdout
+            replacer::render_stdin_stdout(&args)?;
+        },
         args::Mode::None => {
             // do nothing
             return Err(anyhow::anyhow!("No action specified. Use --help for more information."));
         }
     }
 
+    // Files that looked binary weren't replaced in, which shouldn't go unnoticed
+    binary::report_skipped();
+    logger::check_strict()?;
+
+    // Verify the replacement count if expectations were given
+    replacer::check_expected_count(&args)?;
+
+    hooks::run_hooks(&args, hooks::Stage::Post)?;
+
     Ok(())
 }
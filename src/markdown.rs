@@ -0,0 +1,283 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use std::path::Path;
+
+/// Which part of a Markdown document to replace in
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum MarkdownScope {
+    /// Fenced code blocks and inline code spans
+    Code,
+
+    /// Text outside code and link URLs
+    Prose,
+
+    /// Link and image URLs
+    Links,
+}
+
+/// What a piece of a Markdown document is
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Region {
+    Code,
+    Prose,
+    Link,
+
+    /// Fence lines and code span delimiters, which are never replaced
+    Markup,
+}
+
+/// Check whether a path is a Markdown file, by extension
+pub fn is_markdown(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| matches!(ext.to_ascii_lowercase().as_str(), "md" | "markdown" | "mdown" | "mkd"))
+}
+
+/// Replace only inside one part of a Markdown document
+///
+/// Everything outside the selected part is copied verbatim.
+///
+/// # Arguments
+/// * `text` - Document text
+/// * `scope` - Which part to replace in
+/// * `replace` - Replacement for one piece of the selected part
+///
+/// # Returns
+/// * `Result<String>` - The document with replacements applied
+pub fn replace(text: &str, scope: MarkdownScope, mut replace: impl FnMut(&str) -> Result<String>) -> Result<String> {
+    let selected = match scope {
+        MarkdownScope::Code => Region::Code,
+        MarkdownScope::Prose => Region::Prose,
+        MarkdownScope::Links => Region::Link,
+    };
+
+    let mut result = String::with_capacity(text.len());
+    let mut copied = 0;
+    let mut piece = |start: usize, end: usize, region: Region, result: &mut String| -> Result<()> {
+        if start == end {
+            return Ok(());
+        }
+        if region == selected {
+            result.push_str(&replace(&text[start..end])?);
+        } else {
+            result.push_str(&text[start..end]);
+        }
+        Ok(())
+    };
+
+    // Whatever lies between the regions found is prose
+    for (start, end, region) in regions(text) {
+        piece(copied, start, Region::Prose, &mut result)?;
+        piece(start, end, region, &mut result)?;
+        copied = end;
+    }
+    piece(copied, text.len(), Region::Prose, &mut result)?;
+
+    Ok(result)
+}
+
+/// A run of three or more backticks or tildes opening a code fence
+///
+/// # Returns
+/// * `Option<(u8, usize)>` - The fence character and the run length
+fn fence_run(line: &str) -> Option<(u8, usize)> {
+    let first = *line.as_bytes().first().filter(|&&b| b == b'`' || b == b'~')?;
+    let len = line.bytes().take_while(|&b| b == first).count();
+    (len >= 3).then_some((first, len))
+}
+
+/// Find the code, link and markup regions of a document (in order, prose left out)
+fn regions(text: &str) -> Vec<(usize, usize, Region)> {
+    let mut regions = Vec::new();
+    let mut fence: Option<(u8, usize)> = None;
+    let mut offset = 0;
+
+    for line in text.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        let content = line.trim_end_matches(['\n', '\r']);
+        let indent = content.len() - content.trim_start_matches(' ').len();
+        let rest = &content[indent..];
+
+        if let Some((fence_char, fence_len)) = fence {
+            let closing = fence_run(rest)
+                .filter(|&(c, len)| indent < 4 && c == fence_char && len >= fence_len && rest[len..].trim().is_empty());
+            if closing.is_some() {
+                regions.push((start, offset, Region::Markup));
+                fence = None;
+            } else {
+                regions.push((start, offset, Region::Code));
+            }
+            continue;
+        }
+
+        if indent < 4 {
+            // Opening fence; the info string of a backtick fence can't contain backticks
+            if let Some((c, len)) = fence_run(rest).filter(|&(c, len)| c == b'~' || !rest[len..].contains('`')) {
+                fence = Some((c, len));
+                regions.push((start, offset, Region::Markup));
+                continue;
+            }
+
+            // Link reference definition: [label]: url "title"
+            if let Some(close) = rest.strip_prefix('[').and_then(|r| r.find("]:")).map(|n| n + 1) {
+                let after = &rest[close + 2..];
+                let url = after.trim_start();
+                let url_start = start + indent + close + 2 + (after.len() - url.len());
+                let url = url.split_whitespace().next().unwrap_or("");
+                let (trim_start, trim_end) = if url.starts_with('<') && url.ends_with('>') { (1, 1) } else { (0, 0) };
+                if url.len() > trim_start + trim_end {
+                    regions.push((url_start + trim_start, url_start + url.len() - trim_end, Region::Link));
+                }
+                continue;
+            }
+        }
+
+        inline_regions(content, start, &mut regions);
+    }
+
+    regions
+}
+
+/// Find the inline code spans and link URLs of one line
+fn inline_regions(line: &str, offset: usize, regions: &mut Vec<(usize, usize, Region)>) {
+    let bytes = line.as_bytes();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'`' => {
+                // A code span closes with a backtick run of the same length
+                let len = bytes[i..].iter().take_while(|&&b| b == b'`').count();
+                let mut j = i + len;
+                let mut close = None;
+                while j < bytes.len() {
+                    let run = bytes[j..].iter().take_while(|&&b| b == b'`').count();
+                    if run == len {
+                        close = Some(j);
+                        break;
+                    }
+                    j += run.max(1);
+                }
+                match close {
+                    Some(close) => {
+                        regions.push((offset + i, offset + i + len, Region::Markup));
+                        regions.push((offset + i + len, offset + close, Region::Code));
+                        regions.push((offset + close, offset + close + len, Region::Markup));
+                        i = close + len;
+                    },
+                    None => i += len,
+                }
+            },
+            b']' if line[i + 1..].starts_with('(') => {
+                // Inline link or image destination: [text](url "title")
+                let mut start = i + 2;
+                start += line[start..].len() - line[start..].trim_start().len();
+                let end = if line[start..].starts_with('<') {
+                    start += 1;
+                    line[start..].find('>').map_or(start, |n| start + n)
+                } else {
+                    let mut depth = 0;
+                    bytes[start..].iter()
+                        .position(|&b| match b {
+                            b'(' => { depth += 1; false },
+                            b')' if depth == 0 => true,
+                            b')' => { depth -= 1; false },
+                            b' ' | b'\t' => true,
+                            _ => false,
+                        })
+                        .map_or(bytes.len(), |n| start + n)
+                };
+                if end > start {
+                    regions.push((offset + start, offset + end, Region::Link));
+                }
+                i = end.max(i + 1);
+            },
+            b'<' => {
+                // Autolink: <https://example.com> or <user@example.com>
+                let autolink = line[i + 1..].find('>')
+                    .map(|n| i + 1 + n)
+                    .filter(|&end| {
+                        let inner = &line[i + 1..end];
+                        !inner.is_empty() && !inner.contains([' ', '<']) && inner.contains([':', '@'])
+                    });
+                match autolink {
+                    Some(end) => {
+                        regions.push((offset + i + 1, offset + end, Region::Link));
+                        i = end + 1;
+                    },
+                    None => i += 1,
+                }
+            },
+            _ => i += 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOC: &str = "\
+# Using foo
+
+Call `foo()` to start, see [the foo docs](https://example.com/foo) or <https://foo.dev>.
+
+```rust
+let x = foo();
+```
+
+[ref]: https://example.com/foo \"foo\"
+";
+
+    fn upper(scope: MarkdownScope) -> String {
+        replace(DOC, scope, |s| Ok(s.replace("foo", "FOO"))).unwrap()
+    }
+
+    #[test]
+    fn test_scopes() {
+        assert_eq!(upper(MarkdownScope::Code), "\
+# Using foo
+
+Call `FOO()` to start, see [the foo docs](https://example.com/foo) or <https://foo.dev>.
+
+```rust
+let x = FOO();
+```
+
+[ref]: https://example.com/foo \"foo\"
+");
+        assert_eq!(upper(MarkdownScope::Prose), "\
+# Using FOO
+
+Call `foo()` to start, see [the FOO docs](https://example.com/foo) or <https://foo.dev>.
+
+```rust
+let x = foo();
+```
+
+[ref]: https://example.com/foo \"FOO\"
+");
+        assert_eq!(upper(MarkdownScope::Links), "\
+# Using foo
+
+Call `foo()` to start, see [the foo docs](https://example.com/FOO) or <https://FOO.dev>.
+
+```rust
+let x = foo();
+```
+
+[ref]: https://example.com/FOO \"foo\"
+");
+    }
+
+    #[test]
+    fn test_fences() {
+        // A longer closing fence closes, a shorter one doesn't
+        let doc = "````\n```\nfoo\n`````\nfoo\n";
+        assert_eq!(replace(doc, MarkdownScope::Code, |s| Ok(s.replace("foo", "bar"))).unwrap(), "````\n```\nbar\n`````\nfoo\n");
+        assert!(is_markdown(Path::new("README.md")));
+        assert!(!is_markdown(Path::new("main.rs")));
+    }
+}
@@ -0,0 +1,243 @@
+use crate::args::Args;
+use crate::json::{self, Value};
+use crate::sha256;
+use anyhow::{Result, Context, anyhow};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Directory the cache is kept in, in the current directory
+pub const CACHE_DIR: &str = ".mane-cache";
+
+/// Name of the cache file in the cache directory
+const CACHE_FILE: &str = "matches.json";
+
+const CACHE_VERSION: usize = 1;
+
+/// Modification times this recent aren't trusted, as the file may still change within the same tick
+const SETTLE_TIME: Duration = Duration::from_secs(2);
+
+/// Files the rules didn't match on previous runs (--match-cache)
+///
+/// Each file is recorded with its size and modification time, a digest of the
+/// rules and options that apply to it, and a digest of its content. A file
+/// whose size and time are unchanged is skipped without being read; one whose
+/// content has the same digest as last time is skipped without being replaced in.
+#[derive(Debug)]
+pub struct MatchCache {
+    /// The cache file
+    path: PathBuf,
+
+    /// Entries of the previous run, by path
+    previous: HashMap<String, Entry>,
+
+    /// Entries of this run, by path
+    current: Mutex<HashMap<String, Entry>>,
+
+    /// Paths checked this run; previous entries of other paths are kept
+    checked: Mutex<HashSet<String>>,
+}
+
+/// What is known about a file the rules didn't match
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    /// Size and modification time, or empty if the time was too recent to trust
+    stamp: String,
+
+    /// Digest of the rules and options that applied to the file
+    rules: String,
+
+    /// Digest of the content
+    content: String,
+}
+
+impl MatchCache {
+    /// Load the cache, or start an empty one
+    ///
+    /// # Arguments
+    /// * `dir` - Directory the cache file is kept in
+    ///
+    /// # Returns
+    /// * `Result<MatchCache>` - The cache
+    pub fn load(dir: &Path) -> Result<MatchCache> {
+        let path = dir.join(CACHE_FILE);
+        let mut previous = HashMap::new();
+
+        if path.exists() {
+            let text = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read cache file: {:?}", path))?;
+            let document = json::parse(&text)
+                .with_context(|| format!("Invalid cache file: {:?}", path))?;
+            if document.get("version") == Some(&Value::from(CACHE_VERSION)) {
+                if let Some(Value::Object(entries)) = document.get("files") {
+                    for (name, value) in entries {
+                        let entry = value.as_str().and_then(Entry::parse)
+                            .ok_or_else(|| anyhow!("Invalid cache entry for {} in {:?}", name, path))?;
+                        previous.insert(name.clone(), entry);
+                    }
+                }
+            }
+        }
+
+        Ok(MatchCache { path, previous, current: Mutex::new(HashMap::new()), checked: Mutex::new(HashSet::new()) })
+    }
+
+    /// Check whether the rules are known not to match a file
+    ///
+    /// A file recorded with the same rules and the same size and time is taken
+    /// as unchanged; otherwise its content digest is compared.
+    ///
+    /// # Arguments
+    /// * `path` - File to check
+    /// * `rules` - Digest of the rules and options that apply to it (see `rules_digest`)
+    ///
+    /// # Returns
+    /// * `bool` - True if the file can be skipped
+    pub fn is_unmatched(&self, path: &Path, rules: &str) -> bool {
+        let key = path.to_string_lossy().to_string();
+        self.checked.lock().unwrap().insert(key.clone());
+        let Some(entry) = self.previous.get(&key).filter(|entry| entry.rules == rules) else {
+            return false;
+        };
+
+        let stamp = stamp(path);
+        let unmatched = !entry.stamp.is_empty() && entry.stamp == stamp
+            || content_digest(path).is_some_and(|content| content == entry.content);
+        if unmatched {
+            self.current.lock().unwrap().insert(key, Entry { stamp, ..entry.clone() });
+        }
+        unmatched
+    }
+
+    /// Record a file the rules didn't match on this run
+    ///
+    /// # Arguments
+    /// * `path` - The file
+    /// * `rules` - Digest of the rules and options that applied to it
+    pub fn record_unmatched(&self, path: &Path, rules: &str) {
+        if let Some(content) = content_digest(path) {
+            let entry = Entry { stamp: stamp(path), rules: rules.to_string(), content };
+            self.current.lock().unwrap().insert(path.to_string_lossy().to_string(), entry);
+        }
+    }
+
+    /// Write the entries of this run, and those of files this run didn't check
+    /// that still exist, creating the cache directory if needed
+    ///
+    /// # Returns
+    /// * `Result<()>` - Result of the operation
+    pub fn save(&self) -> Result<()> {
+        let (current, checked) = (self.current.lock().unwrap(), self.checked.lock().unwrap());
+        let kept = self.previous.iter()
+            .filter(|(key, _)| !checked.contains(*key) && !current.contains_key(*key) && Path::new(key).is_file());
+        let mut entries: Vec<(String, Value)> = current.iter().chain(kept)
+            .map(|(key, entry)| (key.clone(), Value::from(entry.to_string())))
+            .collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let document = json::object([
+            ("version", Value::from(CACHE_VERSION)),
+            ("files", Value::Object(entries)),
+        ]);
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("Failed to create cache directory: {:?}", dir))?;
+        }
+        fs::write(&self.path, document.to_pretty_string() + "\n")
+            .with_context(|| format!("Failed to write cache file: {:?}", self.path))
+    }
+}
+
+impl Entry {
+    fn parse(value: &str) -> Option<Entry> {
+        let mut parts = value.split(' ');
+        let entry = Entry {
+            stamp: parts.next()?.to_string(),
+            rules: parts.next()?.to_string(),
+            content: parts.next()?.to_string(),
+        };
+        parts.next().is_none().then_some(entry)
+    }
+}
+
+impl std::fmt::Display for Entry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.stamp, self.rules, self.content)
+    }
+}
+
+/// Check whether a path is in the cache directory, which is never processed
+pub fn is_cache_path(path: &Path) -> bool {
+    path.components().any(|component| component.as_os_str() == CACHE_DIR)
+}
+
+/// Digest of the rules that apply to a file and the options that change how they replace
+///
+/// # Arguments
+/// * `path` - File the rules are applied to
+/// * `args` - Command line arguments
+///
+/// # Returns
+/// * `String` - Hex digest
+pub fn rules_digest(path: &Path, args: &Args) -> String {
+    let rules: Vec<(&str, &str)> = args.rules.iter()
+        .filter(|rule| rule.applies_to(Some(path)))
+        .map(|rule| (rule.from.as_str(), rule.to.as_str()))
+        .collect();
+    let settings = format!(
+        "{:?}",
+        (
+            env!("CARGO_PKG_VERSION"),
+            rules,
+            (args.case_enabled, args.ordered, args.plurals, args.same_shape, args.split_digits, args.reverse),
+            (&args.structured, &args.json_path, &args.yaml_path, &args.markdown_scope, &args.csv_columns),
+            (args.syntax_aware, args.skip_strings, args.skip_comments),
+            (args.decompress, args.text, args.binary_threshold.to_bits()),
+        ),
+    );
+    sha256::hex_digest(settings.as_bytes())
+}
+
+/// Size and modification time of a file, or empty if its time is too recent to trust
+fn stamp(path: &Path) -> String {
+    let Ok(metadata) = fs::metadata(path) else { return String::new() };
+    let Ok(modified) = metadata.modified() else { return String::new() };
+    if SystemTime::now().duration_since(modified).map_or(true, |age| age < SETTLE_TIME) {
+        return String::new();
+    }
+    let nanos = modified.duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos());
+    format!("{}:{}", metadata.len(), nanos)
+}
+
+fn content_digest(path: &Path) -> Option<String> {
+    fs::read(path).ok().map(|data| sha256::hex_digest(&data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::TestDir;
+
+    #[test]
+    fn test_match_cache() {
+        let dir = TestDir::new("match-cache");
+        let file = dir.join("a.txt");
+        fs::write(&file, "nothing to see").unwrap();
+
+        let cache = MatchCache::load(&dir.join(CACHE_DIR)).unwrap();
+        assert!(!cache.is_unmatched(&file, "rules"));
+        cache.record_unmatched(&file, "rules");
+        cache.save().unwrap();
+
+        // Just written, so the content decides
+        let cache = MatchCache::load(&dir.join(CACHE_DIR)).unwrap();
+        assert!(cache.is_unmatched(&file, "rules"));
+        assert!(!cache.is_unmatched(&file, "other rules"));
+        fs::write(&file, "something else").unwrap();
+        assert!(!cache.is_unmatched(&file, "rules"));
+
+        assert!(is_cache_path(Path::new("./.mane-cache/matches.json")));
+        assert!(!is_cache_path(Path::new("./src/cache.rs")));
+    }
+}
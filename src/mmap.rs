@@ -0,0 +1,72 @@
+use memmap2::Mmap;
+use std::fs::File;
+use std::io;
+use std::ops::Deref;
+use std::path::Path;
+
+/// The bytes of a file, memory-mapped where possible
+///
+/// Mapping lets read-mostly passes look for matches without copying every
+/// file into a `String` first. Files that can't be mapped (empty files, or
+/// where mapping fails) are read into memory instead.
+pub enum Mapped {
+    Map(Mmap),
+    Owned(Vec<u8>),
+}
+
+/// Map a file into memory
+///
+/// The mapping is read-only and shared with the file, so unlike a copy it
+/// sees writes other processes make while it is looked at. If one truncates
+/// the file meanwhile, reading past the new end raises SIGBUS and `mane`
+/// ends. The tree lock keeps other `mane` runs away, and a scan only looks
+/// at a file for as long as it takes to find a match.
+///
+/// # Arguments
+/// * `path` - File to map
+///
+/// # Returns
+/// * `io::Result<Mapped>` - The file's bytes
+pub fn open(path: &Path) -> io::Result<Mapped> {
+    let file = File::open(path)?;
+    let len = file.metadata()?.len() as usize;
+
+    if len > 0 {
+        // SAFETY: the map is read-only; a concurrent truncation is the SIGBUS case documented above
+        if let Ok(map) = unsafe { Mmap::map(&file) } {
+            return Ok(Mapped::Map(map));
+        }
+    }
+
+    let mut data = Vec::with_capacity(len);
+    io::Read::read_to_end(&mut &file, &mut data)?;
+    Ok(Mapped::Owned(data))
+}
+
+impl Deref for Mapped {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Mapped::Map(map) => map,
+            Mapped::Owned(data) => data,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::TestDir;
+
+    #[test]
+    fn test_open() {
+        let dir = TestDir::new("mmap");
+        std::fs::write(dir.join("a.txt"), "hello foo").unwrap();
+        std::fs::write(dir.join("empty.txt"), "").unwrap();
+
+        assert_eq!(&*open(&dir.join("a.txt")).unwrap(), b"hello foo");
+        assert!(open(&dir.join("empty.txt")).unwrap().is_empty());
+        assert!(open(&dir.join("missing.txt")).is_err());
+    }
+}
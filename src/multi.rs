@@ -0,0 +1,212 @@
+use anyhow::{Result, Context, anyhow};
+use globset::Glob;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// What one run of `mane` did in a repository
+#[derive(Debug, Default, PartialEq)]
+struct Outcome {
+    /// Files whose content was modified
+    modified: usize,
+
+    /// Files and directories renamed
+    renamed: usize,
+
+    /// Why the run failed, if it did
+    error: Option<String>,
+}
+
+/// Run `mane` with the same arguments in each of many repositories (`mane multi`)
+///
+/// Each run is a separate process with the repository as its working
+/// directory, so its config file, ignore files and `--commit` are the
+/// repository's own. A failing run doesn't stop the others.
+///
+/// # Arguments
+/// * `list` - File listing repository directories, one per line
+/// * `patterns` - Repository directories, or globs of them
+/// * `run_args` - Arguments of the run in each repository
+///
+/// # Returns
+/// * `Result<()>` - Error if any of the runs failed
+pub fn run(list: Option<&Path>, patterns: &[String], run_args: &[String]) -> Result<()> {
+    let mut repos = Vec::new();
+    if let Some(list) = list {
+        let text = fs::read_to_string(list).with_context(|| format!("Failed to read repository list: {:?}", list))?;
+        repos.extend(parse_list(&text).into_iter().map(PathBuf::from));
+    }
+    for pattern in patterns {
+        repos.extend(expand(pattern)?);
+    }
+    let mut seen = std::collections::HashSet::new();
+    repos.retain(|repo| seen.insert(repo.clone()));
+    if repos.is_empty() {
+        return Err(anyhow!("No repositories given; list them with --repos FILE or as arguments"));
+    }
+    if let Some(repo) = repos.iter().find(|repo| !repo.is_dir()) {
+        return Err(anyhow!("Not a directory: {:?}", repo));
+    }
+
+    let exe = std::env::current_exe().context("Failed to find the mane executable")?;
+    let run_args = resolve_config(run_args)?;
+    let mut outcomes = Vec::new();
+    for repo in &repos {
+        info!("==> {} <==", repo.display());
+        let output = Command::new(&exe)
+            .args(&run_args)
+            .current_dir(repo)
+            .stdin(Stdio::null())
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::piped())
+            .output()
+            .with_context(|| format!("Failed to run mane in {:?}", repo))?;
+        let report = String::from_utf8_lossy(&output.stderr);
+        eprint!("{}", report);
+
+        let mut outcome = parse_report(&report);
+        if !output.status.success() && outcome.error.is_none() {
+            outcome.error = Some(format!("exited with {}", output.status));
+        }
+        outcomes.push((repo, outcome));
+    }
+
+    info!("");
+    info!("Summary:");
+    for (repo, outcome) in &outcomes {
+        match &outcome.error {
+            Some(error) => info!("  {}: failed: {}", repo.display(), error),
+            None => info!("  {}: {} modified, {} renamed", repo.display(), outcome.modified, outcome.renamed),
+        }
+    }
+    let failed = outcomes.iter().filter(|(_, outcome)| outcome.error.is_some()).count();
+    let modified: usize = outcomes.iter().map(|(_, outcome)| outcome.modified).sum();
+    let renamed: usize = outcomes.iter().map(|(_, outcome)| outcome.renamed).sum();
+    info!("{} repositories, {} modified, {} renamed, {} failed", outcomes.len(), modified, renamed, failed);
+
+    match failed {
+        0 => Ok(()),
+        _ => Err(anyhow!("{} of {} repositories failed", failed, outcomes.len())),
+    }
+}
+
+/// Repository directories in a list file: one per line, skipping blank lines and `#` comments
+fn parse_list(text: &str) -> Vec<&str> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect()
+}
+
+/// The directories a glob matches, sorted; a path without glob characters is taken as it is
+fn expand(pattern: &str) -> Result<Vec<PathBuf>> {
+    if !pattern.contains(['*', '?', '[', '{']) {
+        return Ok(vec![PathBuf::from(pattern)]);
+    }
+    let matcher = Glob::new(pattern).with_context(|| format!("Invalid repository glob: {}", pattern))?.compile_matcher();
+
+    // Walk from the literal directories the glob starts with, as deep as it has components
+    let components: Vec<&str> = pattern.split('/').collect();
+    let literal = components.iter().take_while(|part| !part.contains(['*', '?', '[', '{'])).count();
+    let base = match components[..literal].join("/") {
+        prefix if prefix.is_empty() && pattern.starts_with('/') => "/".to_string(),
+        prefix if prefix.is_empty() => ".".to_string(),
+        prefix => prefix,
+    };
+    let depth = if components[literal..].contains(&"**") { usize::MAX } else { components.len() - literal };
+
+    let mut found = Vec::new();
+    collect_dirs(Path::new(&base), literal == 0 && !pattern.starts_with('/'), depth, &mut |dir| {
+        if matcher.is_match(dir) {
+            found.push(dir.to_path_buf());
+        }
+    });
+    found.sort();
+    Ok(found)
+}
+
+/// Visit the directories under `dir`, down to `depth` levels, dropping the `./` of relative walks
+fn collect_dirs(dir: &Path, relative: bool, depth: usize, visit: &mut dyn FnMut(&Path)) {
+    if depth == 0 {
+        return;
+    }
+    let Ok(entries) = fs::read_dir(dir) else { return };
+    for entry in entries.flatten() {
+        if !entry.path().is_dir() {
+            continue;
+        }
+        let path = if relative { PathBuf::from(entry.file_name()) } else { entry.path() };
+        visit(&path);
+        collect_dirs(&path, false, depth - 1, visit);
+    }
+}
+
+/// Make the file given with `--config` absolute, as the runs are in other directories
+fn resolve_config(run_args: &[String]) -> Result<Vec<String>> {
+    let cwd = std::env::current_dir()?;
+    let absolute = |path: &str| cwd.join(path).to_string_lossy().to_string();
+    let mut resolved = Vec::new();
+    let mut args = run_args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            resolved.push(arg.clone());
+            if let Some(path) = args.next() {
+                resolved.push(absolute(path));
+            }
+        } else if let Some(path) = arg.strip_prefix("--config=") {
+            resolved.push(format!("--config={}", absolute(path)));
+        } else {
+            resolved.push(arg.clone());
+        }
+    }
+    Ok(resolved)
+}
+
+/// Count the changes a run reported, and find the error it failed with
+fn parse_report(report: &str) -> Outcome {
+    let mut outcome = Outcome::default();
+    for line in report.lines() {
+        if line.starts_with("Modified content: ") || line.starts_with("Modified archive: ") {
+            outcome.modified += 1;
+        } else if line.starts_with("Renamed: ") {
+            outcome.renamed += 1;
+        } else if let Some(error) = line.strip_prefix("Error: ") {
+            outcome.error = Some(error.to_string());
+        }
+    }
+    outcome
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::TestDir;
+
+    #[test]
+    fn test_parse_list() {
+        let text = "# services\nrepos/api\n\n  repos/web  \n# repos/old\n";
+        assert_eq!(parse_list(text), ["repos/api", "repos/web"]);
+    }
+
+    #[test]
+    fn test_expand() {
+        let dir = TestDir::new("multi");
+        for repo in ["api", "web", "docs/site"] {
+            fs::create_dir_all(dir.join(repo)).unwrap();
+        }
+        fs::write(dir.join("notes.txt"), "").unwrap();
+
+        let pattern = format!("{}/*", dir.display());
+        assert_eq!(expand(&pattern).unwrap(), [dir.join("api"), dir.join("docs"), dir.join("web")]);
+        let pattern = format!("{}/**/site", dir.display());
+        assert_eq!(expand(&pattern).unwrap(), [dir.join("docs/site")]);
+        assert_eq!(expand("repos/api").unwrap(), [PathBuf::from("repos/api")]);
+    }
+
+    #[test]
+    fn test_parse_report() {
+        let report = "Modified content: \"./a.rs\"\nRenamed: ./foo -> ./bar\nModified archive: \"./b.zip\"\n";
+        assert_eq!(parse_report(report), Outcome { modified: 2, renamed: 1, error: None });
+        assert_eq!(parse_report("Error: No replacement rules\n").error.as_deref(), Some("No replacement rules"));
+    }
+}
@@ -0,0 +1,84 @@
+use regex::{Captures, Regex};
+
+/// Check whether a FROM string spans several lines
+///
+/// Such rules are matched against the whole content as one buffer, never
+/// line by line, and are not expanded into case variants (converting the
+/// case of a license header or a code snippet would join its lines).
+pub fn is_multiline(from: &str) -> bool {
+    from.contains('\n')
+}
+
+/// The part of a multi-line FROM string the prefilter searches for
+///
+/// The line breaks themselves can't be searched for literally, since they
+/// match `\r\n` as well, so the longest line stands in for the whole string.
+///
+/// # Arguments
+/// * `from` - Multi-line FROM string
+///
+/// # Returns
+/// * `&str` - Its longest line, or `"\n"` if every line is empty
+pub fn needle(from: &str) -> &str {
+    from.split('\n')
+        .map(|line| line.strip_suffix('\r').unwrap_or(line))
+        .max_by_key(|line| line.len())
+        .filter(|line| !line.is_empty())
+        .unwrap_or("\n")
+}
+
+/// Replace a multi-line FROM string in a whole buffer, counting the replacements
+///
+/// Each line break in FROM matches both `\n` and `\r\n`, so a block copied
+/// from a Unix file also matches in a Windows one. Line breaks in TO are
+/// written the way the matched text had them.
+///
+/// # Arguments
+/// * `content` - The content to replace in
+/// * `from` - The multi-line string to replace
+/// * `to` - The replacement string
+///
+/// # Returns
+/// * `(String, usize)` - The replaced content and the number of replacements
+pub fn replace_counted(content: &str, from: &str, to: &str) -> (String, usize) {
+    let pattern = from.split('\n')
+        .map(|line| regex::escape(line.strip_suffix('\r').unwrap_or(line)))
+        .collect::<Vec<_>>()
+        .join(r"\r?\n");
+    let Ok(re) = Regex::new(&pattern) else {
+        return (content.replace(from, to), content.matches(from).count());
+    };
+
+    let lf = to.replace("\r\n", "\n");
+    let crlf = lf.replace('\n', "\r\n");
+    let mut count = 0;
+    let result = re.replace_all(content, |caps: &Captures| {
+        count += 1;
+        if caps[0].contains("\r\n") { crlf.clone() } else { lf.clone() }
+    });
+    (result.into_owned(), count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replace_counted() {
+        let header = "// Copyright Acme\n// All rights reserved\n";
+        let to = "// SPDX-License-Identifier: MIT\n";
+        assert_eq!(
+            replace_counted("// Copyright Acme\n// All rights reserved\nfn main() {}\n", header, to),
+            ("// SPDX-License-Identifier: MIT\nfn main() {}\n".to_string(), 1),
+        );
+        assert_eq!(
+            replace_counted("// Copyright Acme\r\n// All rights reserved\r\nfn main() {}\r\n", header, to),
+            ("// SPDX-License-Identifier: MIT\r\nfn main() {}\r\n".to_string(), 1),
+        );
+        assert_eq!(replace_counted("a\nb a\nb a b", "a\nb", "c").1, 2);
+        assert_eq!(replace_counted("a.\nb", "a*\nb", "c").1, 0);
+
+        assert_eq!(needle(header), "// All rights reserved");
+        assert_eq!(needle("\n\r\n"), "\n");
+    }
+}
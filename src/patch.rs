@@ -0,0 +1,507 @@
+use crate::diff;
+use crate::plan::Plan;
+use crate::summary::{self, Event};
+use anyhow::{Result, Context, anyhow};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Marker git and patch(1) use for a last line without a trailing newline
+const NO_NEWLINE_MARKER: &str = "\\ No newline at end of file";
+
+/// Number of context lines around each hunk
+const CONTEXT_LINES: usize = 3;
+
+/// Changes to a single file parsed from a patch
+#[derive(Debug, Default, PartialEq)]
+pub struct FilePatch {
+    /// Path before the change
+    pub old_path: PathBuf,
+
+    /// Path after the change
+    pub new_path: PathBuf,
+
+    /// Content hunks (empty for pure renames)
+    pub hunks: Vec<diff::Hunk>,
+}
+
+/// Write the plan as a git-style unified diff
+///
+/// Content changes become hunks and renames become `rename from`/`rename to`
+/// headers, so the patch can be applied with `mane apply` or `git apply`.
+/// A diff only holds files, so moved directories without any are left out
+/// with a warning.
+///
+/// # Arguments
+/// * `out` - Patch file to write
+/// * `plan` - Planned changes
+/// * `files` - All files in the processed tree (needed for files moved by directory renames)
+///
+/// # Returns
+/// * `Result<()>` - Result of the operation
+pub fn write_patch(out: &Path, plan: &Plan, files: &[PathBuf]) -> Result<()> {
+    let patch = format_patch(plan, files);
+    for (from, to) in moved_empty_dirs(plan, files) {
+        warn!("A patch can't hold empty directories, so {:?} -> {:?} is left out of it", from, to);
+    }
+
+    fs::write(out, patch).with_context(|| format!("Failed to write patch file: {:?}", out))?;
+    info!("Wrote patch: {:?}", out);
+
+    Ok(())
+}
+
+/// Render the plan as a git-style unified diff
+///
+/// # Arguments
+/// * `plan` - Planned changes
+/// * `files` - All files in the processed tree
+///
+/// # Returns
+/// * `String` - The patch text
+pub fn format_patch(plan: &Plan, files: &[PathBuf]) -> String {
+    let mut patch = String::new();
+
+    // Edited files first (in plan order), then files that only move
+    let mut paths: Vec<&Path> = plan.edits.iter().map(|e| e.path.as_path()).collect();
+    for file in files {
+        if file.is_file() && !paths.contains(&file.as_path()) {
+            paths.push(file);
+        }
+    }
+
+    for path in paths {
+        let new_path = plan.final_path(path);
+        let edit = plan.edits.iter().find(|e| e.path == path);
+        if edit.is_none() && new_path == path {
+            continue;
+        }
+
+        let old_name = display_path(path);
+        let new_name = display_path(&new_path);
+        patch.push_str(&format!("diff --git a/{} b/{}\n", old_name, new_name));
+
+        if new_path != path {
+            if edit.is_none() {
+                patch.push_str("similarity index 100%\n");
+            }
+            patch.push_str(&format!("rename from {}\nrename to {}\n", old_name, new_name));
+        }
+
+        if let Some(edit) = edit {
+            patch.push_str(&format!("--- a/{}\n+++ b/{}\n", old_name, new_name));
+            patch.push_str(&format_hunks(&edit.original, &edit.replaced));
+        }
+    }
+
+    patch
+}
+
+/// Empty directories the renames of a plan move, which a patch can't express
+///
+/// # Arguments
+/// * `plan` - Planned changes
+/// * `files` - All files in the processed tree
+///
+/// # Returns
+/// * `Vec<(PathBuf, PathBuf)>` - Each outermost directory without files under a
+///   renamed directory (or renamed itself), and where the plan moves it
+fn moved_empty_dirs(plan: &Plan, files: &[PathBuf]) -> Vec<(PathBuf, PathBuf)> {
+    let mut found = Vec::new();
+    for rename in &plan.renames {
+        if rename.from.is_dir() {
+            collect_empty_dirs(&rename.from, files, &mut found);
+        }
+    }
+    found.into_iter().map(|dir| {
+        let to = plan.final_path(&dir);
+        (dir, to)
+    }).collect()
+}
+
+/// Collect the outermost directories under (and including) `dir` that hold none of `files`
+fn collect_empty_dirs(dir: &Path, files: &[PathBuf], found: &mut Vec<PathBuf>) {
+    if !files.iter().any(|file| file.starts_with(dir)) {
+        // A directory renamed inside another renamed one is reached from both
+        if !found.iter().any(|seen| seen == dir) {
+            found.push(dir.to_path_buf());
+        }
+        return;
+    }
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut subdirs: Vec<PathBuf> = entries.flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_dir()))
+        .map(|entry| entry.path())
+        .collect();
+    subdirs.sort();
+    for subdir in subdirs {
+        collect_empty_dirs(&subdir, files, found);
+    }
+}
+
+/// Render the unified-diff hunks between two contents (without file headers)
+///
+/// # Arguments
+/// * `original` - Content before the change
+/// * `replaced` - Content after the change
+///
+/// # Returns
+/// * `String` - The hunks text
+pub fn format_hunks(original: &str, replaced: &str) -> String {
+    let mut text = String::new();
+    let old_lines: Vec<&str> = original.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = replaced.split_inclusive('\n').collect();
+
+    for hunk in diff::hunks(&old_lines, &new_lines, CONTEXT_LINES) {
+        text.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            format_range(hunk.old_start, hunk.old_len),
+            format_range(hunk.new_start, hunk.new_len)
+        ));
+        for line in &hunk.lines {
+            text.push_str(line);
+            if !line.ends_with('\n') {
+                text.push('\n');
+                text.push_str(NO_NEWLINE_MARKER);
+                text.push('\n');
+            }
+        }
+    }
+
+    text
+}
+
+/// Parse hunks written by `format_hunks`
+///
+/// # Arguments
+/// * `text` - The hunks text
+///
+/// # Returns
+/// * `Result<Vec<diff::Hunk>>` - The parsed hunks
+pub fn parse_hunks(text: &str) -> Result<Vec<diff::Hunk>> {
+    // Reuse the patch parser with a synthetic file header
+    let mut patches = parse_patch(&format!("--- a\n+++ b\n{}", text))?;
+    Ok(patches.pop().map(|p| p.hunks).unwrap_or_default())
+}
+
+/// Parse a unified diff (as written by `format_patch` or git)
+///
+/// # Arguments
+/// * `text` - The patch text
+///
+/// # Returns
+/// * `Result<Vec<FilePatch>>` - The per-file changes
+pub fn parse_patch(text: &str) -> Result<Vec<FilePatch>> {
+    let mut patches: Vec<FilePatch> = Vec::new();
+    let mut in_header = false;
+
+    for (index, line) in text.split_inclusive('\n').enumerate() {
+        let trimmed = line.trim_end_matches('\n');
+
+        if trimmed == NO_NEWLINE_MARKER {
+            let last = patches.last_mut()
+                .and_then(|p| p.hunks.last_mut())
+                .and_then(|h| h.lines.last_mut());
+            if let Some(last) = last {
+                if last.ends_with('\n') {
+                    last.pop();
+                }
+            }
+            continue;
+        }
+
+        // Lines belonging to an incomplete hunk are never headers, even if they start with "---"
+        let open_hunk = patches.last_mut()
+            .and_then(|p| p.hunks.last_mut())
+            .filter(|h| !is_complete(h));
+        if let Some(hunk) = open_hunk {
+            if line.starts_with([' ', '-', '+']) {
+                hunk.lines.push(line.to_string());
+                continue;
+            } else if trimmed.is_empty() {
+                // Some tools strip the leading space from empty context lines
+                hunk.lines.push(format!(" {}", line));
+                continue;
+            }
+        }
+
+        if let Some(rest) = trimmed.strip_prefix("diff --git ") {
+            let (old, new) = rest.split_once(" b/")
+                .ok_or_else(|| anyhow!("Malformed diff header at line {}", index + 1))?;
+            patches.push(FilePatch {
+                old_path: strip_prefix(old, "a/"),
+                new_path: PathBuf::from(new),
+                hunks: Vec::new(),
+            });
+            in_header = true;
+            continue;
+        }
+
+        if let Some(rest) = trimmed.strip_prefix("--- ") {
+            // Plain unified diffs have no "diff --git" line
+            if !in_header {
+                patches.push(FilePatch::default());
+            }
+            if let Some(current) = patches.last_mut() {
+                if rest != "/dev/null" {
+                    current.old_path = strip_prefix(rest, "a/");
+                }
+            }
+            in_header = true;
+            continue;
+        }
+
+        let Some(current) = patches.last_mut() else {
+            // Leading text before the first file is ignored, like patch(1) does
+            continue;
+        };
+
+        if let Some(rest) = trimmed.strip_prefix("+++ ") {
+            if rest != "/dev/null" {
+                current.new_path = strip_prefix(rest, "b/");
+            }
+        } else if let Some(rest) = trimmed.strip_prefix("rename from ") {
+            current.old_path = PathBuf::from(rest);
+        } else if let Some(rest) = trimmed.strip_prefix("rename to ") {
+            current.new_path = PathBuf::from(rest);
+        } else if trimmed.starts_with("@@ ") {
+            current.hunks.push(parse_hunk_header(trimmed)
+                .ok_or_else(|| anyhow!("Malformed hunk header at line {}", index + 1))?);
+            in_header = false;
+        }
+    }
+
+    Ok(patches)
+}
+
+/// Apply a patch file to the working tree
+///
+/// # Arguments
+/// * `patch_file` - Patch to apply
+///
+/// # Returns
+/// * `Result<()>` - Result of the operation
+pub fn apply_patch_file(patch_file: &Path) -> Result<()> {
+    let text = fs::read_to_string(patch_file)
+        .with_context(|| format!("Failed to read patch file: {:?}", patch_file))?;
+    let patches = parse_patch(&text)?;
+
+    if patches.is_empty() {
+        return Err(anyhow!("No changes found in patch file: {:?}", patch_file));
+    }
+
+    for file_patch in &patches {
+        apply_file_patch(file_patch)?;
+    }
+
+    Ok(())
+}
+
+/// Apply the changes for a single file
+fn apply_file_patch(file_patch: &FilePatch) -> Result<()> {
+    let old_path = &file_patch.old_path;
+    let new_path = &file_patch.new_path;
+
+    if !file_patch.hunks.is_empty() {
+        let content = fs::read_to_string(old_path)
+            .with_context(|| format!("Failed to read file: {:?}", old_path))?;
+        let patched = apply_hunks(&content, &file_patch.hunks)
+            .with_context(|| format!("Patch does not apply to {:?}", old_path))?;
+
+        fs::write(old_path, patched)
+            .with_context(|| format!("Failed to write file: {:?}", old_path))?;
+        info!("Modified content: {:?}", old_path);
+        summary::record(Event::Modified);
+    }
+
+    if old_path != new_path {
+        if new_path.exists() {
+            return Err(anyhow!("Cannot rename {:?} to {:?}: target already exists", old_path, new_path));
+        }
+        if let Some(parent) = new_path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory: {:?}", parent))?;
+        }
+        fs::rename(old_path, new_path)
+            .with_context(|| format!("Failed to rename {:?} to {:?}", old_path, new_path))?;
+        info!("Renamed: {:?} -> {:?}", old_path, new_path);
+        summary::record(Event::Renamed);
+
+        remove_empty_parents(old_path);
+    }
+
+    Ok(())
+}
+
+/// Apply hunks to content, locating each hunk near its recorded position
+///
+/// # Arguments
+/// * `content` - Original content
+/// * `hunks` - Hunks to apply, in order
+///
+/// # Returns
+/// * `Result<String>` - Patched content
+pub fn apply_hunks(content: &str, hunks: &[diff::Hunk]) -> Result<String> {
+    let lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let mut result = String::with_capacity(content.len());
+    let mut position = 0;
+
+    for hunk in hunks {
+        let expected: Vec<&str> = hunk.lines.iter()
+            .filter(|l| !l.starts_with('+'))
+            .map(|l| &l[1..])
+            .collect();
+
+        let start = find_block(&lines, &expected, hunk.old_start.saturating_sub(1), position)
+            .ok_or_else(|| anyhow!("Hunk at line {} does not match", hunk.old_start))?;
+
+        for line in &lines[position..start] {
+            result.push_str(line);
+        }
+        for line in hunk.lines.iter().filter(|l| !l.starts_with('-')) {
+            result.push_str(&line[1..]);
+        }
+        position = start + expected.len();
+    }
+
+    for line in &lines[position..] {
+        result.push_str(line);
+    }
+
+    Ok(result)
+}
+
+/// Find `block` in `lines` at or after `min`, preferring positions closest to `hint`
+fn find_block(lines: &[&str], block: &[&str], hint: usize, min: usize) -> Option<usize> {
+    let matches_at = |start: usize| {
+        start >= min && start + block.len() <= lines.len() && lines[start..start + block.len()] == *block
+    };
+
+    (0..=lines.len()).find_map(|distance| {
+        [hint.checked_add(distance), hint.checked_sub(distance)]
+            .into_iter()
+            .flatten()
+            .find(|&start| matches_at(start))
+    })
+}
+
+/// Check whether a hunk has received all the lines its header announced
+fn is_complete(hunk: &diff::Hunk) -> bool {
+    let old = hunk.lines.iter().filter(|l| !l.starts_with('+')).count();
+    let new = hunk.lines.iter().filter(|l| !l.starts_with('-')).count();
+    old >= hunk.old_len && new >= hunk.new_len
+}
+
+/// Parse a `@@ -a,b +c,d @@` header into an empty hunk
+fn parse_hunk_header(line: &str) -> Option<diff::Hunk> {
+    let mut parts = line.split_whitespace().skip(1);
+    let (old_start, old_len) = parse_range(parts.next()?.strip_prefix('-')?)?;
+    let (new_start, new_len) = parse_range(parts.next()?.strip_prefix('+')?)?;
+
+    Some(diff::Hunk { old_start, old_len, new_start, new_len, lines: Vec::new() })
+}
+
+/// Parse a `start[,len]` range
+fn parse_range(range: &str) -> Option<(usize, usize)> {
+    match range.split_once(',') {
+        Some((start, len)) => Some((start.parse().ok()?, len.parse().ok()?)),
+        None => Some((range.parse().ok()?, 1)),
+    }
+}
+
+/// Format a `start[,len]` range the way diff(1) does
+fn format_range(start: usize, len: usize) -> String {
+    if len == 1 {
+        start.to_string()
+    } else {
+        format!("{},{}", start, len)
+    }
+}
+
+/// Path as written in patch headers (without a leading `./`)
+fn display_path(path: &Path) -> String {
+    path.strip_prefix(".").unwrap_or(path).display().to_string()
+}
+
+fn strip_prefix(path: &str, prefix: &str) -> PathBuf {
+    PathBuf::from(path.strip_prefix(prefix).unwrap_or(path))
+}
+
+/// Remove directories left empty after moving a file out of them
+fn remove_empty_parents(path: &Path) {
+    let mut current = path.parent();
+    while let Some(dir) = current {
+        if dir.as_os_str().is_empty() || fs::remove_dir(dir).is_err() {
+            break;
+        }
+        current = dir.parent();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plan::{ContentEdit, Rename};
+    use crate::testdir::TestDir;
+
+    #[test]
+    fn test_format_and_parse_roundtrip() {
+        let original = "use foo;\nfn foo() {}\nlast foo".to_string();
+        let replaced = "use bar;\nfn bar() {}\nlast bar".to_string();
+        let plan = Plan {
+            edits: vec![ContentEdit { path: PathBuf::from("src/foo.rs"), original: original.clone(), replaced: replaced.clone(), compressed: false }],
+            renames: vec![Rename { from: PathBuf::from("src/foo.rs"), to: PathBuf::from("src/bar.rs") }],
+            ..Plan::default()
+        };
+
+        let text = format_patch(&plan, &[]);
+        assert!(text.starts_with("diff --git a/src/foo.rs b/src/bar.rs\nrename from src/foo.rs\nrename to src/bar.rs\n"));
+        assert!(text.contains("@@ -1,3 +1,3 @@\n"));
+        assert!(text.contains("-last foo\n\\ No newline at end of file\n"));
+
+        let patches = parse_patch(&text).unwrap();
+        assert_eq!(patches.len(), 1);
+        assert_eq!(patches[0].old_path, PathBuf::from("src/foo.rs"));
+        assert_eq!(patches[0].new_path, PathBuf::from("src/bar.rs"));
+        assert_eq!(apply_hunks(&original, &patches[0].hunks).unwrap(), replaced);
+    }
+
+    #[test]
+    fn test_moved_empty_dirs() {
+        let dir = TestDir::new("patch-empty");
+        fs::create_dir_all(dir.join("foo/empty")).unwrap();
+        fs::create_dir_all(dir.join("foo/full")).unwrap();
+        fs::create_dir_all(dir.join("foo_old/inner")).unwrap();
+        fs::write(dir.join("foo/full/a.txt"), "a\n").unwrap();
+        let files = [dir.join("foo/full/a.txt")];
+        let plan = Plan {
+            renames: vec![
+                Rename { from: dir.join("foo"), to: dir.join("bar") },
+                Rename { from: dir.join("foo_old"), to: dir.join("bar_old") },
+            ],
+            ..Plan::default()
+        };
+
+        // The file moves in the patch; the directories without files can't
+        assert_eq!(moved_empty_dirs(&plan, &files), [
+            (dir.join("foo/empty"), dir.join("bar/empty")),
+            (dir.join("foo_old"), dir.join("bar_old")),
+        ]);
+        let text = format_patch(&plan, &files);
+        assert!(text.contains("rename to ") && text.contains("bar/full/a.txt"));
+        assert!(!text.contains("foo/empty") && !text.contains("foo_old"));
+    }
+
+    #[test]
+    fn test_apply_hunks_with_offset() {
+        let hunk = diff::Hunk {
+            old_start: 1, old_len: 1, new_start: 1, new_len: 1,
+            lines: vec!["-foo\n".to_string(), "+bar\n".to_string()],
+        };
+
+        // The target line moved down; the hunk is still located
+        assert_eq!(apply_hunks("x\ny\nfoo\n", std::slice::from_ref(&hunk)).unwrap(), "x\ny\nbar\n");
+        assert!(apply_hunks("nothing\n", &[hunk]).is_err());
+    }
+}
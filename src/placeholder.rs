@@ -0,0 +1,390 @@
+use crate::sha256;
+use anyhow::{Result, Context, anyhow};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Next value of the `{{n}}` counter
+pub static GLOBAL_COUNTER: AtomicUsize = AtomicUsize::new(1);
+
+/// Default zero-padding width of the `{{n}}` counter
+pub static GLOBAL_COUNTER_WIDTH: AtomicUsize = AtomicUsize::new(0);
+
+/// Expand built-in placeholders in a TO string
+///
+/// Supported placeholders:
+/// * `{{env.NAME}}` - value of environment variable NAME (error if unset)
+/// * `{{date}}` / `{{date:FORMAT}}` - current local date, strftime-style FORMAT (default `%Y-%m-%d`)
+/// * `{{uuid}}` - a random UUID v4
+///
+/// Unknown placeholders are left untouched so other features can use `{{...}}` syntax.
+/// The per-file `{{n}}` counter is expanded later by [`expand_counter`].
+///
+/// # Arguments
+/// * `s` - The string to expand
+///
+/// # Returns
+/// * `Result<String>` - The expanded string
+pub fn expand(s: &str) -> Result<String> {
+    substitute(s, expand_one)
+}
+
+/// Replace each `{{...}}` placeholder for which `expand` returns a value
+///
+/// `expand` receives the trimmed text between the braces; placeholders it
+/// returns None for are kept as-is.
+fn substitute(s: &str, mut expand: impl FnMut(&str) -> Result<Option<String>>) -> Result<String> {
+    let mut result = String::with_capacity(s.len());
+    let mut rest = s;
+
+    while let Some(start) = rest.find("{{") {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            result.push_str(&rest[start..]);
+            return Ok(result);
+        };
+
+        match expand(after[..end].trim())? {
+            Some(value) => result.push_str(&value),
+            None => result.push_str(&rest[start..start + 2 + end + 2]),
+        }
+        rest = &after[end + 2..];
+    }
+
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// Expand a single placeholder name, or None if it isn't a built-in
+fn expand_one(name: &str) -> Result<Option<String>> {
+    if let Some(var) = name.strip_prefix("env.") {
+        return std::env::var(var)
+            .map(Some)
+            .map_err(|_| anyhow!("Environment variable '{}' used in {{{{env.{}}}}} is not set", var, var));
+    }
+
+    if name == "date" {
+        return Ok(Some(format_date("%Y-%m-%d", now())));
+    }
+    if let Some(format) = name.strip_prefix("date:") {
+        return Ok(Some(format_date(format, now())));
+    }
+
+    if name == "uuid" {
+        return Ok(Some(uuid_v4()));
+    }
+
+    Ok(None)
+}
+
+/// Take the next counter value (one per processed file)
+pub fn next_counter() -> usize {
+    GLOBAL_COUNTER.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Check whether a string contains a counter placeholder
+pub fn has_counter(s: &str) -> bool {
+    expand_counter(s, 0) != s
+}
+
+/// Expand the counter placeholders `{{n}}`, `{{counter}}`, `{{n:WIDTH}}` and `{{counter:WIDTH}}`
+///
+/// Without an explicit WIDTH the number is zero-padded to --counter-width.
+///
+/// # Arguments
+/// * `s` - The string to expand
+/// * `n` - The counter value
+///
+/// # Returns
+/// * `String` - The expanded string
+pub fn expand_counter(s: &str, n: usize) -> String {
+    let expanded = substitute(s, |inner| {
+        let (name, width) = match inner.split_once(':') {
+            Some((name, width)) => (name, width.parse::<usize>().ok()),
+            None => (inner, Some(GLOBAL_COUNTER_WIDTH.load(Ordering::Relaxed))),
+        };
+        Ok(match (name, width) {
+            ("n" | "counter", Some(width)) => Some(format!("{:0width$}", n, width = width)),
+            _ => None,
+        })
+    });
+    expanded.unwrap_or_else(|_| s.to_string())
+}
+
+/// Check whether a string contains a file-metadata placeholder
+pub fn has_file_metadata(s: &str) -> bool {
+    let mut found = false;
+    let _ = substitute(s, |inner| {
+        let name = inner.split_once(':').map_or(inner, |(name, _)| name);
+        found |= matches!(name, "mtime" | "size" | "sha256");
+        Ok(None)
+    });
+    found
+}
+
+/// Expand file-metadata placeholders for one file
+///
+/// Supported placeholders:
+/// * `{{mtime}}` / `{{mtime:FORMAT}}` - local modification time (default `%Y-%m-%d`)
+/// * `{{size}}` - file size in bytes
+/// * `{{sha256}}` / `{{sha256:LEN}}` - hex SHA-256 of the content, optionally truncated to LEN
+///
+/// # Arguments
+/// * `s` - The string to expand
+/// * `path` - The file the metadata is read from
+///
+/// # Returns
+/// * `Result<String>` - The expanded string
+pub fn expand_file_metadata(s: &str, path: &Path) -> Result<String> {
+    let mut hash: Option<String> = None;
+
+    substitute(s, |inner| {
+        let (name, arg) = match inner.split_once(':') {
+            Some((name, arg)) => (name, Some(arg)),
+            None => (inner, None),
+        };
+
+        match name {
+            "mtime" => {
+                let modified = std::fs::metadata(path)
+                    .and_then(|m| m.modified())
+                    .with_context(|| format!("Failed to read the modification time of {:?}", path))?;
+                let secs = modified.duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0);
+                Ok(Some(format_date(arg.unwrap_or("%Y-%m-%d"), local_time(secs))))
+            },
+            "size" => {
+                let metadata = std::fs::metadata(path)
+                    .with_context(|| format!("Failed to read the size of {:?}", path))?;
+                Ok(Some(metadata.len().to_string()))
+            },
+            "sha256" => {
+                if hash.is_none() {
+                    let content = std::fs::read(path)
+                        .with_context(|| format!("Failed to read {:?} for {{{{sha256}}}}", path))?;
+                    hash = Some(sha256::hex_digest(&content));
+                }
+                let hex = hash.as_deref().unwrap_or_default();
+                let len = match arg {
+                    Some(len) => len.parse::<usize>()
+                        .map_err(|_| anyhow!("Invalid length in {{{{sha256:{}}}}}", len))?,
+                    None => hex.len(),
+                };
+                Ok(Some(hex[..len.min(hex.len())].to_string()))
+            },
+            _ => Ok(None),
+        }
+    })
+}
+
+/// Broken-down calendar time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DateTime {
+    pub year: i64,
+    pub month: u32,  // 1-12
+    pub day: u32,    // 1-31
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    pub weekday: u32, // 0 = Sunday
+    pub yearday: u32, // 1-366
+}
+
+/// Current local time (UTC where the local offset isn't available)
+pub fn now() -> DateTime {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0);
+    local_time(secs)
+}
+
+/// Convert a Unix timestamp to local time
+pub fn local_time(secs: i64) -> DateTime {
+    utc_time(secs + local_offset(secs))
+}
+
+#[cfg(unix)]
+fn local_offset(secs: i64) -> i64 {
+    // SAFETY: localtime_r only writes into the provided struct
+    unsafe {
+        let t = secs as libc::time_t;
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&t, &mut tm).is_null() {
+            0
+        } else {
+            tm.tm_gmtoff
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn local_offset(_secs: i64) -> i64 {
+    0
+}
+
+/// Convert a Unix timestamp to UTC calendar time
+pub fn utc_time(secs: i64) -> DateTime {
+    let days = secs.div_euclid(86_400);
+    let rem = secs.rem_euclid(86_400);
+
+    // Civil-from-days (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    let leap = (year % 4 == 0 && year % 100 != 0) || year % 400 == 0;
+    let cumulative = [0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334];
+    let yearday = cumulative[(month - 1) as usize] + day + if leap && month > 2 { 1 } else { 0 };
+
+    DateTime {
+        year,
+        month,
+        day,
+        hour: (rem / 3_600) as u32,
+        minute: (rem % 3_600 / 60) as u32,
+        second: (rem % 60) as u32,
+        weekday: (days + 4).rem_euclid(7) as u32,
+        yearday,
+    }
+}
+
+/// Format a date with a strftime-like format string
+///
+/// Supports `%Y %y %m %d %e %H %M %S %j %a %A %b %B %F %T %%`; other sequences are kept as-is.
+///
+/// # Arguments
+/// * `format` - The format string
+/// * `t` - The time to format
+///
+/// # Returns
+/// * `String` - The formatted date
+pub fn format_date(format: &str, t: DateTime) -> String {
+    const DAYS: [&str; 7] = ["Sunday", "Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday"];
+    const MONTHS: [&str; 12] = [
+        "January", "February", "March", "April", "May", "June",
+        "July", "August", "September", "October", "November", "December",
+    ];
+
+    let mut out = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => out.push_str(&t.year.to_string()),
+            Some('y') => out.push_str(&format!("{:02}", t.year.rem_euclid(100))),
+            Some('m') => out.push_str(&format!("{:02}", t.month)),
+            Some('d') => out.push_str(&format!("{:02}", t.day)),
+            Some('e') => out.push_str(&format!("{:>2}", t.day)),
+            Some('H') => out.push_str(&format!("{:02}", t.hour)),
+            Some('M') => out.push_str(&format!("{:02}", t.minute)),
+            Some('S') => out.push_str(&format!("{:02}", t.second)),
+            Some('j') => out.push_str(&format!("{:03}", t.yearday)),
+            Some('a') => out.push_str(&DAYS[t.weekday as usize][..3]),
+            Some('A') => out.push_str(DAYS[t.weekday as usize]),
+            Some('b') => out.push_str(&MONTHS[(t.month - 1) as usize][..3]),
+            Some('B') => out.push_str(MONTHS[(t.month - 1) as usize]),
+            Some('F') => out.push_str(&format!("{}-{:02}-{:02}", t.year, t.month, t.day)),
+            Some('T') => out.push_str(&format!("{:02}:{:02}:{:02}", t.hour, t.minute, t.second)),
+            Some('%') => out.push('%'),
+            Some(other) => {
+                out.push('%');
+                out.push(other);
+            },
+            None => out.push('%'),
+        }
+    }
+    out
+}
+
+/// Fill a buffer with random bytes
+fn random_bytes(buf: &mut [u8]) {
+    use std::io::Read;
+
+    let from_os = std::fs::File::open("/dev/urandom")
+        .and_then(|mut f| f.read_exact(buf))
+        .is_ok();
+    if from_os {
+        return;
+    }
+
+    // Fallback: std's randomly seeded hasher
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+    for (i, chunk) in buf.chunks_mut(8).enumerate() {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_usize(i);
+        hasher.write_u128(SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0));
+        let bytes = hasher.finish().to_le_bytes();
+        chunk.copy_from_slice(&bytes[..chunk.len()]);
+    }
+}
+
+/// Generate a random UUID v4 string
+fn uuid_v4() -> String {
+    let mut b = [0u8; 16];
+    random_bytes(&mut b);
+    b[6] = (b[6] & 0x0f) | 0x40; // version 4
+    b[8] = (b[8] & 0x3f) | 0x80; // RFC 4122 variant
+
+    let hex: String = b.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::TestDir;
+
+    #[test]
+    fn test_format_date() {
+        // 2024-02-29 13:05:09 UTC, a Thursday
+        let t = utc_time(1_709_211_909);
+        assert_eq!(format_date("%Y-%m-%d %H:%M:%S", t), "2024-02-29 13:05:09");
+        assert_eq!(format_date("%a %b %j %y %%", t), "Thu Feb 060 24 %");
+        assert_eq!(utc_time(0).year, 1970);
+    }
+
+    #[test]
+    fn test_expand() {
+        std::env::set_var("MANE_TEST_AUTHOR", "kfly8");
+        assert_eq!(expand("by {{env.MANE_TEST_AUTHOR}}").unwrap(), "by kfly8");
+        assert_eq!(expand("{{unknown}} {{ x").unwrap(), "{{unknown}} {{ x");
+        assert!(expand("{{env.MANE_TEST_SURELY_UNSET}}").is_err());
+
+        assert_eq!(expand("{{n}}").unwrap(), "{{n}}");
+
+        let uuid = expand("{{uuid}}").unwrap();
+        assert_eq!(uuid.len(), 36);
+        assert_eq!(&uuid[14..15], "4");
+    }
+
+    #[test]
+    fn test_expand_counter() {
+        assert_eq!(expand_counter("img-{{n}}.png", 7), "img-7.png");
+        assert_eq!(expand_counter("img-{{ counter:3 }}", 7), "img-007");
+        assert_eq!(expand_counter("{{n:x}} {{date}} {{n", 7), "{{n:x}} {{date}} {{n");
+        assert!(has_counter("a{{n:2}}"));
+        assert!(!has_counter("a{{name}}"));
+    }
+
+    #[test]
+    fn test_expand_file_metadata() {
+        let dir = TestDir::new("placeholder");
+        let path = dir.join("app.js");
+        std::fs::write(&path, "abc").unwrap();
+
+        assert!(has_file_metadata("app.{{sha256:8}}.js"));
+        assert!(!has_file_metadata("app.{{n}}.js"));
+        assert_eq!(expand_file_metadata("app.{{sha256:8}}.js", &path).unwrap(), "app.ba7816bf.js");
+        assert_eq!(expand_file_metadata("{{size}}B {{n}}", &path).unwrap(), "3B {{n}}");
+        assert_eq!(expand_file_metadata("{{mtime:%Y}}", &path).unwrap().len(), 4);
+        assert!(expand_file_metadata("{{sha256:x}}", &path).is_err());
+    }
+}
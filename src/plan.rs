@@ -0,0 +1,467 @@
+use anyhow::{Result, Context, anyhow};
+use atty::Stream;
+use crate::binary::Detection;
+use crate::color;
+use crate::gzip;
+use crate::json::{self, Value};
+use crate::patch;
+use crate::summary::{self, Event};
+use crate::template;
+use crate::trash;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// A content change to a single file
+#[derive(Debug, Clone)]
+pub struct ContentEdit {
+    /// File path (in the original tree)
+    pub path: PathBuf,
+
+    /// Content before replacement
+    pub original: String,
+
+    /// Content after replacement
+    pub replaced: String,
+
+    /// Whether the file is gzip-compressed (--decompress), so it is written back compressed
+    pub compressed: bool,
+}
+
+/// A zip or tar archive repacked with replaced members (--archives)
+#[derive(Debug, Clone)]
+pub struct ArchiveEdit {
+    /// Archive path (in the original tree)
+    pub path: PathBuf,
+
+    /// The repacked archive
+    pub replaced: Vec<u8>,
+}
+
+/// A rename of a single file or directory
+///
+/// `from` and `to` share the same parent directory; parents are renamed by
+/// their own entries, which come later in the plan.
+#[derive(Debug, Clone)]
+pub struct Rename {
+    /// Path before renaming (in the original tree)
+    pub from: PathBuf,
+
+    /// Path after renaming
+    pub to: PathBuf,
+}
+
+/// A symlink pointed at the new path of its renamed target (--fix-symlinks)
+#[derive(Debug, Clone)]
+pub struct Relink {
+    /// Symlink path (in the original tree)
+    pub path: PathBuf,
+
+    /// New target, as stored in the link
+    pub target: PathBuf,
+}
+
+/// The full set of changes an in-place run intends to make
+#[derive(Debug, Clone, Default)]
+pub struct Plan {
+    /// Content edits, applied before any rename
+    pub edits: Vec<ContentEdit>,
+
+    /// Repacked archives, written with the content edits
+    pub archives: Vec<ArchiveEdit>,
+
+    /// Renames, ordered so that children come before their parents
+    pub renames: Vec<Rename>,
+
+    /// Symlinks whose target moved, relinked after the renames
+    pub links: Vec<Relink>,
+}
+
+impl Plan {
+    /// Compute where a path of the original tree ends up after all renames
+    ///
+    /// # Arguments
+    /// * `original` - Path in the original tree
+    ///
+    /// # Returns
+    /// * `PathBuf` - Path after the plan has been applied
+    pub fn final_path(&self, original: &Path) -> PathBuf {
+        let renamed: HashMap<&Path, &Path> = self.renames.iter()
+            .map(|r| (r.from.as_path(), r.to.as_path()))
+            .collect();
+
+        let mut prefix = PathBuf::new();
+        let mut result = PathBuf::new();
+        for component in original.components() {
+            prefix.push(component);
+            match (component, renamed.get(prefix.as_path())) {
+                (Component::Normal(_), Some(to)) => {
+                    result.push(to.file_name().unwrap_or(component.as_os_str()));
+                },
+                _ => result.push(component),
+            }
+        }
+
+        result
+    }
+
+    /// Write the changed files under a directory instead of applying the plan (--output-dir)
+    ///
+    /// Each file whose content or path the plan changes is written under `dir`
+    /// at its new path; the original tree is left as it is.
+    ///
+    /// # Arguments
+    /// * `dir` - The output directory
+    /// * `files` - Files of the original tree
+    ///
+    /// # Returns
+    /// * `Result<()>` - Result of the operation
+    pub fn write_to(&self, dir: &Path, files: &[PathBuf]) -> Result<()> {
+        let mut contents: HashMap<&Path, Vec<u8>> = self.edits.iter()
+            .map(|edit| (edit.path.as_path(), gzip::encode(&edit.replaced, edit.compressed)))
+            .collect();
+        contents.extend(self.archives.iter().map(|edit| (edit.path.as_path(), edit.replaced.clone())));
+
+        for file in files {
+            let target = self.final_path(file);
+            let content = contents.remove(file.as_path());
+            if content.is_none() && &target == file {
+                continue;
+            }
+            let out = mirrored(dir, &target)?;
+            if let Some(parent) = out.parent() {
+                fs::create_dir_all(parent).with_context(|| format!("Failed to create directory: {:?}", parent))?;
+            }
+            match content {
+                Some(content) => {
+                    fs::write(&out, content).with_context(|| format!("Failed to write file: {:?}", out))?;
+                    summary::record(Event::Modified);
+                },
+                None => {
+                    fs::copy(file, &out).with_context(|| format!("Failed to copy {:?} to {:?}", file, out))?;
+                    summary::record(Event::Renamed);
+                },
+            }
+            info!("Wrote: {:?}", out);
+        }
+        Ok(())
+    }
+
+    /// Renames that `apply` will skip because their target already exists
+    ///
+    /// # Arguments
+    /// * `trash` - Whether existing targets are moved to the trash (--trash)
+    ///
+    /// # Returns
+    /// * `Vec<&Rename>` - The renames that won't be made
+    pub fn blocked_renames(&self, trash: bool) -> Vec<&Rename> {
+        // Renames go deepest first, so the parent of each is still where it was
+        self.renames.iter()
+            .filter(|rename| rename.to.exists() && rename.to != rename.from)
+            .filter(|rename| !trash || same_file(&rename.from, &rename.to))
+            .collect()
+    }
+
+    /// Apply the plan to the filesystem
+    ///
+    /// Content edits are written first, then renames are performed deepest
+    /// path first, then symlinks are relinked. Renames whose target already exists are skipped with a warning,
+    /// unless `trash` is set, which moves the existing target to the trash first.
+    ///
+    /// # Arguments
+    /// * `trash` - Whether to trash existing rename targets instead of skipping (--trash)
+    ///
+    /// # Returns
+    /// * `Result<()>` - Result of the operation
+    pub fn apply(&self, trash: bool) -> Result<()> {
+        for edit in &self.edits {
+            fs::write(&edit.path, gzip::encode(&edit.replaced, edit.compressed))
+                .with_context(|| format!("Failed to write file: {:?}", edit.path))?;
+            info!("Modified content: {:?}", edit.path);
+            summary::record(Event::Modified);
+        }
+
+        for edit in &self.archives {
+            fs::write(&edit.path, &edit.replaced)
+                .with_context(|| format!("Failed to write archive: {:?}", edit.path))?;
+            info!("Modified archive: {:?}", edit.path);
+            summary::record(Event::Modified);
+        }
+
+        // Renames are applied bottom-up, each one renaming the last component of
+        // its path as moved by the renames already made, so a plan listing a
+        // directory before its contents still applies
+        let mut renames: Vec<&Rename> = self.renames.iter().collect();
+        renames.sort_by_key(|rename| Reverse(rename.from.components().count()));
+        let mut moved: Vec<(PathBuf, PathBuf)> = Vec::new();
+
+        for rename in renames {
+            let from = moved.iter().fold(rename.from.clone(), |path, (old, new)| match path.strip_prefix(old) {
+                Ok(rest) if !rest.as_os_str().is_empty() => new.join(rest),
+                _ => path,
+            });
+            let to = from.with_file_name(rename.to.file_name().unwrap_or_default());
+
+            // Skip if the new path already exists, or trash it with --trash. A target that
+            // is the path itself (a case-only rename on a case-insensitive filesystem) stays
+            if to.exists() && to != from {
+                if !trash || same_file(&from, &to) {
+                    warn!("Cannot rename {:?} to {:?}: target already exists", from, to);
+                    summary::record(Event::Skipped);
+                    continue;
+                }
+                trash::move_to_trash(&to)?;
+                info!("Moved existing {:?} to the trash", to);
+            }
+
+            fs::rename(&from, &to)
+                .with_context(|| format!("Failed to rename {:?} to {:?}", from, to))?;
+            let (old_display, new_display) = color::highlight_change(
+                &format!("{:?}", from), &format!("{:?}", to), Stream::Stderr);
+            info!("Renamed: {} -> {}", old_display, new_display);
+            summary::record(Event::Renamed);
+            moved.push((from, to));
+        }
+
+        for link in &self.links {
+            // The link itself may have been renamed, or moved with its directory
+            let at = moved.iter().fold(link.path.clone(), |path, (old, new)| match path.strip_prefix(old) {
+                Ok(rest) if rest.as_os_str().is_empty() => new.clone(),
+                Ok(rest) => new.join(rest),
+                Err(_) => path,
+            });
+            fs::remove_file(&at).with_context(|| format!("Failed to remove symlink: {:?}", at))?;
+            template::symlink(&link.target.to_string_lossy(), &at)
+                .with_context(|| format!("Failed to relink {:?} to {:?}", at, link.target))?;
+            info!("Relinked: {:?} -> {:?}", at, link.target);
+            summary::record(Event::Modified);
+        }
+
+        Ok(())
+    }
+}
+
+/// Where a file is written under --output-dir: its path, relative to the current directory, under `dir`
+///
+/// # Arguments
+/// * `dir` - The output directory
+/// * `path` - Path of the file
+///
+/// # Returns
+/// * `Result<PathBuf>` - The output path; error for a path outside the current directory
+pub fn mirrored(dir: &Path, path: &Path) -> Result<PathBuf> {
+    let outside = || anyhow!("Can't write {:?} under the output directory: it is outside the current directory", path);
+    let cwd = std::env::current_dir()?;
+    let relative = match path.is_absolute() {
+        true => path.strip_prefix(&cwd).map_err(|_| outside())?,
+        false => path,
+    };
+    let mut mirrored = dir.to_path_buf();
+    for component in relative.components() {
+        match component {
+            Component::Normal(name) => mirrored.push(name),
+            Component::CurDir => {},
+            _ => return Err(outside()),
+        }
+    }
+    Ok(mirrored)
+}
+
+/// Check whether two paths are the same file or directory
+#[cfg(unix)]
+fn same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (a.symlink_metadata(), b.symlink_metadata()) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn same_file(a: &Path, b: &Path) -> bool {
+    a.to_string_lossy().to_lowercase() == b.to_string_lossy().to_lowercase()
+}
+
+/// Version of the plan file format
+const PLAN_VERSION: usize = 1;
+
+/// Old and new path of everything an applied plan moves, files inside renamed directories included
+///
+/// # Arguments
+/// * `plan` - The applied plan, without the renames it skipped
+/// * `paths` - Files and directories of the original tree
+///
+/// # Returns
+/// * `Vec<(PathBuf, PathBuf)>` - Old and new path of each moved path, in the order given
+pub fn rename_mapping(plan: &Plan, paths: &[PathBuf]) -> Vec<(PathBuf, PathBuf)> {
+    paths.iter()
+        .map(|path| (path.clone(), plan.final_path(path)))
+        .filter(|(old, new)| old != new)
+        .collect()
+}
+
+/// Write the old and new path of everything a run renamed, as a JSON object (--rename-map)
+///
+/// # Arguments
+/// * `out` - File to write, or `-` for stdout
+/// * `mapping` - Old and new paths (see `rename_mapping`)
+///
+/// # Returns
+/// * `Result<()>` - Result of the operation
+pub fn write_rename_map(out: &Path, mapping: &[(PathBuf, PathBuf)]) -> Result<()> {
+    let entries = mapping.iter()
+        .map(|(old, new)| (old.to_string_lossy().to_string(), Value::from(new.to_string_lossy().to_string())))
+        .collect();
+    let document = Value::Object(entries).to_pretty_string() + "\n";
+
+    if out == Path::new("-") {
+        std::io::Write::write_all(&mut std::io::stdout(), document.as_bytes())?;
+    } else {
+        fs::write(out, document).with_context(|| format!("Failed to write rename map: {:?}", out))?;
+        info!("Wrote rename map: {:?}", out);
+    }
+    Ok(())
+}
+
+/// Write the plan as a JSON file for review
+///
+/// Content edits are stored as unified-diff hunks so the file stays readable.
+///
+/// # Arguments
+/// * `out` - Plan file to write
+/// * `plan` - Planned changes
+///
+/// # Returns
+/// * `Result<()>` - Result of the operation
+pub fn write_plan(out: &Path, plan: &Plan) -> Result<()> {
+    let edits = plan.edits.iter()
+        .map(|edit| json::object([
+            ("path", Value::from(edit.path.to_string_lossy().to_string())),
+            ("diff", Value::from(patch::format_hunks(&edit.original, &edit.replaced))),
+        ]))
+        .collect();
+    let renames = plan.renames.iter()
+        .map(|rename| json::object([
+            ("from", Value::from(rename.from.to_string_lossy().to_string())),
+            ("to", Value::from(rename.to.to_string_lossy().to_string())),
+        ]))
+        .collect();
+
+    let document = json::object([
+        ("version", Value::from(PLAN_VERSION)),
+        ("edits", Value::Array(edits)),
+        ("renames", Value::Array(renames)),
+    ]);
+
+    fs::write(out, document.to_pretty_string() + "\n")
+        .with_context(|| format!("Failed to write plan file: {:?}", out))?;
+    info!("Wrote plan: {:?}", out);
+
+    Ok(())
+}
+
+/// Load a plan file written by `write_plan`
+///
+/// The recorded hunks are checked against the current file contents, so a plan
+/// that no longer matches the tree fails before anything is written.
+///
+/// # Arguments
+/// * `path` - Plan file to read
+///
+/// # Returns
+/// * `Result<Plan>` - The plan, ready to apply
+pub fn load_plan(path: &Path) -> Result<Plan> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read plan file: {:?}", path))?;
+    let document = json::parse(&text)
+        .with_context(|| format!("Invalid plan file: {:?}", path))?;
+
+    let field = |value: &Value, key: &str| -> Result<String> {
+        value.get(key)
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .ok_or_else(|| anyhow!("Plan entry is missing \"{}\"", key))
+    };
+    let entries = |key: &str| document.get(key).and_then(Value::as_array).unwrap_or(&[]);
+
+    let mut plan = Plan::default();
+
+    for entry in entries("edits") {
+        let file = PathBuf::from(field(entry, "path")?);
+        let hunks = patch::parse_hunks(&field(entry, "diff")?)?;
+        // Plans made with --decompress may edit gzip files, and plans made with
+        // --text or --binary-threshold files that aren't all UTF-8
+        let (original, compressed) = gzip::read_text(&file, true, Detection::TEXT)
+            .with_context(|| format!("Failed to read file: {:?}", file))?;
+        let replaced = patch::apply_hunks(&original, &hunks)
+            .with_context(|| format!("Plan does not apply to {:?}", file))?;
+        plan.edits.push(ContentEdit { path: file, original, replaced, compressed });
+    }
+
+    for entry in entries("renames") {
+        plan.renames.push(Rename {
+            from: PathBuf::from(field(entry, "from")?),
+            to: PathBuf::from(field(entry, "to")?),
+        });
+    }
+
+    Ok(plan)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::TestDir;
+
+    #[test]
+    fn test_final_path() {
+        let plan = Plan {
+            edits: Vec::new(),
+            renames: vec![
+                Rename { from: PathBuf::from("t/foo/foo.txt"), to: PathBuf::from("t/foo/bar.txt") },
+                Rename { from: PathBuf::from("t/foo"), to: PathBuf::from("t/bar") },
+            ],
+            ..Plan::default()
+        };
+
+        assert_eq!(plan.final_path(Path::new("t/foo/foo.txt")), PathBuf::from("t/bar/bar.txt"));
+        assert_eq!(plan.final_path(Path::new("t/foo/other.txt")), PathBuf::from("t/bar/other.txt"));
+        assert_eq!(plan.final_path(Path::new("t/baz.txt")), PathBuf::from("t/baz.txt"));
+
+        let paths = ["t/foo", "t/foo/foo.txt", "t/foo/other.txt", "t/baz.txt"].map(PathBuf::from);
+        assert_eq!(rename_mapping(&plan, &paths), vec![
+            (PathBuf::from("t/foo"), PathBuf::from("t/bar")),
+            (PathBuf::from("t/foo/foo.txt"), PathBuf::from("t/bar/bar.txt")),
+            (PathBuf::from("t/foo/other.txt"), PathBuf::from("t/bar/other.txt")),
+        ]);
+    }
+
+    #[test]
+    fn test_mirrored() {
+        assert_eq!(mirrored(Path::new("out"), Path::new("./src/a.rs")).unwrap(), PathBuf::from("out/src/a.rs"));
+        let cwd = std::env::current_dir().unwrap();
+        assert_eq!(mirrored(Path::new("out"), &cwd.join("src/a.rs")).unwrap(), PathBuf::from("out/src/a.rs"));
+        assert!(mirrored(Path::new("out"), Path::new("../a.rs")).is_err());
+    }
+
+    #[test]
+    fn test_apply_renames_parents_first() {
+        let root = TestDir::new("plan");
+        fs::create_dir_all(root.join("foo/foo")).unwrap();
+        fs::write(root.join("foo/foo/foo.txt"), "").unwrap();
+
+        // Listed parents first, as a hand-edited plan might be
+        let plan = Plan {
+            renames: vec![
+                Rename { from: root.join("foo"), to: root.join("bar") },
+                Rename { from: root.join("foo/foo"), to: root.join("foo/bar") },
+                Rename { from: root.join("foo/foo/foo.txt"), to: root.join("foo/foo/bar.txt") },
+            ],
+            ..Plan::default()
+        };
+        plan.apply(false).unwrap();
+        assert!(root.join("bar/bar/bar.txt").exists());
+    }
+}
@@ -0,0 +1,166 @@
+use crate::replacer;
+use anyhow::{Result, anyhow};
+use std::path::Path;
+use std::sync::{Condvar, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+/// Default number of jobs: one per available CPU
+pub fn default_jobs() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// Run a function on every item with at most `jobs` threads
+///
+/// Results come back in item order whatever order the items finish in, so
+/// output printed from them stays deterministic.
+///
+/// # Arguments
+/// * `jobs` - Maximum number of threads
+/// * `items` - Items to process
+/// * `f` - Function run on each item
+///
+/// # Returns
+/// * `Vec<R>` - One result per item, in item order
+pub fn map<T: Sync, R: Send>(jobs: usize, items: &[T], f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    let threads = jobs.min(items.len());
+    if threads <= 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<R>>> = Mutex::new((0..items.len()).map(|_| None).collect());
+    // Replacements made on the threads count for the caller's replacer::count_replacements
+    let count_scope = replacer::count_scope();
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| replacer::in_count_scope(count_scope.clone(), || loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(item) = items.get(index) else { break };
+                let result = f(item);
+                results.lock().unwrap()[index] = Some(result);
+            }));
+        }
+    });
+
+    results.into_inner().unwrap().into_iter()
+        .map(|result| result.expect("every item is processed"))
+        .collect()
+}
+
+/// A memory budget the jobs of a pool take their share of before they start (--max-memory)
+///
+/// A job whose share doesn't fit waits until other jobs give theirs back, so
+/// large files are processed fewer at a time. A job needing more than the
+/// whole budget waits until nothing else holds any, and then runs alone.
+pub struct Budget {
+    limit: u64,
+    used: Mutex<u64>,
+    released: Condvar,
+}
+
+/// Part of a budget held by a job, given back when dropped
+pub struct Share<'a> {
+    budget: &'a Budget,
+    amount: u64,
+}
+
+impl Budget {
+    /// A budget of `limit` bytes
+    pub fn new(limit: u64) -> Self {
+        Budget { limit, used: Mutex::new(0), released: Condvar::new() }
+    }
+
+    /// Take `amount` bytes of the budget, waiting until they fit
+    pub fn take(&self, amount: u64) -> Share<'_> {
+        let mut used = self.used.lock().unwrap();
+        while *used > 0 && *used + amount > self.limit {
+            used = self.released.wait(used).unwrap();
+        }
+        *used += amount;
+        Share { budget: self, amount }
+    }
+}
+
+impl Drop for Share<'_> {
+    fn drop(&mut self) {
+        *self.budget.used.lock().unwrap() -= self.amount;
+        self.budget.released.notify_all();
+    }
+}
+
+/// Memory a file takes while it is replaced in: its content, the replaced
+/// content and the bytes written, estimated from its size
+pub fn footprint(path: &Path) -> u64 {
+    path.metadata().map_or(0, |metadata| metadata.len().saturating_mul(3))
+}
+
+/// Parse a size such as `512M`, `2G` or `1048576`
+///
+/// Suffixes are binary (K = 1024 bytes) and may be followed by `B` or `iB`.
+///
+/// # Arguments
+/// * `s` - The size
+///
+/// # Returns
+/// * `Result<u64>` - Number of bytes
+pub fn parse_size(s: &str) -> Result<u64> {
+    let trimmed = s.trim();
+    let digits = trimmed.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(digits);
+    let shift = match unit.trim().to_ascii_uppercase().trim_end_matches("IB").trim_end_matches('B') {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        "T" => 40,
+        _ => return Err(anyhow!("Invalid size: {} (expected a number of bytes, or K, M, G or T)", s)),
+    };
+    let number: f64 = number.parse().map_err(|_| anyhow!("Invalid size: {}", s))?;
+    Ok((number * (1u64 << shift) as f64) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_map() {
+        let items: Vec<u64> = (0..100).collect();
+        let squares = map(8, &items, |&n| {
+            // Finish in a different order than started
+            thread::sleep(std::time::Duration::from_micros(100 - n));
+            n * n
+        });
+        assert_eq!(squares, items.iter().map(|n| n * n).collect::<Vec<_>>());
+        assert_eq!(map(1, &items[..3], |&n| n + 1), [1, 2, 3]);
+        assert!(map(4, &[] as &[u64], |&n| n).is_empty());
+    }
+
+    #[test]
+    fn test_budget() {
+        let budget = Budget::new(100);
+        let (running, peak) = (AtomicUsize::new(0), AtomicUsize::new(0));
+        let amounts = [40, 40, 40, 150, 40, 40];
+        map(8, &amounts, |&amount| {
+            let _share = budget.take(amount);
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            peak.fetch_max(now, Ordering::SeqCst);
+            assert!(amount <= 100 || now == 1, "an oversized job runs alone");
+            thread::sleep(std::time::Duration::from_millis(5));
+            running.fetch_sub(1, Ordering::SeqCst);
+        });
+        assert!(peak.load(Ordering::SeqCst) <= 2);
+        assert_eq!(*budget.used.lock().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("1048576").unwrap(), 1 << 20);
+        assert_eq!(parse_size("512M").unwrap(), 512 << 20);
+        assert_eq!(parse_size("1.5GiB").unwrap(), 3 << 29);
+        assert_eq!(parse_size("64kb").unwrap(), 64 << 10);
+        assert!(parse_size("lots").is_err());
+        assert!(parse_size("10X").is_err());
+    }
+}
@@ -0,0 +1,126 @@
+use crate::args::ReplacementRule;
+use crate::case;
+use crate::multiline;
+use aho_corasick::AhoCorasick;
+use std::path::Path;
+
+/// A multi-pattern search for the FROM strings of all rules
+///
+/// Finding none of them in a text proves that no rule replaces anything in
+/// it: a later rule can only match what an earlier one wrote if the earlier
+/// one matched first. The text can then be left alone without building case
+/// variants or running any replacement pass.
+#[derive(Debug)]
+pub struct Prefilter {
+    /// Automaton over every FROM string and case variant
+    automaton: Option<AhoCorasick>,
+
+    /// Index of the rule each pattern belongs to
+    rule_of_pattern: Vec<usize>,
+
+    /// Whether some rule is limited to certain paths
+    scoped: bool,
+
+    /// Whether every text has to be treated as a candidate
+    always: bool,
+}
+
+impl Prefilter {
+    /// Build the search for a rule set
+    ///
+    /// # Arguments
+    /// * `rules` - Replacement rules
+    /// * `case_enabled` - Whether case variants of FROM are replaced too
+    /// * `same_shape` - Whether FROM's words match in any shape (--same-shape)
+    ///
+    /// # Returns
+    /// * `Prefilter` - The search
+    pub fn new(rules: &[ReplacementRule], case_enabled: bool, same_shape: bool) -> Self {
+        let same_shape = case_enabled && same_shape;
+        let mut patterns = Vec::new();
+        let mut rule_of_pattern = Vec::new();
+        for (index, rule) in rules.iter().enumerate() {
+            let froms = if multiline::is_multiline(&rule.from) {
+                vec![multiline::needle(&rule.from).to_string()]
+            } else if same_shape {
+                // Any occurrence contains the longest word, in some capitalization
+                case::words(&rule.from).into_iter().max_by_key(|word| word.len()).into_iter().collect()
+            } else if case_enabled {
+                case::variant_pairs(&rule.from, &rule.to).into_iter().map(|(from, _)| from).collect()
+            } else {
+                vec![rule.from.clone()]
+            };
+            for from in froms.into_iter().filter(|from| !from.is_empty()) {
+                patterns.push(from);
+                rule_of_pattern.push(index);
+            }
+        }
+
+        let automaton = AhoCorasick::builder()
+            .ascii_case_insensitive(same_shape)
+            .build(&patterns);
+        Prefilter {
+            // An empty FROM can't be searched for, and never matches
+            automaton: automaton.ok().filter(|_| !patterns.is_empty()),
+            rule_of_pattern,
+            scoped: rules.iter().any(|rule| rule.scope.is_some()),
+            // Case-insensitive search is limited to ASCII
+            always: same_shape && patterns.iter().any(|pattern| !pattern.is_ascii()),
+        }
+    }
+
+    /// Check whether any rule could replace something in a text
+    ///
+    /// # Arguments
+    /// * `bytes` - The text
+    /// * `rules` - The rules the search was built for
+    /// * `path` - Path the text belongs to, for path-scoped rules
+    ///
+    /// # Returns
+    /// * `bool` - False if no rule matches
+    pub fn may_match(&self, bytes: &[u8], rules: &[ReplacementRule], path: Option<&Path>) -> bool {
+        if self.always {
+            return true;
+        }
+        let Some(automaton) = &self.automaton else {
+            return false;
+        };
+        if !self.scoped {
+            return automaton.is_match(bytes);
+        }
+
+        // A match of an out-of-scope rule may overlap one that applies, so look at all of them
+        automaton.find_overlapping_iter(bytes)
+            .any(|m| rules[self.rule_of_pattern[m.pattern().as_usize()]].applies_to(path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use globset::{Glob, GlobSetBuilder};
+
+    #[test]
+    fn test_may_match() {
+        let rules = vec![ReplacementRule::new("foo_bar".to_string(), "baz".to_string())];
+        let prefilter = Prefilter::new(&rules, true, false);
+        assert!(prefilter.may_match(b"let FooBar = 1;", &rules, None));
+        assert!(prefilter.may_match(b"FOO_BAR", &rules, None));
+        assert!(!prefilter.may_match(b"foo bar", &rules, None));
+        assert!(!Prefilter::new(&rules, false, false).may_match(b"FooBar", &rules, None));
+        assert!(Prefilter::new(&rules, true, true).may_match(b"Foo_barBaz", &rules, None));
+
+        // Rules limited to other paths don't count
+        let mut scope = GlobSetBuilder::new();
+        scope.add(Glob::new("*.rs").unwrap());
+        let scoped = vec![
+            ReplacementRule { scope: Some(scope.build().unwrap()), ..ReplacementRule::new("foo".to_string(), "x".to_string()) },
+            ReplacementRule::new("oo".to_string(), "y".to_string()),
+        ];
+        let prefilter = Prefilter::new(&scoped[..1], false, false);
+        assert!(prefilter.may_match(b"foo", &scoped[..1], Some(Path::new("a.rs"))));
+        assert!(!prefilter.may_match(b"foo", &scoped[..1], Some(Path::new("a.py"))));
+        let prefilter = Prefilter::new(&scoped, false, false);
+        assert!(prefilter.may_match(b"foo", &scoped, Some(Path::new("a.py"))));
+    }
+}
@@ -0,0 +1,262 @@
+use crate::color;
+use crate::json::{self, Value};
+use anyhow::Result;
+use atty::Stream;
+use clap::ValueEnum;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Format of the match report written instead of file contents (--format)
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum ReportFormat {
+    Quickfix, // path:line:col: old -> new, one line per match, for editor quickfix lists
+    Json,     // one JSON object per match, with the rule and case variant that made it
+}
+
+/// The rule and case variant that made a replacement
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Origin {
+    /// 1-based number of the rule, in the order rules are applied
+    pub rule: usize,
+
+    /// Case variant of the rule that matched, or `as written`
+    pub variant: &'static str,
+}
+
+impl std::fmt::Display for Origin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "rule #{}, {}", self.rule, self.variant)
+    }
+}
+
+/// One replacement at a position of the original content
+#[derive(Debug, PartialEq)]
+pub struct Location {
+    /// 1-based line number in the original content
+    pub line: usize,
+
+    /// 1-based byte column in that line
+    pub column: usize,
+
+    /// Text replaced
+    pub old: String,
+
+    /// Text it is replaced with
+    pub new: String,
+
+    /// What made the replacement, if a single match of a rule explains it
+    pub origin: Option<Origin>,
+}
+
+/// A line that differs between the original and the replaced content
+#[derive(Debug, PartialEq)]
+pub struct ChangedLine<'a> {
+    /// 1-based line number in the original content
+    pub line_number: usize,
+
+    /// Line before replacement
+    pub old: &'a str,
+
+    /// Line after replacement
+    pub new: &'a str,
+}
+
+/// Collect the lines that changed between `old` and `new`
+///
+/// Replacements that keep the number of lines are paired line by line; otherwise
+/// the whole content is reported as a single change starting at line 1.
+///
+/// # Arguments
+/// * `old` - Content before replacement
+/// * `new` - Content after replacement
+///
+/// # Returns
+/// * `Vec<ChangedLine>` - The changed lines
+pub fn changed_lines<'a>(old: &'a str, new: &'a str) -> Vec<ChangedLine<'a>> {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+
+    if old_lines.len() != new_lines.len() {
+        return vec![ChangedLine { line_number: 1, old, new }];
+    }
+
+    old_lines
+        .into_iter()
+        .zip(new_lines)
+        .enumerate()
+        .filter(|(_, (o, n))| o != n)
+        .map(|(i, (o, n))| ChangedLine { line_number: i + 1, old: o, new: n })
+        .collect()
+}
+
+/// Print changed lines grep-style as `path:line:-old` / `path:line:+new` pairs
+///
+/// # Arguments
+/// * `path` - Path of the file the content belongs to
+/// * `old` - Content before replacement
+/// * `new` - Content after replacement
+///
+/// # Returns
+/// * `Result<()>` - Result of the operation
+pub fn print_changes(path: &Path, old: &str, new: &str) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+
+    for change in changed_lines(old, new) {
+        let (old_line, new_line) = color::highlight_change(change.old, change.new, Stream::Stdout);
+        writeln!(stdout, "{}:{}:-{}", path.display(), change.line_number, old_line)?;
+        writeln!(stdout, "{}:{}:+{}", path.display(), change.line_number, new_line)?;
+    }
+
+    Ok(())
+}
+
+/// Locate the replacements that turn `old` into `new`
+///
+/// Each changed line is split into the matches `find` reports, as long as
+/// replacing them gives the new line. Otherwise (and for changes spanning
+/// lines) the differing middle part is reported as one replacement.
+///
+/// # Arguments
+/// * `old` - Content before replacement
+/// * `new` - Content after replacement
+/// * `find` - Matches in a line: byte offset, matched text, replacement and its origin
+///
+/// # Returns
+/// * `Vec<Location>` - The replacements, in order
+pub fn locations(old: &str, new: &str, find: impl Fn(&str) -> Vec<(usize, String, String, Origin)>) -> Vec<Location> {
+    let mut result = Vec::new();
+
+    for change in changed_lines(old, new) {
+        if change.old != old {
+            let matches = find(change.old);
+            let mut rebuilt = String::new();
+            let mut last = 0;
+            for (start, from, to, _) in &matches {
+                rebuilt.push_str(&change.old[last..*start]);
+                rebuilt.push_str(to);
+                last = start + from.len();
+            }
+            rebuilt.push_str(&change.old[last..]);
+
+            if !matches.is_empty() && rebuilt == change.new {
+                result.extend(matches.into_iter().map(|(start, old, new, origin)| {
+                    Location { line: change.line_number, column: start + 1, old, new, origin: Some(origin) }
+                }));
+                continue;
+            }
+        }
+
+        // The differing middle part, positioned where it starts
+        let prefix = change.old.char_indices().zip(change.new.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(change.old.len().min(change.new.len()), |((i, _), _)| i);
+        let suffix = change.old[prefix..].chars().rev().zip(change.new[prefix..].chars().rev())
+            .take_while(|(a, b)| a == b)
+            .map(|(c, _)| c.len_utf8())
+            .sum::<usize>();
+        let before = &change.old[..prefix];
+        let line = change.line_number + before.matches('\n').count();
+        let column = prefix - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+        result.push(Location {
+            line,
+            column,
+            old: change.old[prefix..change.old.len() - suffix].replace('\n', "\\n"),
+            new: change.new[prefix..change.new.len() - suffix].replace('\n', "\\n"),
+            origin: None,
+        });
+    }
+
+    result
+}
+
+/// Print replacements as `path:line:col: old -> new` lines (--format quickfix)
+///
+/// # Arguments
+/// * `path` - Path of the file the content belongs to
+/// * `locations` - The replacements
+/// * `with_origin` - Whether to add the rule and case variant of each (-v)
+///
+/// # Returns
+/// * `Result<()>` - Result of the operation
+pub fn print_quickfix(path: &Path, locations: &[Location], with_origin: bool) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+
+    for location in locations {
+        writeln!(stdout, "{}:{}:{}: {}", path.display(), location.line, location.column, describe(location, with_origin))?;
+    }
+
+    Ok(())
+}
+
+/// A replacement as `old -> new`, optionally followed by `(rule #N, variant)`
+pub fn describe(location: &Location, with_origin: bool) -> String {
+    match location.origin.filter(|_| with_origin) {
+        Some(origin) => format!("{} -> {} ({})", location.old, location.new, origin),
+        None => format!("{} -> {}", location.old, location.new),
+    }
+}
+
+/// Print replacements as one JSON object per line (--format json)
+///
+/// # Arguments
+/// * `path` - Path of the file the content belongs to
+/// * `locations` - The replacements
+///
+/// # Returns
+/// * `Result<()>` - Result of the operation
+pub fn print_json(path: &Path, locations: &[Location]) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+
+    for location in locations {
+        let (rule, variant) = match location.origin {
+            Some(origin) => (Value::from(origin.rule), Value::from(origin.variant)),
+            None => (Value::Null, Value::Null),
+        };
+        let report = json::object([
+            ("path", Value::from(path.to_string_lossy().to_string())),
+            ("line", Value::from(location.line)),
+            ("column", Value::from(location.column)),
+            ("old", Value::from(location.old.as_str())),
+            ("new", Value::from(location.new.as_str())),
+            ("rule", rule),
+            ("variant", variant),
+        ]);
+        writeln!(stdout, "{}", report.to_compact_string())?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changed_lines() {
+        let changes = changed_lines("a foo\nb\nc foo\n", "a bar\nb\nc bar\n");
+        assert_eq!(changes, vec![
+            ChangedLine { line_number: 1, old: "a foo", new: "a bar" },
+            ChangedLine { line_number: 3, old: "c foo", new: "c bar" },
+        ]);
+
+        assert!(changed_lines("same\n", "same\n").is_empty());
+    }
+
+    #[test]
+    fn test_locations() {
+        let origin = Origin { rule: 1, variant: "as written" };
+        let find = |line: &str| line.match_indices("foo").map(|(i, m)| (i, m.to_string(), "bar".to_string(), origin)).collect();
+        let found = locations("a foo foo\nb\n", "a bar bar\nb\n", find);
+        assert_eq!(found, vec![
+            Location { line: 1, column: 3, old: "foo".to_string(), new: "bar".to_string(), origin: Some(origin) },
+            Location { line: 1, column: 7, old: "foo".to_string(), new: "bar".to_string(), origin: Some(origin) },
+        ]);
+        assert_eq!(describe(&found[0], true), "foo -> bar (rule #1, as written)");
+        assert_eq!(describe(&found[0], false), "foo -> bar");
+
+        // Changes the matches don't explain, and changes spanning lines, are reported as a whole
+        let none = |_: &str| Vec::new();
+        assert_eq!(locations("x = 1\n", "x = 22\n", none)[0], Location { line: 1, column: 5, old: "1".to_string(), new: "22".to_string(), origin: None });
+        assert_eq!(locations("a\nb\nc\n", "a\nd\n", none)[0], Location { line: 2, column: 1, old: "b\\nc".to_string(), new: "d".to_string(), origin: None });
+    }
+}
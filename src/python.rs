@@ -0,0 +1,98 @@
+use crate::engine::{self, Options};
+use pyo3::exceptions::PyTypeError;
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyString};
+
+/// Read the rules of a call: `(from, to)` pairs or `{"from": ..., "to": ...}` dicts, in order
+fn rules(rules: &Bound<'_, PyAny>) -> PyResult<Vec<(String, String)>> {
+    let mut pairs = Vec::new();
+    for rule in rules.try_iter()? {
+        let rule = rule?;
+        let pair: (String, String) = match rule.downcast::<PyDict>() {
+            Ok(dict) => {
+                let field = |key: &str| -> PyResult<String> {
+                    dict.get_item(key)?
+                        .ok_or_else(|| PyTypeError::new_err(format!("rule is missing {:?}", key)))?
+                        .extract()
+                };
+                (field("from")?, field("to")?)
+            },
+            Err(_) => rule.extract()?,
+        };
+        if !pair.0.is_empty() {
+            pairs.push(pair);
+        }
+    }
+    Ok(pairs)
+}
+
+/// Read the options of a call; `case` is on unless turned off, like the config file's `case`
+fn options(options: Option<&Bound<'_, PyDict>>) -> PyResult<Options> {
+    let mut result = Options::new(true);
+    for (name, value) in options.into_iter().flatten() {
+        let value: bool = value.is_truthy()?;
+        match name.downcast::<PyString>()?.to_str()? {
+            "case" => result.case_enabled = value,
+            "ordered" => result.ordered = value,
+            "same_shape" => result.same_shape = value,
+            "split_digits" => result.split_digits = value,
+            name => return Err(PyTypeError::new_err(format!("unknown option: {:?}", name))),
+        }
+    }
+    Ok(result)
+}
+
+/// Replace in a text like `mane -r FROM TO` does
+///
+/// `rules` is a list of `(from, to)` pairs or `{"from": ..., "to": ...}`
+/// dicts, in order. `options` may set `case` (default True), `ordered`,
+/// `same_shape` and `split_digits`. The GIL is released while replacing.
+#[pyfunction]
+#[pyo3(signature = (content, rules, options = None))]
+fn replace(py: Python<'_>, content: &str, rules: &Bound<'_, PyAny>, options: Option<&Bound<'_, PyDict>>) -> PyResult<String> {
+    let (rules, options) = (self::rules(rules)?, self::options(options)?);
+    let pairs: Vec<(&str, String)> = rules.iter().map(|(from, to)| (from.as_str(), to.clone())).collect();
+    Ok(py.allow_threads(|| engine::replace_rules_counted(content, &pairs, options).0))
+}
+
+/// Apply the rules to every file and directory name of a `/`-separated path
+#[pyfunction]
+#[pyo3(signature = (path, rules, options = None))]
+fn rename_path(py: Python<'_>, path: &str, rules: &Bound<'_, PyAny>, options: Option<&Bound<'_, PyDict>>) -> PyResult<String> {
+    let (rules, options) = (self::rules(rules)?, self::options(options)?);
+    let pairs: Vec<(&str, String)> = rules.iter().map(|(from, to)| (from.as_str(), to.clone())).collect();
+    Ok(py.allow_threads(|| engine::rename_path(path, &pairs, options)))
+}
+
+/// The `mane` Python module
+#[pymodule]
+fn mane(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_function(wrap_pyfunction!(replace, module)?)?;
+    module.add_function(wrap_pyfunction!(rename_path, module)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_module() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let module = PyModule::new(py, "mane").unwrap();
+            mane(&module).unwrap();
+            let call = |code: &str| -> PyResult<String> {
+                let locals = PyDict::new(py);
+                locals.set_item("mane", &module)?;
+                py.eval(&std::ffi::CString::new(code).unwrap(), None, Some(&locals))?.extract()
+            };
+
+            assert_eq!(call(r#"mane.replace("let fooBar = 1;", [("foo_bar", "baz_qux")])"#).unwrap(), "let bazQux = 1;");
+            assert_eq!(call(r#"mane.replace("user_id", [{"from": "user", "to": "customer"}], {"ordered": True, "case": False})"#).unwrap(), "customer_id");
+            assert_eq!(call(r#"mane.rename_path("src/foo_bar/FooBar.ts", [("foo_bar", "baz_qux")])"#).unwrap(), "src/baz_qux/BazQux.ts");
+            assert!(call(r#"mane.replace("x", [("x", "y")], {"cases": False})"#).is_err());
+            assert!(call(r#"mane.replace("x", [{"from": "x"}])"#).is_err());
+        });
+    }
+}
@@ -0,0 +1,256 @@
+use crate::args::Args;
+use crate::gzip;
+use crate::plan::{ContentEdit, Plan, Relink};
+use anyhow::{Result, Context};
+use regex::Regex;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Quoted strings, `<...>` includes, and Markdown link targets (inline and reference-style)
+fn reference_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| Regex::new(r#""([^"\s]+)"|'([^'\s]+)'|<([^<>\s]+)>|\]\(([^()\s]+)\)|(?m)^\s*\[[^\]]+\]:\s*(\S+)"#).unwrap())
+}
+
+/// Add content edits rewriting references to renamed paths (--fix-references)
+///
+/// Every text file of the tree is searched for relative paths (relative
+/// imports, include paths, Markdown links) that resolve to a renamed file or
+/// directory, and those are pointed at its new path, relative to where the
+/// referring file ends up. Files the rules already edit are fixed on top of
+/// their replaced content.
+///
+/// # Arguments
+/// * `plan` - The plan to add the fixes to
+/// * `mapping` - Old and new path of everything renamed (see `plan::rename_mapping`)
+/// * `files` - Files of the original tree
+/// * `args` - Command line arguments
+///
+/// # Returns
+/// * `Result<()>` - Result of the operation
+pub fn fix_references(plan: &mut Plan, mapping: &[(PathBuf, PathBuf)], files: &[PathBuf], args: &Args) -> Result<()> {
+    if mapping.is_empty() {
+        return Ok(());
+    }
+    let renamed: HashMap<PathBuf, PathBuf> = mapping.iter()
+        .map(|(old, new)| (normalize(old), normalize(new)))
+        .collect();
+
+    for file in files {
+        let old_dir = normalize(file.parent().unwrap_or(Path::new("")));
+        let new_dir = normalize(plan.final_path(file).parent().unwrap_or(Path::new("")));
+        match plan.edits.iter_mut().find(|edit| &edit.path == file) {
+            Some(edit) => {
+                let (fixed, count) = fix_text(&edit.replaced, &old_dir, &new_dir, &renamed);
+                if count > 0 {
+                    info!("Fixed references: {:?} ({})", file, count);
+                    edit.replaced = fixed;
+                }
+            },
+            None => {
+                // Binary and unreadable files have no references to fix
+                let Ok((content, compressed)) = gzip::read_text(file, args.decompress, args.detection()) else { continue };
+                let (fixed, count) = fix_text(&content, &old_dir, &new_dir, &renamed);
+                if count > 0 {
+                    info!("Fixed references: {:?} ({})", file, count);
+                    plan.edits.push(ContentEdit { path: file.clone(), original: content, replaced: fixed, compressed });
+                }
+            },
+        }
+    }
+    Ok(())
+}
+
+/// Relink the symlinks whose target is renamed, or moves with a renamed directory (--fix-symlinks)
+///
+/// A relative target stays relative, from where the link ends up; an absolute
+/// one stays absolute.
+///
+/// # Arguments
+/// * `plan` - The plan to add the relinks to
+/// * `mapping` - Old and new path of everything renamed (see `plan::rename_mapping`)
+/// * `paths` - Files and directories of the original tree
+///
+/// # Returns
+/// * `Result<()>` - Result of the operation
+pub fn fix_symlinks(plan: &mut Plan, mapping: &[(PathBuf, PathBuf)], paths: &[PathBuf]) -> Result<()> {
+    if mapping.is_empty() {
+        return Ok(());
+    }
+    let renamed: HashMap<PathBuf, PathBuf> = mapping.iter()
+        .map(|(old, new)| (normalize(old), normalize(new)))
+        .collect();
+    let cwd = std::env::current_dir()?;
+
+    for path in paths.iter().filter(|path| path.is_symlink()) {
+        let target = std::fs::read_link(path).with_context(|| format!("Failed to read symlink: {:?}", path))?;
+        let old_dir = normalize(path.parent().unwrap_or(Path::new("")));
+        let new_target = if target.is_absolute() {
+            // The tree may be walked from a relative root, with the mapping relative to it
+            let target = normalize(&target);
+            new_location(&target, &renamed).or_else(|| {
+                let relative = target.strip_prefix(&cwd).ok()?;
+                new_location(relative, &renamed).map(|new| cwd.join(new))
+            })
+        } else {
+            let new_dir = normalize(plan.final_path(path).parent().unwrap_or(Path::new("")));
+            new_location(&normalize(&old_dir.join(&target)), &renamed).map(|new| relative(&new_dir, &new))
+        };
+        if let Some(new_target) = new_target.filter(|new_target| normalize(new_target) != normalize(&target)) {
+            verbose!("{:?}: {:?} -> {:?}", path, target, new_target);
+            plan.links.push(Relink { path: path.clone(), target: new_target });
+        }
+    }
+    Ok(())
+}
+
+/// Rewrite the references of one file
+///
+/// # Arguments
+/// * `text` - Content of the file
+/// * `old_dir` - Directory of the file in the original tree
+/// * `new_dir` - Directory of the file after renaming
+/// * `renamed` - New path of each renamed path, both normalized
+///
+/// # Returns
+/// * `(String, usize)` - The content with fixed references, and how many were fixed
+fn fix_text(text: &str, old_dir: &Path, new_dir: &Path, renamed: &HashMap<PathBuf, PathBuf>) -> (String, usize) {
+    let mut fixed = String::with_capacity(text.len());
+    let mut count = 0;
+    let mut last = 0;
+    for captures in reference_pattern().captures_iter(text) {
+        let Some(reference) = captures.iter().skip(1).flatten().next() else { continue };
+        if let Some(new_reference) = rewrite(reference.as_str(), old_dir, new_dir, renamed) {
+            verbose!("{} -> {}", reference.as_str(), new_reference);
+            fixed.push_str(&text[last..reference.start()]);
+            fixed.push_str(&new_reference);
+            last = reference.end();
+            count += 1;
+        }
+    }
+    fixed.push_str(&text[last..]);
+    (fixed, count)
+}
+
+/// The reference pointed at the new path of what it refers to, if that was renamed
+fn rewrite(reference: &str, old_dir: &Path, new_dir: &Path, renamed: &HashMap<PathBuf, PathBuf>) -> Option<String> {
+    // Anchors and queries of links are kept as they are
+    let (path, suffix) = reference.split_at(reference.find(['#', '?']).unwrap_or(reference.len()));
+    let trailing_slash = path.ends_with('/');
+    let path = path.trim_end_matches('/');
+    if path.is_empty() || path.starts_with('/') || path.contains("://") {
+        return None;
+    }
+
+    let target = normalize(&old_dir.join(path));
+    // `./widget` importing `widget.ts` is found before what its directory was renamed to
+    let extensionless = || renamed.iter()
+        .find(|(old, _)| target.extension().is_none() && old.extension().is_some() && old.with_extension("") == target)
+        .map(|(_, new)| new.with_extension(""));
+    let new_target = renamed.get(&target).cloned()
+        .or_else(extensionless)
+        .or_else(|| new_location(&target, renamed))?;
+
+    let mut new_path = relative(new_dir, &new_target).to_string_lossy().to_string();
+    if path.starts_with("./") && !new_path.starts_with("..") {
+        new_path.insert_str(0, "./");
+    }
+    if new_path == path {
+        return None;
+    }
+    if trailing_slash {
+        new_path.push('/');
+    }
+    Some(new_path + suffix)
+}
+
+/// Where a path ends up, if it or one of its parent directories was renamed
+fn new_location(target: &Path, renamed: &HashMap<PathBuf, PathBuf>) -> Option<PathBuf> {
+    target.ancestors()
+        .find_map(|ancestor| renamed.get(ancestor).map(|new| (ancestor, new)))
+        .map(|(ancestor, new)| match target.strip_prefix(ancestor) {
+            Ok(rest) if !rest.as_os_str().is_empty() => new.join(rest),
+            _ => new.clone(),
+        })
+}
+
+/// A path with `.` dropped and `..` resolved, without touching the filesystem
+fn normalize(path: &Path) -> PathBuf {
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir if matches!(normal.components().next_back(), Some(Component::Normal(_))) => {
+                normal.pop();
+            },
+            component => normal.push(component),
+        }
+    }
+    normal
+}
+
+/// The path of `target` relative to the directory `from`, both normalized
+fn relative(from: &Path, target: &Path) -> PathBuf {
+    let from: Vec<Component> = from.components().collect();
+    let target: Vec<Component> = target.components().collect();
+    let common = from.iter().zip(&target).take_while(|(a, b)| a == b).count();
+    let mut path: PathBuf = std::iter::repeat_n(Component::ParentDir, from.len() - common).collect();
+    path.extend(&target[common..]);
+    if path.as_os_str().is_empty() {
+        path.push(".");
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::TestDir;
+
+    #[test]
+    fn test_fix_text() {
+        let renamed: HashMap<PathBuf, PathBuf> = [
+            ("src/foo", "src/bar"),
+            ("src/foo/widget.ts", "src/bar/gadget.ts"),
+            ("docs/setup.md", "docs/install.md"),
+        ].into_iter().map(|(old, new)| (PathBuf::from(old), PathBuf::from(new))).collect();
+
+        let text = "import { w } from './foo/widget';\n#include \"foo/util.h\"\n";
+        let (fixed, count) = fix_text(text, Path::new("src"), Path::new("src"), &renamed);
+        assert_eq!(fixed, "import { w } from './bar/gadget';\n#include \"bar/util.h\"\n");
+        assert_eq!(count, 2);
+
+        let text = "See [setup](../../docs/setup.md#linux) and [home](https://example.com/docs/setup.md).\n";
+        let (fixed, count) = fix_text(text, Path::new("src/foo"), Path::new("src/bar"), &renamed);
+        assert_eq!(fixed, "See [setup](../../docs/install.md#linux) and [home](https://example.com/docs/setup.md).\n");
+        assert_eq!(count, 1);
+
+        let text = "[setup]: setup.md\nimport a from \"./unrelated\";\n";
+        let (fixed, _) = fix_text(text, Path::new("docs"), Path::new("docs"), &renamed);
+        assert_eq!(fixed, "[setup]: install.md\nimport a from \"./unrelated\";\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_fix_symlinks() {
+        let dir = TestDir::new("symlinks");
+        std::fs::create_dir_all(dir.join("foo")).unwrap();
+        std::os::unix::fs::symlink("foo/a.txt", dir.join("link")).unwrap();
+        std::os::unix::fs::symlink("elsewhere", dir.join("other")).unwrap();
+
+        let mut plan = Plan::default();
+        let mapping = [(dir.join("foo"), dir.join("bar"))];
+        fix_symlinks(&mut plan, &mapping, &[dir.join("link"), dir.join("other")]).unwrap();
+        assert_eq!(plan.links.len(), 1);
+        assert_eq!(plan.links[0].target, PathBuf::from("bar/a.txt"));
+    }
+
+    #[test]
+    fn test_relative() {
+        assert_eq!(relative(Path::new("src/bar"), Path::new("docs/install.md")), PathBuf::from("../../docs/install.md"));
+        assert_eq!(relative(Path::new("src"), Path::new("src/bar/gadget.ts")), PathBuf::from("bar/gadget.ts"));
+        assert_eq!(relative(Path::new("src"), Path::new("src")), PathBuf::from("."));
+        assert_eq!(normalize(Path::new("./src/../docs/./a.md")), PathBuf::from("docs/a.md"));
+    }
+}
@@ -0,0 +1,139 @@
+use anyhow::{Result, Context, anyhow};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// A copy target on another machine, written as `[user@]host:path`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Remote {
+    /// Host to connect to, with the user if given
+    pub host: String,
+
+    /// Directory on the host, relative to the login directory unless absolute
+    pub path: String,
+}
+
+impl Remote {
+    /// Parse a copy target as a remote one, the way scp tells them apart
+    ///
+    /// A target is remote if it has a colon before any slash, isn't a
+    /// single-letter drive like `C:`, and doesn't exist as a local path.
+    ///
+    /// # Arguments
+    /// * `target` - Copy target as given on the command line
+    ///
+    /// # Returns
+    /// * `Option<Remote>` - The host and path, or None for a local target
+    pub fn parse(target: &str) -> Option<Remote> {
+        let (host, path) = target.split_once(':')?;
+        let name = host.rsplit('@').next().unwrap_or(host);
+        if name.len() < 2 || host.contains('/') || Path::new(target).exists() {
+            return None;
+        }
+        let path = if path.is_empty() { "." } else { path };
+        Some(Remote { host: host.to_string(), path: path.to_string() })
+    }
+
+    /// Target to stage the copy under, so it lands on the host as it would locally
+    ///
+    /// A path naming a file or directory is created like a local target that
+    /// doesn't exist yet; a path ending in `/` (or `.`) is a directory the
+    /// sources are copied into.
+    ///
+    /// # Returns
+    /// * `PathBuf` - Target relative to the staging directory
+    pub fn staged_target(&self) -> PathBuf {
+        match self.name() {
+            Some(name) => PathBuf::from(name),
+            None => PathBuf::from("."),
+        }
+    }
+
+    /// The last component of the path, unless the path is a directory to copy into
+    fn name(&self) -> Option<&str> {
+        let name = self.path.rsplit('/').next()?;
+        (!self.path.ends_with('/') && !matches!(name, "" | "." | ".." | "~")).then_some(name)
+    }
+
+    /// Directory on the host the staged tree is unpacked in
+    fn unpack_dir(&self) -> &str {
+        match self.name() {
+            Some(_) => match self.path.rfind('/') {
+                Some(0) => "/",
+                Some(slash) => &self.path[..slash],
+                None => ".",
+            },
+            None => &self.path,
+        }
+    }
+
+    /// Unpack a tar archive into the remote directory over SSH, creating it if needed
+    ///
+    /// The archive is piped to `tar` run on the host by `ssh`, so the host only needs
+    /// an SSH server and tar. Authentication is left to ssh and its config.
+    ///
+    /// # Arguments
+    /// * `archive` - Tar archive of the copied tree
+    ///
+    /// # Returns
+    /// * `Result<()>` - Error if ssh or the remote tar failed
+    pub fn upload(&self, archive: &[u8]) -> Result<()> {
+        let path = shell_quote(self.unpack_dir());
+        let command = format!("mkdir -p -- {} && tar -xf - -C {}", path, path);
+        let mut child = Command::new("ssh")
+            .args(["--", &self.host, &command])
+            .stdin(Stdio::piped())
+            .spawn()
+            .context("Failed to run ssh")?;
+
+        // Write from this thread while ssh runs; a failed ssh closes the pipe early
+        let written = child.stdin.take().unwrap().write_all(archive);
+        let status = child.wait().context("Failed to wait for ssh")?;
+        if !status.success() {
+            return Err(anyhow!("Copying to {} failed: ssh exited with {}", self, status));
+        }
+        written.context(format!("Failed to send the copied tree to {}", self))?;
+        info!("Copied to {}", self);
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for Remote {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.host, self.path)
+    }
+}
+
+/// Quote a word for a POSIX shell
+fn shell_quote(word: &str) -> String {
+    format!("'{}'", word.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let remote = Remote::parse("deploy@web1:/srv/app").unwrap();
+        assert_eq!(remote, Remote { host: "deploy@web1".to_string(), path: "/srv/app".to_string() });
+        assert_eq!(Remote::parse("web1:").unwrap().path, ".");
+        assert_eq!(remote.to_string(), "deploy@web1:/srv/app");
+
+        assert_eq!((remote.staged_target(), remote.unpack_dir()), (PathBuf::from("app"), "/srv"));
+        let remote = Remote::parse("web1:releases/").unwrap();
+        assert_eq!((remote.staged_target(), remote.unpack_dir()), (PathBuf::from("."), "releases/"));
+        let remote = Remote::parse("web1:app").unwrap();
+        assert_eq!((remote.staged_target(), remote.unpack_dir()), (PathBuf::from("app"), "."));
+
+        assert_eq!(Remote::parse("build/out"), None);
+        assert_eq!(Remote::parse("./a:b"), None);
+        assert_eq!(Remote::parse("C:/project"), None);
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("/srv/my app"), "'/srv/my app'");
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+    }
+}
@@ -0,0 +1,129 @@
+use crate::case;
+use clap::ValueEnum;
+use std::ops::Range;
+
+/// How much of a file or directory name a rule has to match to rename it
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Default)]
+pub enum RenameMatch {
+    /// The whole name, or the whole name before its extensions
+    Whole,
+
+    /// Whole words of the name, split at separators and camelCase humps
+    #[default]
+    Word,
+
+    /// Anywhere in the name, even inside a word
+    Substring,
+}
+
+/// Apply the rules to a name, only where they match as much of it as the mode requires
+///
+/// The parts of the name that match are replaced one at a time with `replace`,
+/// so they are rendered exactly as a substring replacement would render them.
+///
+/// # Arguments
+/// * `name` - File or directory name
+/// * `pairs` - FROM strings and their TO strings, in rule order
+/// * `mode` - How much of the name a FROM has to match
+/// * `case_enabled` - Whether the case variants of each FROM match too
+/// * `replace` - Replacement of a matching part of the name
+///
+/// # Returns
+/// * `String` - The renamed name
+pub fn replace(
+    name: &str,
+    pairs: &[(&str, String)],
+    mode: RenameMatch,
+    case_enabled: bool,
+    replace: impl Fn(&str) -> String,
+) -> String {
+    if mode == RenameMatch::Substring {
+        return replace(name);
+    }
+
+    let froms: Vec<String> = pairs.iter()
+        .flat_map(|(from, to)| if case_enabled {
+            case::variant_pairs(from, to).into_iter().map(|(from, _)| from).collect()
+        } else {
+            vec![from.to_string()]
+        })
+        .filter(|from| !from.is_empty())
+        .collect();
+
+    let mut result = String::with_capacity(name.len());
+    let mut last = 0;
+    for span in matches(name, &froms, mode) {
+        result.push_str(&name[last..span.start]);
+        result.push_str(&replace(&name[span.clone()]));
+        last = span.end;
+    }
+    result.push_str(&name[last..]);
+    result
+}
+
+/// Parts of a name that one of the FROM strings matches as a whole word or name
+///
+/// At each position the longest FROM wins, like in a substring replacement.
+fn matches(name: &str, froms: &[String], mode: RenameMatch) -> Vec<Range<usize>> {
+    // A leading dot marks a hidden file, it isn't part of the name
+    let start = if name.starts_with('.') { 1 } else { 0 };
+    let longest_at = |pos: usize, ends: &dyn Fn(usize) -> bool| {
+        froms.iter()
+            .filter(|from| name[pos..].starts_with(from.as_str()) && ends(pos + from.len()))
+            .map(|from| pos..pos + from.len())
+            .max_by_key(|span| span.len())
+    };
+
+    if mode == RenameMatch::Whole {
+        let ends = |end: usize| end == name.len() || name[end..].starts_with('.');
+        return longest_at(start, &ends).into_iter().collect();
+    }
+
+    let ends = |end: usize| is_word_boundary(name, end);
+    let mut spans = Vec::new();
+    let mut pos = start;
+    while pos < name.len() {
+        if let Some(span) = is_word_boundary(name, pos).then(|| longest_at(pos, &ends)).flatten() {
+            pos = span.end;
+            spans.push(span);
+        } else {
+            pos += name[pos..].chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    spans
+}
+
+/// Check whether a word of a name can start or end at a byte position: at either
+/// end, next to a separator, or between a lowercase letter or digit and a capital
+fn is_word_boundary(name: &str, pos: usize) -> bool {
+    let (Some(before), Some(after)) = (name[..pos].chars().next_back(), name[pos..].chars().next()) else {
+        return true;
+    };
+    !before.is_alphanumeric()
+        || !after.is_alphanumeric()
+        || (before.is_lowercase() || before.is_ascii_digit()) && after.is_uppercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rename(name: &str, mode: RenameMatch) -> String {
+        let pairs = [("net", "new".to_string())];
+        replace(name, &pairs, mode, true, |part| part.replace("net", "new").replace("Net", "New").replace("NET", "NEW"))
+    }
+
+    #[test]
+    fn test_rename_modes() {
+        assert_eq!(rename("internet.rs", RenameMatch::Substring), "internew.rs");
+        assert_eq!(rename("internet.rs", RenameMatch::Word), "internet.rs");
+        assert_eq!(rename("net_client.rs", RenameMatch::Word), "new_client.rs");
+        assert_eq!(rename("NetClient.rs", RenameMatch::Word), "NewClient.rs");
+        assert_eq!(rename("dot.net", RenameMatch::Word), "dot.new");
+        assert_eq!(rename("NETWORK", RenameMatch::Word), "NETWORK");
+
+        assert_eq!(rename("net.test.ts", RenameMatch::Whole), "new.test.ts");
+        assert_eq!(rename(".net", RenameMatch::Whole), ".new");
+        assert_eq!(rename("net_client.rs", RenameMatch::Whole), "net_client.rs");
+    }
+}
@@ -1,8 +1,92 @@
-use crate::args::Args;
+use crate::args::{Args, ReplacementRule};
+use crate::binary;
 use crate::case;
+use crate::csv;
+use crate::engine;
+use crate::gzip;
+use crate::logger::{self, Level};
+use crate::markdown;
+use crate::mmap;
+use crate::patch;
+use crate::placeholder;
+use crate::plan::{self, ContentEdit, Plan};
+use crate::preview::{self, Location, Origin, ReportFormat};
+use crate::rename_match;
+use crate::ripgrep;
+use crate::scanner;
+use crate::script;
+use crate::structured::{self, Quote};
+use crate::summary::{self, Event};
+use crate::syntax;
+use crate::template;
+use atty::Stream;
 use anyhow::{Result, Context, anyhow};
 use std::fs;
-use std::io::{self, Read, Write};
+use std::io::{self, BufRead, Read, Write};
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Total number of replacements made during this run
+pub static GLOBAL_REPLACEMENT_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// Replacements of the `count_replacements` call this thread works for, if any
+    static SCOPE_COUNT: RefCell<Option<Arc<AtomicUsize>>> = const { RefCell::new(None) };
+}
+
+/// Run a function and count the replacements it makes
+///
+/// Unlike the difference of `GLOBAL_REPLACEMENT_COUNT` before and after, the
+/// count leaves out what other threads replace meanwhile (`mane serve`
+/// answers requests concurrently). The pool's threads count for the call
+/// that started them.
+///
+/// # Arguments
+/// * `f` - Function to run
+///
+/// # Returns
+/// * `(R, usize)` - Its result and the number of replacements it made
+pub fn count_replacements<R>(f: impl FnOnce() -> R) -> (R, usize) {
+    let count = Arc::new(AtomicUsize::new(0));
+    let result = in_count_scope(Some(count.clone()), f);
+    (result, count.load(Ordering::Relaxed))
+}
+
+/// The count of the `count_replacements` call the current thread works for
+pub fn count_scope() -> Option<Arc<AtomicUsize>> {
+    SCOPE_COUNT.with(|scope| scope.borrow().clone())
+}
+
+/// Run a function counting for a `count_replacements` call (from `count_scope`)
+pub fn in_count_scope<R>(scope: Option<Arc<AtomicUsize>>, f: impl FnOnce() -> R) -> R {
+    let outer = SCOPE_COUNT.with(|current| current.replace(scope));
+    let result = f();
+    SCOPE_COUNT.with(|current| *current.borrow_mut() = outer);
+    result
+}
+
+/// The file (or directory name) a replacement is made for
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Target<'a> {
+    /// Path of the file, for path-scoped rules (None for stdin)
+    pub path: Option<&'a Path>,
+
+    /// `{{n}}` value of the file, or None to use the current counter value
+    pub counter: Option<usize>,
+
+    /// Whether this is the file's name rather than its content (renames);
+    /// `{{mtime}}`, `{{size}}` and `{{sha256}}` are only expanded in names
+    pub name: bool,
+}
+
+impl<'a> Target<'a> {
+    /// Target for a file path
+    pub fn path(path: &'a Path) -> Self {
+        Target { path: Some(path), ..Target::default() }
+    }
+}
 
 /// Replace content from stdin and write to stdout
 /// 
@@ -12,6 +96,16 @@ use std::io::{self, Read, Write};
 /// # Returns
 /// * `Result<()>` - Result of the operation
 pub fn replace_stdin_stdout(args: &Args) -> Result<()> {
+    if args.names {
+        return rename_stdin_lines(args);
+    }
+    if args.line_buffered && !can_stream(args) {
+        return Err(anyhow!("--line-buffered can't be used with options that need the whole input or with a FROM containing a newline"));
+    }
+    if args.line_buffered || stdin_is_pipe() && can_stream(args) && args.output.is_none() {
+        return stream_lines(io::stdin().lock(), io::stdout().lock(), args);
+    }
+    
     let mut input = String::new();
     io::stdin().read_to_string(&mut input)?;
     
@@ -21,16 +115,234 @@ pub fn replace_stdin_stdout(args: &Args) -> Result<()> {
     }
     
     let replaced = replace_content(&input, args)?;
-    io::stdout().write_all(replaced.as_bytes())?;
+    check_idempotent(&replaced, args, None, "the output")?;
+    // Nothing is written when --expect fails, --output least of all
+    check_expected_count(args)?;
+    match &args.output {
+        Some(path) => {
+            write_atomically(path, replaced.as_bytes())?;
+            verbose!("Wrote: {:?}", path);
+        },
+        None => io::stdout().write_all(replaced.as_bytes())?,
+    }
     
     // Check if any replacements were made
     if replaced == input && !args.rules.is_empty() {
-        eprintln!("Warning: No replacements were made. Check if the pattern exists in the input.");
+        warn!("No replacements were made. Check if the pattern exists in the input.");
     }
     
     Ok(())
 }
 
+/// Write a file by renaming a complete temporary file over it (--output)
+///
+/// The input has been read in full by then, so `mane ... --output f < f` is
+/// safe, and a failed run leaves the file as it was. An existing file keeps
+/// its permissions.
+///
+/// # Arguments
+/// * `path` - File to write
+/// * `data` - Its new content
+///
+/// # Returns
+/// * `Result<()>` - Result of the operation
+fn write_atomically(path: &Path, data: &[u8]) -> Result<()> {
+    let name = path.file_name().ok_or_else(|| anyhow!("Not a file name: {:?}", path))?;
+    let temp = path.with_file_name(format!(".{}.mane-{}", name.to_string_lossy(), std::process::id()));
+    let written = fs::write(&temp, data)
+        .and_then(|_| match fs::metadata(path) {
+            Ok(metadata) => fs::set_permissions(&temp, metadata.permissions()),
+            Err(_) => Ok(()),
+        })
+        .and_then(|_| fs::rename(&temp, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    written.with_context(|| format!("Failed to write file: {:?}", path))
+}
+
+/// Render stdin as a template to stdout (--render)
+///
+/// `{{var}}` placeholders and `{{#if var}}` sections are filled in from
+/// `--var` and the config file's `vars`, and the built-in placeholders
+/// (`{{env.NAME}}`, `{{date}}`, `{{uuid}}`) are expanded. Placeholders left
+/// without a value are kept as they are, with a warning. Replacement rules,
+/// if any, apply to the rendered text.
+///
+/// # Arguments
+/// * `args` - Command line arguments
+///
+/// # Returns
+/// * `Result<()>` - Ok if successful, Error otherwise
+pub fn render_stdin_stdout(args: &Args) -> Result<()> {
+    let mut input = String::new();
+    io::stdin().read_to_string(&mut input).context("Failed to read the template from stdin")?;
+
+    let mut rendered = template::render(&placeholder::expand(&input)?, &args.vars);
+    for name in template::placeholder_names(&rendered) {
+        warn!("No value for template variable '{}'. Use --var {}=VALUE", name, name);
+    }
+    if !args.rules.is_empty() {
+        rendered = replace_content(&rendered, args)?;
+    }
+    io::stdout().write_all(rendered.as_bytes())?;
+    Ok(())
+}
+
+/// Number of files whose content has been written to stdout
+static FILES_OUTPUT: AtomicUsize = AtomicUsize::new(0);
+
+/// Write the replaced content of a file to stdout, with the --separator and
+/// --with-filename header that go before it
+/// 
+/// # Arguments
+/// * `path` - Path of the file
+/// * `content` - Replaced content
+/// * `args` - Command line arguments
+/// 
+/// # Returns
+/// * `Result<()>` - Result of the operation
+pub fn write_file_output(path: &Path, content: &str, args: &Args) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+    let first = FILES_OUTPUT.fetch_add(1, Ordering::Relaxed) == 0;
+    
+    if let Some(separator) = args.separator.as_ref().filter(|_| !first) {
+        stdout.write_all(separator.as_bytes())?;
+    }
+    if args.with_filename {
+        // Like head/tail, a blank line separates a header from the previous file
+        let blank = if first || args.separator.is_some() { "" } else { "\n" };
+        writeln!(stdout, "{}==> {} <==", blank, path.display())?;
+    }
+    stdout.write_all(content.as_bytes())?;
+    Ok(())
+}
+
+/// Print a path listed by --files-with-matches, ended by a newline or with --print0 by NUL
+/// 
+/// # Arguments
+/// * `path` - Path to print
+/// * `args` - Command line arguments
+/// 
+/// # Returns
+/// * `Result<()>` - Result of the operation
+pub fn print_path(path: &Path, args: &Args) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+    #[cfg(unix)]
+    stdout.write_all(std::os::unix::ffi::OsStrExt::as_bytes(path.as_os_str()))?;
+    #[cfg(not(unix))]
+    stdout.write_all(path.to_string_lossy().as_bytes())?;
+    stdout.write_all(if args.print0 { b"\0" } else { b"\n" })?;
+    Ok(())
+}
+
+/// Check whether the rules can be applied to stdin one line at a time
+/// 
+/// Structured, Markdown, CSV and syntax-aware replacement need the whole
+/// document, and a FROM containing a newline can match across lines.
+fn can_stream(args: &Args) -> bool {
+    !args.is_structured()
+        && args.markdown_scope.is_none()
+        && args.csv_columns.is_empty()
+        && !(args.syntax_aware || args.skip_strings || args.skip_comments)
+        && !args.rules.iter().any(|rule| rule.from.contains('\n'))
+}
+
+/// Check whether stdin is a pipe (rather than a file or a terminal)
+#[cfg(unix)]
+fn stdin_is_pipe() -> bool {
+    // SAFETY: fstat only writes into the provided struct
+    unsafe {
+        let mut stat: libc::stat = std::mem::zeroed();
+        libc::fstat(libc::STDIN_FILENO, &mut stat) == 0 && stat.st_mode & libc::S_IFMT == libc::S_IFIFO
+    }
+}
+
+#[cfg(not(unix))]
+fn stdin_is_pipe() -> bool {
+    false
+}
+
+/// Replace an input line by line, writing each line as soon as it is read
+/// 
+/// # Arguments
+/// * `input` - Input to read, stdin when run
+/// * `output` - Output to write, stdout when run
+/// * `args` - Command line arguments
+/// 
+/// # Returns
+/// * `Result<()>` - Result of the operation
+fn stream_lines(mut input: impl BufRead, mut output: impl Write, args: &Args) -> Result<()> {
+    let mut line = String::new();
+    let mut any_input = false;
+    let mut any_replacement = false;
+    
+    while input.read_line(&mut line)? > 0 {
+        any_input = true;
+        let replaced = replace_content(&line, args)?;
+        check_idempotent(&replaced, args, None, "the output")?;
+        any_replacement |= replaced != line;
+        
+        output.write_all(replaced.as_bytes())?;
+        output.flush()?;
+        line.clear();
+    }
+    
+    if !any_input {
+        return Err(anyhow!("No input provided for replacement"));
+    }
+    if !any_replacement && !args.rules.is_empty() {
+        warn!("No replacements were made. Check if the pattern exists in the input.");
+    }
+    
+    Ok(())
+}
+
+/// Rename the path or name on each line of stdin (--names)
+/// 
+/// Only the last component of each path is renamed, as `-i` renames each file
+/// in its own directory, and each changed line is printed followed by the new
+/// path, so `| xargs -n2 mv` performs the renames. Feed children before their
+/// parents (`find -depth`) when directories are renamed too.
+/// 
+/// # Arguments
+/// * `args` - Command line arguments
+/// 
+/// # Returns
+/// * `Result<()>` - Result of the operation
+fn rename_stdin_lines(args: &Args) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+    let mut any_rename = false;
+    
+    for line in io::stdin().lock().lines() {
+        let line = line.context("Failed to read names from stdin")?;
+        let path = line.strip_suffix('\r').unwrap_or(&line);
+        let trimmed = path.trim_end_matches('/');
+        let (parent, name) = match trimmed.rsplit_once('/') {
+            Some((parent, name)) => (Some(parent), name),
+            None => (None, trimmed),
+        };
+        if name.is_empty() || args.keeps_name(Path::new(trimmed)) {
+            continue;
+        }
+        
+        let renamed = replace_content_for(name, args, Target { path: Some(Path::new(path)), counter: None, name: true })?;
+        if renamed == name {
+            continue;
+        }
+        any_rename = true;
+        match parent {
+            Some(parent) => writeln!(stdout, "{}\n{}/{}", path, parent, renamed)?,
+            None => writeln!(stdout, "{}\n{}", path, renamed)?,
+        }
+    }
+    
+    if !any_rename && !args.rules.is_empty() {
+        warn!("No names were changed. Check if the pattern exists in the input.");
+    }
+    Ok(())
+}
+
 /// Replace content in specified files
 /// 
 /// # Arguments
@@ -45,51 +357,124 @@ pub fn replace_files(args: &Args) -> Result<()> {
     }
     
     let mut any_replacements_made = false;
+    let mut plan = Plan::default();
+    
+    let files = collect_files(args)?;
+    
+    // Whole files of a tree would scroll by on a terminal, so only the changed lines are shown
+    let prints_files = !args.in_place && !args.writes_plan() && args.output_dir.is_none() && args.format.is_none()
+        && !args.print_changes && !args.files_with_matches;
+    let print_changes = args.print_changes
+        || (prints_files && files.len() > 1 && !args.stdout && atty::is(Stream::Stdout));
+    if print_changes && !args.print_changes {
+        info!("Showing the changed lines of {} files; use --stdout to print them whole", files.len());
+    }
     
-    for file_path in &args.files {
+    for file_path in &files {
         if !file_path.exists() {
-            eprintln!("Warning: File not found: {:?}", file_path);
+            warn!("File not found: {:?}", file_path);
             continue;
         }
         
-        if file_path.is_dir() {
-            eprintln!("Warning: Skipping directory: {:?}", file_path);
-            continue;
-        }
-        
-        let content = fs::read_to_string(file_path)
-            .with_context(|| format!("Failed to read file: {:?}", file_path))?;
+        let (content, compressed) = match gzip::read_text(file_path, args.decompress, args.detection()) {
+            Err(e) if binary::is_binary_error(&e) => {
+                binary::record_skipped(file_path, &e.to_string());
+                continue;
+            },
+            result => result.with_context(|| format!("Failed to read file: {:?}", file_path))?,
+        };
         
-        let replaced = replace_content(&content, args)?;
+        // Files containing a match are numbered in the order given
+        let counter = (uses_counter(args) && matches(&content, args, Some(file_path))).then(placeholder::next_counter);
+        let replaced = replace_content_for(&content, args, Target { counter, ..Target::path(file_path) })?;
         
         // Track if any replacements were made
         if content != replaced {
             any_replacements_made = true;
+            check_idempotent(&replaced, args, Some(file_path), &format!("{:?}", file_path))?;
+            
+            if args.files_with_matches {
+                print_path(file_path, args)?;
+            }
             
-            if args.in_place {
+            if args.writes_plan() {
+                // Collect the change for the patch instead of writing it
+                plan.edits.push(ContentEdit {
+                    path: file_path.clone(),
+                    original: content,
+                    replaced,
+                    compressed,
+                });
+            } else if let Some(dir) = &args.output_dir {
+                // Write the replaced file under the output directory instead
+                let out = plan::mirrored(dir, file_path)?;
+                if let Some(parent) = out.parent() {
+                    fs::create_dir_all(parent).with_context(|| format!("Failed to create directory: {:?}", parent))?;
+                }
+                fs::write(&out, gzip::encode(&replaced, compressed))
+                    .with_context(|| format!("Failed to write file: {:?}", out))?;
+                info!("Wrote: {:?}", out);
+                summary::record(Event::Modified);
+            } else if args.in_place {
                 // If in-place mode, modify the file
-                fs::write(file_path, &replaced)
+                fs::write(file_path, gzip::encode(&replaced, compressed))
                     .with_context(|| format!("Failed to write file: {:?}", file_path))?;
-                if args.verbose {
-                    println!("Modified: {:?}", file_path);
-                }
-            } else {
+                verbose!("Modified: {:?}", file_path);
+                summary::record(Event::Modified);
+                log_origins(file_path, &content, &replaced, args);
+            } else if args.format.is_some() {
+                // Only report where the replacements are
+                print_locations(file_path, &content, &replaced, args)?;
+            } else if print_changes {
+                // Only show the lines that changed
+                preview::print_changes(file_path, &content, &replaced)?;
+            } else if !args.files_with_matches {
                 // If not in-place mode, output to stdout
-                io::stdout().write_all(replaced.as_bytes())?;
+                write_file_output(file_path, &replaced, args)?;
             }
-        } else if args.verbose {
-            eprintln!("No replacements made in file: {:?}", file_path);
+        } else {
+            debug!("No replacements made in file: {:?}", file_path);
         }
     }
     
     // If no replacements were made across all files, show a warning
     if !any_replacements_made && !args.rules.is_empty() {
-        eprintln!("Warning: No replacements were made in any files. Check if the pattern exists in the files.");
+        warn!("No replacements were made in any files. Check if the pattern exists in the files.");
+    }
+    
+    if let Some(patch_path) = &args.emit_patch {
+        patch::write_patch(patch_path, &plan, &[])?;
+    } else if let Some(plan_path) = &args.plan {
+        plan::write_plan(plan_path, &plan)?;
+    } else if let Some(format) = args.emit_script {
+        script::write_script(format, &plan)?;
     }
     
     Ok(())
 }
 
+/// The files to replace in: the file arguments, and the files under directory arguments
+/// 
+/// Directories are walked as `scanner` walks them for -i, so the same ignore
+/// files apply.
+/// 
+/// # Arguments
+/// * `args` - Command line arguments containing files to process
+/// 
+/// # Returns
+/// * `Result<Vec<PathBuf>>` - Paths in the order given, each directory's files in walk order
+fn collect_files(args: &Args) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for path in &args.files {
+        if path.is_dir() {
+            files.extend(scanner::collect_paths(path, args)?.into_iter().filter(|path| path.is_file()));
+        } else {
+            files.push(path.clone());
+        }
+    }
+    Ok(files)
+}
+
 /// Replace content according to the specified arguments
 /// 
 /// # Arguments
@@ -99,41 +484,486 @@ pub fn replace_files(args: &Args) -> Result<()> {
 /// # Returns
 /// * `Result<String>` - The replaced content
 pub fn replace_content(content: &str, args: &Args) -> Result<String> {
+    replace_content_for(content, args, Target::default())
+}
+
+/// Replace content of one file, applying only the rules scoped to it and
+/// expanding per-file placeholders in TO strings
+/// 
+/// # Arguments
+/// * `content` - The content to replace in
+/// * `args` - Command line arguments containing replacement options
+/// * `target` - The file the content belongs to
+/// 
+/// # Returns
+/// * `Result<String>` - The replaced content
+pub fn replace_content_for(content: &str, args: &Args, target: Target) -> Result<String> {
+    // Text without any FROM string is left alone without building case variants
+    if !may_match(content.as_bytes(), args, target.path) {
+        return Ok(content.to_string());
+    }
+    
+    // Names are never structured, Markdown or source code
+    if target.name {
+        return apply_rules(content, args, target, str::to_string);
+    }
+    
+    let replace = |text: &str| replace_selected(text, target.path, args, |piece, quote| {
+        apply_rules(piece, args, target, |to| quote.escape(to))
+    });
+    
+    // --scope-from-rg only replaces on the lines ripgrep reported
+    if let Some(lines) = args.rg_scope.as_ref().zip(target.path).and_then(|(scope, path)| scope.lines(path)) {
+        return ripgrep::replace_lines(content, lines, replace);
+    }
+    replace(content)
+}
+
+/// Apply replacements only to the parts of a file selected by --structured,
+/// --json-path/--yaml-path, --markdown-scope, --csv-columns, --syntax-aware or
+/// --skip-strings/--skip-comments
+/// 
+/// # Arguments
+/// * `content` - The content to replace in
+/// * `path` - Path of the file (its extension selects the format), or None for stdin
+/// * `args` - Command line arguments containing replacement options
+/// * `apply` - Replacement for one selected piece, given the quoting TO strings need
+/// 
+/// # Returns
+/// * `Result<String>` - The replaced content
+pub fn replace_selected(
+    content: &str,
+    path: Option<&Path>,
+    args: &Args,
+    mut apply: impl FnMut(&str, Quote) -> Result<String>,
+) -> Result<String> {
+    // --structured/--json-path/--yaml-path only touch selected scalars of JSON/YAML/TOML files
+    if let (true, Some(path)) = (args.is_structured(), path) {
+        let Some((format, selection)) = args.structured_selection(path) else {
+            debug!("Skipping {:?}: no structured selection applies to it", path);
+            return Ok(content.to_string());
+        };
+        return structured::replace(content, format, selection, apply)
+            .with_context(|| format!("Failed to parse {:?} as {}", path, format.name()));
+    }
+    
+    // --markdown-scope limits Markdown files (and stdin) to code, prose or link URLs
+    if let Some(scope) = args.markdown_scope {
+        if path.is_none_or(markdown::is_markdown) {
+            return markdown::replace(content, scope, |piece| apply(piece, Quote::None));
+        }
+    }
+    
+    // --csv-columns limits CSV/TSV files (and stdin, as CSV) to some columns
+    if !args.csv_columns.is_empty() {
+        if let Some(delimiter) = path.map_or(Some(b','), csv::delimiter) {
+            return csv::replace(content, delimiter, &args.csv_columns, |field| apply(field, Quote::None))
+                .map_err(|e| anyhow!("{:?}: {}", path.unwrap_or(Path::new("stdin")), e));
+        }
+    }
+    
+    // --syntax-aware only touches identifiers, --skip-strings/--skip-comments leave literals alone
+    let skip = syntax::Skip { strings: args.skip_strings, comments: args.skip_comments };
+    if let (true, Some(path)) = (args.syntax_aware || skip.strings || skip.comments, path) {
+        match syntax::Language::detect(path) {
+            Some(lang) if args.syntax_aware => {
+                debug!("Replacing only identifiers in {:?} ({})", path, lang.name);
+                return syntax::replace_identifiers(content, lang, |ident| apply(ident, Quote::None));
+            },
+            Some(lang) => {
+                debug!("Replacing outside skipped literals in {:?} ({})", path, lang.name);
+                return syntax::replace_outside(content, lang, skip, |piece| apply(piece, Quote::None));
+            },
+            None => debug!("No syntax support for {:?}, replacing as plain text", path),
+        }
+    }
+    
+    apply(content, Quote::None)
+}
+
+/// Apply the rules that apply to a target to some text
+/// 
+/// # Arguments
+/// * `content` - The text to replace in
+/// * `args` - Command line arguments containing replacement options
+/// * `target` - The file the text belongs to
+/// * `escape` - Escaping applied to TO strings (for quoted structured scalars)
+/// 
+/// # Returns
+/// * `Result<String>` - The replaced text
+fn apply_rules(content: &str, args: &Args, target: Target, escape: impl Fn(&str) -> String) -> Result<String> {
     let mut result = content.to_string();
+    let n = target.counter.unwrap_or_else(|| placeholder::GLOBAL_COUNTER.load(Ordering::Relaxed));
     
-    // Apply all replacement rules sequentially
-    for rule in &args.rules {
-        // Use apply_replacement which handles all the case conversion
-        result = apply_replacement(&result, &rule.from, &rule.to, args.case_enabled);
+    // Fill in the TO strings of all rules that apply
+    let mut pairs = Vec::new();
+    for rule in args.rules.iter().filter(|rule| rule.applies_to(target.path)) {
+        let mut to = escape(&placeholder::expand_counter(&rule.to, n));
+        let metadata_file = target.path.filter(|_| target.name && placeholder::has_file_metadata(&to));
+        if let Some(file) = metadata_file {
+            // Only read metadata for paths the rule actually matches
+            if replace_counted(&result, &rule.from, &to, args.case_enabled).1 == 0 {
+                continue;
+            }
+            to = placeholder::expand_file_metadata(&to, file)?;
+        }
+        pairs.push((rule.from.as_str(), to));
     }
     
+    result = if target.name {
+        apply_name_replacements(&result, &pairs, args)
+    } else {
+        apply_replacements(&result, &pairs, args.case_enabled)
+    };
     Ok(result)
 }
 
+/// Verify with --idempotent that applying the rules again leaves a result unchanged
+/// 
+/// # Arguments
+/// * `replaced` - Content (or name) after replacement
+/// * `args` - Command line arguments containing replacement options
+/// * `path` - Path the content belongs to, for path-scoped rules
+/// * `what` - Description of the content for the error message
+/// 
+/// # Returns
+/// * `Result<()>` - Error if a second run would change the result
+pub fn check_idempotent(replaced: &str, args: &Args, path: Option<&Path>, what: &str) -> Result<()> {
+    check_idempotent_rules(replaced, args, args.rules.iter().filter(|rule| rule.applies_to(path)), what)
+}
 
-/// Apply a single replacement with case handling
+/// Verify with --idempotent that applying the given rules again leaves a result unchanged
+/// 
+/// # Arguments
+/// * `replaced` - Content (or name) after replacement
+/// * `args` - Command line arguments containing replacement options
+/// * `rules` - Rules that were applied
+/// * `what` - Description of the content for the error message
+/// 
+/// # Returns
+/// * `Result<()>` - Error if a second run would change the result
+pub fn check_idempotent_rules<'r>(
+    replaced: &str,
+    args: &Args,
+    rules: impl IntoIterator<Item = &'r ReplacementRule>,
+    what: &str,
+) -> Result<()> {
+    if !args.idempotent {
+        return Ok(());
+    }
+    
+    let pairs: Vec<(&str, String)> = rules.into_iter().map(|rule| (rule.from.as_str(), rule.to.clone())).collect();
+    let again = replace_rules_counted(replaced, &pairs, args.case_enabled).0;
+    
+    if again != replaced {
+        return Err(anyhow!(
+            "Not idempotent (--idempotent): applying the rules again would change {}; nothing was written", what));
+    }
+    Ok(())
+}
+
+/// Check whether any TO string uses the `{{n}}` counter
+pub fn uses_counter(args: &Args) -> bool {
+    args.rules.iter().any(|rule| placeholder::has_counter(&rule.to))
+}
+
+/// Check whether any rule matches the content (without counting replacements)
+/// 
+/// # Arguments
+/// * `content` - The content to check
+/// * `args` - Command line arguments containing replacement options
+/// * `path` - Path the content belongs to, for path-scoped rules
+/// 
+/// # Returns
+/// * `bool` - True if at least one rule would replace something
+pub fn matches(content: &str, args: &Args, path: Option<&Path>) -> bool {
+    may_match(content.as_bytes(), args, path) && args.rules.iter()
+        .filter(|rule| rule.applies_to(path))
+        .any(|rule| replace_counted(content, &rule.from, &rule.to, args.case_enabled).1 > 0)
+}
+
+
+/// Check whether any rule could match the raw bytes of a file
+///
+/// Looks for the FROM strings (and their case variants) without decoding the
+/// content, so files without any candidate can be skipped cheaply. A true
+/// result still has to be confirmed by replacing.
+///
+/// # Arguments
+/// * `bytes` - The content to check
+/// * `args` - Command line arguments containing replacement options
+/// * `path` - Path the content belongs to, for path-scoped rules
+///
+/// # Returns
+/// * `bool` - False if no rule can replace anything in the content
+pub fn may_match(bytes: &[u8], args: &Args, path: Option<&Path>) -> bool {
+    args.prefilter().may_match(bytes, &args.rules, path)
+}
+
+/// Check, by memory-mapping it, whether any rule could match a file
+///
+/// # Arguments
+/// * `file_path` - File to check
+/// * `args` - Command line arguments containing replacement options
+///
+/// # Returns
+/// * `Result<bool>` - False if the file certainly needs no replacement
+pub fn file_may_match(file_path: &Path, args: &Args) -> Result<bool> {
+    let bytes = mmap::open(file_path)
+        .with_context(|| format!("Failed to read file: {:?}", file_path))?;
+    // Compressed content can only be checked after decompressing
+    if args.decompress && gzip::is_gzip(&bytes) {
+        return Ok(true);
+    }
+    Ok(may_match(&bytes, args, Some(file_path)))
+}
+
+/// Apply several FROM/TO pairs to some text with case handling
 /// 
 /// # Arguments
 /// * `content` - The content to replace in
-/// * `from` - The string to replace
-/// * `to` - The replacement string
+/// * `pairs` - FROM strings and their TO strings, in rule order
 /// * `case_enabled` - Whether to enable case handling
 /// 
 /// # Returns
 /// * `String` - The replaced content
-pub fn apply_replacement(content: &str, from: &str, to: &str, case_enabled: bool) -> String {
-    use std::sync::atomic::Ordering;
-    use crate::args::GLOBAL_CASE_ENABLED;
-    
-    // Store the case enabled flag in the global atomic
-    GLOBAL_CASE_ENABLED.store(case_enabled, Ordering::Relaxed);
-    
-    // Use the case-aware replacement function
-    match case::replace_with_case_variants(content, from, to) {
-        Ok(result) => result,
-        Err(_) => {
-            // Fallback to simple replacement if case-aware replacement fails
-            content.replace(from, to)
+pub fn apply_replacements(content: &str, pairs: &[(&str, String)], case_enabled: bool) -> String {
+    let (result, count) = replace_rules_counted(content, pairs, case_enabled);
+    
+    GLOBAL_REPLACEMENT_COUNT.fetch_add(count, Ordering::Relaxed);
+    if let Some(scope) = count_scope() {
+        scope.fetch_add(count, Ordering::Relaxed);
+    }
+    
+    result
+}
+
+/// Apply several FROM/TO pairs to a file or directory name, where they match
+/// as much of it as --rename-match requires
+/// 
+/// With --exclude-extension the part from the last dot on is kept as it is.
+/// 
+/// # Arguments
+/// * `name` - The name to rename
+/// * `pairs` - FROM strings and their TO strings, in rule order
+/// * `args` - Command line arguments containing replacement options
+/// 
+/// # Returns
+/// * `String` - The renamed name
+pub fn apply_name_replacements(name: &str, pairs: &[(&str, String)], args: &Args) -> String {
+    // A leading dot starts a hidden name, not an extension
+    let (stem, extension) = match name.rfind('.') {
+        Some(i) if args.exclude_extension && i > 0 => name.split_at(i),
+        _ => (name, ""),
+    };
+    let renamed = rename_match::replace(stem, pairs, args.rename_match, args.case_enabled, |part| {
+        apply_replacements(part, pairs, args.case_enabled)
+    });
+    renamed + extension
+}
+
+/// Apply several FROM/TO pairs with the run's options and return the number of replacements
+fn replace_rules_counted(content: &str, pairs: &[(&str, String)], case_enabled: bool) -> (String, usize) {
+    engine::replace_rules_counted(content, pairs, engine_options(case_enabled))
+}
+
+/// Engine options of this run, from --ordered, --same-shape and --split-digits
+fn engine_options(case_enabled: bool) -> engine::Options {
+    use crate::args::{GLOBAL_ORDERED, GLOBAL_SAME_SHAPE, GLOBAL_SPLIT_DIGITS};
+    
+    engine::Options {
+        case_enabled,
+        ordered: GLOBAL_ORDERED.load(Ordering::Relaxed),
+        same_shape: GLOBAL_SAME_SHAPE.load(Ordering::Relaxed),
+        split_digits: GLOBAL_SPLIT_DIGITS.load(Ordering::Relaxed),
+    }
+}
+
+/// Find the single matches of the rules in a line, for reporting their positions
+/// 
+/// # Arguments
+/// * `line` - The text to search
+/// * `args` - Command line arguments containing replacement options
+/// * `path` - Path the text belongs to, for path-scoped rules
+/// 
+/// # Returns
+/// * `Vec<(usize, String, String, Origin)>` - Byte offset, matched text, replacement and origin of each match
+pub fn find_matches(line: &str, args: &Args, path: Option<&Path>) -> Vec<(usize, String, String, Origin)> {
+    let applicable: Vec<(usize, &ReplacementRule)> = args.rules.iter()
+        .enumerate()
+        .filter(|(_, rule)| rule.applies_to(path))
+        .collect();
+    let pairs: Vec<(&str, String)> = applicable.iter()
+        .map(|(_, rule)| (rule.from.as_str(), rule.to.clone()))
+        .collect();
+    let Some((automaton, tos)) = engine::combined_automaton(&pairs, engine_options(args.case_enabled)) else {
+        return Vec::new();
+    };
+
+    // The automaton's patterns are the same variants in the same order
+    let origins: Vec<Origin> = applicable.iter()
+        .flat_map(|(index, rule)| {
+            let variants = match args.case_enabled {
+                true => case::named_variant_pairs(&rule.from, &rule.to),
+                false => vec![(rule.from.clone(), rule.to.clone(), case::AS_WRITTEN)],
+            };
+            variants.into_iter()
+                .filter(|(from, _, _)| !from.is_empty())
+                .map(move |(_, _, variant)| Origin { rule: index + 1, variant })
+        })
+        .collect();
+    
+    automaton.find_iter(line)
+        .map(|m| {
+            let pattern = m.pattern().as_usize();
+            (m.start(), line[m.range()].to_string(), tos[pattern].clone(), origins[pattern])
+        })
+        .collect()
+}
+
+/// Where the rules replace something in a file, with the rule and case variant of each match
+/// 
+/// # Arguments
+/// * `path` - Path of the file
+/// * `content` - Content before replacement
+/// * `replaced` - Content after replacement
+/// * `args` - Command line arguments containing replacement options
+/// 
+/// # Returns
+/// * `Vec<Location>` - The replacements, in order
+pub fn locations(path: &Path, content: &str, replaced: &str, args: &Args) -> Vec<Location> {
+    preview::locations(content, replaced, |line| find_matches(line, args, Some(path)))
+}
+
+/// Print where the rules replace something in a file (--format)
+/// 
+/// # Arguments
+/// * `path` - Path of the file
+/// * `content` - Content before replacement
+/// * `replaced` - Content after replacement
+/// * `args` - Command line arguments containing replacement options
+/// 
+/// # Returns
+/// * `Result<()>` - Result of the operation
+pub fn print_locations(path: &Path, content: &str, replaced: &str, args: &Args) -> Result<()> {
+    let locations = locations(path, content, replaced, args);
+    match args.format {
+        Some(ReportFormat::Json) => preview::print_json(path, &locations),
+        _ => preview::print_quickfix(path, &locations, logger::enabled(Level::Verbose)),
+    }
+}
+
+/// Log each replacement in a file with the rule and case variant that made it (-v)
+/// 
+/// # Arguments
+/// * `path` - Path of the file
+/// * `content` - Content before replacement
+/// * `replaced` - Content after replacement
+/// * `args` - Command line arguments containing replacement options
+pub fn log_origins(path: &Path, content: &str, replaced: &str, args: &Args) {
+    if !logger::enabled(Level::Verbose) {
+        return;
+    }
+    for location in locations(path, content, replaced, args) {
+        verbose!("  {}:{}:{}: {}", path.display(), location.line, location.column, preview::describe(&location, true));
+    }
+}
+
+/// Apply a single replacement with case handling and return the number of replacements
+fn replace_counted(content: &str, from: &str, to: &str, case_enabled: bool) -> (String, usize) {
+    engine::replace_counted(content, from, to, engine_options(case_enabled))
+}
+
+/// Verify the total number of replacements against --expect/--expect-min/--expect-max
+/// 
+/// # Arguments
+/// * `args` - Command line arguments containing the expectations
+/// 
+/// # Returns
+/// * `Result<()>` - Ok if the count matches expectations, Error otherwise
+pub fn check_expected_count(args: &Args) -> Result<()> {
+    let count = GLOBAL_REPLACEMENT_COUNT.load(Ordering::Relaxed);
+    let made = |count: usize| if count == 1 { "1 was made".to_string() } else { format!("{} were made", count) };
+    let replacements = |n: usize| if n == 1 { "1 replacement".to_string() } else { format!("{} replacements", n) };
+    
+    if let Some(expected) = args.expect {
+        if count != expected {
+            return Err(anyhow!("Expected exactly {}, but {}", replacements(expected), made(count)));
+        }
+    }
+    
+    if let Some(min) = args.expect_min {
+        if count < min {
+            return Err(anyhow!("Expected at least {}, but {}", replacements(min), made(count)));
         }
     }
+    
+    if let Some(max) = args.expect_max {
+        if count > max {
+            return Err(anyhow!("Expected at most {}, but {}", replacements(max), made(count)));
+        }
+    }
+    
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::Mode;
+    use crate::testdir::TestDir;
+    use clap::{CommandFactory, Parser};
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_atomically() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TestDir::new("replacer-output");
+        let path = dir.join("run.sh");
+
+        write_atomically(&path, b"echo foo\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "echo foo\n");
+
+        // Replacing an existing file keeps its mode and leaves no temporary file behind
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        write_atomically(&path, b"echo bar\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "echo bar\n");
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o755);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        assert!(write_atomically(&dir.join("missing/out.txt"), b"x").is_err());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_output_is_for_stdin_only() {
+        // Only clap's parse, as the full one infers stdin mode from whether stdin is a terminal
+        let args = Args::try_parse_from(["mane", "-r", "foo", "bar", "--output", "out.txt"]).unwrap();
+        assert_eq!(args.output, Some(PathBuf::from("out.txt")));
+        let rejects = |argv: &[&str]| Args::command().try_get_matches_from(argv).is_err();
+        assert!(rejects(&["mane", "-r", "foo", "bar", "--output", "out.txt", "-i", "src"]));
+        assert!(rejects(&["mane", "-r", "foo", "bar", "--output", "out.txt", "--line-buffered"]));
+    }
+
+    #[test]
+    fn test_stream_lines() {
+        // Built by hand, as parsing only infers stdin mode when stdin isn't a terminal
+        let stdin_args = |from: &str, to: &str| Args {
+            mode: Mode::StdinStdout,
+            rules: vec![ReplacementRule::new(from.to_string(), to.to_string())],
+            ..Args::default()
+        };
+        let args = stdin_args("fooBar", "bazQux");
+        assert!(can_stream(&args));
+        let mut output = Vec::new();
+        stream_lines("let fooBar = 1;\nFOO_BAR\nno newline at the end".as_bytes(), &mut output, &args).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "let bazQux = 1;\nBAZ_QUX\nno newline at the end");
+        assert!(stream_lines("".as_bytes(), Vec::new(), &args).is_err());
+
+        // What needs the whole input is replaced in one go
+        assert!(!can_stream(&stdin_args("a\nb", "c")));
+        assert!(!can_stream(&Args { skip_strings: true, ..stdin_args("a", "b") }));
+        assert!(!can_stream(&Args { csv_columns: vec!["name".to_string()], ..stdin_args("a", "b") }));
+    }
 }
@@ -0,0 +1,130 @@
+use crate::json::{self, Value};
+use anyhow::{Result, Context, anyhow};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// The files and lines `rg --json` reported matches on (--scope-from-rg)
+#[derive(Debug, Clone, Default)]
+pub struct RgScope {
+    /// Line numbers (1-based) of the matches, by file
+    files: BTreeMap<PathBuf, BTreeSet<usize>>,
+}
+
+impl RgScope {
+    /// Read the output of `rg --json` from a file, or from stdin for `-`
+    ///
+    /// # Arguments
+    /// * `source` - File holding the results, or `-`
+    ///
+    /// # Returns
+    /// * `Result<RgScope>` - The files and lines of the matches
+    pub fn read(source: &Path) -> Result<Self> {
+        let mut text = String::new();
+        if source == Path::new("-") {
+            std::io::stdin().read_to_string(&mut text).context("Failed to read ripgrep results from stdin")?;
+        } else {
+            text = std::fs::read_to_string(source)
+                .with_context(|| format!("Failed to read ripgrep results: {}", source.display()))?;
+        }
+        Self::parse(&text).with_context(|| format!("Invalid ripgrep results in {}", source.display()))
+    }
+
+    /// Parse the JSON Lines output of `rg --json`
+    ///
+    /// Only `match` messages are used; `begin`, `end`, `context` and `summary`
+    /// messages are skipped. A match spanning lines (`rg -U`) covers all of them.
+    ///
+    /// # Arguments
+    /// * `text` - One JSON message per line
+    ///
+    /// # Returns
+    /// * `Result<RgScope>` - The files and lines of the matches
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut scope = RgScope::default();
+        for (i, line) in text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+            let message = json::parse(line).with_context(|| format!("line {}", i + 1))?;
+            if message.get("type").and_then(Value::as_str) != Some("match") {
+                continue;
+            }
+            let data = message.get("data").ok_or_else(|| anyhow!("line {}: match without data", i + 1))?;
+
+            // Paths that aren't UTF-8 come base64-encoded as `bytes` and are skipped
+            let Some(path) = data.get("path").and_then(|path| path.get("text")).and_then(Value::as_str) else {
+                continue;
+            };
+            let Some(Value::Number(first)) = data.get("line_number") else {
+                return Err(anyhow!("line {}: match without a line number (run rg with --json)", i + 1));
+            };
+            let matched = data.get("lines").and_then(|lines| lines.get("text")).and_then(Value::as_str).unwrap_or("");
+            let count = matched.trim_end_matches('\n').matches('\n').count() + 1;
+
+            let first = *first as usize;
+            scope.files.entry(normalize(Path::new(path))).or_default().extend(first..first + count);
+        }
+        Ok(scope)
+    }
+
+    /// Files with at least one match, in path order
+    pub fn files(&self) -> Vec<PathBuf> {
+        self.files.keys().cloned().collect()
+    }
+
+    /// Lines of a file that had matches, or None for a file rg didn't report
+    pub fn lines(&self, path: &Path) -> Option<&BTreeSet<usize>> {
+        self.files.get(&normalize(path))
+    }
+}
+
+/// A path without its leading `./`, as rg prints it when searching `.`
+fn normalize(path: &Path) -> PathBuf {
+    path.strip_prefix(".").unwrap_or(path).to_path_buf()
+}
+
+/// Apply a replacement only to some lines of a text, leaving the others as they are
+///
+/// # Arguments
+/// * `content` - The text to replace in
+/// * `lines` - Line numbers (1-based) to replace on
+/// * `apply` - Replacement of one line, with its line ending
+///
+/// # Returns
+/// * `Result<String>` - The replaced text
+pub fn replace_lines(content: &str, lines: &BTreeSet<usize>, mut apply: impl FnMut(&str) -> Result<String>) -> Result<String> {
+    let mut result = String::with_capacity(content.len());
+    for (i, line) in content.split_inclusive('\n').enumerate() {
+        if lines.contains(&(i + 1)) {
+            result.push_str(&apply(line)?);
+        } else {
+            result.push_str(line);
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_replace_lines() {
+        let results = r#"{"type":"begin","data":{"path":{"text":"./src/a.rs"}}}
+{"type":"match","data":{"path":{"text":"./src/a.rs"},"lines":{"text":"let foo = 1;\n"},"line_number":2,"absolute_offset":10,"submatches":[]}}
+{"type":"match","data":{"path":{"text":"./src/a.rs"},"lines":{"text":"foo(\n  foo)\n"},"line_number":4,"absolute_offset":30,"submatches":[]}}
+{"type":"context","data":{"path":{"text":"./src/b.rs"},"lines":{"text":"foo\n"},"line_number":1}}
+{"type":"end","data":{"path":{"text":"./src/a.rs"}}}
+{"type":"summary","data":{}}
+"#;
+        let scope = RgScope::parse(results).unwrap();
+        assert_eq!(scope.files(), [PathBuf::from("src/a.rs")]);
+        let lines = scope.lines(Path::new("src/a.rs")).unwrap();
+        assert_eq!(lines.iter().copied().collect::<Vec<_>>(), [2, 4, 5]);
+        assert!(scope.lines(Path::new("./src/b.rs")).is_none());
+
+        let content = "foo\nlet foo = 1;\nfoo\nfoo(\n  foo)";
+        let replaced = replace_lines(content, lines, |line| Ok(line.replace("foo", "bar"))).unwrap();
+        assert_eq!(replaced, "foo\nlet bar = 1;\nfoo\nbar(\n  bar)");
+
+        assert!(RgScope::parse(r#"{"type":"match","data":{"path":{"text":"a"}}}"#).is_err());
+    }
+}
@@ -1,10 +1,25 @@
+use crate::archive;
+use crate::binary;
 use crate::args::Args;
-use crate::replacer;
+use crate::config;
+use crate::git;
+use crate::gzip;
+use crate::lock::{self, TreeLock};
+use crate::logger;
+use crate::match_cache::{self, MatchCache};
+use crate::patch;
+use crate::placeholder;
+use crate::plan::{self, ArchiveEdit, ContentEdit, Plan, Rename};
+use crate::pool;
+use crate::preview;
+use crate::references;
+use crate::replacer::{self, Target};
+use crate::script;
+use crate::tui;
 use anyhow::{Result, Context};
-use ignore::Walk;
+use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::io::Write;
 
 /// Scan directories and replace content in files and file names
 /// 
@@ -21,30 +36,152 @@ pub fn scan_and_replace(args: &Args) -> Result<()> {
         args.files.clone()
     };
     
+    // Hold an advisory lock per tree while modifying it so concurrent runs can't interleave renames
+    let mut locks: Vec<TreeLock> = Vec::new();
+    if args.in_place {
+        let mut locked_dirs = Vec::new();
+        for root_path in &root_paths {
+            let dir = lock::lock_dir(root_path);
+            if !locked_dirs.contains(&dir) {
+                locks.push(TreeLock::acquire(root_path)?);
+                locked_dirs.push(dir);
+            }
+        }
+    }
+    
+    // A commit needs a work tree with nothing staged yet, checked before anything changes
+    if args.commit.is_some() {
+        git::check_ready()?;
+    }
+    
+    let mut plan = Plan::default();
+    let mut all_files = Vec::new();
+    let mut walked = Vec::new();
+    let maps_renames = args.rename_map.is_some() || args.fix_references || args.fix_symlinks;
+    let cache = if args.match_cache && args.in_place {
+        Some(MatchCache::load(Path::new(match_cache::CACHE_DIR))?)
+    } else {
+        None
+    };
+    
     for root_path in root_paths {
-        walk_and_process_path(&root_path, args)?;
+        let all_paths = collect_paths(&root_path, args)?;
+        
+        if args.in_place {
+            plan_path_changes(&all_paths, args, cache.as_ref(), &mut plan)?;
+            if maps_renames {
+                walked.extend(all_paths.iter().cloned());
+            }
+            all_files.extend(all_paths.into_iter().filter(|p| p.is_file()));
+        } else {
+            // For non-in-place mode, just process and output file contents
+            for path in &all_paths {
+                if path.is_file() {
+                    output_file_content(path, args)?;
+                }
+            }
+        }
+    }
+    
+    if let Some(cache) = &cache {
+        cache.save()?;
     }
     
+    if args.in_place {
+        // A strict run stops on the warnings of planning, before anything is written
+        if args.strict {
+            for rename in plan.blocked_renames(args.trash) {
+                warn!("Cannot rename {:?} to {:?}: target already exists", rename.from, rename.to);
+            }
+            binary::report_skipped();
+            logger::check_strict()?;
+        }
+        
+        // Re-running the rules on the result must be a no-op
+        for edit in &plan.edits {
+            replacer::check_idempotent(&edit.replaced, args, Some(&edit.path), &format!("{:?}", edit.path))?;
+        }
+        for rename in &plan.renames {
+            let new_name = rename.to.file_name().unwrap_or_default().to_string_lossy();
+            replacer::check_idempotent(&new_name, args, Some(&rename.to), &format!("the new name of {:?}", rename.from))?;
+        }
+        
+        // Planning made every replacement, so --expect fails before the files are touched
+        replacer::check_expected_count(args)?;
+        
+        if args.writes_plan() && !plan.archives.is_empty() {
+            warn!("Changes inside archives can't be written to a patch, plan or script and are left out: {:?}",
+                plan.archives.iter().map(|edit| &edit.path).collect::<Vec<_>>());
+        }
+        
+        // Let the user drop false positives before anything is written
+        if args.tui && !tui::review(&mut plan)? {
+            info!("Quit without changing anything");
+            return Ok(());
+        }
+        
+        // Renames skipped because their target exists are left out of the mapping
+        let mapping = maps_renames.then(|| {
+            let blocked = plan.blocked_renames(args.trash);
+            let applied = Plan {
+                renames: plan.renames.iter().filter(|rename| !blocked.iter().any(|b| std::ptr::eq(*b, *rename))).cloned().collect(),
+                ..Plan::default()
+            };
+            plan::rename_mapping(&applied, &walked)
+        });
+        if let (true, Some(mapping)) = (args.fix_references, &mapping) {
+            references::fix_references(&mut plan, mapping, &all_files, args)?;
+        }
+        if let (true, Some(mapping)) = (args.fix_symlinks, &mapping) {
+            references::fix_symlinks(&mut plan, mapping, &walked)?;
+        }
+        
+        if let Some(patch_path) = &args.emit_patch {
+            patch::write_patch(patch_path, &plan, &all_files)?;
+        } else if let Some(plan_path) = &args.plan {
+            plan::write_plan(plan_path, &plan)?;
+        } else if let Some(format) = args.emit_script {
+            script::write_script(format, &plan)?;
+        } else if let Some(dir) = &args.output_dir {
+            plan.write_to(dir, &all_files)?;
+        } else {
+            plan.apply(args.trash)?;
+            if let Some(message) = &args.commit {
+                git::commit(&plan, &args.rules, message.as_deref())?;
+            }
+        }
+        
+        if let (Some(out), Some(mapping)) = (&args.rename_map, &mapping) {
+            plan::write_rename_map(out, mapping)?;
+        }
+        
+        // List the changed files, where they end up if the plan was applied
+        if args.files_with_matches {
+            let applied = !args.writes_plan();
+            let changed = plan.edits.iter().map(|edit| &edit.path).chain(plan.archives.iter().map(|edit| &edit.path));
+            for path in changed {
+                replacer::print_path(&if applied { plan.final_path(path) } else { path.clone() }, args)?;
+            }
+        }
+    }
+    
+    drop(locks);
+    
     Ok(())
 }
 
-/// Walk through directory structure and process files
+/// Walk through directory structure and collect files and directories
 /// 
 /// # Arguments
 /// * `root_path` - Root path to start scanning from
 /// * `args` - Command line arguments
 /// 
 /// # Returns
-/// * `Result<()>` - Result of the operation
-fn walk_and_process_path(root_path: &Path, args: &Args) -> Result<()> {
-    let walker = if args.include_git_ignore {
-        Walk::new(root_path)
-    } else {
-        ignore::WalkBuilder::new(root_path)
-            .hidden(false)   // Process hidden files too
-            .git_ignore(true)
-            .build()
-    };
+/// * `Result<Vec<PathBuf>>` - All paths found, in walk order
+pub fn collect_paths(root_path: &Path, args: &Args) -> Result<Vec<PathBuf>> {
+    let walker = args.walk_builder(root_path)
+        .hidden(false)   // Process hidden files too
+        .build();
     
     // Collect all files and directories
     let mut all_paths = Vec::new();
@@ -53,72 +190,195 @@ fn walk_and_process_path(root_path: &Path, args: &Args) -> Result<()> {
         match result {
             Ok(entry) => {
                 let path = entry.path().to_path_buf();
+                // Never process our own lock file, cache or config file
+                if lock::is_lock_file(&path) || match_cache::is_cache_path(&path) || config::is_config_file(&path, args) {
+                    continue;
+                }
                 all_paths.push(path);
             },
-            Err(err) => {
-                eprintln!("Error walking directory: {}", err);
-            }
+            Err(err) => args.walk_error(err)?,
         }
     }
     
-    // Process files and directories
-    if args.in_place {
-        // First, process file contents
-        for path in &all_paths {
-            if path.is_file() {
-                process_file_content(path, args)?;
-            }
-        }
-        
-        // Then, rename files and directories (starting with the deepest paths first)
-        let mut sorted_paths = all_paths.clone();
-        sorted_paths.sort_by(|a, b| {
-            let a_str = a.to_string_lossy();
-            let b_str = b.to_string_lossy();
-            // Sort by path length (descending) to handle nested paths correctly
-            b_str.len().cmp(&a_str.len())
-        });
-        
-        for path in &sorted_paths {
-            rename_path(path, args)?;
+    Ok(all_paths)
+}
+
+/// Plan content edits and renames for the collected paths of one tree
+/// 
+/// # Arguments
+/// * `all_paths` - Paths collected from the tree
+/// * `args` - Command line arguments
+/// * `cache` - Files known not to match (--match-cache)
+/// * `plan` - Plan to add the changes to
+/// 
+/// # Returns
+/// * `Result<()>` - Result of the operation
+fn plan_path_changes(all_paths: &[PathBuf], args: &Args, cache: Option<&MatchCache>, plan: &mut Plan) -> Result<()> {
+    let counters = number_paths(all_paths, args, cache)?;
+    
+    // First, process file contents (in parallel, keeping the plan in path order)
+    let files: Vec<&PathBuf> = all_paths.iter().filter(|path| path.is_file()).collect();
+    let budget = args.budget();
+    let results = pool::map(args.jobs, &files, |path| -> Result<(Option<ContentEdit>, Option<ArchiveEdit>)> {
+        let _share = budget.as_ref().map(|budget| budget.take(pool::footprint(path)));
+        match archive::Kind::detect(path).filter(|_| args.archives) {
+            Some(kind) => Ok((None, plan_archive(path, kind, args)?)),
+            None => Ok((plan_file_content(path, args, counters.get(*path).copied(), cache)?, None)),
         }
-    } else {
-        // For non-in-place mode, just process and output file contents
-        for path in &all_paths {
-            if path.is_file() {
-                output_file_content(path, args)?;
-            }
+    });
+    for result in results {
+        let (edit, archive) = result?;
+        plan.edits.extend(edit);
+        plan.archives.extend(archive);
+    }
+    
+    // Then, rename files and directories, children before their parents
+    let mut sorted_paths = all_paths.to_vec();
+    sort_deepest_first(&mut sorted_paths);
+    
+    for path in &sorted_paths {
+        if let Some(rename) = plan_rename(path, args, counters.get(path).copied())? {
+            plan.renames.push(rename);
         }
     }
     
     Ok(())
 }
 
-/// Process and replace content in a file for in-place mode
+/// Sort paths so every path comes before its parent directories
+/// 
+/// Paths are ordered by their number of components, deepest first, which a
+/// path's length doesn't give (`a/b/c/d` is deeper than `averyverylongname`).
+/// Paths of the same depth stay in name order, so the plan is reproducible.
+/// 
+/// # Arguments
+/// * `paths` - Paths to sort
+fn sort_deepest_first(paths: &mut [PathBuf]) {
+    paths.sort_by(|a, b| b.components().count().cmp(&a.components().count()).then_with(|| a.cmp(b)));
+}
+
+/// Assign `{{n}}` counter values to the paths whose name or content the rules match
+/// 
+/// Paths are numbered in sorted order so the numbering doesn't depend on the walk order.
+/// 
+/// # Arguments
+/// * `all_paths` - Paths collected from the tree
+/// * `args` - Command line arguments
+/// 
+/// # Returns
+/// * `Result<HashMap<PathBuf, usize>>` - Counter value per path (empty if no TO uses the counter)
+fn number_paths(all_paths: &[PathBuf], args: &Args, cache: Option<&MatchCache>) -> Result<HashMap<PathBuf, usize>> {
+    if !replacer::uses_counter(args) {
+        return Ok(HashMap::new());
+    }
+    
+    let mut matched = Vec::new();
+    for path in all_paths {
+        let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        let name_matches = replacer::matches(&name, args, Some(path))
+            && if path.is_file() { args.rename_file } else { args.rename_dir }
+            && !args.keeps_name(path);
+        let content_matches = path.is_file()
+            && !cache.is_some_and(|cache| cache.is_unmatched(path, &match_cache::rules_digest(path, args)))
+            && replacer::file_may_match(path, args)? && match gzip::read_text(path, args.decompress, args.detection()) {
+            Err(e) if binary::is_binary_error(&e) => false,
+            result => replacer::matches(&result.with_context(|| format!("Failed to read file: {:?}", path))?.0, args, Some(path)),
+        };
+        if name_matches || content_matches {
+            matched.push(path.clone());
+        }
+    }
+    matched.sort();
+    
+    Ok(matched.into_iter().map(|path| (path, placeholder::next_counter())).collect())
+}
+
+/// Compute the replaced content of a file for in-place mode
 /// 
 /// # Arguments
 /// * `file_path` - Path to the file to process
 /// * `args` - Command line arguments
+/// * `counter` - `{{n}}` value of the file, if numbered
+/// * `cache` - Files known not to match (--match-cache)
 /// 
 /// # Returns
-/// * `Result<()>` - Result of the operation
-fn process_file_content(file_path: &Path, args: &Args) -> Result<()> {
+/// * `Result<Option<ContentEdit>>` - The edit, or None if the content is unchanged
+fn plan_file_content(file_path: &Path, args: &Args, counter: Option<usize>, cache: Option<&MatchCache>) -> Result<Option<ContentEdit>> {
     if !file_path.is_file() {
-        return Ok(());
+        return Ok(None);
     }
     
-    let content = fs::read_to_string(file_path)
-        .with_context(|| format!("Failed to read file: {:?}", file_path))?;
+    // Files the rules didn't match last time are skipped if they haven't changed
+    let cached = cache.map(|cache| (cache, match_cache::rules_digest(file_path, args)));
+    if let Some((cache, rules)) = &cached {
+        if cache.is_unmatched(file_path, rules) {
+            return Ok(None);
+        }
+    }
+    let unmatched = || {
+        if let Some((cache, rules)) = &cached {
+            cache.record_unmatched(file_path, rules);
+        }
+        Ok(None)
+    };
+    
+    // Most files contain none of the FROM strings; those are skipped without being decoded
+    if !replacer::file_may_match(file_path, args)? {
+        return unmatched();
+    }
     
-    let replaced = replacer::replace_content(&content, args)?;
+    // Binary files are left as they are
+    let (content, compressed) = match gzip::read_text(file_path, args.decompress, args.detection()) {
+        Err(e) if binary::is_binary_error(&e) => {
+            binary::record_skipped(file_path, &e.to_string());
+            return Ok(None);
+        },
+        result => result.with_context(|| format!("Failed to read file: {:?}", file_path))?,
+    };
     
-    if content != replaced {
-        fs::write(file_path, replaced)
-            .with_context(|| format!("Failed to write file: {:?}", file_path))?;
-        println!("Modified content: {:?}", file_path);
+    let replaced = replacer::replace_content_for(&content, args, Target { counter, ..Target::path(file_path) })?;
+    
+    if content == replaced {
+        return unmatched();
     }
+    replacer::log_origins(file_path, &content, &replaced, args);
     
-    Ok(())
+    Ok(Some(ContentEdit {
+        path: file_path.to_path_buf(),
+        original: content,
+        replaced,
+        compressed,
+    }))
+}
+
+/// Compute the repacked archive for in-place mode (--archives)
+/// 
+/// # Arguments
+/// * `path` - Path to the archive
+/// * `kind` - Archive format
+/// * `args` - Command line arguments
+/// 
+/// # Returns
+/// * `Result<Option<ArchiveEdit>>` - The edit, or None if no member changed
+fn plan_archive(path: &Path, kind: archive::Kind, args: &Args) -> Result<Option<ArchiveEdit>> {
+    let data = fs::read(path)
+        .with_context(|| format!("Failed to read file: {:?}", path))?;
+    
+    // Members are matched against rule scopes as if the archive were a directory
+    let replaced = archive::transform(
+        &data,
+        kind,
+        |member, content| replacer::replace_content_for(content, args, Target::path(&path.join(member))),
+        |name, is_dir| {
+            if (is_dir && args.rename_dir) || (!is_dir && args.rename_file) {
+                replacer::replace_content_for(name, args, Target { name: true, ..Target::path(path) })
+            } else {
+                Ok(name.to_string())
+            }
+        },
+    ).with_context(|| format!("Failed to process archive: {:?}", path))?;
+    
+    Ok(replaced.map(|replaced| ArchiveEdit { path: path.to_path_buf(), replaced }))
 }
 
 /// Process and output file content for non-in-place mode
@@ -134,54 +394,139 @@ fn output_file_content(file_path: &Path, args: &Args) -> Result<()> {
         return Ok(());
     }
     
-    let content = fs::read_to_string(file_path)
-        .with_context(|| format!("Failed to read file: {:?}", file_path))?;
+    let (content, _) = match gzip::read_text(file_path, args.decompress, args.detection()) {
+        Err(e) if binary::is_binary_error(&e) => {
+            if replacer::file_may_match(file_path, args)? {
+                binary::record_skipped(file_path, &e.to_string());
+            }
+            return Ok(());
+        },
+        result => result.with_context(|| format!("Failed to read file: {:?}", file_path))?,
+    };
     
-    let replaced = replacer::replace_content(&content, args)?;
+    let replaced = replacer::replace_content_for(&content, args, Target::path(file_path))?;
     
-    // Output to stdout
-    std::io::stdout().write_all(replaced.as_bytes())?;
+    if args.files_with_matches {
+        if replaced != content {
+            replacer::print_path(file_path, args)?;
+        }
+    } else if args.format.is_some() {
+        if replaced != content {
+            replacer::print_locations(file_path, &content, &replaced, args)?;
+        }
+    } else if args.print_changes {
+        preview::print_changes(file_path, &content, &replaced)?;
+    } else {
+        // Output to stdout
+        replacer::write_file_output(file_path, &replaced, args)?;
+    }
     
     Ok(())
 }
 
-/// Rename file or directory path
+/// Compute the rename of a file or directory path
 /// 
 /// # Arguments
 /// * `path` - Path to rename
 /// * `args` - Command line arguments
+/// * `counter` - `{{n}}` value of the path, if numbered
 /// 
 /// # Returns
-/// * `Result<()>` - Result of the operation
-fn rename_path(path: &Path, args: &Args) -> Result<()> {
-    // Skip based on configuration
-    if path.is_file() && !crate::args::GLOBAL_RENAME_FILE_ENABLED.load(std::sync::atomic::Ordering::Relaxed) {
-        return Ok(());
+/// * `Result<Option<Rename>>` - The rename, or None if the name is unchanged
+fn plan_rename(path: &Path, args: &Args, counter: Option<usize>) -> Result<Option<Rename>> {
+    // Skip based on configuration; a symlink is renamed like a file, whatever it points at
+    let is_link = path.is_symlink();
+    if (path.is_file() || is_link) && !crate::args::GLOBAL_RENAME_FILE_ENABLED.load(std::sync::atomic::Ordering::Relaxed) {
+        return Ok(None);
     }
     
-    if path.is_dir() && !crate::args::GLOBAL_RENAME_DIR_ENABLED.load(std::sync::atomic::Ordering::Relaxed) {
-        return Ok(());
+    if path.is_dir() && !is_link && !crate::args::GLOBAL_RENAME_DIR_ENABLED.load(std::sync::atomic::Ordering::Relaxed) {
+        return Ok(None);
+    }
+    
+    if args.keeps_name(path) {
+        return Ok(None);
     }
     
     if let Some(file_name) = path.file_name() {
         let old_name = file_name.to_string_lossy();
-        let new_name = replacer::replace_content(&old_name, args)?;
+        // Metadata placeholders ({{mtime}}, {{size}}, {{sha256}}) describe the file being renamed
+        let new_name = replacer::replace_content_for(&old_name, args, Target { counter, name: true, ..Target::path(path) })?;
         
         if old_name != new_name {
             let parent = path.parent().unwrap_or(Path::new(""));
-            let new_path = parent.join(&new_name);
-            
-            // Skip if the new path already exists
-            if new_path.exists() && new_path != path {
-                eprintln!("Warning: Cannot rename {:?} to {:?}: target already exists", path, new_path);
-                return Ok(());
-            }
-            
-            fs::rename(path, &new_path)
-                .with_context(|| format!("Failed to rename {:?} to {:?}", path, new_path))?;
-            println!("Renamed: {:?} -> {:?}", path, new_path);
+            return Ok(Some(Rename {
+                from: path.to_path_buf(),
+                to: parent.join(&new_name),
+            }));
         }
     }
     
-    Ok(())
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args;
+    use crate::init;
+    use crate::testdir::TestDir;
+
+    #[test]
+    fn test_config_file_is_not_rewritten() {
+        let dir = TestDir::new("scanner-config");
+        fs::write(dir.join("Cargo.toml"), "[package]\nname = \"my-app\"\n").unwrap();
+        fs::write(dir.join("main.rs"), "my_app::run();\n").unwrap();
+
+        // What `mane init` prints to do next, with the file set as it tells
+        init::write_starter(&dir, false).unwrap();
+        let config = dir.join(config::CONFIG_FILE_NAME);
+        let text = fs::read_to_string(&config).unwrap().replace("to = \"new-name\"", "to = \"cool-tool\"");
+        fs::write(&config, &text).unwrap();
+        let config_arg = config.to_string_lossy().to_string();
+        let dir_arg = dir.to_string_lossy().to_string();
+        let args = args::parse_from(["mane", "--config", &config_arg, "--profile", "rename", "-i", &dir_arg]).unwrap();
+        scan_and_replace(&args).unwrap();
+
+        assert_eq!(fs::read_to_string(dir.join("main.rs")).unwrap(), "cool_tool::run();\n");
+        assert_eq!(fs::read_to_string(&config).unwrap(), text);
+    }
+
+    #[test]
+    fn test_expect_fails_before_writing() {
+        let dir = TestDir::new("scanner-expect");
+        fs::write(dir.join("foo.txt"), "foo and foo\n").unwrap();
+
+        let dir_arg = dir.to_string_lossy().to_string();
+        let args = args::parse_from(["mane", "-r", "foo", "bar", "--expect", "1", "-i", &dir_arg]).unwrap();
+        let error = scan_and_replace(&args).unwrap_err().to_string();
+        assert!(error.starts_with("Expected exactly 1 replacement, but"), "{}", error);
+
+        assert_eq!(fs::read_to_string(dir.join("foo.txt")).unwrap(), "foo and foo\n");
+        assert!(!dir.join("bar.txt").exists());
+    }
+
+    #[test]
+    fn test_sort_deepest_first() {
+        let mut paths: Vec<PathBuf> = ["averyverylongname", "a", "a/b/c/d", "a/b", "a/b/c", "a/b/x"].iter().map(PathBuf::from).collect();
+        sort_deepest_first(&mut paths);
+        assert_eq!(paths, ["a/b/c/d", "a/b/c", "a/b/x", "a/b", "a", "averyverylongname"].map(PathBuf::from));
+    }
+
+    #[test]
+    fn test_rename_nested_matches() {
+        let dir = TestDir::new("scanner-nested");
+        fs::create_dir_all(dir.join("foo/foo_dir/deep_foo")).unwrap();
+        fs::write(dir.join("foo/foo_dir/foo.txt"), "foo\n").unwrap();
+        fs::write(dir.join("foo/foo_dir/deep_foo/x.txt"), "x\n").unwrap();
+
+        // Every component of the paths matches, parents and children alike
+        let dir_arg = dir.join("foo").to_string_lossy().to_string();
+        let args = args::parse_from(["mane", "-r", "foo", "bar", "-i", &dir_arg]).unwrap();
+        scan_and_replace(&args).unwrap();
+
+        assert_eq!(fs::read_to_string(dir.join("bar/bar_dir/bar.txt")).unwrap(), "bar\n");
+        assert!(dir.join("bar/bar_dir/deep_bar/x.txt").exists());
+        assert!(!dir.join("foo").exists());
+    }
 }
@@ -0,0 +1,169 @@
+use crate::plan::{ContentEdit, Plan};
+use anyhow::Result;
+use clap::ValueEnum;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Shell of the script written by --emit-script
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum ScriptFormat {
+    Sh,  // POSIX shell with sed, printf and mv
+    Ps1, // PowerShell
+}
+
+/// Write a script reproducing a plan to stdout (--emit-script)
+///
+/// Content edits come first and renames after them, in plan order, so each
+/// command finds its paths in the state the previous ones left.
+///
+/// # Arguments
+/// * `format` - Shell to write the script for
+/// * `plan` - Planned changes
+///
+/// # Returns
+/// * `Result<()>` - Result of the operation
+pub fn write_script(format: ScriptFormat, plan: &Plan) -> Result<()> {
+    let compressed: Vec<&Path> = plan.edits.iter().filter(|edit| edit.compressed).map(|edit| edit.path.as_path()).collect();
+    if !compressed.is_empty() {
+        warn!("Compressed files can't be rewritten by a script and are left out: {:?}", compressed);
+    }
+    let edits: Vec<&ContentEdit> = plan.edits.iter().filter(|edit| !edit.compressed).collect();
+
+    let script = match format {
+        ScriptFormat::Sh => sh_script(&edits, plan),
+        ScriptFormat::Ps1 => ps1_script(&edits, plan),
+    };
+    io::stdout().lock().write_all(script.as_bytes())?;
+    Ok(())
+}
+
+/// A POSIX shell script: `sed` for edits that keep the line count, `printf` for the others, `mv` for renames
+fn sh_script(edits: &[&ContentEdit], plan: &Plan) -> String {
+    let mut out = String::from("#!/bin/sh\n# Generated by mane\nset -e\n");
+
+    for edit in edits {
+        let path = sh_quote(&edit.path.to_string_lossy());
+        out.push('\n');
+        match sed_expressions(&edit.original, &edit.replaced) {
+            Some(expressions) => {
+                out.push_str("sed -i.mane-bak");
+                for expression in expressions {
+                    out.push_str(" -e ");
+                    out.push_str(&sh_quote(&expression));
+                }
+                out.push_str(&format!(" -- {} && rm -f -- {}\n", path, sh_quote(&format!("{}.mane-bak", edit.path.to_string_lossy()))));
+            },
+            None => out.push_str(&format!("printf '%s' {} > {}\n", sh_quote(&edit.replaced), path)),
+        }
+    }
+
+    if !plan.renames.is_empty() {
+        out.push('\n');
+    }
+    for rename in &plan.renames {
+        out.push_str(&format!("mv -- {} {}\n", sh_quote(&rename.from.to_string_lossy()), sh_quote(&rename.to.to_string_lossy())));
+    }
+    out
+}
+
+/// A PowerShell script writing each edited file whole and moving renamed paths
+fn ps1_script(edits: &[&ContentEdit], plan: &Plan) -> String {
+    let mut out = String::from("# Generated by mane\n$ErrorActionPreference = 'Stop'\n");
+
+    for edit in edits {
+        // WriteAllText takes paths relative to the process directory, not the PowerShell location
+        out.push_str(&format!(
+            "\n[System.IO.File]::WriteAllText((Join-Path (Get-Location) {}), {})\n",
+            ps1_quote(&edit.path.to_string_lossy()),
+            ps1_quote(&edit.replaced),
+        ));
+    }
+
+    if !plan.renames.is_empty() {
+        out.push('\n');
+    }
+    for rename in &plan.renames {
+        out.push_str(&format!(
+            "Move-Item -LiteralPath {} -Destination {}\n",
+            ps1_quote(&rename.from.to_string_lossy()),
+            ps1_quote(&rename.to.to_string_lossy()),
+        ));
+    }
+    out
+}
+
+/// `sed` expressions rewriting each changed line, or None when lines are added
+/// or removed or the final newline changes, which line edits can't express
+///
+/// # Arguments
+/// * `original` - Content before replacement
+/// * `replaced` - Content after replacement
+///
+/// # Returns
+/// * `Option<Vec<String>>` - One `Ns/.*/NEW/` expression per changed line
+fn sed_expressions(original: &str, replaced: &str) -> Option<Vec<String>> {
+    let old: Vec<&str> = original.split('\n').collect();
+    let new: Vec<&str> = replaced.split('\n').collect();
+    if old.len() != new.len() || original.ends_with('\n') != replaced.ends_with('\n') {
+        return None;
+    }
+
+    let expressions = old.iter().zip(&new).enumerate()
+        .filter(|(_, (old, new))| old != new)
+        .map(|(i, (_, new))| {
+            let escaped = new.replace('\\', "\\\\").replace('/', "\\/").replace('&', "\\&");
+            format!("{}s/.*/{}/", i + 1, escaped)
+        })
+        .collect();
+    Some(expressions)
+}
+
+/// Quote a string for a POSIX shell
+fn sh_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// Quote a string for PowerShell
+fn ps1_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plan::Rename;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_scripts() {
+        let plan = Plan {
+            edits: vec![
+                ContentEdit {
+                    path: PathBuf::from("src/foo.rs"),
+                    original: "use foo;\nfn main() {}\nfoo::run();\n".to_string(),
+                    replaced: "use bar;\nfn main() {}\nbar::run(a/b & 'c');\n".to_string(),
+                    compressed: false,
+                },
+                ContentEdit {
+                    path: PathBuf::from("README"),
+                    original: "foo\n".to_string(),
+                    replaced: "bar\nbaz".to_string(),
+                    compressed: false,
+                },
+            ],
+            archives: Vec::new(),
+            renames: vec![Rename { from: PathBuf::from("src/foo.rs"), to: PathBuf::from("src/bar.rs") }],
+            links: Vec::new(),
+        };
+        let edits: Vec<&ContentEdit> = plan.edits.iter().collect();
+
+        assert_eq!(sh_script(&edits, &plan), concat!(
+            "#!/bin/sh\n# Generated by mane\nset -e\n\n",
+            "sed -i.mane-bak -e '1s/.*/use bar;/' -e '3s/.*/bar::run(a\\/b \\& '\\''c'\\'');/' -- 'src/foo.rs' && rm -f -- 'src/foo.rs.mane-bak'\n\n",
+            "printf '%s' 'bar\nbaz' > 'README'\n\n",
+            "mv -- 'src/foo.rs' 'src/bar.rs'\n",
+        ));
+        assert!(ps1_script(&edits, &plan).ends_with("Move-Item -LiteralPath 'src/foo.rs' -Destination 'src/bar.rs'\n"));
+        assert!(ps1_script(&edits, &plan).contains("bar::run(a/b & ''c'');\n'"));
+    }
+}
@@ -0,0 +1,424 @@
+use crate::args::{Args, Mode};
+use crate::config;
+use crate::json::{self, Value};
+use crate::placeholder::GLOBAL_COUNTER;
+use crate::replacer::{self, Target};
+use crate::scanner;
+use anyhow::{Result, Context};
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex, RwLock};
+
+/// JSON-RPC error codes
+const PARSE_ERROR: f64 = -32700.0;
+const INVALID_REQUEST: f64 = -32600.0;
+const METHOD_NOT_FOUND: f64 = -32601.0;
+const INVALID_PARAMS: f64 = -32602.0;
+const SERVER_ERROR: f64 = -32000.0;
+
+/// An error answered to a request
+struct RpcError {
+    code: f64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: f64, message: impl Into<String>) -> Self {
+        RpcError { code, message: message.into() }
+    }
+}
+
+impl From<anyhow::Error> for RpcError {
+    fn from(e: anyhow::Error) -> Self {
+        RpcError::new(SERVER_ERROR, format!("{:#}", e))
+    }
+}
+
+/// A long-running mane answering JSON-RPC requests (`mane serve`)
+///
+/// Rule sets sent with requests are compiled once and kept, so repeated
+/// calls with the same rules reuse their prefilter.
+struct Server<'a> {
+    /// Arguments with the rules of the config file and `serve -r`
+    base: &'a Args,
+
+    /// Arguments compiled for the rule sets of earlier requests, by their JSON
+    rule_sets: Mutex<HashMap<String, Arc<Args>>>,
+
+    /// Held for writing by `apply`, which takes `{{n}}` values from the
+    /// process-wide counter, and for reading by the requests that read it
+    counting: RwLock<()>,
+}
+
+impl Server<'_> {
+    /// Arguments for a request: the server's rules, or the `rules` it sends
+    fn args_for(&self, params: &Value) -> Result<Option<Arc<Args>>, RpcError> {
+        let Some(rules) = params.get("rules") else {
+            return Ok(None);
+        };
+
+        let key = rules.to_compact_string();
+        let mut rule_sets = self.rule_sets.lock().unwrap();
+        if let Some(args) = rule_sets.get(&key) {
+            return Ok(Some(args.clone()));
+        }
+        let rules = config::parse_rules(rules, "the request")
+            .map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))?;
+        let args = Arc::new(Args { rules, ..self.options() });
+        rule_sets.insert(key, args.clone());
+        Ok(Some(args))
+    }
+
+    /// The server's options, without its rules
+    fn options(&self) -> Args {
+        Args {
+            case_enabled: self.base.case_enabled,
+            rename_file: self.base.rename_file,
+            rename_dir: self.base.rename_dir,
+            include_git_ignore: self.base.include_git_ignore,
+            jobs: self.base.jobs,
+            ..Args::default()
+        }
+    }
+
+    /// A server with the rules and options of `args`
+    fn new(base: &Args) -> Server<'_> {
+        Server { base, rule_sets: Mutex::new(HashMap::new()), counting: RwLock::new(()) }
+    }
+
+    /// Run a method
+    fn call(&self, method: &str, params: &Value) -> Result<Value, RpcError> {
+        let string = |key: &str| params.get(key).and_then(Value::as_str)
+            .ok_or_else(|| RpcError::new(INVALID_PARAMS, format!("Missing string parameter \"{}\"", key)));
+        let custom = self.args_for(params)?;
+        let args = custom.as_deref().unwrap_or(self.base);
+
+        match method {
+            // Replace in a text, as if it were the content of `path`
+            "replace-content" => {
+                let content = string("content")?;
+                let _counting = self.counting.read().unwrap();
+                let path = params.get("path").and_then(Value::as_str).map(Path::new);
+                let replaced = replacer::replace_content_for(content, args, Target { path, ..Target::default() })?;
+                Ok(json::object([
+                    ("changed", Value::from(replaced != content)),
+                    ("content", Value::from(replaced)),
+                ]))
+            },
+            // Where a path ends up when its file and directory names are renamed
+            "plan-rename" => {
+                let path = PathBuf::from(string("path")?);
+                let _counting = self.counting.read().unwrap();
+                Ok(json::object([("path", Value::from(rename_path(&path, args)?.to_string_lossy().to_string()))]))
+            },
+            // Replace in the contents and names of a tree in place
+            "apply" => {
+                let root = PathBuf::from(string("path")?);
+                if !root.exists() {
+                    return Err(RpcError::new(INVALID_PARAMS, format!("Path not found: {}", root.display())));
+                }
+                let tree_args = Args {
+                    rules: args.rules.clone(),
+                    files: vec![root],
+                    in_place: true,
+                    mode: Mode::FilesAndNames,
+                    ..self.options()
+                };
+                let _counting = self.counting.write().unwrap();
+                GLOBAL_COUNTER.store(self.base.counter_start, Ordering::Relaxed);
+                let (result, count) = replacer::count_replacements(|| scanner::scan_and_replace(&tree_args));
+                result?;
+                Ok(json::object([("replacements", Value::from(count))]))
+            },
+            _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("Unknown method: {}", method))),
+        }
+    }
+
+    /// Answer one request line, or None for a notification (a request without an id)
+    fn handle(&self, line: &str) -> Option<String> {
+        let (id, outcome) = match json::parse(line) {
+            Err(e) => (Some(Value::Null), Err(RpcError::new(PARSE_ERROR, e.to_string()))),
+            Ok(request) => {
+                let id = request.get("id").cloned();
+                let outcome = match request.get("method").and_then(Value::as_str) {
+                    Some(method) => self.call(method, request.get("params").unwrap_or(&Value::Object(Vec::new()))),
+                    None => Err(RpcError::new(INVALID_REQUEST, "Missing \"method\"")),
+                };
+                (id, outcome)
+            },
+        };
+
+        let id = id?;
+        let response = match outcome {
+            Ok(result) => json::object([("jsonrpc", Value::from("2.0")), ("id", id), ("result", result)]),
+            Err(error) => json::object([
+                ("jsonrpc", Value::from("2.0")),
+                ("id", id),
+                ("error", json::object([("code", Value::Number(error.code)), ("message", Value::from(error.message))])),
+            ]),
+        };
+        Some(response.to_compact_string())
+    }
+
+    /// Answer the requests of one connection, one JSON object per line
+    fn serve_stream(&self, reader: impl BufRead, mut writer: impl Write) -> Result<()> {
+        for line in reader.lines() {
+            let line = line.context("Failed to read request")?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Some(response) = self.handle(&line) {
+                writer.write_all(format!("{}\n", response).as_bytes())?;
+                writer.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Apply the rules to every file and directory name of a path
+///
+/// # Arguments
+/// * `path` - Path to rename
+/// * `args` - Arguments holding the rules
+///
+/// # Returns
+/// * `Result<PathBuf>` - The renamed path
+fn rename_path(path: &Path, args: &Args) -> Result<PathBuf> {
+    let components: Vec<Component> = path.components().collect();
+    let mut prefix = PathBuf::new();
+    let mut result = PathBuf::new();
+
+    for (i, component) in components.iter().enumerate() {
+        prefix.push(component);
+        let Component::Normal(name) = component else {
+            result.push(component);
+            continue;
+        };
+        let is_file = i + 1 == components.len() && !path.is_dir();
+        if (is_file && !args.rename_file) || (!is_file && !args.rename_dir) || args.keeps_name(&prefix) {
+            result.push(name);
+            continue;
+        }
+        let name = name.to_string_lossy();
+        result.push(replacer::replace_content_for(&name, args, Target { path: Some(&prefix), counter: None, name: true })?);
+    }
+
+    Ok(result)
+}
+
+/// Answer JSON-RPC requests on stdio or a unix socket until the input ends
+///
+/// Requests and responses are single-line JSON-RPC 2.0 objects. Methods:
+/// `replace-content` (`content`, optional `path`), `plan-rename` (`path`) and
+/// `apply` (`path`), each taking optional `rules` in the config file format.
+///
+/// # Arguments
+/// * `args` - Arguments with the default rules
+/// * `socket` - Unix socket to listen on instead of stdio
+///
+/// # Returns
+/// * `Result<()>` - Result of the operation
+pub fn run(args: &Args, socket: Option<&Path>) -> Result<()> {
+    let server = Server::new(args);
+
+    let Some(socket) = socket else {
+        return server.serve_stream(io::stdin().lock(), io::stdout().lock());
+    };
+    listen(&server, socket)
+}
+
+#[cfg(unix)]
+fn listen(server: &Server, socket: &Path) -> Result<()> {
+    use std::os::unix::net::UnixListener;
+
+    remove_stale_socket(socket)?;
+    let listener = UnixListener::bind(socket).with_context(|| format!("Failed to listen on {:?}", socket))?;
+    let _socket_file = SocketFile::new(socket);
+    info!("Listening on {:?}", socket);
+
+    std::thread::scope(|scope| {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    scope.spawn(move || {
+                        let reader = BufReader::new(&stream);
+                        if let Err(e) = server.serve_stream(reader, &stream) {
+                            warn!("Connection closed: {}", e);
+                        }
+                    });
+                },
+                Err(e) => warn!("Failed to accept a connection: {}", e),
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Remove a socket left behind by an earlier server, which would make bind fail
+///
+/// # Arguments
+/// * `socket` - Path to listen on
+///
+/// # Returns
+/// * `Result<()>` - Error if something other than a socket is at the path
+#[cfg(unix)]
+fn remove_stale_socket(socket: &Path) -> Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+
+    match std::fs::symlink_metadata(socket) {
+        Ok(metadata) if metadata.file_type().is_socket() => {
+            std::fs::remove_file(socket).with_context(|| format!("Failed to remove stale socket: {:?}", socket))
+        },
+        Ok(_) => Err(anyhow::anyhow!("{:?} already exists and isn't a socket", socket)),
+        Err(_) => Ok(()),
+    }
+}
+
+/// Path of the socket being listened on, for the signal handler to remove
+#[cfg(unix)]
+static SOCKET_PATH: std::sync::OnceLock<std::ffi::CString> = std::sync::OnceLock::new();
+
+/// The socket file of a listening server, removed when the server stops
+///
+/// A server usually stops with SIGINT or SIGTERM, so those remove it too
+/// before the process ends the way it would have.
+#[cfg(unix)]
+struct SocketFile<'a> {
+    path: &'a Path,
+}
+
+#[cfg(unix)]
+impl SocketFile<'_> {
+    fn new(path: &Path) -> SocketFile<'_> {
+        use std::os::unix::ffi::OsStrExt;
+
+        extern "C" fn remove_and_exit(signal: libc::c_int) {
+            // SAFETY: unlink, signal and raise are async-signal-safe, and the path is set before the handler
+            unsafe {
+                if let Some(path) = SOCKET_PATH.get() {
+                    libc::unlink(path.as_ptr());
+                }
+                libc::signal(signal, libc::SIG_DFL);
+                libc::raise(signal);
+            }
+        }
+
+        if let Ok(path) = std::ffi::CString::new(path.as_os_str().as_bytes()) {
+            if SOCKET_PATH.set(path).is_ok() {
+                let handler = remove_and_exit as extern "C" fn(libc::c_int) as libc::sighandler_t;
+                // SAFETY: the handler only makes async-signal-safe calls
+                unsafe {
+                    libc::signal(libc::SIGINT, handler);
+                    libc::signal(libc::SIGTERM, handler);
+                }
+            }
+        }
+        SocketFile { path }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for SocketFile<'_> {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(self.path);
+    }
+}
+
+#[cfg(not(unix))]
+fn listen(_server: &Server, _socket: &Path) -> Result<()> {
+    Err(anyhow::anyhow!("--socket is only supported on Unix; use stdio instead"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::ReplacementRule;
+    use crate::testdir::TestDir;
+    use std::fs;
+
+    #[test]
+    fn test_handle() {
+        let args = Args {
+            rules: vec![ReplacementRule::new("foo_bar".to_string(), "baz_qux".to_string())],
+            case_enabled: true,
+            rename_file: true,
+            rename_dir: true,
+            ..Args::default()
+        };
+        let server = Server::new(&args);
+
+        assert_eq!(
+            server.handle(r#"{"jsonrpc":"2.0","id":1,"method":"replace-content","params":{"content":"let fooBar = 1;"}}"#).unwrap(),
+            r#"{"jsonrpc":"2.0","id":1,"result":{"changed":true,"content":"let bazQux = 1;"}}"#,
+        );
+        assert_eq!(
+            server.handle(r#"{"jsonrpc":"2.0","id":"a","method":"plan-rename","params":{"path":"src/foo_bar/FooBar.rs"}}"#).unwrap(),
+            r#"{"jsonrpc":"2.0","id":"a","result":{"path":"src/baz_qux/BazQux.rs"}}"#,
+        );
+
+        // Rules sent with a request replace the server's, and are kept for the next one
+        let request = r#"{"jsonrpc":"2.0","id":2,"method":"replace-content","params":{"content":"x","rules":[{"from":"x","to":"y"}]}}"#;
+        assert!(server.handle(request).unwrap().contains(r#""content":"y""#));
+        assert_eq!(server.rule_sets.lock().unwrap().len(), 1);
+
+        assert!(server.handle(r#"{"jsonrpc":"2.0","id":3,"method":"nope"}"#).unwrap().contains("-32601"));
+        assert!(server.handle("{").unwrap().contains("-32700"));
+        assert!(server.handle(r#"{"jsonrpc":"2.0","method":"replace-content","params":{"content":"x"}}"#).is_none());
+    }
+
+    #[test]
+    fn test_concurrent_applies_count_their_own() {
+        let dirs: Vec<TestDir> = (0..4).map(|i| TestDir::new(&format!("serve-apply-{}", i))).collect();
+        for (i, dir) in dirs.iter().enumerate() {
+            for file in 0..=i {
+                fs::write(dir.join(format!("{}.txt", file)), "foo foo\n").unwrap();
+            }
+        }
+
+        let args = Args {
+            rules: vec![ReplacementRule::new("foo".to_string(), "bar".to_string())],
+            ..Args::default()
+        };
+        let server = Server::new(&args);
+        let counts: Vec<String> = std::thread::scope(|scope| {
+            let handles: Vec<_> = dirs.iter().map(|dir| {
+                let server = &server;
+                let request = json::object([
+                    ("jsonrpc", Value::from("2.0")),
+                    ("id", Value::Number(1.0)),
+                    ("method", Value::from("apply")),
+                    ("params", json::object([("path", Value::from(dir.to_string_lossy().to_string()))])),
+                ]).to_compact_string();
+                scope.spawn(move || server.handle(&request).unwrap())
+            }).collect();
+            handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+        });
+
+        // Two replacements per file, however the requests interleave
+        for (i, response) in counts.iter().enumerate() {
+            assert!(response.contains(&format!(r#""replacements":{}"#, 2 * (i + 1))), "{}", response);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_remove_stale_socket() {
+        let dir = TestDir::new("serve-socket");
+        let socket = dir.join("mane.sock");
+        remove_stale_socket(&socket).unwrap();
+
+        // A socket whose server is gone is removed
+        drop(std::os::unix::net::UnixListener::bind(&socket).unwrap());
+        remove_stale_socket(&socket).unwrap();
+        assert!(!socket.exists());
+
+        // Anything else is left alone
+        let notes = dir.join("notes.txt");
+        fs::write(&notes, "notes\n").unwrap();
+        assert!(remove_stale_socket(&notes).is_err());
+        assert_eq!(fs::read_to_string(&notes).unwrap(), "notes\n");
+    }
+}
@@ -0,0 +1,114 @@
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+
+/// Check whether a file has holes, which copying it byte by byte would fill in
+///
+/// Sparse files are usually disk images; they are copied as binary without
+/// being read into memory.
+///
+/// # Arguments
+/// * `path` - File to check
+///
+/// # Returns
+/// * `bool` - true if part of the file isn't allocated on disk
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd"))]
+pub fn has_holes(path: &Path) -> bool {
+    use std::os::unix::io::AsRawFd;
+
+    let Ok(file) = File::open(path) else { return false };
+    let Ok(metadata) = file.metadata() else { return false };
+
+    // SAFETY: the descriptor is valid for the lifetime of the file
+    let hole = unsafe { libc::lseek(file.as_raw_fd(), 0, libc::SEEK_HOLE) };
+    metadata.is_file() && hole >= 0 && (hole as u64) < metadata.len()
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "freebsd")))]
+pub fn has_holes(_path: &Path) -> bool {
+    false
+}
+
+/// Copy a sparse file, writing only its data and leaving its holes unallocated
+///
+/// The data is streamed in chunks, so large images aren't held in memory.
+///
+/// # Arguments
+/// * `source` - Source file path
+/// * `target` - Target file path
+///
+/// # Returns
+/// * `io::Result<()>` - Ok if successful, Error otherwise
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd"))]
+pub fn copy(source: &Path, target: &Path) -> io::Result<()> {
+    use std::io::{Read, Seek, SeekFrom};
+    use std::os::unix::io::AsRawFd;
+
+    let mut src = File::open(source)?;
+    let metadata = src.metadata()?;
+    let len = metadata.len() as i64;
+    let mut dst = File::create(target)?;
+    dst.set_len(0)?;
+
+    let mut pos = 0;
+    while pos < len {
+        // SAFETY: the descriptor is valid for the lifetime of the file
+        let data = unsafe { libc::lseek(src.as_raw_fd(), pos, libc::SEEK_DATA) };
+        if data < 0 {
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() == Some(libc::ENXIO) {
+                break; // Only a hole is left
+            }
+            return Err(err);
+        }
+        // SAFETY: as above
+        let hole = unsafe { libc::lseek(src.as_raw_fd(), data, libc::SEEK_HOLE) };
+        if hole < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        src.seek(SeekFrom::Start(data as u64))?;
+        dst.seek(SeekFrom::Start(data as u64))?;
+        io::copy(&mut (&mut src).take((hole - data) as u64), &mut dst)?;
+        pos = hole;
+    }
+
+    // A hole at the end only exists as the file's length
+    dst.set_len(len as u64)?;
+    fs::set_permissions(target, metadata.permissions())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "freebsd")))]
+pub fn copy(source: &Path, target: &Path) -> io::Result<()> {
+    fs::copy(source, target).map(|_| ())
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::testdir::TestDir;
+    use std::io::{Seek, SeekFrom, Write};
+    use std::os::unix::fs::MetadataExt;
+
+    #[test]
+    fn test_copy_keeps_holes() {
+        let dir = TestDir::new("sparse");
+        let (source, target) = (dir.join("disk.img"), dir.join("copy.img"));
+
+        // 1 MiB of data in the middle of a 64 MiB file
+        let mut file = File::create(&source).unwrap();
+        file.seek(SeekFrom::Start(16 << 20)).unwrap();
+        file.write_all(&vec![7u8; 1 << 20]).unwrap();
+        file.set_len(64 << 20).unwrap();
+        drop(file);
+
+        // Not every filesystem (and not every temp dir) supports holes
+        if has_holes(&source) {
+            copy(&source, &target).unwrap();
+            assert!(has_holes(&target));
+            assert_eq!(fs::read(&source).unwrap(), fs::read(&target).unwrap());
+            assert!(fs::metadata(&target).unwrap().blocks() * 512 < 8 << 20);
+        }
+        assert!(!has_holes(&dir.join("missing")));
+    }
+}
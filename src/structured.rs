@@ -0,0 +1,747 @@
+use anyhow::{Result, anyhow};
+use clap::ValueEnum;
+use std::path::Path;
+
+/// Which scalars --structured replaces in
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum StructuredPart {
+    /// Mapping keys (including TOML table headers)
+    Keys,
+
+    /// String values
+    Values,
+}
+
+/// Structured file formats
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl Format {
+    /// Detect the format from a file extension
+    pub fn detect(path: &Path) -> Option<Format> {
+        let extension = path.extension()?.to_string_lossy().to_lowercase();
+        match extension.as_str() {
+            "json" => Some(Format::Json),
+            "yaml" | "yml" => Some(Format::Yaml),
+            "toml" => Some(Format::Toml),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Json => "JSON",
+            Format::Yaml => "YAML",
+            Format::Toml => "TOML",
+        }
+    }
+}
+
+/// How a scalar is quoted in the source text
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Quote {
+    /// Plain (bare) scalar
+    None,
+
+    /// Double-quoted: `\` and `"` must be escaped
+    Double,
+
+    /// Single-quoted YAML: `'` is doubled
+    Single,
+
+    /// TOML literal string: no escapes exist
+    Literal,
+}
+
+impl Quote {
+    /// Escape text inserted into a scalar with this quoting
+    pub fn escape(self, s: &str) -> String {
+        match self {
+            Quote::None | Quote::Literal => s.to_string(),
+            Quote::Double => s.replace('\\', "\\\\").replace('"', "\\\""),
+            Quote::Single => s.replace('\'', "''"),
+        }
+    }
+}
+
+/// One step of a path into a structured document
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+/// One step of a selector
+#[derive(Debug, Clone, PartialEq)]
+enum SelectorSegment {
+    Key(String),
+    Index(usize),
+    Any,
+}
+
+/// A JSONPath-like selector such as `$.dependencies.*` or `spec.containers[0].image`
+///
+/// Supports `.name`, `['name']`, `[N]`, `*` and `[*]`. A selector addresses a node
+/// together with everything below it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selector {
+    segments: Vec<SelectorSegment>,
+}
+
+impl Selector {
+    /// Parse a selector
+    ///
+    /// # Arguments
+    /// * `s` - Selector text, with or without the leading `$`
+    ///
+    /// # Returns
+    /// * `Result<Selector>` - The parsed selector
+    pub fn parse(s: &str) -> Result<Selector> {
+        let invalid = || anyhow!("Invalid path selector: {}", s);
+        let mut segments = Vec::new();
+        let mut rest = s.trim().strip_prefix('$').unwrap_or(s.trim());
+
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix('[') {
+                let end = after.find(']').ok_or_else(invalid)?;
+                let inner = after[..end].trim();
+                let segment = if inner == "*" {
+                    SelectorSegment::Any
+                } else if let Some(quoted) = inner.strip_prefix(['\'', '"']) {
+                    SelectorSegment::Key(quoted.strip_suffix(['\'', '"']).ok_or_else(invalid)?.to_string())
+                } else {
+                    SelectorSegment::Index(inner.parse().map_err(|_| invalid())?)
+                };
+                segments.push(segment);
+                rest = &after[end + 1..];
+                continue;
+            }
+
+            let name_start = rest.strip_prefix('.').unwrap_or(rest);
+            let end = name_start.find(['.', '[']).unwrap_or(name_start.len());
+            let name = &name_start[..end];
+            if name.is_empty() {
+                return Err(invalid());
+            }
+            segments.push(if name == "*" { SelectorSegment::Any } else { SelectorSegment::Key(name.to_string()) });
+            rest = &name_start[end..];
+        }
+
+        Ok(Selector { segments })
+    }
+
+    /// Check whether a node path is the selected node or below it
+    fn matches(&self, path: &[PathSegment]) -> bool {
+        path.len() >= self.segments.len()
+            && self.segments.iter().zip(path).all(|(selector, segment)| match (selector, segment) {
+                (SelectorSegment::Any, _) => true,
+                (SelectorSegment::Key(a), PathSegment::Key(b)) => a == b,
+                (SelectorSegment::Index(a), PathSegment::Index(b)) => a == b,
+                _ => false,
+            })
+    }
+}
+
+/// Which scalars of a structured document to replace in
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Selection<'a> {
+    /// Only keys or only values (both if None)
+    pub part: Option<StructuredPart>,
+
+    /// Only nodes under this selector (all if None)
+    pub selector: Option<&'a Selector>,
+}
+
+/// A key or value scalar in the source text (byte offsets of its content, without quotes)
+#[derive(Debug, Clone, PartialEq)]
+struct Span {
+    start: usize,
+    end: usize,
+    part: StructuredPart,
+    quote: Quote,
+
+    /// Path of the node the scalar belongs to (a key belongs to the entry it names)
+    path: Vec<PathSegment>,
+}
+
+/// Replace only inside selected keys or values of a structured document
+///
+/// Everything outside the selected scalars is copied verbatim, so formatting and
+/// comments are preserved.
+///
+/// # Arguments
+/// * `text` - Document text
+/// * `format` - Document format
+/// * `selection` - Which scalars to replace in
+/// * `replace` - Replacement for one scalar's content, given its quoting
+///
+/// # Returns
+/// * `Result<String>` - The document with replacements applied
+pub fn replace(
+    text: &str,
+    format: Format,
+    selection: Selection,
+    mut replace: impl FnMut(&str, Quote) -> Result<String>,
+) -> Result<String> {
+    let spans = match format {
+        Format::Json => json_spans(text)?,
+        Format::Toml => toml_spans(text)?,
+        Format::Yaml => yaml_spans(text),
+    };
+
+    let selected = spans.iter().filter(|span| {
+        selection.part.is_none_or(|part| span.part == part)
+            && selection.selector.is_none_or(|selector| selector.matches(&span.path))
+    });
+
+    let mut result = String::with_capacity(text.len());
+    let mut copied = 0;
+    for span in selected {
+        result.push_str(&text[copied..span.start]);
+        result.push_str(&replace(&text[span.start..span.end], span.quote)?);
+        copied = span.end;
+    }
+    result.push_str(&text[copied..]);
+
+    Ok(result)
+}
+
+/// Find the end of a quoted string starting after its opening quote
+///
+/// # Returns
+/// * `Option<usize>` - Offset of the closing quote
+fn find_closing_quote(text: &str, from: usize, quote: u8, escapes: bool) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut i = from;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' if escapes => i += 2,
+            b if b == quote => return Some(i),
+            b'\n' => return None,
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+/// An open `{...}` or `[...]` while scanning JSON, TOML inline values or YAML flow collections
+enum Frame {
+    /// Mapping, with the key of the current entry
+    Object(Option<String>),
+
+    /// Sequence, with the index of the current item
+    Array(usize),
+}
+
+/// Path of the current position given the open frames
+fn frames_path(base: &[PathSegment], frames: &[Frame]) -> Vec<PathSegment> {
+    let mut path = base.to_vec();
+    for frame in frames {
+        match frame {
+            Frame::Object(Some(key)) => path.push(PathSegment::Key(key.clone())),
+            Frame::Object(None) => {},
+            Frame::Array(index) => path.push(PathSegment::Index(*index)),
+        }
+    }
+    path
+}
+
+fn json_spans(text: &str) -> Result<Vec<Span>> {
+    let bytes = text.as_bytes();
+    let mut spans = Vec::new();
+    let mut frames: Vec<Frame> = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'{' => frames.push(Frame::Object(None)),
+            b'[' => frames.push(Frame::Array(0)),
+            b'}' | b']' => {
+                frames.pop();
+            },
+            b',' => match frames.last_mut() {
+                Some(Frame::Array(index)) => *index += 1,
+                Some(Frame::Object(key)) => *key = None,
+                None => {},
+            },
+            b'"' => {
+                let end = find_closing_quote(text, i + 1, b'"', true)
+                    .ok_or_else(|| anyhow!("Unterminated string at byte {}", i))?;
+                let is_key = text[end + 1..].trim_start().starts_with(':');
+                if is_key {
+                    if let Some(Frame::Object(key)) = frames.last_mut() {
+                        *key = Some(text[i + 1..end].to_string());
+                    }
+                }
+                spans.push(Span {
+                    start: i + 1,
+                    end,
+                    part: if is_key { StructuredPart::Keys } else { StructuredPart::Values },
+                    quote: Quote::Double,
+                    path: frames_path(&[], &frames),
+                });
+                i = end + 1;
+                continue;
+            },
+            _ => {},
+        }
+        i += 1;
+    }
+
+    Ok(spans)
+}
+
+fn is_bare_key_char(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b == b'-'
+}
+
+fn toml_spans(text: &str) -> Result<Vec<Span>> {
+    let bytes = text.as_bytes();
+    let mut spans = Vec::new();
+
+    // Path of the current [table], the dotted key of the current line, and open inline values
+    let mut table_path: Vec<PathSegment> = Vec::new();
+    let mut line_keys: Vec<String> = Vec::new();
+    let mut frames: Vec<Frame> = Vec::new();
+    let mut inline_keys: Vec<Vec<String>> = Vec::new();
+    let mut array_table_counts: std::collections::HashMap<String, usize> = std::collections::HashMap::new();
+
+    let mut header: Option<(Vec<String>, bool)> = None; // keys so far, [[array of tables]]
+    let mut expect_key = true;
+    let mut line_start = true;
+    let mut i = 0;
+
+    // Path of the current position (keys included)
+    let current_path = |table_path: &[PathSegment], line_keys: &[String], frames: &[Frame], inline_keys: &[Vec<String>]| {
+        let mut path = table_path.to_vec();
+        path.extend(line_keys.iter().cloned().map(PathSegment::Key));
+        let mut inline = inline_keys.iter();
+        for frame in frames {
+            match frame {
+                Frame::Array(index) => path.push(PathSegment::Index(*index)),
+                Frame::Object(_) => {
+                    if let Some(keys) = inline.next() {
+                        path.extend(keys.iter().cloned().map(PathSegment::Key));
+                    }
+                },
+            }
+        }
+        path
+    };
+
+    while i < bytes.len() {
+        let b = bytes[i];
+        let in_key = expect_key || header.is_some();
+
+        // Scalars: quoted strings, or bare keys
+        let scalar = match b {
+            b'"' | b'\'' => {
+                let quote = if b == b'"' { Quote::Double } else { Quote::Literal };
+                let delimiter = if b == b'"' { "\"\"\"" } else { "'''" };
+                if text[i..].starts_with(delimiter) {
+                    // Multi-line string
+                    let end = text[i + 3..].find(delimiter)
+                        .map(|n| i + 3 + n)
+                        .ok_or_else(|| anyhow!("Unterminated multi-line string at byte {}", i))?;
+                    Some((i + 3, end, end + 3, quote))
+                } else {
+                    let end = find_closing_quote(text, i + 1, b, b == b'"')
+                        .ok_or_else(|| anyhow!("Unterminated string at byte {}", i))?;
+                    Some((i + 1, end, end + 1, quote))
+                }
+            },
+            _ if is_bare_key_char(b) => {
+                let end = bytes[i..].iter().position(|&c| !is_bare_key_char(c)).map_or(bytes.len(), |n| i + n);
+                Some((i, end, end, Quote::None))
+            },
+            _ => None,
+        };
+
+        if let Some((start, end, next, quote)) = scalar {
+            let name = text[start..end].to_string();
+            if let Some((keys, _)) = header.as_mut() {
+                keys.push(name);
+                let path = keys.iter().cloned().map(PathSegment::Key).collect();
+                spans.push(Span { start, end, part: StructuredPart::Keys, quote, path });
+            } else if in_key {
+                match inline_keys.last_mut().filter(|_| matches!(frames.last(), Some(Frame::Object(_)))) {
+                    Some(keys) => keys.push(name),
+                    None => line_keys.push(name),
+                }
+                let path = current_path(&table_path, &line_keys, &frames, &inline_keys);
+                spans.push(Span { start, end, part: StructuredPart::Keys, quote, path });
+            } else if quote != Quote::None {
+                // Bare values (numbers, booleans, dates) are never replaced
+                let path = current_path(&table_path, &line_keys, &frames, &inline_keys);
+                spans.push(Span { start, end, part: StructuredPart::Values, quote, path });
+            }
+            i = next;
+            line_start = false;
+            continue;
+        }
+
+        match b {
+            b'\n' => {
+                if frames.is_empty() {
+                    expect_key = true;
+                    line_keys.clear();
+                }
+                line_start = true;
+                i += 1;
+                continue;
+            },
+            b' ' | b'\t' | b'\r' => {
+                i += 1;
+                continue;
+            },
+            b'#' => {
+                i = text[i..].find('\n').map_or(bytes.len(), |n| i + n);
+                continue;
+            },
+            b'[' if line_start && frames.is_empty() => {
+                // Table header: [a.b] or [[a.b]]
+                let array = text[i..].starts_with("[[");
+                header = Some((Vec::new(), array));
+                i += if array { 2 } else { 1 };
+                line_start = false;
+                continue;
+            },
+            b']' if header.is_some() => {
+                let (keys, array) = header.take().unwrap_or_default();
+                table_path = keys.iter().cloned().map(PathSegment::Key).collect();
+                if array {
+                    let count = array_table_counts.entry(keys.join(".")).or_insert(0);
+                    table_path.push(PathSegment::Index(*count));
+                    *count += 1;
+                }
+                expect_key = false;
+                i += if array { 2 } else { 1 };
+                line_start = false;
+                continue;
+            },
+            b'[' => {
+                frames.push(Frame::Array(0));
+                expect_key = false;
+            },
+            b'{' => {
+                frames.push(Frame::Object(None));
+                inline_keys.push(Vec::new());
+                expect_key = true;
+            },
+            b']' => {
+                frames.pop();
+            },
+            b'}' => {
+                frames.pop();
+                inline_keys.pop();
+                expect_key = false;
+            },
+            b',' => match frames.last_mut() {
+                Some(Frame::Array(index)) => *index += 1,
+                Some(Frame::Object(_)) => {
+                    if let Some(keys) = inline_keys.last_mut() {
+                        keys.clear();
+                    }
+                    expect_key = true;
+                },
+                None => {},
+            },
+            b'=' => expect_key = false,
+            _ => {},
+        }
+        i += 1;
+        line_start = false;
+    }
+
+    Ok(spans)
+}
+
+fn yaml_spans(text: &str) -> Vec<Span> {
+    let mut spans = Vec::new();
+
+    // Open block nodes as (indent, segment)
+    let mut nodes: Vec<(usize, PathSegment)> = Vec::new();
+    let mut block: Option<(usize, Vec<PathSegment>)> = None;
+    let mut offset = 0;
+
+    let path_of = |nodes: &[(usize, PathSegment)]| nodes.iter().map(|(_, s)| s.clone()).collect::<Vec<_>>();
+
+    for line in text.split_inclusive('\n') {
+        let line_offset = offset;
+        offset += line.len();
+        let content = line.trim_end_matches(['\n', '\r']);
+        let indent = content.len() - content.trim_start().len();
+
+        // Lines of a literal/folded block scalar are values
+        if let Some((parent_indent, path)) = &block {
+            if content.trim().is_empty() {
+                continue;
+            }
+            if indent > *parent_indent {
+                spans.push(Span {
+                    start: line_offset + indent,
+                    end: line_offset + content.len(),
+                    part: StructuredPart::Values,
+                    quote: Quote::None,
+                    path: path.clone(),
+                });
+                continue;
+            }
+            block = None;
+        }
+
+        let trimmed = content.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with('%')
+            || trimmed.starts_with("---") || trimmed.starts_with("...") {
+            if trimmed.starts_with("---") {
+                nodes.clear();
+            }
+            continue;
+        }
+
+        // Sequence indicators ("- - item") open or advance sequence items
+        let mut pos = indent;
+        while content[pos..].starts_with("- ") || &content[pos..] == "-" {
+            while nodes.last().is_some_and(|(node_indent, _)| *node_indent > pos) {
+                nodes.pop();
+            }
+            match nodes.last_mut() {
+                Some((node_indent, PathSegment::Index(index))) if *node_indent == pos => *index += 1,
+                _ => nodes.push((pos, PathSegment::Index(0))),
+            }
+            pos += 1;
+            pos += content[pos..].len() - content[pos..].trim_start().len();
+        }
+
+        let Some((scalar, after_scalar)) = yaml_scalar(content, pos, true) else {
+            if content[pos..].starts_with(['{', '[']) {
+                yaml_flow_spans(content, pos, line_offset, &path_of(&nodes), &mut spans);
+            }
+            continue;
+        };
+        let absolute = |(start, end, quote): (usize, usize, Quote), part, path| Span {
+            start: line_offset + start,
+            end: line_offset + end,
+            part,
+            quote,
+            path,
+        };
+
+        let rest = &content[after_scalar..];
+        if let Some(value) = rest.strip_prefix(':').filter(|r| r.is_empty() || r.starts_with([' ', '\t'])) {
+            // A mapping entry at column `pos`
+            while nodes.last().is_some_and(|(node_indent, _)| *node_indent >= pos) {
+                nodes.pop();
+            }
+            let key = content[scalar.0..scalar.1].to_string();
+            nodes.push((pos, PathSegment::Key(key)));
+            let path = path_of(&nodes);
+            spans.push(absolute(scalar, StructuredPart::Keys, path.clone()));
+
+            pos = content.len() - value.trim_start().len();
+
+            // Skip anchors and tags before the value
+            while content[pos..].starts_with(['&', '!']) {
+                pos = content[pos..].find(' ').map_or(content.len(), |n| pos + n);
+                pos += content[pos..].len() - content[pos..].trim_start().len();
+            }
+
+            if content[pos..].starts_with(['|', '>']) {
+                block = Some((indent, path));
+            } else if content[pos..].starts_with(['{', '[']) {
+                yaml_flow_spans(content, pos, line_offset, &path, &mut spans);
+            } else if let Some((value, _)) = yaml_scalar(content, pos, false) {
+                spans.push(absolute(value, StructuredPart::Values, path));
+            }
+        } else {
+            // A sequence item or continuation line
+            spans.push(absolute(scalar, StructuredPart::Values, path_of(&nodes)));
+        }
+    }
+
+    spans
+}
+
+/// Read a YAML scalar at `pos`, returning its content (start, end, quoting) and where it ends
+///
+/// Plain scalars end at ` #` or the end of the line; in key position (`as_key`) they also
+/// end at `: ` or a trailing `:`.
+fn yaml_scalar(line: &str, pos: usize, as_key: bool) -> Option<((usize, usize, Quote), usize)> {
+    let bytes = line.as_bytes();
+    let first = *bytes.get(pos)?;
+
+    if first == b'"' || first == b'\'' {
+        let end = find_closing_quote(line, pos + 1, first, first == b'"')?;
+        let quote = if first == b'"' { Quote::Double } else { Quote::Single };
+        return Some(((pos + 1, end, quote), end + 1));
+    }
+    if matches!(first, b'*' | b'#' | b'{' | b'[') {
+        return None;
+    }
+
+    let mut end = line.len();
+    for (i, c) in line[pos..].char_indices() {
+        let at = pos + i;
+        let next = line[at + 1..].chars().next();
+        if c == ':' && (next.is_none() || next == Some(' ') || next == Some('\t')) && as_key {
+            end = at;
+            break;
+        }
+        if c == '#' && at > pos && line[..at].ends_with([' ', '\t']) {
+            end = at;
+            break;
+        }
+    }
+    let value = line[pos..end].trim_end();
+    if value.is_empty() {
+        return None;
+    }
+    Some(((pos, pos + value.len(), Quote::None), pos + value.len()))
+}
+
+/// Spans of a single-line flow collection (`{a: b, c: [d]}`)
+fn yaml_flow_spans(line: &str, pos: usize, line_offset: usize, base: &[PathSegment], spans: &mut Vec<Span>) {
+    let bytes = line.as_bytes();
+    let mut frames: Vec<Frame> = Vec::new();
+    let mut i = pos;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'{' => frames.push(Frame::Object(None)),
+            b'[' => frames.push(Frame::Array(0)),
+            b'}' | b']' => {
+                frames.pop();
+                if frames.is_empty() {
+                    break;
+                }
+            },
+            b',' => match frames.last_mut() {
+                Some(Frame::Array(index)) => *index += 1,
+                Some(Frame::Object(key)) => *key = None,
+                None => {},
+            },
+            b' ' | b'\t' | b':' => {},
+            b'#' => break,
+            b => {
+                let (start, end, next, quote) = if b == b'"' || b == b'\'' {
+                    let Some(end) = find_closing_quote(line, i + 1, b, b == b'"') else { break };
+                    let quote = if b == b'"' { Quote::Double } else { Quote::Single };
+                    (i + 1, end, end + 1, quote)
+                } else {
+                    let end = bytes[i..].iter()
+                        .position(|&c| matches!(c, b',' | b'}' | b']' | b':'))
+                        .map_or(bytes.len(), |n| i + n);
+                    (i, i + line[i..end].trim_end().len(), end, Quote::None)
+                };
+
+                let is_key = line[next..].trim_start().starts_with(':');
+                if is_key {
+                    if let Some(Frame::Object(key)) = frames.last_mut() {
+                        *key = Some(line[start..end].to_string());
+                    }
+                }
+                spans.push(Span {
+                    start: line_offset + start,
+                    end: line_offset + end,
+                    part: if is_key { StructuredPart::Keys } else { StructuredPart::Values },
+                    quote,
+                    path: frames_path(base, &frames),
+                });
+                i = next;
+                continue;
+            },
+        }
+        i += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn upper(text: &str, format: Format, part: StructuredPart) -> String {
+        let selection = Selection { part: Some(part), selector: None };
+        replace(text, format, selection, |s, _| Ok(s.to_uppercase())).unwrap()
+    }
+
+    fn upper_selected(text: &str, format: Format, selector: &str) -> String {
+        let selector = Selector::parse(selector).unwrap();
+        let selection = Selection { part: None, selector: Some(&selector) };
+        replace(text, format, selection, |s, _| Ok(s.to_uppercase())).unwrap()
+    }
+
+    #[test]
+    fn test_json() {
+        let text = "{\n  \"name\": \"name\",\n  \"list\": [\"a\", 1]\n}\n";
+        assert_eq!(upper(text, Format::Json, StructuredPart::Keys), "{\n  \"NAME\": \"name\",\n  \"LIST\": [\"a\", 1]\n}\n");
+        assert_eq!(upper(text, Format::Json, StructuredPart::Values), "{\n  \"name\": \"NAME\",\n  \"list\": [\"A\", 1]\n}\n");
+    }
+
+    #[test]
+    fn test_toml() {
+        let text = "# name\n[package.name]\nname = \"name\" # name\n\"q\" = { name = 'name', list = [\"name\"] }\nn = 1\n";
+        assert_eq!(
+            upper(text, Format::Toml, StructuredPart::Keys),
+            "# name\n[PACKAGE.NAME]\nNAME = \"name\" # name\n\"Q\" = { NAME = 'name', LIST = [\"name\"] }\nN = 1\n"
+        );
+        assert_eq!(
+            upper(text, Format::Toml, StructuredPart::Values),
+            "# name\n[package.name]\nname = \"NAME\" # name\n\"q\" = { name = 'NAME', list = [\"NAME\"] }\nn = 1\n"
+        );
+    }
+
+    #[test]
+    fn test_yaml() {
+        let text = "# name: name\nname: name # name\nlist:\n  - name\n  - \"key\": 'name'\nflow: {name: name}\nblock: |\n  name\nurl: http://x\n";
+        assert_eq!(
+            upper(text, Format::Yaml, StructuredPart::Keys),
+            "# name: name\nNAME: name # name\nLIST:\n  - name\n  - \"KEY\": 'name'\nFLOW: {NAME: name}\nBLOCK: |\n  name\nURL: http://x\n"
+        );
+        assert_eq!(
+            upper(text, Format::Yaml, StructuredPart::Values),
+            "# name: name\nname: NAME # name\nlist:\n  - NAME\n  - \"key\": 'NAME'\nflow: {name: NAME}\nblock: |\n  NAME\nurl: HTTP://X\n"
+        );
+    }
+
+    #[test]
+    fn test_selectors() {
+        let json = "{\"dependencies\": {\"lib\": \"1.0\"}, \"devDependencies\": {\"lib\": \"1.0\"}, \"list\": [\"a\", \"b\"]}";
+        assert_eq!(
+            upper_selected(json, Format::Json, "$.dependencies.*"),
+            "{\"dependencies\": {\"LIB\": \"1.0\"}, \"devDependencies\": {\"lib\": \"1.0\"}, \"list\": [\"a\", \"b\"]}"
+        );
+        assert_eq!(
+            upper_selected(json, Format::Json, "$.list[1]"),
+            "{\"dependencies\": {\"lib\": \"1.0\"}, \"devDependencies\": {\"lib\": \"1.0\"}, \"list\": [\"a\", \"B\"]}"
+        );
+
+        let yaml = "spec:\n  labels:\n    app: web\n  containers:\n    - name: web\n      image: web\n    - name: web\nother: web\n";
+        assert_eq!(
+            upper_selected(yaml, Format::Yaml, "spec.labels"),
+            "spec:\n  LABELS:\n    APP: WEB\n  containers:\n    - name: web\n      image: web\n    - name: web\nother: web\n"
+        );
+        assert_eq!(
+            upper_selected(yaml, Format::Yaml, "spec.containers[0].image"),
+            "spec:\n  labels:\n    app: web\n  containers:\n    - name: web\n      IMAGE: WEB\n    - name: web\nother: web\n"
+        );
+
+        let toml = "[dependencies]\nlib = \"1\"\n[[bin]]\nname = \"a\"\n[[bin]]\nname = \"b\"\n";
+        assert_eq!(
+            upper_selected(toml, Format::Toml, "bin[1].name"),
+            "[dependencies]\nlib = \"1\"\n[[bin]]\nname = \"a\"\n[[bin]]\nNAME = \"B\"\n"
+        );
+
+        assert!(Selector::parse("$.a[").is_err());
+        assert_eq!(Selector::parse("$['a.b'][*]").unwrap(), Selector::parse("['a.b'].*").unwrap());
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(Quote::Double.escape("a\"b\\"), "a\\\"b\\\\");
+        assert_eq!(Quote::Single.escape("it's"), "it''s");
+    }
+}
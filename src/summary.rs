@@ -0,0 +1,74 @@
+use crate::binary;
+use crate::logger;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Something a run did, counted for the summary (--summary)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    /// A file's content was modified
+    Modified,
+
+    /// A file or directory was renamed
+    Renamed,
+
+    /// A file was copied
+    Copied,
+
+    /// A change was left out, such as a rename whose target exists
+    Skipped,
+
+    /// Something failed
+    Error,
+
+    /// A directory couldn't be walked
+    WalkError,
+}
+
+static COUNTS: [AtomicUsize; 6] = [const { AtomicUsize::new(0) }; 6];
+
+/// Count an event
+pub fn record(event: Event) {
+    COUNTS[event as usize].fetch_add(1, Ordering::Relaxed);
+}
+
+fn count(event: Event) -> usize {
+    COUNTS[event as usize].load(Ordering::Relaxed)
+}
+
+/// The counts of the run as one block
+///
+/// Files skipped as binary are counted with the other skipped changes.
+///
+/// # Returns
+/// * `String` - One line per count
+pub fn render() -> String {
+    let lines = [
+        ("Files changed", count(Event::Modified)),
+        ("Renamed", count(Event::Renamed)),
+        ("Copied", count(Event::Copied)),
+        ("Skipped", count(Event::Skipped) + binary::skipped_count()),
+        ("Warnings", logger::warnings()),
+        ("Walk errors", count(Event::WalkError)),
+        ("Errors", count(Event::Error)),
+    ];
+    let width = lines.iter().map(|(label, _)| label.len()).max().unwrap_or(0) + 1;
+    let mut block = String::from("Summary:\n");
+    for (label, n) in lines {
+        block.push_str(&format!("  {:width$} {}\n", format!("{}:", label), n, width = width));
+    }
+    block
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        record(Event::Renamed);
+        let block = render();
+        assert!(block.starts_with("Summary:\n  Files changed: "), "{}", block);
+        assert!(block.contains("\n  Renamed:       "), "{}", block);
+        assert_eq!(block.lines().count(), 8);
+    }
+}
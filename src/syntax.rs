@@ -0,0 +1,580 @@
+// Source code is split into code, string and comment pieces by a lexer
+// driven by the per-language tables below. It is a heuristic, not a parser:
+// it knows how each language opens and closes its comments and strings
+// (nesting comments, raw strings, interpolations), and nothing else. With the
+// `tree-sitter` feature, Rust, JavaScript/TypeScript, Python and Go are
+// parsed by their grammars instead (see grammar.rs).
+
+use anyhow::Result;
+use std::path::Path;
+
+/// How string literals of a language start and end
+struct StringSyntax {
+    open: &'static str,
+    close: &'static str,
+
+    /// Whether `\` escapes the next character
+    escapes: bool,
+
+    /// Whether this is a character literal (one character, so `'a` lifetimes aren't strings)
+    char_literal: bool,
+
+    /// Delimiters of code embedded in the string, such as `${` and `}`
+    interpolation: Option<(&'static str, &'static str)>,
+
+    /// Letters one of which must prefix the string for it to interpolate, such as Python's `f`
+    interpolation_prefix: &'static str,
+}
+
+const fn string(open: &'static str, close: &'static str, escapes: bool) -> StringSyntax {
+    StringSyntax { open, close, escapes, char_literal: false, interpolation: None, interpolation_prefix: "" }
+}
+
+const fn char_literal(quote: &'static str) -> StringSyntax {
+    StringSyntax { open: quote, close: quote, escapes: true, char_literal: true, interpolation: None, interpolation_prefix: "" }
+}
+
+/// A string with code between `interpolation` delimiters, when prefixed by one of `prefix` (if any)
+const fn interpolated(open: &'static str, close: &'static str, interpolation: (&'static str, &'static str), prefix: &'static str) -> StringSyntax {
+    StringSyntax { open, close, escapes: true, char_literal: false, interpolation: Some(interpolation), interpolation_prefix: prefix }
+}
+
+/// Comment and string syntax of a programming language
+pub struct Language {
+    pub name: &'static str,
+    extensions: &'static [&'static str],
+    line_comments: &'static [&'static str],
+    block_comments: &'static [(&'static str, &'static str)],
+
+    /// Whether a block comment may hold another one, as `/* a /* b */ c */` does in Rust
+    nested_comments: bool,
+
+    /// Longer openers first, as the first match wins
+    strings: &'static [StringSyntax],
+}
+
+// JavaScript, TypeScript and TSX only differ in their tree-sitter grammars
+const JAVASCRIPT_STRINGS: &[StringSyntax] = &[string("\"", "\"", true), string("'", "'", true), interpolated("`", "`", ("${", "}"), "")];
+
+const LANGUAGES: &[Language] = &[
+    Language {
+        name: "Rust",
+        extensions: &["rs"],
+        line_comments: &["//"],
+        block_comments: &[("/*", "*/")],
+        nested_comments: true,
+        strings: &[
+            string("r##\"", "\"##", false),
+            string("r#\"", "\"#", false),
+            string("r\"", "\"", false),
+            string("\"", "\"", true),
+            char_literal("'"),
+        ],
+    },
+    Language {
+        name: "JavaScript",
+        extensions: &["js", "jsx", "mjs", "cjs"],
+        line_comments: &["//"],
+        block_comments: &[("/*", "*/")],
+        nested_comments: false,
+        strings: JAVASCRIPT_STRINGS,
+    },
+    Language {
+        name: "TypeScript",
+        extensions: &["ts", "mts", "cts"],
+        line_comments: &["//"],
+        block_comments: &[("/*", "*/")],
+        nested_comments: false,
+        strings: JAVASCRIPT_STRINGS,
+    },
+    Language {
+        name: "TSX",
+        extensions: &["tsx"],
+        line_comments: &["//"],
+        block_comments: &[("/*", "*/")],
+        nested_comments: false,
+        strings: JAVASCRIPT_STRINGS,
+    },
+    Language {
+        name: "Python",
+        extensions: &["py", "pyi"],
+        line_comments: &["#"],
+        block_comments: &[],
+        nested_comments: false,
+        strings: &[
+            interpolated("\"\"\"", "\"\"\"", ("{", "}"), "fF"),
+            interpolated("'''", "'''", ("{", "}"), "fF"),
+            interpolated("\"", "\"", ("{", "}"), "fF"),
+            interpolated("'", "'", ("{", "}"), "fF"),
+        ],
+    },
+    Language {
+        name: "Go",
+        extensions: &["go"],
+        line_comments: &["//"],
+        block_comments: &[("/*", "*/")],
+        nested_comments: false,
+        strings: &[string("\"", "\"", true), string("`", "`", false), char_literal("'")],
+    },
+    Language {
+        name: "C/C++",
+        extensions: &["c", "h", "cc", "cpp", "cxx", "hh", "hpp", "hxx", "m", "mm"],
+        line_comments: &["//"],
+        block_comments: &[("/*", "*/")],
+        nested_comments: false,
+        strings: &[string("\"", "\"", true), char_literal("'")],
+    },
+    Language {
+        name: "Java/C#",
+        extensions: &["java", "cs"],
+        line_comments: &["//"],
+        block_comments: &[("/*", "*/")],
+        nested_comments: false,
+        strings: &[string("\"\"\"", "\"\"\"", true), string("\"", "\"", true), char_literal("'")],
+    },
+    Language {
+        name: "Kotlin/Scala/Swift",
+        extensions: &["kt", "kts", "scala", "sc", "swift"],
+        line_comments: &["//"],
+        block_comments: &[("/*", "*/")],
+        nested_comments: true,
+        strings: &[string("\"\"\"", "\"\"\"", false), string("\"", "\"", true), char_literal("'")],
+    },
+    Language {
+        name: "PHP",
+        extensions: &["php"],
+        line_comments: &["//", "#"],
+        block_comments: &[("/*", "*/")],
+        nested_comments: false,
+        strings: &[string("\"", "\"", true), string("'", "'", true)],
+    },
+    Language {
+        name: "Ruby",
+        extensions: &["rb", "rake", "gemspec"],
+        line_comments: &["#"],
+        block_comments: &[("=begin", "=end")],
+        nested_comments: false,
+        strings: &[string("\"", "\"", true), string("'", "'", true)],
+    },
+    Language {
+        name: "Shell",
+        extensions: &["sh", "bash", "zsh", "fish"],
+        line_comments: &["#"],
+        block_comments: &[],
+        nested_comments: false,
+        strings: &[string("\"", "\"", true), string("'", "'", false)],
+    },
+    Language {
+        name: "Perl",
+        extensions: &["pl", "pm"],
+        line_comments: &["#"],
+        block_comments: &[],
+        nested_comments: false,
+        strings: &[string("\"", "\"", true), string("'", "'", true)],
+    },
+    Language {
+        name: "Lua",
+        extensions: &["lua"],
+        line_comments: &["--"],
+        block_comments: &[("--[[", "]]")],
+        nested_comments: false,
+        strings: &[string("[[", "]]", false), string("\"", "\"", true), string("'", "'", true)],
+    },
+    Language {
+        name: "SQL",
+        extensions: &["sql"],
+        line_comments: &["--"],
+        block_comments: &[("/*", "*/")],
+        nested_comments: false,
+        strings: &[string("'", "'", false), string("\"", "\"", false)],
+    },
+    Language {
+        name: "Haskell",
+        extensions: &["hs"],
+        line_comments: &["--"],
+        block_comments: &[("{-", "-}")],
+        nested_comments: true,
+        strings: &[string("\"", "\"", true), char_literal("'")],
+    },
+    Language {
+        name: "CSS",
+        extensions: &["css", "scss", "sass", "less"],
+        line_comments: &[],
+        block_comments: &[("/*", "*/")],
+        nested_comments: false,
+        strings: &[string("\"", "\"", true), string("'", "'", true)],
+    },
+    Language {
+        name: "HTML/XML",
+        extensions: &["html", "htm", "xhtml", "xml", "svg", "vue", "svelte"],
+        line_comments: &[],
+        block_comments: &[("<!--", "-->")],
+        nested_comments: false,
+        strings: &[],
+    },
+    Language {
+        name: "YAML/TOML/INI",
+        extensions: &["yaml", "yml", "toml", "ini", "cfg", "conf"],
+        line_comments: &["#"],
+        block_comments: &[],
+        nested_comments: false,
+        strings: &[string("\"", "\"", true), string("'", "'", false)],
+    },
+];
+
+impl Language {
+    /// Find the language of a file by its extension
+    ///
+    /// # Arguments
+    /// * `path` - Path of the file
+    ///
+    /// # Returns
+    /// * `Option<&'static Language>` - The language, or None if it isn't supported
+    pub fn detect(path: &Path) -> Option<&'static Language> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        LANGUAGES.iter().find(|lang| lang.extensions.contains(&ext.as_str()))
+    }
+}
+
+/// What a piece of source code is
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Token {
+    Code,
+    String,
+    Comment,
+}
+
+/// Split source code into code, string and comment pieces
+///
+/// The code interpolated into a string, like the `x` of `${x}`, is a code piece
+/// between the string's pieces. With the `tree-sitter` feature, languages
+/// with a grammar are parsed instead, unless the text doesn't parse.
+///
+/// # Returns
+/// * `Vec<(usize, usize, Token)>` - Consecutive pieces covering the whole text
+fn tokens(text: &str, lang: &Language) -> Vec<(usize, usize, Token)> {
+    #[cfg(feature = "tree-sitter")]
+    if let Some(pieces) = crate::grammar::tokens(text, lang.name) {
+        return pieces;
+    }
+
+    lex(text, lang)
+}
+
+/// Split source code into code, string and comment pieces with the language's syntax table
+///
+/// # Returns
+/// * `Vec<(usize, usize, Token)>` - Consecutive pieces covering the whole text
+fn lex(text: &str, lang: &Language) -> Vec<(usize, usize, Token)> {
+    let mut pieces = Vec::new();
+    let mut code_start = 0;
+    let mut i = 0;
+
+    let push = |pieces: &mut Vec<(usize, usize, Token)>, start: usize, end: usize, token: Token| {
+        if start < end {
+            pieces.push((start, end, token));
+        }
+    };
+
+    while i < text.len() {
+        let rest = &text[i..];
+
+        // Block comments first, as they may start like a line comment (`--[[` in Lua)
+        let literal = if let Some((open, close)) = lang.block_comments.iter().find(|(open, _)| rest.starts_with(open)) {
+            Some((block_comment_end(rest, open, close, lang.nested_comments), Vec::new(), Token::Comment))
+        } else if let Some(prefix) = lang.line_comments.iter().find(|p| rest.starts_with(**p)) {
+            Some((rest.find('\n').unwrap_or(rest.len()).max(prefix.len()), Vec::new(), Token::Comment))
+        } else {
+            // Inside an identifier, `r"` is not a raw string and `'` is a lifetime, not a character
+            let prefix = identifier_before(text, i);
+            lang.strings.iter()
+                .filter(|s| rest.starts_with(s.open))
+                .filter(|s| prefix.is_empty() || !(s.char_literal || s.open.starts_with(char::is_alphanumeric)))
+                .find_map(|s| string_end(rest, s, interpolates(s, prefix), lang))
+                .map(|(end, code)| (end, code, Token::String))
+        };
+
+        match literal {
+            Some((len, code, token)) => {
+                push(&mut pieces, code_start, i, Token::Code);
+                let mut start = i;
+                for (from, to) in code {
+                    let (from, to) = (i + from, i + to);
+                    push(&mut pieces, start, from, token);
+                    // Interpolated code is lexed like any other, strings and all
+                    for (piece_start, piece_end, piece) in lex(&text[from..to], lang) {
+                        push(&mut pieces, from + piece_start, from + piece_end, piece);
+                    }
+                    start = to;
+                }
+                push(&mut pieces, start, i + len, token);
+                i += len;
+                code_start = i;
+            },
+            None => i += rest.chars().next().map_or(1, char::len_utf8),
+        }
+    }
+    push(&mut pieces, code_start, text.len(), Token::Code);
+
+    pieces
+}
+
+/// Length of a block comment at the start of `rest`, counting the comments inside it if they nest
+fn block_comment_end(rest: &str, open: &str, close: &str, nested: bool) -> usize {
+    let mut depth = 0;
+    let mut i = 0;
+    while i < rest.len() {
+        if rest[i..].starts_with(open) && (nested || depth == 0) {
+            depth += 1;
+            i += open.len();
+        } else if rest[i..].starts_with(close) {
+            depth -= 1;
+            i += close.len();
+            if depth == 0 {
+                return i;
+            }
+        } else {
+            i += rest[i..].chars().next().map_or(1, char::len_utf8);
+        }
+    }
+
+    // Unterminated: the rest of the file is part of the comment
+    rest.len()
+}
+
+/// The identifier that ends at `i`, such as the `f` of `f"..."`
+fn identifier_before(text: &str, i: usize) -> &str {
+    let start = text[..i].char_indices().rev()
+        .take_while(|(_, c)| is_identifier_char(*c))
+        .last()
+        .map_or(i, |(n, _)| n);
+    &text[start..i]
+}
+
+/// Whether a string with the given identifier right before it has interpolations
+fn interpolates(syntax: &StringSyntax, prefix: &str) -> bool {
+    syntax.interpolation.is_some()
+        && (syntax.interpolation_prefix.is_empty()
+            || (prefix.len() <= 2 && prefix.contains(|c| syntax.interpolation_prefix.contains(c))))
+}
+
+/// Length of a string literal at the start of `rest`, if it is one
+///
+/// # Returns
+/// * `Option<(usize, Vec<(usize, usize)>)>` - Its length, and where the code of
+///   each interpolation starts and ends in it
+fn string_end(rest: &str, syntax: &StringSyntax, interpolates: bool, lang: &Language) -> Option<(usize, Vec<(usize, usize)>)> {
+    let body = &rest[syntax.open.len()..];
+
+    if syntax.char_literal {
+        // A single (possibly escaped) character: `'a` followed by more is a lifetime or label
+        let len = if body.starts_with('\\') {
+            2 + body.get(2..)?.find(syntax.close)?
+        } else {
+            body.chars().next()?.len_utf8()
+        };
+        let literal = len <= 12 && !body[..len].contains('\n') && body[len..].starts_with(syntax.close);
+        return literal.then_some((syntax.open.len() + len + syntax.close.len(), Vec::new()));
+    }
+
+    let mut code = Vec::new();
+    let mut n = 0;
+    while n < body.len() {
+        let at = &body[n..];
+        if syntax.escapes && at.starts_with('\\') {
+            n += 1 + at[1..].chars().next().map_or(0, char::len_utf8);
+            continue;
+        }
+        if at.starts_with(syntax.close) {
+            return Some((syntax.open.len() + n + syntax.close.len(), code));
+        }
+        if let (true, Some((open, close))) = (interpolates, syntax.interpolation) {
+            // A doubled brace is a literal one, as in Python's f"{{x}}"
+            if open == "{" && at.starts_with("{{") {
+                n += 2;
+                continue;
+            }
+            if at.starts_with(open) {
+                let start = syntax.open.len() + n + open.len();
+                let len = interpolation_end(&rest[start..], close, lang);
+                code.push((start, start + len));
+                n += open.len() + len + close.len();
+                continue;
+            }
+        }
+        n += at.chars().next().map_or(1, char::len_utf8);
+    }
+
+    // Unterminated: the rest of the file is part of the string
+    code.retain(|(_, end)| *end <= rest.len());
+    Some((rest.len(), code))
+}
+
+/// Length of the code of an interpolation, up to the `close` that ends it
+///
+/// Braces inside it are balanced, and strings inside it skipped, so
+/// `${f({ a: "}" })}` ends at the last brace.
+fn interpolation_end(code: &str, close: &str, lang: &Language) -> usize {
+    let mut depth = 0;
+    let mut n = 0;
+    while n < code.len() {
+        let at = &code[n..];
+        if depth == 0 && at.starts_with(close) {
+            return n;
+        }
+        if let Some((len, _)) = lang.strings.iter()
+            .filter(|s| !s.char_literal && at.starts_with(s.open))
+            .find_map(|s| string_end(at, s, false, lang)) {
+            n += len;
+            continue;
+        }
+        match at.chars().next() {
+            Some('{') => depth += 1,
+            Some('}') => depth -= 1,
+            _ => {},
+        }
+        n += at.chars().next().map_or(1, char::len_utf8);
+    }
+    code.len()
+}
+
+/// Check whether a character can be part of an identifier
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+/// Replace only inside identifiers, leaving string literals, comments and other tokens alone
+///
+/// # Arguments
+/// * `text` - Source code
+/// * `lang` - Language of the source code
+/// * `replace` - Replacement for one identifier
+///
+/// # Returns
+/// * `Result<String>` - The source code with replacements applied
+pub fn replace_identifiers(text: &str, lang: &Language, mut replace: impl FnMut(&str) -> Result<String>) -> Result<String> {
+    let mut result = String::with_capacity(text.len());
+
+    for (start, end, token) in tokens(text, lang) {
+        let piece = &text[start..end];
+        if token != Token::Code {
+            result.push_str(piece);
+            continue;
+        }
+
+        // Split code into identifiers and everything else (operators, whitespace, numbers)
+        let mut rest = piece;
+        while let Some(ident_start) = rest.find(is_identifier_char) {
+            let ident_len = rest[ident_start..].find(|c| !is_identifier_char(c)).unwrap_or(rest.len() - ident_start);
+            let ident = &rest[ident_start..ident_start + ident_len];
+            result.push_str(&rest[..ident_start]);
+            if ident.starts_with(|c: char| c.is_ascii_digit()) {
+                result.push_str(ident);
+            } else {
+                result.push_str(&replace(ident)?);
+            }
+            rest = &rest[ident_start + ident_len..];
+        }
+        result.push_str(rest);
+    }
+
+    Ok(result)
+}
+
+/// Which literals to leave alone
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Skip {
+    pub strings: bool,
+    pub comments: bool,
+}
+
+/// Replace outside string literals and/or comments
+///
+/// Code between skipped literals is replaced as a whole, so rules can still
+/// match across operators and whitespace.
+///
+/// # Arguments
+/// * `text` - Source code
+/// * `lang` - Language of the source code
+/// * `skip` - Which literals to leave alone
+/// * `replace` - Replacement for one piece of code
+///
+/// # Returns
+/// * `Result<String>` - The source code with replacements applied
+pub fn replace_outside(text: &str, lang: &Language, skip: Skip, mut replace: impl FnMut(&str) -> Result<String>) -> Result<String> {
+    let mut result = String::with_capacity(text.len());
+    let mut piece_start = 0;
+
+    for (start, end, token) in tokens(text, lang) {
+        let skipped = match token {
+            Token::Code => false,
+            Token::String => skip.strings,
+            Token::Comment => skip.comments,
+        };
+        if skipped {
+            result.push_str(&replace(&text[piece_start..start])?);
+            result.push_str(&text[start..end]);
+            piece_start = end;
+        }
+    }
+    result.push_str(&replace(&text[piece_start..])?);
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rename(text: &str, file: &str) -> String {
+        let lang = Language::detect(Path::new(file)).unwrap();
+        replace_identifiers(text, lang, |s| Ok(s.replace("foo", "bar"))).unwrap()
+    }
+
+    #[test]
+    fn test_replace_identifiers() {
+        assert_eq!(
+            rename("fn foo<'a>(x: &'a str) -> char { /* foo */ foo_x(\"foo\", 'f', r#\"foo\"#) } // foo\n", "lib.rs"),
+            "fn bar<'a>(x: &'a str) -> char { /* foo */ bar_x(\"foo\", 'f', r#\"foo\"#) } // foo\n"
+        );
+        assert_eq!(
+            rename("const foo = `foo ${x}`; // foo\nfoo('foo');\n", "app.ts"),
+            "const bar = `foo ${x}`; // foo\nbar('foo');\n"
+        );
+        assert_eq!(
+            rename("def foo():\n    \"\"\"foo\"\"\"\n    return f\"foo\"  # foo\n", "mod.py"),
+            "def bar():\n    \"\"\"foo\"\"\"\n    return f\"foo\"  # foo\n"
+        );
+        assert_eq!(rename("foo := `foo` + \"foo\" // foo\n", "main.go"), "bar := `foo` + \"foo\" // foo\n");
+
+        // Rust block comments nest, C ones don't
+        assert_eq!(rename("/* a /* b */ foo */ foo", "lib.rs"), "/* a /* b */ foo */ bar");
+        assert_eq!(rename("/* a /* b */ foo */ foo", "main.c"), "/* a /* b */ bar */ bar");
+
+        // Interpolated code is code, whatever braces and strings it holds
+        assert_eq!(
+            rename("`foo ${foo + 'foo'} ${ {foo: \"}\"}.foo } foo`", "app.js"),
+            "`foo ${bar + 'foo'} ${ {bar: \"}\"}.bar } foo`"
+        );
+        assert_eq!(
+            rename("f\"foo {foo!r} {{foo}}\" + rf'{foo}' + \"{foo}\"", "mod.py"),
+            "f\"foo {bar!r} {{foo}}\" + rf'{bar}' + \"{foo}\""
+        );
+        assert!(Language::detect(Path::new("notes.txt")).is_none());
+    }
+
+    #[test]
+    fn test_replace_outside() {
+        let skip = |strings, comments, text: &str, file: &str| {
+            let lang = Language::detect(Path::new(file)).unwrap();
+            replace_outside(text, lang, Skip { strings, comments }, |s| Ok(s.replace("foo", "bar"))).unwrap()
+        };
+        let code = "foo = \"foo\" # foo\n";
+        assert_eq!(skip(true, false, code, "run.sh"), "bar = \"foo\" # bar\n");
+        assert_eq!(skip(false, true, code, "run.sh"), "bar = \"bar\" # foo\n");
+        assert_eq!(skip(true, true, code, "run.sh"), "bar = \"foo\" # foo\n");
+        assert_eq!(skip(false, true, "--[[ foo ]] foo -- foo\n", "init.lua"), "--[[ foo ]] bar -- foo\n");
+        assert_eq!(skip(false, true, "<!-- foo --><foo/>", "index.html"), "<!-- foo --><bar/>");
+        assert_eq!(skip(true, false, "log(`foo ${foo}`)", "app.ts"), "log(`foo ${bar}`)");
+    }
+}
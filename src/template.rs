@@ -0,0 +1,1164 @@
+use crate::args::Args;
+use crate::case::{self, StringCase};
+use crate::config;
+use crate::inflect;
+use crate::json::{self, Value};
+use anyhow::{Result, Context, anyhow};
+use atty::Stream;
+use std::io::{self, BufRead, Write};
+use regex::Regex;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Manifest file name looked up in the root of a copy source
+pub const MANIFEST_FILE_NAME: &str = "mane-template.toml";
+
+/// Cookiecutter's manifest file name
+pub const COOKIECUTTER_FILE_NAME: &str = "cookiecutter.json";
+
+/// Directory holding cookiecutter's Python hooks (not copied)
+const COOKIECUTTER_HOOKS_DIR: &str = "hooks";
+
+/// A template variable declared in the manifest
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariableSpec {
+    /// Variable name used in `{{name}}` placeholders
+    pub name: String,
+
+    /// Human readable description shown when prompting
+    pub description: Option<String>,
+
+    /// Value used when none is given (may reference earlier variables)
+    pub default: Option<String>,
+
+    /// Allowed values, if the variable is a choice
+    pub choices: Vec<String>,
+}
+
+/// Template manifest (`mane-template.toml`)
+#[derive(Debug, Clone, Default)]
+pub struct Manifest {
+    /// Declared variables, in declaration order
+    pub variables: Vec<VariableSpec>,
+
+    /// Whether the manifest came from a cookiecutter template
+    pub cookiecutter: bool,
+
+    /// Glob patterns of files copied without rendering (cookiecutter's `_copy_without_render`)
+    pub copy_without_render: Vec<String>,
+
+    /// Steps run on the generated tree after copying, in declaration order
+    pub actions: Vec<Action>,
+
+    /// Source globs copied only when their condition holds (`[conditions]`)
+    pub conditions: Vec<(String, String)>,
+}
+
+/// A post-processing step declared in the manifest's `[[actions]]`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    /// Set the permissions of matching files (`chmod = "+x"` or an octal mode like `"755"`)
+    Chmod { mode: Mode, paths: Vec<String> },
+
+    /// Delete matching files and directories, unless the variable named by `unless` is true
+    Delete { paths: Vec<String>, unless: Option<String> },
+
+    /// Create a symlink at `symlink` pointing to `to`
+    Symlink { path: String, to: String },
+}
+
+/// Permissions set by a chmod action
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    /// Add the execute bits wherever the read bits are set
+    Executable,
+
+    /// Replace the permission bits
+    Octal(u32),
+}
+
+/// Resolved template variables (name, value)
+pub type Vars = Vec<(String, String)>;
+
+/// Load the manifest from a template directory, if it has one
+///
+/// # Arguments
+/// * `source_dir` - Root of the template
+///
+/// # Returns
+/// * `Result<Option<Manifest>>` - The manifest, or None if the template has none
+pub fn load_manifest(source_dir: &Path) -> Result<Option<Manifest>> {
+    if !source_dir.is_dir() {
+        return Ok(None);
+    }
+
+    let path = source_dir.join(MANIFEST_FILE_NAME);
+    if !path.is_file() {
+        let cookiecutter_path = source_dir.join(COOKIECUTTER_FILE_NAME);
+        if cookiecutter_path.is_file() {
+            return load_cookiecutter(&cookiecutter_path).map(Some);
+        }
+        return Ok(None);
+    }
+
+    let document = config::read_config(&path)?;
+    let mut manifest = Manifest::default();
+
+    if let Some(Value::Object(entries)) = document.get("variables") {
+        for (name, spec) in entries {
+            let variable = match spec {
+                // Shorthand: name = "default"
+                Value::String(default) => VariableSpec {
+                    name: name.clone(),
+                    description: None,
+                    default: Some(default.clone()),
+                    choices: Vec::new(),
+                },
+                Value::Object(_) => VariableSpec {
+                    name: name.clone(),
+                    description: spec.get("description").and_then(Value::as_str).map(str::to_string),
+                    default: spec.get("default").and_then(Value::as_str).map(str::to_string),
+                    choices: string_list(spec.get("choices")),
+                },
+                _ => return Err(anyhow!("Variable '{}' in {:?} must be a string or a table", name, path)),
+            };
+            manifest.variables.push(variable);
+        }
+    }
+
+    if let Some(conditions) = document.get("conditions") {
+        let Value::Object(entries) = conditions else {
+            return Err(anyhow!("'conditions' must be a table of glob = \"condition\" in {:?}", path));
+        };
+        for (glob, condition) in entries {
+            let condition = condition.as_str()
+                .ok_or_else(|| anyhow!("Condition of '{}' in {:?} must be a string", glob, path))?;
+            manifest.conditions.push((glob.clone(), condition.to_string()));
+        }
+    }
+
+    if let Some(actions) = document.get("actions") {
+        manifest.actions = parse_actions(actions).with_context(|| format!("Invalid actions in {:?}", path))?;
+    }
+
+    Ok(Some(manifest))
+}
+
+/// Parse the `[[actions]]` tables of a manifest
+///
+/// # Arguments
+/// * `value` - The `actions` array
+///
+/// # Returns
+/// * `Result<Vec<Action>>` - The actions, in declaration order
+fn parse_actions(value: &Value) -> Result<Vec<Action>> {
+    let items = value.as_array().ok_or_else(|| anyhow!("'actions' must be an array of tables"))?;
+
+    items.iter().enumerate().map(|(i, item)| {
+        let paths = |key: &str| match item.get(key) {
+            Some(Value::String(path)) => Ok(vec![path.clone()]),
+            Some(value @ Value::Array(_)) => Ok(string_list(Some(value))),
+            _ => Err(anyhow!("Action #{} needs '{}' as a glob or an array of globs", i + 1, key)),
+        };
+        let string = |key: &str| item.get(key).and_then(Value::as_str).map(str::to_string);
+
+        if let Some(mode) = string("chmod") {
+            let mode = match mode.as_str() {
+                "+x" => Mode::Executable,
+                octal => Mode::Octal(u32::from_str_radix(octal, 8)
+                    .map_err(|_| anyhow!("Action #{} has chmod '{}', expected \"+x\" or an octal mode", i + 1, octal))?),
+            };
+            Ok(Action::Chmod { mode, paths: paths("paths")? })
+        } else if item.get("delete").is_some() {
+            Ok(Action::Delete { paths: paths("delete")?, unless: string("unless") })
+        } else if let Some(path) = string("symlink") {
+            let to = string("to").ok_or_else(|| anyhow!("Action #{} needs the 'to' the symlink points to", i + 1))?;
+            Ok(Action::Symlink { path, to })
+        } else {
+            Err(anyhow!("Action #{} must have 'chmod', 'delete' or 'symlink'", i + 1))
+        }
+    }).collect()
+}
+
+/// Source paths of a template left out because their condition doesn't hold
+///
+/// Globs are matched against paths relative to the template root. Conditions
+/// on unknown variables hold, so those paths are copied.
+///
+/// # Arguments
+/// * `conditions` - Globs and their conditions
+/// * `vars` - Template variables
+///
+/// # Returns
+/// * `Result<GlobSet>` - Globs of the paths not to copy
+pub fn excluded_paths(conditions: &[(String, String)], vars: &Vars) -> Result<globset::GlobSet> {
+    let mut builder = globset::GlobSetBuilder::new();
+    for (glob, expr) in conditions {
+        if condition(expr, vars) == Some(false) {
+            builder.add(globset::Glob::new(glob).with_context(|| format!("Invalid glob in [conditions]: {}", glob))?);
+        }
+    }
+    Ok(builder.build()?)
+}
+
+/// Run the manifest's actions on a generated tree
+///
+/// Paths and globs are rendered with the template variables and matched
+/// against the generated paths, relative to the generated tree.
+///
+/// # Arguments
+/// * `actions` - Actions to run, in order
+/// * `root` - Root of the generated tree
+/// * `vars` - Template variables
+///
+/// # Returns
+/// * `Result<Vec<PathBuf>>` - Files and directories deleted
+pub fn run_actions(actions: &[Action], root: &Path, vars: &Vars) -> Result<Vec<PathBuf>> {
+    let mut deleted = Vec::new();
+    for action in actions {
+        match action {
+            Action::Chmod { mode, paths } => {
+                for path in matching_paths(root, paths, vars)?.into_iter().filter(|path| path.is_file()) {
+                    set_mode(&path, *mode).with_context(|| format!("Failed to chmod {}", path.display()))?;
+                    verbose!("Changed mode of {}", path.display());
+                }
+            },
+            Action::Delete { paths, unless } => {
+                if unless.as_ref().is_some_and(|expr| condition(expr, vars) == Some(true)) {
+                    continue;
+                }
+                for path in matching_paths(root, paths, vars)? {
+                    // A directory deleted earlier takes its matching contents with it
+                    let Ok(metadata) = path.symlink_metadata() else {
+                        continue;
+                    };
+                    let removed = if metadata.is_dir() { std::fs::remove_dir_all(&path) } else { std::fs::remove_file(&path) };
+                    removed.with_context(|| format!("Failed to delete {}", path.display()))?;
+                    verbose!("Deleted {}", path.display());
+                    deleted.push(path);
+                }
+            },
+            Action::Symlink { path, to } => {
+                let link = root.join(render(path, vars));
+                if let Some(parent) = link.parent() {
+                    std::fs::create_dir_all(parent)
+                        .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+                }
+                if link.symlink_metadata().is_ok() {
+                    std::fs::remove_file(&link).with_context(|| format!("Failed to replace {}", link.display()))?;
+                }
+                symlink(&render(to, vars), &link)
+                    .with_context(|| format!("Failed to create symlink {}", link.display()))?;
+                verbose!("Linked {} -> {}", link.display(), render(to, vars));
+            },
+        }
+    }
+    Ok(deleted)
+}
+
+/// Evaluate a condition: a variable name, or `!name` to negate it
+///
+/// A variable is true when it is `true`, `yes`, `y`, `on` or `1`, in any case.
+///
+/// # Arguments
+/// * `expr` - The condition
+/// * `vars` - Variables
+///
+/// # Returns
+/// * `Option<bool>` - Whether the condition holds, or None if the variable is unknown
+pub fn condition(expr: &str, vars: &Vars) -> Option<bool> {
+    let expr = expr.trim();
+    let (negated, name) = match expr.strip_prefix('!') {
+        Some(name) => (true, name.trim()),
+        None => (false, expr),
+    };
+    let (_, value) = vars.iter().find(|(n, _)| n == name)?;
+    let truthy = ["true", "yes", "y", "on", "1"].contains(&value.to_lowercase().as_str());
+    Some(truthy != negated)
+}
+
+/// Paths of a generated tree matching rendered globs, parents before their contents
+fn matching_paths(root: &Path, globs: &[String], vars: &Vars) -> Result<Vec<PathBuf>> {
+    let mut builder = globset::GlobSetBuilder::new();
+    for glob in globs {
+        let glob = render(glob, vars);
+        builder.add(globset::Glob::new(&glob).with_context(|| format!("Invalid action glob: {}", glob))?);
+    }
+    let globs = builder.build()?;
+
+    let mut matched = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in std::fs::read_dir(&dir).with_context(|| format!("Failed to read directory: {:?}", dir))? {
+            let path = entry?.path();
+            if globs.is_match(path.strip_prefix(root).unwrap_or(&path)) {
+                matched.push(path.clone());
+            }
+            if path.symlink_metadata().is_ok_and(|metadata| metadata.is_dir()) {
+                pending.push(path);
+            }
+        }
+    }
+    matched.sort();
+    Ok(matched)
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: Mode) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let current = std::fs::metadata(path)?.permissions().mode();
+    let mode = match mode {
+        Mode::Executable => current | (current & 0o444) >> 2,
+        Mode::Octal(bits) => (current & !0o7777) | bits,
+    };
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: Mode) -> io::Result<()> {
+    Ok(())
+}
+
+/// Create a symlink at `link` pointing to `to`, resolved from the link's directory
+#[cfg(unix)]
+pub fn symlink(to: &str, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(to, link)
+}
+
+#[cfg(windows)]
+pub fn symlink(to: &str, link: &Path) -> io::Result<()> {
+    // The target is resolved from the link's directory, like on unix
+    let resolved = link.parent().unwrap_or(Path::new("")).join(to);
+    if resolved.is_dir() {
+        std::os::windows::fs::symlink_dir(to, link)
+    } else {
+        std::os::windows::fs::symlink_file(to, link)
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn symlink(_to: &str, _link: &Path) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "symlinks are not supported on this platform"))
+}
+
+/// Load a cookiecutter.json as a manifest
+///
+/// Every non-private key becomes a `cookiecutter.KEY` variable: strings are
+/// defaults, lists are choices (the first one being the default).
+fn load_cookiecutter(path: &Path) -> Result<Manifest> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {:?}", path))?;
+    let document = json::parse(&text).with_context(|| format!("Invalid {:?}", path))?;
+    let Value::Object(entries) = &document else {
+        return Err(anyhow!("{:?} must contain a JSON object", path));
+    };
+
+    let mut manifest = Manifest { cookiecutter: true, ..Manifest::default() };
+    for (key, value) in entries {
+        if key == "_copy_without_render" {
+            manifest.copy_without_render = string_list(Some(value));
+        }
+        // Other private keys (_extensions, _jinja2_env_vars, ...) don't apply to mane
+        if key.starts_with('_') {
+            continue;
+        }
+
+        let (default, choices) = match value {
+            Value::String(s) => (Some(s.clone()), Vec::new()),
+            Value::Bool(b) => (Some(b.to_string()), Vec::new()),
+            Value::Number(n) => (Some(n.to_string()), Vec::new()),
+            Value::Array(_) => {
+                let choices = string_list(Some(value));
+                (choices.first().cloned(), choices)
+            },
+            _ => {
+                warn!("Skipping unsupported cookiecutter variable '{}' in {:?}", key, path);
+                continue;
+            },
+        };
+        manifest.variables.push(VariableSpec {
+            name: format!("cookiecutter.{}", key),
+            description: None,
+            default,
+            choices,
+        });
+    }
+
+    Ok(manifest)
+}
+
+/// Collect the strings of an array value
+fn string_list(value: Option<&Value>) -> Vec<String> {
+    value.and_then(Value::as_array)
+        .map(|items| items.iter().filter_map(Value::as_str).map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+/// Check whether a path is template metadata that isn't copied
+/// (the manifest, cookiecutter.json or cookiecutter hooks)
+pub fn is_manifest(path: &Path, source_dir: &Path) -> bool {
+    path == source_dir.join(MANIFEST_FILE_NAME)
+        || path == source_dir.join(COOKIECUTTER_FILE_NAME)
+        || path.starts_with(source_dir.join(COOKIECUTTER_HOOKS_DIR))
+            && source_dir.join(COOKIECUTTER_FILE_NAME).is_file()
+}
+
+/// Find the project directory of a cookiecutter template
+///
+/// Cookiecutter renders the single top-level directory whose name contains a
+/// `{{cookiecutter.*}}` placeholder; everything else in the template root is metadata.
+///
+/// # Arguments
+/// * `source_dir` - Root of the template
+///
+/// # Returns
+/// * `Result<PathBuf>` - The project directory
+pub fn cookiecutter_project_dir(source_dir: &Path) -> Result<PathBuf> {
+    let mut candidates = Vec::new();
+    for entry in std::fs::read_dir(source_dir)
+        .with_context(|| format!("Failed to read directory: {:?}", source_dir))?
+    {
+        let path = entry?.path();
+        let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        if path.is_dir() && name.contains("{{") && name.contains("cookiecutter") {
+            candidates.push(path);
+        }
+    }
+
+    match candidates.len() {
+        1 => Ok(candidates.remove(0)),
+        0 => Err(anyhow!("No {{{{cookiecutter.*}}}} project directory found in {:?}", source_dir)),
+        _ => Err(anyhow!("Multiple {{{{cookiecutter.*}}}} project directories found in {:?}", source_dir)),
+    }
+}
+
+/// A variable as listed by `mane template vars`
+struct ListedVariable {
+    spec: VariableSpec,
+
+    /// Whether the manifest declares it
+    declared: bool,
+
+    /// Files and directories whose content or name use it, relative to the template
+    used_in: Vec<PathBuf>,
+}
+
+/// Print the variables a template declares, and those its files use without
+/// declaring them (`mane template vars`)
+///
+/// # Arguments
+/// * `args` - Command line arguments, for the ignore options of the walk
+/// * `source_dir` - Root of the template
+/// * `as_json` - Whether to print JSON instead of a table
+///
+/// # Returns
+/// * `Result<()>` - Result of the operation
+pub fn print_vars(args: &Args, source_dir: &Path, as_json: bool) -> Result<()> {
+    if !source_dir.is_dir() {
+        return Err(anyhow!("Template directory does not exist: {}", source_dir.display()));
+    }
+    let manifest = load_manifest(source_dir)?.unwrap_or_default();
+    let mut listed: Vec<ListedVariable> = manifest.variables.iter()
+        .map(|spec| ListedVariable { spec: spec.clone(), declared: true, used_in: Vec::new() })
+        .collect();
+
+    // Files are walked like a copy walks them
+    let mut builder = args.walk_builder(source_dir);
+    builder.require_git(false);
+    for entry in builder.build().filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        if path == source_dir || is_manifest(path, source_dir) {
+            continue;
+        }
+        let relative = path.strip_prefix(source_dir).unwrap_or(path);
+        let name = relative.file_name().unwrap_or_default().to_string_lossy();
+        let content = if path.is_file() { std::fs::read_to_string(path).unwrap_or_default() } else { String::new() };
+
+        // Tokens only count for declared variables, as they could be any word
+        let declared: Vec<String> = manifest.variables.iter().map(|spec| spec.name.clone()).collect();
+        let tokens: Vec<String> = [&*name, &*content].into_iter()
+            .flat_map(|text| token_pattern().captures_iter(text))
+            .filter_map(|captures| token_var(&captures, declared.iter().map(String::as_str)).map(|(name, _)| name.to_string()))
+            .collect();
+        let used_names = placeholder_names(&name).into_iter()
+            .chain(placeholder_names(&content))
+            .chain(tokens.iter().map(String::as_str));
+        for used in used_names {
+            let index = match listed.iter().position(|variable| variable.spec.name == used) {
+                Some(index) => index,
+                None => {
+                    let spec = VariableSpec { name: used.to_string(), description: None, default: None, choices: Vec::new() };
+                    listed.push(ListedVariable { spec, declared: false, used_in: Vec::new() });
+                    listed.len() - 1
+                },
+            };
+            if !listed[index].used_in.iter().any(|p| p == relative) {
+                listed[index].used_in.push(relative.to_path_buf());
+            }
+        }
+    }
+
+    let mut out = io::stdout().lock();
+    if as_json {
+        let variables = listed.iter().map(|variable| {
+            let optional = |value: &Option<String>| value.clone().map_or(Value::Null, Value::from);
+            json::object([
+                ("name", Value::from(variable.spec.name.as_str())),
+                ("description", optional(&variable.spec.description)),
+                ("default", optional(&variable.spec.default)),
+                ("choices", Value::Array(variable.spec.choices.iter().map(|c| Value::from(c.as_str())).collect())),
+                ("declared", Value::from(variable.declared)),
+                ("used_in", Value::Array(variable.used_in.iter().map(|p| Value::from(p.to_string_lossy().to_string())).collect())),
+            ])
+        }).collect();
+        writeln!(out, "{}", json::object([("variables", Value::Array(variables))]).to_pretty_string())?;
+        return Ok(());
+    }
+
+    if listed.is_empty() {
+        info!("{} declares and uses no variables", source_dir.display());
+        return Ok(());
+    }
+    let width = listed.iter().map(|variable| variable.spec.name.len()).max().unwrap_or(0);
+    for variable in &listed {
+        let mut details = Vec::new();
+        if let Some(description) = &variable.spec.description {
+            details.push(description.clone());
+        }
+        if !variable.spec.choices.is_empty() {
+            details.push(format!("one of: {}", variable.spec.choices.join(", ")));
+        }
+        match &variable.spec.default {
+            Some(default) => details.push(format!("default: {}", default)),
+            None if variable.declared => details.push("required".to_string()),
+            None => {},
+        }
+        if !variable.declared {
+            let first = variable.used_in.first().map(|p| p.display().to_string()).unwrap_or_default();
+            details.push(format!("not declared, used in {}", first));
+        }
+        writeln!(out, "{:width$}  {}", variable.spec.name, details.join("; "), width = width)?;
+    }
+    Ok(())
+}
+
+/// Names of the variables the placeholders and section conditions of a text refer to
+///
+/// # Arguments
+/// * `text` - Content or name to search
+///
+/// # Returns
+/// * `Vec<&str>` - Names in order of first use, each once
+pub fn placeholder_names(text: &str) -> Vec<&str> {
+    let mut names: Vec<&str> = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            break;
+        };
+        let inner = after[..end].trim();
+        rest = &after[end + 2..];
+        if inner == "else" {
+            continue;
+        }
+
+        let expr = match inner.strip_prefix("#if ") {
+            Some(condition) => condition.trim().trim_start_matches('!').trim(),
+            None => split_pipeline(inner)[0].trim(),
+        };
+        // `name.lower().replace(...)` uses `name`
+        let mut name = expr.split(['(', ' ']).next().unwrap_or("");
+        match &expr[name.len()..] {
+            "" => {},
+            calls if calls.starts_with('(') => name = name.rsplit_once('.').map_or("", |(name, _)| name),
+            _ => continue,
+        }
+        let valid = !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '.');
+        if valid && !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+/// Parse a `--var NAME=VALUE` argument
+///
+/// # Arguments
+/// * `raw` - The raw argument
+///
+/// # Returns
+/// * `Result<(String, String)>` - Name and value
+pub fn parse_var(raw: &str) -> Result<(String, String)> {
+    let (name, value) = raw.split_once('=')
+        .ok_or_else(|| anyhow!("Invalid --var '{}': expected NAME=VALUE", raw))?;
+    if name.is_empty() {
+        return Err(anyhow!("Invalid --var '{}': empty variable name", raw));
+    }
+    Ok((name.to_string(), value.to_string()))
+}
+
+/// Resolve the value of every declared variable
+///
+/// Values come from `--var` first, then the declared default. Variables still
+/// missing are prompted for on the terminal; without a terminal they are an error.
+///
+/// # Arguments
+/// * `manifest` - Declared variables
+/// * `given` - Values from --var
+///
+/// # Returns
+/// * `Result<Vars>` - All variables with values
+pub fn resolve_variables(manifest: &Manifest, given: &Vars) -> Result<Vars> {
+    let mut vars = given.clone();
+    let interactive = atty::is(Stream::Stdin);
+
+    for spec in &manifest.variables {
+        if vars.iter().any(|(name, _)| name == &spec.name) {
+            continue;
+        }
+
+        // Defaults may refer to earlier variables (e.g. cookiecutter's project_slug)
+        let resolved_spec = VariableSpec {
+            default: spec.default.as_ref().map(|d| render(d, &vars)),
+            ..spec.clone()
+        };
+
+        let value = if interactive {
+            prompt(&resolved_spec)?
+        } else if let Some(default) = &resolved_spec.default {
+            default.clone()
+        } else {
+            return Err(anyhow!(
+                "No value for template variable '{}'. Use --var {}=VALUE",
+                spec.name, spec.name
+            ));
+        };
+        vars.push((spec.name.clone(), value));
+    }
+
+    Ok(vars)
+}
+
+/// Ask for a variable value on the terminal
+fn prompt(spec: &VariableSpec) -> Result<String> {
+    let stdin = io::stdin();
+    loop {
+        let mut message = spec.name.clone();
+        if let Some(description) = &spec.description {
+            message.push_str(&format!(" ({})", description));
+        }
+        if !spec.choices.is_empty() {
+            message.push_str(&format!(" {{{}}}", spec.choices.join(", ")));
+        }
+        if let Some(default) = &spec.default {
+            message.push_str(&format!(" [{}]", default));
+        }
+        eprint!("{}: ", message);
+        io::stderr().flush()?;
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            return Err(anyhow!("No value given for template variable '{}'", spec.name));
+        }
+
+        let answer = line.trim_end_matches(['\n', '\r']);
+        if !answer.is_empty() && !spec.choices.is_empty() && !spec.choices.iter().any(|c| c == answer) {
+            eprintln!("Choose one of: {}", spec.choices.join(", "));
+            continue;
+        }
+        match (answer.is_empty(), &spec.default) {
+            (false, _) => return Ok(answer.to_string()),
+            (true, Some(default)) => return Ok(default.clone()),
+            (true, None) => eprintln!("A value is required."),
+        }
+    }
+}
+
+/// Substitute `{{name}}` placeholders for the given variables
+///
+/// Whitespace inside the braces is allowed (`{{ name }}`), as are the string
+/// methods cookiecutter templates commonly use (`{{ name.lower().replace(' ', '_') }}`).
+/// `{{#if name}} ... {{else}} ... {{/if}}` sections are kept or dropped first.
+/// Placeholders and sections for unknown names are left untouched.
+///
+/// # Arguments
+/// * `content` - Text to render
+/// * `vars` - Variables to substitute
+///
+/// # Returns
+/// * `String` - The rendered text
+pub fn render(content: &str, vars: &Vars) -> String {
+    if vars.is_empty() {
+        return content.to_string();
+    }
+    let content = render_tokens(content, vars);
+    if !content.contains("{{") {
+        return content;
+    }
+
+    let sections = render_sections(&content, vars);
+    let content = sections.as_str();
+    let mut result = String::with_capacity(content.len());
+    let mut rest = content;
+
+    while let Some(start) = rest.find("{{") {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            result.push_str(&rest[start..]);
+            return result;
+        };
+
+        match evaluate(after[..end].trim(), vars) {
+            Some(value) => result.push_str(&value),
+            None => result.push_str(&rest[start..start + end + 4]),
+        }
+        rest = &after[end + 2..];
+    }
+
+    result.push_str(rest);
+    result
+}
+
+/// `__name__` and `%NAME%` tokens, with an optional case (`__name_snake__`, `__NamePascal__`)
+fn token_pattern() -> &'static Regex {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    PATTERN.get_or_init(|| Regex::new(r"__([A-Za-z][A-Za-z0-9]*(?:_[A-Za-z0-9]+)*)__|%([A-Za-z][A-Za-z0-9_]*)%").unwrap())
+}
+
+/// Filters a token can name as its case
+const TOKEN_CASES: [&str; 8] = ["pascal", "camel", "snake", "kebab", "screaming_snake", "constant", "upper", "lower"];
+
+/// Substitute the explicit placeholder tokens of a text for the given variables
+///
+/// `%name%` stands for the value as given; a case after the name, either
+/// `_snake` or `Snake`, converts it like the filter of that name
+/// (`__name_kebab__`, `%NAME_CONSTANT%`, `__NamePascal__`). Variable names
+/// match regardless of case. Only tokens naming a variable are substituted,
+/// and `__name__` needs a case too, so Python's `__name__` and other dunder
+/// names survive a template that declares `name`.
+///
+/// # Arguments
+/// * `content` - Text to render
+/// * `vars` - Variables to substitute
+///
+/// # Returns
+/// * `String` - The text with the tokens substituted
+fn render_tokens(content: &str, vars: &Vars) -> String {
+    token_pattern().replace_all(content, |captures: &regex::Captures| {
+        token_var(captures, vars.iter().map(|(name, _)| name.as_str()))
+            .and_then(|(name, case)| {
+                let value = &vars.iter().find(|(var, _)| var == name)?.1;
+                match case {
+                    Some(case) => apply_filter(value, case),
+                    None => Some(value.clone()),
+                }
+            })
+            .unwrap_or_else(|| captures[0].to_string())
+    }).into_owned()
+}
+
+/// The variable a matched token refers to and the case it asks for
+///
+/// # Arguments
+/// * `captures` - The token, matched by `token_pattern`
+/// * `names` - Names of the variables
+///
+/// # Returns
+/// * `Option<(&str, Option<&str>)>` - As `resolve_token` has it, except that a
+///   `__name__` token without a case refers to nothing
+fn token_var<'a>(captures: &regex::Captures, names: impl Iterator<Item = &'a str>) -> Option<(&'a str, Option<&'static str>)> {
+    match (captures.get(1), captures.get(2)) {
+        (Some(dunder), _) => resolve_token(dunder.as_str(), names).filter(|(_, case)| case.is_some()),
+        (None, Some(percent)) => resolve_token(percent.as_str(), names),
+        (None, None) => None,
+    }
+}
+
+/// The variable a token refers to and the case it asks for
+///
+/// # Arguments
+/// * `body` - Text between the token's delimiters
+/// * `names` - Names of the variables
+///
+/// # Returns
+/// * `Option<(&str, Option<&str>)>` - The longest name the token starts with, and the case filter after it
+fn resolve_token<'a>(body: &str, names: impl Iterator<Item = &'a str>) -> Option<(&'a str, Option<&'static str>)> {
+    names
+        .filter(|name| body.get(..name.len()).is_some_and(|prefix| prefix.eq_ignore_ascii_case(name)))
+        .filter_map(|name| {
+            let rest = body[name.len()..].trim_start_matches('_').replace('_', "");
+            if rest.is_empty() {
+                return Some((name, None));
+            }
+            TOKEN_CASES.iter()
+                .find(|case| case.replace('_', "").eq_ignore_ascii_case(&rest))
+                .map(|case| (name, Some(*case)))
+        })
+        .max_by_key(|(name, _)| name.len())
+}
+
+/// A tag of a conditional section
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SectionTag<'a> {
+    /// `{{#if condition}}`
+    If(&'a str),
+
+    /// `{{else}}`
+    Else,
+
+    /// `{{/if}}`
+    End,
+}
+
+/// Keep or drop the `{{#if name}} ... {{else}} ... {{/if}}` sections of a text
+///
+/// Sections nest. A tag alone on its line is removed with the line, so
+/// sections around whole lines leave no blank lines behind. Text whose tags
+/// don't balance is returned unchanged.
+///
+/// # Arguments
+/// * `content` - Text to render
+/// * `vars` - Variables the conditions refer to
+///
+/// # Returns
+/// * `String` - The text with the sections resolved
+fn render_sections(content: &str, vars: &Vars) -> String {
+    let tags = section_tags(content);
+    let mut depth = 0usize;
+    for (_, _, tag) in &tags {
+        match tag {
+            SectionTag::If(_) => depth += 1,
+            SectionTag::Else if depth == 0 => return content.to_string(),
+            SectionTag::Else => {},
+            SectionTag::End if depth == 0 => return content.to_string(),
+            SectionTag::End => depth -= 1,
+        }
+    }
+    if tags.is_empty() || depth != 0 {
+        return content.to_string();
+    }
+
+    // Each open section, as Some((condition, in else branch)), or None for an
+    // unknown variable whose tags are kept as they are
+    let mut stack: Vec<Option<(bool, bool)>> = Vec::new();
+    let emitting = |stack: &[Option<(bool, bool)>]| {
+        stack.iter().flatten().all(|(condition, in_else)| condition != in_else)
+    };
+
+    let mut result = String::with_capacity(content.len());
+    let mut last = 0;
+    for (line, tag_span, tag) in tags {
+        if emitting(&stack) {
+            result.push_str(&content[last..line.start]);
+        }
+        let literal = match tag {
+            SectionTag::If(expr) => {
+                let frame = condition(expr, vars).map(|condition| (condition, false));
+                stack.push(frame);
+                frame.is_none()
+            },
+            SectionTag::Else => match stack.last_mut() {
+                Some(Some((_, in_else))) => {
+                    *in_else = true;
+                    false
+                },
+                _ => true,
+            },
+            SectionTag::End => stack.pop().flatten().is_none(),
+        };
+        if literal && emitting(&stack) {
+            result.push_str(&content[line.start..tag_span.start]);
+            result.push_str(&content[tag_span.clone()]);
+            result.push_str(&content[tag_span.end..line.end]);
+        }
+        last = line.end;
+    }
+    result.push_str(&content[last..]);
+    result
+}
+
+/// Find the section tags of a text
+///
+/// # Returns
+/// * `Vec<(Range<usize>, Range<usize>, SectionTag)>` - For each tag, the span it
+///   takes (its whole line when it stands alone), the tag itself and its kind
+fn section_tags(content: &str) -> Vec<(Range<usize>, Range<usize>, SectionTag<'_>)> {
+    let mut tags = Vec::new();
+    let mut offset = 0;
+    while let Some(start) = content[offset..].find("{{").map(|i| offset + i) {
+        let Some(end) = content[start..].find("}}").map(|i| start + i + 2) else {
+            break;
+        };
+        offset = end;
+
+        let inner = content[start + 2..end - 2].trim();
+        let tag = match inner {
+            "else" => SectionTag::Else,
+            "/if" => SectionTag::End,
+            _ => match inner.strip_prefix("#if ") {
+                Some(expr) => SectionTag::If(expr.trim()),
+                None => continue,
+            },
+        };
+
+        // A tag alone on its line takes the line with it
+        let line_start = content[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = content[end..].find('\n').map_or(content.len(), |i| end + i + 1);
+        let alone = content[line_start..start].trim().is_empty() && content[end..line_end].trim().is_empty();
+        let line = if alone { line_start..line_end } else { start..end };
+        tags.push((line, start..end, tag));
+    }
+    tags
+}
+
+/// Evaluate a placeholder expression: a variable name followed by optional
+/// method calls, then optional `| filter` stages
+///
+/// # Arguments
+/// * `expr` - Expression inside the braces
+/// * `vars` - Variables
+///
+/// # Returns
+/// * `Option<String>` - The value, or None if the expression isn't understood
+fn evaluate(expr: &str, vars: &Vars) -> Option<String> {
+    let mut stages = split_pipeline(expr).into_iter();
+    let expr = stages.next()?.trim();
+
+    // Longest variable name that prefixes the expression (names may contain dots)
+    let (name, value) = vars.iter()
+        .filter(|(name, _)| {
+            expr.strip_prefix(name.as_str()).is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+        })
+        .max_by_key(|(name, _)| name.len())?;
+
+    let mut value = value.clone();
+    let mut rest = &expr[name.len()..];
+    while let Some(call) = rest.strip_prefix('.') {
+        let open = call.find('(')?;
+        let close = call.find(')')?;
+        value = apply_method(&value, &call[..open], &call[open + 1..close])?;
+        rest = &call[close + 1..];
+    }
+    if !rest.is_empty() {
+        return None;
+    }
+
+    for stage in stages {
+        let stage = stage.trim();
+        value = match stage.split_once('(') {
+            Some((filter, arguments)) => apply_method(&value, filter.trim(), arguments.strip_suffix(')')?)?,
+            None => apply_filter(&value, stage)?,
+        };
+    }
+    Some(value)
+}
+
+/// Split an expression at the `|` of its filter stages, outside quoted arguments
+fn split_pipeline(expr: &str) -> Vec<&str> {
+    let mut stages = Vec::new();
+    let mut quote = None;
+    let mut start = 0;
+    for (i, c) in expr.char_indices() {
+        match (quote, c) {
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            (None, '|') => {
+                stages.push(&expr[start..i]);
+                start = i + 1;
+            },
+            _ => {},
+        }
+    }
+    stages.push(&expr[start..]);
+    stages
+}
+
+/// Apply a cookiecutter string method, or a filter taking arguments
+///
+/// # Arguments
+/// * `value` - Value to transform
+/// * `method` - Method name
+/// * `arguments` - Text between the parentheses
+///
+/// # Returns
+/// * `Option<String>` - The result, or None for an unknown method
+fn apply_method(value: &str, method: &str, arguments: &str) -> Option<String> {
+    let arguments = parse_string_arguments(arguments)?;
+    match (method, arguments.as_slice()) {
+        ("replace", [from, to]) => Some(value.replace(from.as_str(), to)),
+        (name, []) => apply_filter(value, name),
+        _ => None,
+    }
+}
+
+/// Apply a filter such as `pascal`, `snake` or `upper` (`{{name | pascal}}`)
+///
+/// # Arguments
+/// * `value` - Value to transform
+/// * `filter` - Filter name
+///
+/// # Returns
+/// * `Option<String>` - The result, or None for an unknown filter
+fn apply_filter(value: &str, filter: &str) -> Option<String> {
+    let case = match filter {
+        "pascal" => StringCase::Pascal,
+        "camel" => StringCase::Camel,
+        "snake" => StringCase::Snake,
+        "kebab" => StringCase::Kebab,
+        "screaming_snake" | "constant" => StringCase::ScreamingSnake,
+        "lower" => return Some(value.to_lowercase()),
+        "upper" => return Some(value.to_uppercase()),
+        "strip" | "trim" => return Some(value.trim().to_string()),
+        "title" => return Some(title_case(value)),
+        "capitalize" => return Some(capitalize(value)),
+        "plural" => return Some(inflect::pluralize(value).unwrap_or_else(|| value.to_string())),
+        _ => return None,
+    };
+    Some(case::convert_case(value, &case))
+}
+
+/// Parse a comma-separated list of quoted string literals
+fn parse_string_arguments(s: &str) -> Option<Vec<String>> {
+    if s.trim().is_empty() {
+        return Some(Vec::new());
+    }
+
+    let mut arguments = Vec::new();
+    let mut rest = s.trim();
+    loop {
+        let quote = rest.chars().next().filter(|c| *c == '\'' || *c == '"')?;
+        let end = rest[1..].find(quote)? + 1;
+        arguments.push(rest[1..end].to_string());
+        rest = rest[end + 1..].trim_start();
+        match rest.strip_prefix(',') {
+            Some(next) => rest = next.trim_start(),
+            None if rest.is_empty() => return Some(arguments),
+            None => return None,
+        }
+    }
+}
+
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars.flat_map(char::to_lowercase)).collect(),
+        None => String::new(),
+    }
+}
+
+fn title_case(s: &str) -> String {
+    s.split(' ').map(capitalize).collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testdir::TestDir;
+
+    #[test]
+    fn test_render() {
+        let vars = vec![("name".to_string(), "billing".to_string())];
+        assert_eq!(render("mod {{name}}; // {{ name }}", &vars), "mod billing; // billing");
+        assert_eq!(render("{{other}} {{name", &vars), "{{other}} {{name");
+    }
+
+    #[test]
+    fn test_render_tokens() {
+        let vars = vec![("name".to_string(), "billing account".to_string()), ("name_prefix".to_string(), "acme".to_string())];
+        assert_eq!(render("%name%/%NAME%.rs", &vars), "billing account/billing account.rs");
+        assert_eq!(render("__name_snake__ __NamePascal__ %NAME_CONSTANT% __name_kebab__", &vars), "billing_account BillingAccount BILLING_ACCOUNT billing-account");
+        assert_eq!(render("%name_prefix% __name_prefix_upper__", &vars), "acme ACME");
+
+        // Tokens for unknown variables or cases are left alone, and so are dunder names without a case
+        assert_eq!(render("__init__ __name_shout__ %PATH% 100%", &vars), "__init__ __name_shout__ %PATH% 100%");
+        assert_eq!(render("if __name__ == \"__main__\": __name_prefix__", &vars), "if __name__ == \"__main__\": __name_prefix__");
+    }
+
+    #[test]
+    fn test_render_sections() {
+        let vars = vec![("docker".to_string(), "yes".to_string()), ("ci".to_string(), "false".to_string())];
+        let content = "a\n{{#if docker}}\nFROM rust\n  {{#if ci}}\nci\n  {{else}}\nlocal\n  {{/if}}\n{{/if}}\nb {{#if !docker}}x{{/if}}\n";
+        assert_eq!(render(content, &vars), "a\nFROM rust\nlocal\nb \n");
+
+        // Unknown variables and unbalanced tags are left alone
+        assert_eq!(render("{{#if other}}x{{/if}}", &vars), "{{#if other}}x{{/if}}");
+        assert_eq!(render("{{#if docker}}x", &vars), "{{#if docker}}x");
+    }
+
+    #[test]
+    fn test_render_cookiecutter_expressions() {
+        let vars = vec![
+            ("cookiecutter.project_name".to_string(), "My Project".to_string()),
+            ("cookiecutter.project".to_string(), "short".to_string()),
+        ];
+        assert_eq!(
+            render("{{ cookiecutter.project_name.lower().replace(' ', '_') }}", &vars),
+            "my_project"
+        );
+        assert_eq!(render("{{cookiecutter.project}}", &vars), "short");
+        assert_eq!(render("{{ cookiecutter.project_name.unknown() }}", &vars), "{{ cookiecutter.project_name.unknown() }}");
+    }
+
+    #[test]
+    fn test_render_filters() {
+        let vars = vec![("name".to_string(), "billing account".to_string())];
+        assert_eq!(render("{{name | pascal}} {{ name|snake }} {{name | kebab | upper}}", &vars), "BillingAccount billing_account BILLING-ACCOUNT");
+        assert_eq!(render("{{name | constant}} {{name | camel | plural}}", &vars), "BILLING_ACCOUNT billingAccounts");
+        assert_eq!(render("{{name | replace('a', '|') | title}}", &vars), "Billing |ccount");
+        assert_eq!(render("{{name | unknown}}", &vars), "{{name | unknown}}");
+    }
+
+    #[test]
+    fn test_actions() {
+        let document = crate::toml::parse(r#"
+[[actions]]
+chmod = "+x"
+paths = "bin/*"
+
+[[actions]]
+delete = ["{{name}}/docker/**", "Dockerfile"]
+unless = "docker"
+
+[[actions]]
+symlink = "docs/README.md"
+to = "../README.md"
+"#).unwrap();
+        let actions = parse_actions(document.get("actions").unwrap()).unwrap();
+        assert_eq!(actions[0], Action::Chmod { mode: Mode::Executable, paths: vec!["bin/*".to_string()] });
+
+        let root = TestDir::new("actions");
+        std::fs::create_dir_all(root.join("bin")).unwrap();
+        std::fs::create_dir_all(root.join("app/docker")).unwrap();
+        std::fs::write(root.join("bin/run"), "").unwrap();
+        std::fs::write(root.join("app/docker/compose.yml"), "").unwrap();
+        std::fs::write(root.join("Dockerfile"), "").unwrap();
+        std::fs::write(root.join("README.md"), "").unwrap();
+
+        let vars = vec![("name".to_string(), "app".to_string()), ("docker".to_string(), "no".to_string())];
+        let deleted = run_actions(&actions, &root, &vars).unwrap();
+        assert_eq!(deleted, [root.join("Dockerfile"), root.join("app/docker/compose.yml")]);
+        assert!(root.join("docs/README.md").exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_ne!(std::fs::metadata(root.join("bin/run")).unwrap().permissions().mode() & 0o100, 0);
+        }
+    }
+
+    #[test]
+    fn test_placeholder_names() {
+        let text = "{{ name | pascal }} {{#if !docker}}{{else}}{{/if}} {{ cookiecutter.slug.lower().strip() }} {{name}} {{ not a var }}";
+        assert_eq!(placeholder_names(text), ["name", "docker", "cookiecutter.slug"]);
+    }
+
+    #[test]
+    fn test_parse_var() {
+        assert_eq!(parse_var("a=b=c").unwrap(), ("a".to_string(), "b=c".to_string()));
+        assert!(parse_var("novalue").is_err());
+        assert!(parse_var("=x").is_err());
+    }
+}
@@ -0,0 +1,52 @@
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Directories created so far, to keep the names of parallel tests apart
+static CREATED: AtomicUsize = AtomicUsize::new(0);
+
+/// Empty directory for a test, under the system temp directory
+///
+/// The directory is removed with everything in it when the value is dropped,
+/// so it is cleaned up even when an assertion fails. It derefs to its path.
+pub struct TestDir {
+    path: PathBuf,
+}
+
+impl TestDir {
+    /// Create the directory
+    ///
+    /// # Arguments
+    /// * `name` - Name of the test, part of the directory name
+    ///
+    /// # Returns
+    /// * `TestDir` - The new, empty directory
+    pub fn new(name: &str) -> TestDir {
+        let count = CREATED.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!("mane-{}-{}-{}", name, std::process::id(), count));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TestDir { path }
+    }
+}
+
+impl Deref for TestDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl AsRef<Path> for TestDir {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
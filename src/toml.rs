@@ -0,0 +1,467 @@
+use crate::json::Value;
+use anyhow::{Result, anyhow};
+
+/// Parse a TOML document into a JSON-like value tree
+///
+/// Supports the subset used by `.mane.toml`: tables, dotted and quoted keys,
+/// arrays of tables, strings (basic, literal and multi-line), integers, floats,
+/// booleans, arrays and inline tables. Date/time values are kept as strings.
+///
+/// # Arguments
+/// * `text` - The TOML text
+///
+/// # Returns
+/// * `Result<Value>` - The document as a `Value::Object`
+pub fn parse(text: &str) -> Result<Value> {
+    let mut parser = Parser { chars: text.chars().collect(), pos: 0, line: 1 };
+    parser.parse_document()
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl Parser {
+    fn error(&self, message: &str) -> anyhow::Error {
+        anyhow!("{} at line {}", message, self.line)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        s.chars().enumerate().all(|(i, c)| self.chars.get(self.pos + i) == Some(&c))
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    /// Skip spaces and tabs on the current line
+    fn skip_inline_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.bump();
+        }
+    }
+
+    /// Skip whitespace, newlines and comments
+    fn skip_whitespace_and_comments(&mut self) {
+        loop {
+            match self.peek() {
+                Some(' ' | '\t' | '\r' | '\n') => {
+                    self.bump();
+                },
+                Some('#') => self.skip_comment(),
+                _ => break,
+            }
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        while !matches!(self.peek(), None | Some('\n')) {
+            self.bump();
+        }
+    }
+
+    /// Expect the end of a line (optionally with a trailing comment)
+    fn expect_line_end(&mut self) -> Result<()> {
+        self.skip_inline_whitespace();
+        if self.peek() == Some('#') {
+            self.skip_comment();
+        }
+        match self.peek() {
+            None | Some('\n') => Ok(()),
+            Some('\r') if self.chars.get(self.pos + 1) == Some(&'\n') => Ok(()),
+            _ => Err(self.error("Expected end of line")),
+        }
+    }
+
+    fn parse_document(&mut self) -> Result<Value> {
+        let mut root = Value::Object(Vec::new());
+        // Path of the table that key/value pairs currently go to
+        let mut current: Vec<String> = Vec::new();
+
+        loop {
+            self.skip_whitespace_and_comments();
+            match self.peek() {
+                None => break,
+                Some('[') => {
+                    let array = self.starts_with("[[");
+                    self.bump();
+                    if array {
+                        self.bump();
+                    }
+                    self.skip_inline_whitespace();
+                    let path = self.parse_key()?;
+                    self.skip_inline_whitespace();
+                    let close = if array { "]]" } else { "]" };
+                    if !self.starts_with(close) {
+                        return Err(self.error(&format!("Expected '{}'", close)));
+                    }
+                    for _ in 0..close.len() {
+                        self.bump();
+                    }
+                    self.expect_line_end()?;
+
+                    if array {
+                        let (last, parents) = path.split_last().ok_or_else(|| self.error("Empty table name"))?;
+                        let parent = table_at(&mut root, parents).map_err(|e| self.error(&e))?;
+                        let entry = get_or_insert(parent, last, Value::Array(Vec::new()));
+                        match entry {
+                            Value::Array(items) => items.push(Value::Object(Vec::new())),
+                            _ => return Err(self.error(&format!("Key '{}' is not an array of tables", last))),
+                        }
+                    } else {
+                        table_at(&mut root, &path).map_err(|e| self.error(&e))?;
+                    }
+                    current = path;
+                },
+                Some(_) => {
+                    let key = self.parse_key()?;
+                    self.skip_inline_whitespace();
+                    if self.bump() != Some('=') {
+                        return Err(self.error("Expected '='"));
+                    }
+                    self.skip_inline_whitespace();
+                    let value = self.parse_value()?;
+                    self.expect_line_end()?;
+
+                    let table = table_at(&mut root, &current).map_err(|e| self.error(&e))?;
+                    insert_dotted(table, &key, value).map_err(|e| self.error(&e))?;
+                },
+            }
+        }
+
+        Ok(root)
+    }
+
+    /// Parse a (possibly dotted, possibly quoted) key
+    fn parse_key(&mut self) -> Result<Vec<String>> {
+        let mut parts = Vec::new();
+        loop {
+            self.skip_inline_whitespace();
+            let part = match self.peek() {
+                Some('"') => self.parse_basic_string()?,
+                Some('\'') => self.parse_literal_string()?,
+                _ => {
+                    let mut bare = String::new();
+                    while let Some(c) = self.peek().filter(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '-') {
+                        bare.push(c);
+                        self.bump();
+                    }
+                    if bare.is_empty() {
+                        return Err(self.error("Expected key"));
+                    }
+                    bare
+                },
+            };
+            parts.push(part);
+            self.skip_inline_whitespace();
+            if self.peek() == Some('.') {
+                self.bump();
+            } else {
+                return Ok(parts);
+            }
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Value> {
+        match self.peek() {
+            Some('"') if self.starts_with("\"\"\"") => self.parse_multiline_basic_string().map(Value::String),
+            Some('\'') if self.starts_with("'''") => self.parse_multiline_literal_string().map(Value::String),
+            Some('"') => self.parse_basic_string().map(Value::String),
+            Some('\'') => self.parse_literal_string().map(Value::String),
+            Some('[') => self.parse_array(),
+            Some('{') => self.parse_inline_table(),
+            Some(_) if self.starts_with("true") => {
+                self.pos += 4;
+                Ok(Value::Bool(true))
+            },
+            Some(_) if self.starts_with("false") => {
+                self.pos += 5;
+                Ok(Value::Bool(false))
+            },
+            Some(_) => self.parse_scalar(),
+            None => Err(self.error("Expected value")),
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Value> {
+        self.bump();
+        let mut items = Vec::new();
+        loop {
+            self.skip_whitespace_and_comments();
+            if self.peek() == Some(']') {
+                self.bump();
+                return Ok(Value::Array(items));
+            }
+            items.push(self.parse_value()?);
+            self.skip_whitespace_and_comments();
+            match self.bump() {
+                Some(',') => {},
+                Some(']') => return Ok(Value::Array(items)),
+                _ => return Err(self.error("Expected ',' or ']' in array")),
+            }
+        }
+    }
+
+    fn parse_inline_table(&mut self) -> Result<Value> {
+        self.bump();
+        let mut table = Value::Object(Vec::new());
+        self.skip_inline_whitespace();
+        if self.peek() == Some('}') {
+            self.bump();
+            return Ok(table);
+        }
+        loop {
+            let key = self.parse_key()?;
+            self.skip_inline_whitespace();
+            if self.bump() != Some('=') {
+                return Err(self.error("Expected '=' in inline table"));
+            }
+            self.skip_inline_whitespace();
+            let value = self.parse_value()?;
+            insert_dotted(&mut table, &key, value).map_err(|e| self.error(&e))?;
+            self.skip_inline_whitespace();
+            match self.bump() {
+                Some(',') => self.skip_inline_whitespace(),
+                Some('}') => return Ok(table),
+                _ => return Err(self.error("Expected ',' or '}' in inline table")),
+            }
+        }
+    }
+
+    fn parse_basic_string(&mut self) -> Result<String> {
+        self.bump();
+        let mut out = String::new();
+        loop {
+            match self.bump() {
+                Some('"') => return Ok(out),
+                Some('\\') => out.push(self.parse_escape()?),
+                Some('\n') | None => return Err(self.error("Unterminated string")),
+                Some(c) => out.push(c),
+            }
+        }
+    }
+
+    fn parse_multiline_basic_string(&mut self) -> Result<String> {
+        self.pos += 3;
+        // A newline right after the opening delimiter is trimmed
+        self.skip_leading_newline();
+        let mut out = String::new();
+        loop {
+            if self.starts_with("\"\"\"") {
+                self.pos += 3;
+                return Ok(out);
+            }
+            match self.bump() {
+                Some('\\') if matches!(self.peek(), Some('\n' | '\r' | ' ' | '\t')) => {
+                    // Line-ending backslash: trim all whitespace up to the next content
+                    while matches!(self.peek(), Some('\n' | '\r' | ' ' | '\t')) {
+                        self.bump();
+                    }
+                },
+                Some('\\') => out.push(self.parse_escape()?),
+                Some(c) => out.push(c),
+                None => return Err(self.error("Unterminated multi-line string")),
+            }
+        }
+    }
+
+    fn parse_literal_string(&mut self) -> Result<String> {
+        self.bump();
+        let mut out = String::new();
+        loop {
+            match self.bump() {
+                Some('\'') => return Ok(out),
+                Some('\n') | None => return Err(self.error("Unterminated string")),
+                Some(c) => out.push(c),
+            }
+        }
+    }
+
+    fn parse_multiline_literal_string(&mut self) -> Result<String> {
+        self.pos += 3;
+        self.skip_leading_newline();
+        let mut out = String::new();
+        loop {
+            if self.starts_with("'''") {
+                self.pos += 3;
+                return Ok(out);
+            }
+            out.push(self.bump().ok_or_else(|| self.error("Unterminated multi-line string"))?);
+        }
+    }
+
+    fn skip_leading_newline(&mut self) {
+        if self.starts_with("\r\n") {
+            self.pos += 1;
+        }
+        if self.peek() == Some('\n') {
+            self.bump();
+        }
+    }
+
+    fn parse_escape(&mut self) -> Result<char> {
+        let c = self.bump().ok_or_else(|| self.error("Unterminated escape"))?;
+        Ok(match c {
+            'b' => '\u{8}',
+            't' => '\t',
+            'n' => '\n',
+            'f' => '\u{c}',
+            'r' => '\r',
+            'e' => '\u{1b}',
+            '"' => '"',
+            '\\' => '\\',
+            'u' | 'U' => {
+                let len = if c == 'u' { 4 } else { 8 };
+                let hex: String = self.chars.get(self.pos..self.pos + len)
+                    .ok_or_else(|| self.error("Truncated unicode escape"))?
+                    .iter()
+                    .collect();
+                self.pos += len;
+                u32::from_str_radix(&hex, 16).ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| self.error("Invalid unicode escape"))?
+            },
+            _ => return Err(self.error(&format!("Invalid escape '\\{}'", c))),
+        })
+    }
+
+    /// Parse numbers; anything else number-like (dates, times) is kept as a string
+    fn parse_scalar(&mut self) -> Result<Value> {
+        let mut raw = String::new();
+        while let Some(c) = self.peek().filter(|c| !matches!(c, ',' | ']' | '}' | '#' | '\n' | '\r')) {
+            raw.push(c);
+            self.bump();
+        }
+        let raw = raw.trim_end().to_string();
+        if raw.is_empty() {
+            return Err(self.error("Expected value"));
+        }
+
+        let digits = raw.replace('_', "");
+        let number = if let Some(hex) = digits.strip_prefix("0x") {
+            i64::from_str_radix(hex, 16).ok().map(|n| n as f64)
+        } else if let Some(oct) = digits.strip_prefix("0o") {
+            i64::from_str_radix(oct, 8).ok().map(|n| n as f64)
+        } else if let Some(bin) = digits.strip_prefix("0b") {
+            i64::from_str_radix(bin, 2).ok().map(|n| n as f64)
+        } else {
+            match digits.as_str() {
+                "inf" | "+inf" => Some(f64::INFINITY),
+                "-inf" => Some(f64::NEG_INFINITY),
+                _ => digits.parse::<f64>().ok(),
+            }
+        };
+
+        match number {
+            Some(n) => Ok(Value::Number(n)),
+            None if raw.starts_with(|c: char| c.is_ascii_digit()) => Ok(Value::String(raw)),
+            None => Err(self.error(&format!("Invalid value '{}'", raw))),
+        }
+    }
+}
+
+/// Get the table at `path`, creating intermediate tables (and following arrays of tables)
+fn table_at<'a>(root: &'a mut Value, path: &[String]) -> std::result::Result<&'a mut Value, String> {
+    let mut table = root;
+    for key in path {
+        let entry = get_or_insert(table, key, Value::Object(Vec::new()));
+        table = match entry {
+            Value::Object(_) => entry,
+            // [a.b] after [[a]] refers to the last element of the array
+            Value::Array(items) => match items.last_mut() {
+                Some(last @ Value::Object(_)) => last,
+                _ => return Err(format!("Key '{}' is not a table", key)),
+            },
+            _ => return Err(format!("Key '{}' is not a table", key)),
+        };
+    }
+    Ok(table)
+}
+
+/// Get the value for `key` in an object, inserting `default` if missing
+fn get_or_insert<'a>(table: &'a mut Value, key: &str, default: Value) -> &'a mut Value {
+    let Value::Object(entries) = table else {
+        unreachable!("table_at only returns objects");
+    };
+    let index = match entries.iter().position(|(k, _)| k == key) {
+        Some(index) => index,
+        None => {
+            entries.push((key.to_string(), default));
+            entries.len() - 1
+        },
+    };
+    &mut entries[index].1
+}
+
+/// Insert a value under a dotted key
+fn insert_dotted(table: &mut Value, key: &[String], value: Value) -> std::result::Result<(), String> {
+    let (last, parents) = key.split_last().ok_or("Empty key")?;
+    let table = table_at(table, parents)?;
+    let Value::Object(entries) = table else {
+        unreachable!("table_at only returns objects");
+    };
+    if entries.iter().any(|(k, _)| k == last) {
+        return Err(format!("Duplicate key '{}'", last));
+    }
+    entries.push((last.clone(), value));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_toml() {
+        let doc = parse(r#"
+# Global rules
+rules = [
+  { from = "foo", to = "bar" },  # trailing comment
+  { from = 'C:\path', to = "x\ty" },
+]
+include_git_ignore = true
+
+[profiles.rebrand]
+case = false
+count = 1_000
+
+[[profiles.rebrand.rules]]
+from = """
+multi
+line"""
+to = "z"
+"#).unwrap();
+
+        let rules = doc.get("rules").and_then(Value::as_array).unwrap();
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0].get("to").and_then(Value::as_str), Some("bar"));
+        assert_eq!(rules[1].get("from").and_then(Value::as_str), Some("C:\\path"));
+        assert_eq!(rules[1].get("to").and_then(Value::as_str), Some("x\ty"));
+        assert_eq!(doc.get("include_git_ignore"), Some(&Value::Bool(true)));
+
+        let profile = doc.get("profiles").and_then(|p| p.get("rebrand")).unwrap();
+        assert_eq!(profile.get("case"), Some(&Value::Bool(false)));
+        assert_eq!(profile.get("count"), Some(&Value::Number(1000.0)));
+        let profile_rules = profile.get("rules").and_then(Value::as_array).unwrap();
+        assert_eq!(profile_rules[0].get("from").and_then(Value::as_str), Some("multi\nline"));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse("key = ").is_err());
+        assert!(parse("a = 1\na = 2").is_err());
+        assert!(parse("[table\nk = 1").is_err());
+        assert!(parse("k = \"unterminated").is_err());
+    }
+}
@@ -0,0 +1,43 @@
+use anyhow::{Result, Context};
+use std::path::Path;
+
+/// Move a file or directory to the user's trash (--trash)
+///
+/// This is the platform's own trash, so the file manager can restore the path
+/// to where it was: the freedesktop.org trash on Linux and the BSDs, the
+/// Finder's on macOS and the Recycle Bin on Windows.
+///
+/// # Arguments
+/// * `path` - File or directory to move
+///
+/// # Returns
+/// * `Result<()>` - Error if the path can't be moved to the trash
+pub fn move_to_trash(path: &Path) -> Result<()> {
+    ::trash::delete(path).context(format!("Failed to move {} to the trash", path.display()))
+}
+
+#[cfg(all(test, unix, not(target_os = "macos")))]
+mod tests {
+    use super::*;
+    use crate::testdir::TestDir;
+    use std::fs;
+
+    #[test]
+    fn test_move_to_trash() {
+        // The home trash is found from XDG_DATA_HOME, kept on the same filesystem as the file
+        let dir = TestDir::new("trash");
+        std::env::set_var("XDG_DATA_HOME", &*dir);
+
+        for content in ["first", "second"] {
+            fs::write(dir.join("my file%.txt"), content).unwrap();
+            move_to_trash(&dir.join("my file%.txt")).unwrap();
+        }
+        assert!(!dir.join("my file%.txt").exists());
+        let mut trashed: Vec<String> = fs::read_dir(dir.join("Trash/files")).unwrap()
+            .map(|entry| fs::read_to_string(entry.unwrap().path()).unwrap())
+            .collect();
+        trashed.sort();
+        assert_eq!(trashed, ["first", "second"]);
+        assert_eq!(fs::read_dir(dir.join("Trash/info")).unwrap().count(), 2);
+    }
+}
@@ -0,0 +1,374 @@
+use crate::diff::{self, Hunk};
+use crate::patch;
+use crate::plan::Plan;
+use anyhow::{Result, Context, anyhow};
+use ratatui::backend::CrosstermBackend;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use ratatui::crossterm::{cursor, execute};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph};
+use ratatui::{Frame, Terminal};
+
+/// Number of context lines around each reviewed hunk
+const CONTEXT_LINES: usize = 3;
+
+/// One change that can be accepted or dropped
+#[derive(Debug, Clone, PartialEq)]
+enum Item {
+    /// A hunk of a content edit
+    Hunk { edit: usize, hunk: Hunk },
+
+    /// A repacked archive
+    Archive(usize),
+
+    /// A rename
+    Rename(usize),
+}
+
+/// The changes of a plan and which of them are accepted (--tui)
+#[derive(Debug)]
+struct Review {
+    items: Vec<Item>,
+    accepted: Vec<bool>,
+}
+
+impl Review {
+    /// List the changes of a plan, all accepted
+    fn new(plan: &Plan) -> Self {
+        let mut items = Vec::new();
+        for (index, edit) in plan.edits.iter().enumerate() {
+            let old_lines: Vec<&str> = edit.original.split_inclusive('\n').collect();
+            let new_lines: Vec<&str> = edit.replaced.split_inclusive('\n').collect();
+            for hunk in diff::hunks(&old_lines, &new_lines, CONTEXT_LINES) {
+                items.push(Item::Hunk { edit: index, hunk });
+            }
+        }
+        items.extend((0..plan.archives.len()).map(Item::Archive));
+        items.extend((0..plan.renames.len()).map(Item::Rename));
+
+        let accepted = vec![true; items.len()];
+        Review { items, accepted }
+    }
+
+    /// One-line summary of a change for the list
+    fn summary(&self, plan: &Plan, index: usize) -> String {
+        match &self.items[index] {
+            Item::Hunk { edit, hunk } => {
+                let changed = hunk.lines.iter().find(|line| line.starts_with('-') || line.starts_with('+'));
+                format!("{}:{}: {}", plan.edits[*edit].path.display(), hunk.old_start,
+                    changed.map_or("", |line| line[1..].trim()))
+            },
+            Item::Archive(archive) => format!("{} (archive members)", plan.archives[*archive].path.display()),
+            Item::Rename(rename) => {
+                let rename = &plan.renames[*rename];
+                format!("rename {} -> {}", rename.from.display(),
+                    rename.to.file_name().unwrap_or_default().to_string_lossy())
+            },
+        }
+    }
+
+    /// Lines describing a change in detail, each starting with ' ', '-' or '+'
+    fn details(&self, plan: &Plan, index: usize) -> Vec<String> {
+        match &self.items[index] {
+            Item::Hunk { hunk, .. } => hunk.lines.iter().map(|line| line.trim_end_matches(['\n', '\r']).to_string()).collect(),
+            Item::Archive(archive) => vec![format!(" Replaced members of {}", plan.archives[*archive].path.display())],
+            Item::Rename(rename) => {
+                let rename = &plan.renames[*rename];
+                vec![format!("-{}", rename.from.display()), format!("+{}", rename.to.display())]
+            },
+        }
+    }
+
+    /// Reduce a plan to the accepted changes
+    ///
+    /// Files with only some hunks accepted get those hunks applied to their
+    /// original content.
+    fn apply_to(&self, plan: &mut Plan) -> Result<()> {
+        let accepted = |item: &Item| self.items.iter().zip(&self.accepted).any(|(i, a)| i == item && *a);
+
+        let mut edits = Vec::new();
+        for (index, edit) in plan.edits.iter().enumerate() {
+            let hunks: Vec<&Hunk> = self.items.iter().zip(&self.accepted)
+                .filter_map(|(item, accepted)| match item {
+                    Item::Hunk { edit, hunk } if *edit == index => Some((hunk, *accepted)),
+                    _ => None,
+                })
+                .filter(|(_, accepted)| *accepted)
+                .map(|(hunk, _)| hunk)
+                .collect();
+            let total = self.items.iter().filter(|item| matches!(item, Item::Hunk { edit, .. } if *edit == index)).count();
+            if hunks.is_empty() {
+                continue;
+            }
+
+            let mut edit = edit.clone();
+            if hunks.len() < total {
+                let hunks: Vec<Hunk> = hunks.into_iter().cloned().collect();
+                edit.replaced = patch::apply_hunks(&edit.original, &hunks)
+                    .with_context(|| format!("Failed to apply the accepted hunks to {:?}", edit.path))?;
+            }
+            edits.push(edit);
+        }
+        plan.edits = edits;
+
+        let archives = std::mem::take(&mut plan.archives);
+        plan.archives = archives.into_iter().enumerate()
+            .filter(|(index, _)| accepted(&Item::Archive(*index)))
+            .map(|(_, archive)| archive)
+            .collect();
+        let renames = std::mem::take(&mut plan.renames);
+        plan.renames = renames.into_iter().enumerate()
+            .filter(|(index, _)| accepted(&Item::Rename(*index)))
+            .map(|(_, rename)| rename)
+            .collect();
+        Ok(())
+    }
+}
+
+/// What the review screen does next, after a key
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    /// Keep reviewing
+    Continue,
+
+    /// Apply the accepted changes
+    Apply,
+
+    /// Change nothing
+    Quit,
+}
+
+/// The review and the change selected on screen
+struct Screen<'a> {
+    review: &'a mut Review,
+    list: ListState,
+}
+
+impl Screen<'_> {
+    fn cursor(&self) -> usize {
+        self.list.selected().unwrap_or(0)
+    }
+
+    /// Act on a key press
+    fn key(&mut self, key: KeyCode) -> Outcome {
+        let last = self.review.items.len() - 1;
+        let cursor = self.cursor();
+        match key {
+            KeyCode::Down | KeyCode::Char('j') => self.list.select(Some((cursor + 1).min(last))),
+            KeyCode::Up | KeyCode::Char('k') => self.list.select(Some(cursor.saturating_sub(1))),
+            KeyCode::Char(' ') => self.review.accepted[cursor] = !self.review.accepted[cursor],
+            KeyCode::Char('a') => self.review.accepted.iter_mut().for_each(|a| *a = true),
+            KeyCode::Char('n') => self.review.accepted.iter_mut().for_each(|a| *a = false),
+            KeyCode::Enter | KeyCode::Char('y') => return Outcome::Apply,
+            KeyCode::Char('q') | KeyCode::Esc => return Outcome::Quit,
+            _ => {},
+        }
+        Outcome::Continue
+    }
+
+    /// Draw the list of changes, and the diff of the selected one below it
+    fn draw(&mut self, frame: &mut Frame, plan: &Plan) {
+        let [header, list, details] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Percentage(50),
+            Constraint::Fill(1),
+        ]).areas(frame.area());
+
+        let accepted = self.review.accepted.iter().filter(|a| **a).count();
+        frame.render_widget(
+            Paragraph::new(format!(" {}/{} changes accepted   space: toggle  a/n: all/none  enter: apply  q: quit",
+                accepted, self.review.items.len())).reversed(),
+            header,
+        );
+
+        let items: Vec<ListItem> = (0..self.review.items.len())
+            .map(|index| {
+                let mark = if self.review.accepted[index] { "[x]" } else { "[ ]" };
+                ListItem::new(format!("{} {}", mark, self.review.summary(plan, index)))
+            })
+            .collect();
+        frame.render_stateful_widget(
+            List::new(items).highlight_style(Style::new().reversed()).block(Block::new().borders(Borders::BOTTOM)),
+            list,
+            &mut self.list,
+        );
+
+        let lines: Vec<Line> = self.review.details(plan, self.cursor()).into_iter()
+            .map(|line| {
+                let style = match line.chars().next() {
+                    Some('-') => Style::new().red(),
+                    Some('+') => Style::new().green(),
+                    _ => Style::new(),
+                };
+                Line::styled(line.replace('\t', "    "), style)
+            })
+            .collect();
+        frame.render_widget(Paragraph::new(lines), details);
+    }
+}
+
+/// Let the user pick the changes of a plan to keep in a full-screen review (--tui)
+///
+/// Keys: up/down or j/k move, space toggles a change, `a`/`n` accept or drop
+/// all of them, enter applies the accepted changes and `q` quits without
+/// changing anything.
+///
+/// # Arguments
+/// * `plan` - Planned changes, reduced to the accepted ones
+///
+/// # Returns
+/// * `Result<bool>` - False if the user quit without accepting
+pub fn review(plan: &mut Plan) -> Result<bool> {
+    let mut review = Review::new(plan);
+    if review.items.is_empty() {
+        info!("Nothing to review");
+        return Ok(true);
+    }
+
+    let confirmed = run(&mut review, plan)?;
+    if confirmed {
+        review.apply_to(plan)?;
+        let count = review.accepted.iter().filter(|a| **a).count();
+        info!("Accepted {} of {} changes", count, review.items.len());
+    }
+    Ok(confirmed)
+}
+
+/// Run the review screen until the user applies or quits
+///
+/// The screen is drawn on the controlling terminal rather than stdout, so
+/// stdin and stdout can still be piped.
+fn run(review: &mut Review, plan: &Plan) -> Result<bool> {
+    let mut terminal = Terminal::new(CrosstermBackend::new(tty()?))
+        .map_err(|e| anyhow!("--tui needs a terminal: {}", e))?;
+    terminal::enable_raw_mode().map_err(|e| anyhow!("--tui needs a terminal: {}", e))?;
+    let _restore = Restore;
+    execute!(terminal.backend_mut(), EnterAlternateScreen)?;
+    terminal.clear()?;
+
+    let mut screen = Screen { review, list: ListState::default().with_selected(Some(0)) };
+    loop {
+        terminal.draw(|frame| screen.draw(frame, plan))?;
+        let Event::Key(key) = event::read()? else { continue };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match screen.key(key.code) {
+            Outcome::Continue => {},
+            Outcome::Apply => return Ok(true),
+            Outcome::Quit => return Ok(false),
+        }
+    }
+}
+
+/// Leaves raw mode and the alternate screen when dropped, however the review ends
+struct Restore;
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        let _ = terminal::disable_raw_mode();
+        if let Ok(mut tty) = tty() {
+            let _ = execute!(tty, LeaveAlternateScreen, cursor::Show);
+        }
+    }
+}
+
+/// The controlling terminal
+#[cfg(unix)]
+fn tty() -> Result<std::fs::File> {
+    std::fs::OpenOptions::new().read(true).write(true).open("/dev/tty")
+        .map_err(|e| anyhow!("--tui needs a terminal: {}", e))
+}
+
+#[cfg(not(unix))]
+fn tty() -> Result<std::io::Stdout> {
+    Ok(std::io::stdout())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plan::{ContentEdit, Rename};
+    use crate::testdir::TestDir;
+    use std::fs;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_apply_to() {
+        let original = (1..=20).map(|n| format!("line {}\n", n)).collect::<String>();
+        let replaced = original.replace("line 2\n", "row 2\n").replace("line 18\n", "row 18\n");
+        let mut plan = Plan {
+            edits: vec![ContentEdit { path: PathBuf::from("a.txt"), original: original.clone(), replaced, compressed: false }],
+            archives: Vec::new(),
+            renames: vec![Rename { from: PathBuf::from("a.txt"), to: PathBuf::from("b.txt") }],
+            links: Vec::new(),
+        };
+
+        let mut review = Review::new(&plan);
+        assert_eq!(review.items.len(), 3);
+        assert!(review.summary(&plan, 0).starts_with("a.txt:1: line 2"));
+
+        // Drop the second hunk and the rename
+        review.accepted[1] = false;
+        review.accepted[2] = false;
+        review.apply_to(&mut plan).unwrap();
+        assert_eq!(plan.edits[0].replaced, original.replace("line 2\n", "row 2\n"));
+        assert!(plan.renames.is_empty());
+    }
+
+    #[test]
+    fn test_apply_toggled_subset() {
+        let dir = TestDir::new("tui-apply");
+        let original = (1..=20).map(|n| format!("foo {}\n", n)).collect::<String>();
+        let replaced = original.replace("foo 2\n", "bar 2\n").replace("foo 18\n", "bar 18\n");
+        fs::write(dir.join("a.txt"), &original).unwrap();
+        fs::write(dir.join("foo.txt"), "").unwrap();
+        fs::write(dir.join("foo.rs"), "").unwrap();
+        let mut plan = Plan {
+            edits: vec![ContentEdit { path: dir.join("a.txt"), original: original.clone(), replaced, compressed: false }],
+            renames: vec![
+                Rename { from: dir.join("foo.txt"), to: dir.join("bar.txt") },
+                Rename { from: dir.join("foo.rs"), to: dir.join("bar.rs") },
+            ],
+            ..Plan::default()
+        };
+
+        // Drop the second hunk and the first rename from the screen, then apply
+        let mut review = Review::new(&plan);
+        let mut screen = Screen { review: &mut review, list: ListState::default().with_selected(Some(0)) };
+        for key in [KeyCode::Down, KeyCode::Char(' '), KeyCode::Char('j'), KeyCode::Char(' '), KeyCode::Up, KeyCode::Up] {
+            assert_eq!(screen.key(key), Outcome::Continue);
+        }
+        assert_eq!(screen.cursor(), 0);
+        assert_eq!(screen.key(KeyCode::Enter), Outcome::Apply);
+        assert_eq!(review.accepted, [true, false, false, true]);
+        review.apply_to(&mut plan).unwrap();
+        plan.apply(false).unwrap();
+
+        assert_eq!(fs::read_to_string(dir.join("a.txt")).unwrap(), original.replace("foo 2\n", "bar 2\n"));
+        assert!(dir.join("foo.txt").exists() && !dir.join("bar.txt").exists());
+        assert!(dir.join("bar.rs").exists() && !dir.join("foo.rs").exists());
+    }
+
+    #[test]
+    fn test_draw() {
+        let plan = Plan {
+            renames: vec![Rename { from: PathBuf::from("src/foo.rs"), to: PathBuf::from("src/bar.rs") }],
+            ..Plan::default()
+        };
+        let mut review = Review::new(&plan);
+        review.accepted[0] = false;
+        let mut screen = Screen { review: &mut review, list: ListState::default().with_selected(Some(0)) };
+        let mut terminal = Terminal::new(ratatui::backend::TestBackend::new(60, 8)).unwrap();
+        terminal.draw(|frame| screen.draw(frame, &plan)).unwrap();
+
+        let buffer = terminal.backend().buffer();
+        let text: Vec<String> = (0..8).map(|y| (0..60).map(|x| buffer[(x, y)].symbol()).collect::<String>().trim_end().to_string()).collect();
+        assert!(text[0].starts_with(" 0/1 changes accepted"));
+        assert_eq!(text[1], "[ ] rename src/foo.rs -> bar.rs");
+        assert!(text.contains(&"-src/foo.rs".to_string()) && text.contains(&"+src/bar.rs".to_string()), "{:?}", text);
+    }
+}